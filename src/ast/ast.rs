@@ -1,15 +1,302 @@
-use crate::tokeniser::token_enum::Operations;
+use crate::tokeniser::token_enum::Span;
+use std::fmt;
+
+/// Infix operators
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Mod,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+/// Prefix and postfix operators
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    /// -a
+    Neg,
+    /// ~a, evaluates to -(a + 1)
+    Not,
+    /// a!
+    Factorial,
+}
+
+/// Mathematical functions built into the language
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Builtin {
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Sinh,
+    Cosh,
+    Tanh,
+    /// log(a) is base 10, log(a, b) is base b
+    Log,
+    Abs,
+    Sqrt,
+    Exp,
+    Floor,
+    Ceil,
+    Round,
+}
+
+/// Named constants, usable anywhere a variable of the same name isn't in scope
+pub const CONSTANTS: [(&str, f64); 2] = [("e", std::f64::consts::E), ("pi", std::f64::consts::PI)];
+
+pub fn constant(name: &str) -> Option<f64> {
+    CONSTANTS
+        .iter()
+        .find(|(constant, _)| *constant == name)
+        .map(|&(_, val)| val)
+}
+
+impl BinaryOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Pow => "^",
+            BinaryOp::Mod => "%",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+        }
+    }
+    /// Comparisons produce a bool rather than a number
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Less
+                | BinaryOp::LessEqual
+                | BinaryOp::Greater
+                | BinaryOp::GreaterEqual
+                | BinaryOp::Equal
+                | BinaryOp::NotEqual
+        )
+    }
+}
+
+impl UnaryOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Not => "~",
+            UnaryOp::Factorial => "!",
+        }
+    }
+}
+
+impl Builtin {
+    pub const ALL: [Builtin; 16] = [
+        Builtin::Sin,
+        Builtin::Cos,
+        Builtin::Tan,
+        Builtin::Asin,
+        Builtin::Acos,
+        Builtin::Atan,
+        Builtin::Sinh,
+        Builtin::Cosh,
+        Builtin::Tanh,
+        Builtin::Log,
+        Builtin::Abs,
+        Builtin::Sqrt,
+        Builtin::Exp,
+        Builtin::Floor,
+        Builtin::Ceil,
+        Builtin::Round,
+    ];
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::Sin => "sin",
+            Builtin::Cos => "cos",
+            Builtin::Tan => "tan",
+            Builtin::Asin => "asin",
+            Builtin::Acos => "acos",
+            Builtin::Atan => "atan",
+            Builtin::Sinh => "sinh",
+            Builtin::Cosh => "cosh",
+            Builtin::Tanh => "tanh",
+            Builtin::Log => "log",
+            Builtin::Abs => "abs",
+            Builtin::Sqrt => "sqrt",
+            Builtin::Exp => "exp",
+            Builtin::Floor => "floor",
+            Builtin::Ceil => "ceil",
+            Builtin::Round => "round",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        Builtin::ALL
+            .into_iter()
+            .find(|builtin| builtin.name() == name)
+    }
+    /// Smallest and largest number of arguments accepted
+    pub fn arity(self) -> (usize, usize) {
+        match self {
+            Builtin::Log => (1, 2),
+            _ => (1, 1),
+        }
+    }
+    /// Evaluate on arguments already checked against `arity`
+    pub fn call(self, args: &[f64]) -> f64 {
+        let val = args[0];
+        match self {
+            Builtin::Sin => val.sin(),
+            Builtin::Cos => val.cos(),
+            Builtin::Tan => val.tan(),
+            Builtin::Asin => val.asin(),
+            Builtin::Acos => val.acos(),
+            Builtin::Atan => val.atan(),
+            Builtin::Sinh => val.sinh(),
+            Builtin::Cosh => val.cosh(),
+            Builtin::Tanh => val.tanh(),
+            Builtin::Log => val.ln() / args.get(1).copied().unwrap_or(10.0).ln(),
+            Builtin::Abs => val.abs(),
+            Builtin::Sqrt => val.sqrt(),
+            Builtin::Exp => f64::powf(std::f64::consts::E, val),
+            Builtin::Floor => val.floor(),
+            Builtin::Ceil => val.ceil(),
+            Builtin::Round => val.round(),
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{0}", self.symbol())
+    }
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{0}", self.symbol())
+    }
+}
+
+impl fmt::Display for TypeAnn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeAnn::Num => write!(f, "num"),
+            TypeAnn::Bool => write!(f, "bool"),
+            TypeAnn::Unit => write!(f, "unit"),
+            TypeAnn::Fn(params, ret) => {
+                let params: Vec<String> = params.iter().map(|param| param.to_string()).collect();
+                write!(f, "fn({0}) -> {ret}", params.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{0}", self.name())
+    }
+}
+
+/// A name as written in the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
 
 #[derive(Debug, Clone)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
     Number(f64),
+    Bool(bool),
     Variable(String),
-    BinaryOp(Box<Expr>, Operations, Box<Expr>),
-    UnaryOp(Box<Expr>, Operations),
-    Assignment(Box<Expr>, String),
-    ScopeExp(Vec<Expr>),
-    Function(String, Vec<String>, Box<Expr>),
-    FunctionCall(String, Vec<Box<Expr>>),
+    BinaryOp(Box<Expr>, BinaryOp, Box<Expr>),
+    UnaryOp(Box<Expr>, UnaryOp),
+    /// Builtin applied to arguments, arity is checked by the parser
+    Builtin(Builtin, Vec<Expr>),
+    /// `{ stmt* }`, evaluates to its trailing expression statement or unit
+    Block(Vec<Stmt>),
+    FunctionCall(Ident, Vec<Expr>),
+    If(Box<Expr>, Box<Expr>, Option<Box<Expr>>),
+}
+
+/// Type written in an annotation such as `x: num`
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeAnn {
+    Num,
+    Bool,
+    Unit,
+    /// `fn(num, num) -> bool`
+    Fn(Vec<TypeAnn>, Box<TypeAnn>),
+}
+
+/// A function parameter and its optional annotation
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: Ident,
+    pub ty: Option<TypeAnn>,
+}
+
+/// `fn name(params) -> ret { body }`
+#[derive(Debug, Clone)]
+pub struct FnDecl {
+    pub name: Ident,
+    pub params: Vec<Param>,
+    pub ret: Option<TypeAnn>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum StmtKind {
+    Let(Ident, Expr),
+    Const(Ident, Expr),
+    /// Reassignment of an existing `let` binding
+    Assign(Ident, Expr),
+    Fn(FnDecl),
+    Expr(Expr),
+    Return(Option<Expr>),
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+    /// Definitions bind names rather than produce a value
+    pub fn is_definition(&self) -> bool {
+        matches!(
+            self.kind,
+            StmtKind::Let(..) | StmtKind::Const(..) | StmtKind::Assign(..) | StmtKind::Fn(..)
+        )
+    }
 }
 
 pub fn factorial(n: f64) -> f64 {
@@ -17,6 +304,8 @@ pub fn factorial(n: f64) -> f64 {
         f64::NAN // Factorial is not defined for negative numbers
     } else if n == 0.0 || n == 1.0 {
         1.0
+    } else if n >= 171.0 {
+        f64::INFINITY // Past 170! the product overflows, so don't count that far
     } else {
         (1..=(n as u64)).fold(1.0, |acc, x| acc * x as f64)
     }
@@ -0,0 +1,84 @@
+use crate::ast::ast::Expr;
+use crate::ast::ast::ExprKind;
+use crate::ast::ast::Stmt;
+use crate::ast::ast::StmtKind;
+use crate::ast::ast::UnaryOp;
+use std::fmt;
+
+/// Comma separated list of displayable items
+fn join<T: fmt::Display>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Expressions print fully parenthesised so the shape of the tree is visible
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ExprKind::Number(val) => write!(f, "{val}"),
+            ExprKind::Bool(val) => write!(f, "{val}"),
+            ExprKind::Variable(id) => write!(f, "{id}"),
+            ExprKind::BinaryOp(left, op, right) => write!(f, "({left} {op} {right})"),
+            ExprKind::UnaryOp(operand, UnaryOp::Factorial) => write!(f, "({operand}!)"),
+            ExprKind::UnaryOp(operand, op) => write!(f, "({op}{operand})"),
+            ExprKind::Builtin(builtin, args) => write!(f, "{builtin}({0})", join(args)),
+            ExprKind::FunctionCall(id, args) => write!(f, "{0}({1})", id.name, join(args)),
+            ExprKind::Block(stmts) => {
+                let body = stmts
+                    .iter()
+                    .map(|stmt| stmt.to_string())
+                    .collect::<Vec<_>>()
+                    .join("; ");
+                if body.is_empty() {
+                    write!(f, "{{}}")
+                } else {
+                    write!(f, "{{ {body} }}")
+                }
+            }
+            ExprKind::If(cond, then_branch, else_branch) => {
+                write!(f, "if {cond} {then_branch}")?;
+                match else_branch {
+                    Some(else_branch) => write!(f, " else {else_branch}"),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            StmtKind::Let(id, expr) => write!(f, "let {0} = {expr}", id.name),
+            StmtKind::Const(id, expr) => write!(f, "const {0} = {expr}", id.name),
+            StmtKind::Assign(id, expr) => write!(f, "{0} = {expr}", id.name),
+            StmtKind::Fn(decl) => {
+                let params: Vec<String> = decl
+                    .params
+                    .iter()
+                    .map(|param| match &param.ty {
+                        Some(ty) => format!("{0}: {ty}", param.name.name),
+                        None => param.name.name.clone(),
+                    })
+                    .collect();
+                let ret = match &decl.ret {
+                    Some(ty) => format!(" -> {ty}"),
+                    None => String::new(),
+                };
+                let body = Expr::new(ExprKind::Block(decl.body.clone()), self.span);
+                write!(
+                    f,
+                    "fn {0}({1}){ret} {body}",
+                    decl.name.name,
+                    params.join(", ")
+                )
+            }
+            StmtKind::Expr(expr) => write!(f, "{expr}"),
+            StmtKind::Return(Some(expr)) => write!(f, "return {expr}"),
+            StmtKind::Return(None) => write!(f, "return"),
+        }
+    }
+}
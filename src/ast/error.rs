@@ -0,0 +1,43 @@
+use std::fmt;
+
+/// Errors raised while evaluating an AST
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    UndeclaredVariable(String),
+    UndefinedFunction(String),
+    AssignToConst(String),
+    ArityMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    DivisionByZero,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::UndeclaredVariable(id) => write!(f, "Undeclared Variable: {id}"),
+            RuntimeError::UndefinedFunction(name) => write!(f, "Undefined Function: {name}"),
+            RuntimeError::AssignToConst(id) => write!(f, "Cannot assign to const: {id}"),
+            RuntimeError::ArityMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Function {name} called with wrong # of params, expected {expected} got {found}"
+            ),
+            RuntimeError::TypeMismatch { expected, found } => {
+                write!(f, "Type error: expected {expected}, found {found}")
+            }
+            RuntimeError::DivisionByZero => write!(f, "Division by zero"),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
@@ -0,0 +1,54 @@
+use crate::ast::error::RuntimeError;
+use crate::ast::value::Value;
+use crate::tokeniser::token_enum::Span;
+
+/// Outcome of evaluating a single top level statement
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// An expression statement produced a value
+    Value(Value),
+    /// Definitions (`let`, `fn`, reassignment) produce no value
+    Unit,
+    Error(RuntimeError),
+}
+
+/// Outcome of a statement alongside the source it came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementResult {
+    pub outcome: Outcome,
+    pub span: Span,
+}
+
+/// Per statement results of a program, evaluation stops at the first error
+/// so an error is always the final entry
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Evaluation {
+    pub results: Vec<StatementResult>,
+}
+
+impl Evaluation {
+    /// Result of the last expression statement
+    pub fn value(&self) -> Option<Value> {
+        self.results
+            .iter()
+            .rev()
+            .find_map(|res| match &res.outcome {
+                Outcome::Value(val) => Some(val.clone()),
+                _ => None,
+            })
+    }
+    /// The error that stopped evaluation, if any
+    pub fn error(&self) -> Option<(&RuntimeError, Span)> {
+        self.results.last().and_then(|res| match &res.outcome {
+            Outcome::Error(err) => Some((err, res.span)),
+            _ => None,
+        })
+    }
+    /// Collapse into the final value or the first error
+    pub fn into_result(self) -> Result<Option<Value>, RuntimeError> {
+        match self.error() {
+            Some((err, _)) => Err(err.clone()),
+            None => Ok(self.value()),
+        }
+    }
+}
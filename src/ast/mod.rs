@@ -1,2 +1,8 @@
+#[allow(clippy::module_inception)]
 pub mod ast;
+pub mod display;
+pub mod error;
+pub mod evaluation;
 pub mod parser;
+pub mod precedence;
+pub mod value;
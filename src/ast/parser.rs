@@ -1,19 +1,90 @@
-use crate::ast::ast::factorial;
+use crate::ast::ast::constant;
+use crate::ast::ast::BinaryOp;
+use crate::ast::ast::Builtin;
 use crate::ast::ast::Expr;
-use crate::tokeniser::token_enum::Operations;
+use crate::ast::ast::ExprKind;
+use crate::ast::ast::FnDecl;
+use crate::ast::ast::Ident;
+use crate::ast::ast::Param;
+use crate::ast::ast::Stmt;
+use crate::ast::ast::StmtKind;
+use crate::ast::ast::TypeAnn;
+use crate::ast::ast::UnaryOp;
+use crate::ast::error::RuntimeError;
+use crate::ast::evaluation::Evaluation;
+use crate::ast::evaluation::Outcome;
+use crate::ast::evaluation::StatementResult;
+use crate::ast::precedence::infix_binding;
+use crate::ast::precedence::Assoc;
+use crate::ast::precedence::Precedence;
+use crate::ast::value::Function;
+use crate::ast::value::Value;
+use crate::semantic::resolver::Resolver;
+use crate::tokeniser::token_enum::Span;
 use crate::tokeniser::token_enum::Token;
+use crate::tokeniser::token_enum::TokenKind;
 use crate::tokeniser::token_enum::TokenStream;
-use crate::tokeniser::token_enum::ValueType;
 use std::collections::HashMap;
+use std::rc::Rc;
 
 pub type ExprStream = Vec<Expr>;
 
+/// A variable slot, `const` bindings can't be reassigned
+#[derive(Debug, Clone)]
+struct Binding {
+    value: Value,
+    mutable: bool,
+}
+
+/// Ways evaluation can leave an expression early, `return` unwinds up to the
+/// enclosing function call
+enum Control {
+    Error(RuntimeError),
+    Return(Value),
+}
+
+impl From<RuntimeError> for Control {
+    fn from(err: RuntimeError) -> Self {
+        Control::Error(err)
+    }
+}
+
+/// Optional syntax that can be switched off
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dialect {
+    /// Treat juxtaposition such as `2x` or `(a)(b)` as multiplication
+    pub implicit_multiplication: bool,
+}
+
+impl Dialect {
+    /// Every operator has to be written out
+    pub fn strict() -> Self {
+        Self {
+            implicit_multiplication: false,
+        }
+    }
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Self {
+            implicit_multiplication: true,
+        }
+    }
+}
+
 pub struct Parser {
     tokens: TokenStream,
+    dialect: Dialect,
     cursor: usize,
     line: i16,
-    scopes: Vec<HashMap<String, f64>>,
-    fmap: HashMap<String, (Vec<String>, Box<Expr>)>,
+    prev_span: Span,
+    fn_depth: usize,
+    /// Open brackets around the cursor, line breaks inside them don't end
+    /// a statement
+    nesting: usize,
+    scopes: Vec<HashMap<String, Binding>>,
+    fmap: HashMap<String, Rc<Function>>,
 }
 
 impl Parser {
@@ -21,110 +92,254 @@ impl Parser {
     pub fn new(inp_tokens: TokenStream) -> Self {
         Self {
             tokens: inp_tokens,
+            dialect: Dialect::default(),
             cursor: 0usize,
             line: 1i16,
+            prev_span: Span::default(),
+            fn_depth: 0usize,
+            nesting: 0usize,
             scopes: vec![HashMap::new()],
             fmap: HashMap::new(),
         }
     }
-    /// Evaluate arg 'expr' which is going to be the AST tree representation
-    pub fn evaluate(&mut self, exprs: &[Expr]) -> f64 {
-        let mut last_result = 0f64;
+    /// Parse with the given dialect instead of the default one
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+    /// Swap in a fresh token stream while keeping variables and functions,
+    /// used by the REPL to evaluate each line against the same state
+    pub fn feed(&mut self, inp_tokens: TokenStream) {
+        self.tokens = inp_tokens;
+        self.cursor = 0usize;
+        self.line = 1i16;
+        self.prev_span = Span::default();
+        self.fn_depth = 0usize;
+        self.nesting = 0usize;
+    }
+    /// Drop every variable and function defined so far
+    pub fn reset(&mut self) {
+        self.scopes = vec![HashMap::new()];
+        self.fmap.clear();
+    }
+    /// Global variables sorted by name
+    pub fn variables(&self) -> Vec<(String, Value)> {
+        let mut vars: Vec<(String, Value)> = self.scopes[0]
+            .iter()
+            .map(|(id, binding)| (id.clone(), binding.value.clone()))
+            .collect();
+        vars.sort_by(|a, b| a.0.cmp(&b.0));
+        vars
+    }
+    /// User defined functions and their parameters sorted by name
+    pub fn functions(&self) -> Vec<(String, Vec<String>)> {
+        let mut funcs: Vec<(String, Vec<String>)> = self
+            .fmap
+            .iter()
+            .map(|(name, func)| (name.clone(), func.params.clone()))
+            .collect();
+        funcs.sort_by(|a, b| a.0.cmp(&b.0));
+        funcs
+    }
+    /// A resolver that already knows every global and function defined so far
+    pub fn resolver(&self) -> Resolver {
+        let mut resolver = Resolver::new();
+        for (id, binding) in &self.scopes[0] {
+            resolver.declare_global(id, binding.mutable);
+        }
+        for (name, func) in &self.fmap {
+            resolver.declare_function(name, func.params.len());
+        }
+        resolver
+    }
+    /// Define (or overwrite) a variable in the outermost scope
+    pub fn set_global(&mut self, id: &str, value: Value) {
+        self.scopes[0].insert(
+            id.to_string(),
+            Binding {
+                value,
+                mutable: true,
+            },
+        );
+    }
+    /// Evaluate each statement in order, recording what every statement
+    /// produced and stopping at the first error
+    pub fn evaluate(&mut self, stmts: &[Stmt]) -> Evaluation {
+        let mut evaluation = Evaluation::default();
 
-        for expr in exprs {
-            match self.eval(expr) {
-                val if val != 0.0 => last_result = val,
-                _ => {}
+        for stmt in stmts {
+            let outcome = match self.exec(stmt) {
+                Ok(_) if stmt.is_definition() => Outcome::Unit,
+                Ok(val) | Err(Control::Return(val)) => Outcome::Value(val),
+                Err(Control::Error(err)) => Outcome::Error(err),
+            };
+            let failed = matches!(outcome, Outcome::Error(_));
+            evaluation.results.push(StatementResult {
+                outcome,
+                span: stmt.span,
+            });
+            if failed {
+                break;
             }
         }
 
-        last_result
+        evaluation
     }
-    pub fn eval(&mut self, expr: &Expr) -> f64 {
-        match expr {
-            Expr::Number(arb_val) => *arb_val,
-            Expr::Function(fn_name, params, exprs) => {
-                self.fmap
-                    .insert(fn_name.clone(), (params.clone(), exprs.clone()));
-                0.0
-            }
-            Expr::FunctionCall(name, params) => {
-                if let Some((param_names, body)) = self.fmap.get(name).cloned() {
-                    if param_names.len() != params.len() {
-                        panic!("Function {0} called with wrong # of params", name)
-                    }
-                    self.enter_scope();
-                    for (param_name, param_exp) in param_names.iter().zip(params.iter()) {
-                        let exp = self.eval(param_exp);
-                        self.set_variable(param_name.to_string(), exp);
-                    }
-                    let res = self.eval(&body);
-                    self.exit_scope();
-                    res
-                } else {
-                    panic!("Undefined Function: {0}", name)
+    /// Evaluate a single expression against the current state
+    pub fn eval(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        match self.eval_expr(expr) {
+            Ok(val) | Err(Control::Return(val)) => Ok(val),
+            Err(Control::Error(err)) => Err(err),
+        }
+    }
+    /// Execute a statement, expression statements give back their value and
+    /// everything else gives unit
+    fn exec(&mut self, stmt: &Stmt) -> Result<Value, Control> {
+        match &stmt.kind {
+            StmtKind::Let(id, expr) | StmtKind::Const(id, expr) => {
+                let value = self.eval_expr(expr)?;
+                let mutable = matches!(stmt.kind, StmtKind::Let(..));
+                self.set_variable(id.name.clone(), Binding { value, mutable });
+                Ok(Value::Unit)
+            }
+            StmtKind::Assign(id, expr) => {
+                let value = self.eval_expr(expr)?;
+                let binding = self
+                    .scopes
+                    .iter_mut()
+                    .rev()
+                    .find_map(|scope| scope.get_mut(&id.name))
+                    .ok_or_else(|| RuntimeError::UndeclaredVariable(id.name.clone()))?;
+                if !binding.mutable {
+                    return Err(RuntimeError::AssignToConst(id.name.clone()).into());
                 }
+                binding.value = value;
+                Ok(Value::Unit)
+            }
+            StmtKind::Fn(decl) => {
+                let func = Rc::new(Function {
+                    name: decl.name.name.clone(),
+                    params: decl
+                        .params
+                        .iter()
+                        .map(|param| param.name.name.clone())
+                        .collect(),
+                    body: decl.body.clone(),
+                    code: None,
+                });
+                self.fmap.insert(decl.name.name.clone(), func);
+                Ok(Value::Unit)
+            }
+            StmtKind::Expr(expr) => self.eval_expr(expr),
+            StmtKind::Return(expr) => {
+                let value = match expr {
+                    Some(expr) => self.eval_expr(expr)?,
+                    None => Value::Unit,
+                };
+                Err(Control::Return(value))
+            }
+        }
+    }
+    /// Run statements in a fresh scope, the value is that of a trailing
+    /// expression statement
+    fn exec_block(&mut self, stmts: &[Stmt]) -> Result<Value, Control> {
+        self.enter_scope();
+        let mut last_result = Ok(Value::Unit);
+        for stmt in stmts {
+            last_result = self.exec(stmt);
+            if last_result.is_err() {
+                break;
+            }
+            if stmt.is_definition() {
+                last_result = Ok(Value::Unit);
             }
-            Expr::Variable(id) => self
-                .get_variable(id.to_string())
-                .ok_or_else(|| panic!("Undeclared Variable: {0}", id))
-                .unwrap(),
-            Expr::ScopeExp(exprs) => {
-                self.enter_scope();
-                let mut last_result = 0f64;
-                for expr in exprs {
-                    last_result = self.eval(expr);
+        }
+        self.exit_scope();
+        last_result
+    }
+    fn call(&mut self, name: &Ident, params: &[Expr]) -> Result<Value, Control> {
+        let func = match self.get_variable(&name.name) {
+            Some(Value::Function(func)) => func,
+            Some(other) => {
+                return Err(RuntimeError::TypeMismatch {
+                    expected: "function",
+                    found: other.type_name(),
                 }
-                self.exit_scope();
-                last_result
-            }
-            Expr::Assignment(expr, id) => {
-                let val = self.eval(expr);
-                self.set_variable(id.to_string(), val);
-                0.0
-            }
-            Expr::BinaryOp(left, op, right) => {
-                let left_val = self.eval(left);
-                let right_val = self.eval(right);
-                match op {
-                    Operations::ADD => left_val + right_val,
-                    Operations::MINUS => left_val - right_val,
-                    Operations::POWER => f64::powf(left_val, right_val),
-                    Operations::DIVIDE => {
-                        if right_val == 0f64 {
-                            panic!("Division by zero")
-                        }
-                        left_val / right_val
-                    }
-                    Operations::MULTIPLY => left_val * right_val,
-                    Operations::FNLOG => left_val.ln() / right_val.ln(),
-                    Operations::FNMOD => left_val % right_val,
-                    _ => 0.0,
+                .into())
+            }
+            None => self
+                .fmap
+                .get(&name.name)
+                .cloned()
+                .ok_or_else(|| RuntimeError::UndefinedFunction(name.name.clone()))?,
+        };
+        if func.params.len() != params.len() {
+            return Err(RuntimeError::ArityMismatch {
+                name: name.name.clone(),
+                expected: func.params.len(),
+                found: params.len(),
+            }
+            .into());
+        }
+        let mut args = Vec::new();
+        for param_exp in params {
+            args.push(self.eval_expr(param_exp)?);
+        }
+        // The body sees the globals and its parameters, not the caller's locals
+        let caller_scopes = self.scopes.split_off(1);
+        self.enter_scope();
+        for (param_name, arg) in func.params.iter().zip(args) {
+            self.set_variable(
+                param_name.to_string(),
+                Binding {
+                    value: arg,
+                    mutable: true,
+                },
+            );
+        }
+        let res = match self.exec_block(&func.body) {
+            Err(Control::Return(val)) => Ok(val),
+            res => res,
+        };
+        self.exit_scope();
+        self.scopes.extend(caller_scopes);
+        res
+    }
+    fn eval_expr(&mut self, expr: &Expr) -> Result<Value, Control> {
+        match &expr.kind {
+            ExprKind::Number(arb_val) => Ok(Value::Number(*arb_val)),
+            ExprKind::Bool(arb_val) => Ok(Value::Bool(*arb_val)),
+            ExprKind::FunctionCall(name, params) => self.call(name, params),
+            ExprKind::Variable(id) => Ok(self
+                .get_variable(id)
+                .or_else(|| self.fmap.get(id).cloned().map(Value::Function))
+                .or_else(|| constant(id).map(Value::Number))
+                .ok_or_else(|| RuntimeError::UndeclaredVariable(id.to_string()))?),
+            ExprKind::Block(stmts) => self.exec_block(stmts),
+            ExprKind::If(cond, then_branch, else_branch) => {
+                if self.eval_expr(cond)?.as_bool()? {
+                    self.eval_expr(then_branch)
+                } else if let Some(else_branch) = else_branch {
+                    self.eval_expr(else_branch)
+                } else {
+                    Ok(Value::Unit)
                 }
             }
-            Expr::UnaryOp(left, op) => {
-                let val = self.eval(left);
-                match op {
-                    Operations::FNCOS => val.cos(),
-                    Operations::FNSIN => val.sin(),
-                    Operations::FNTAN => val.tan(),
-                    Operations::FNFACT => factorial(val),
-                    Operations::MINUS => -val,
-                    Operations::NOT => -(val + 1.0),
-                    Operations::FNABS => val.abs(),
-                    Operations::FNSQRT => val.sqrt(),
-                    Operations::FNEXP => f64::powf(std::f64::consts::E, val),
-                    Operations::FNASIN => val.asin(),
-                    Operations::FNACOS => val.acos(),
-                    Operations::FNATAN => val.atan(),
-                    Operations::FNSINH => val.sinh(),
-                    Operations::FNCOSH => val.cosh(),
-                    Operations::FNTANH => val.tanh(),
-                    Operations::FNFLOOR => val.floor(),
-                    Operations::FNCEIL => val.ceil(),
-                    Operations::FNROUND => val.round(),
-                    _ => 0.0,
+            ExprKind::BinaryOp(left, op, right) => {
+                let left_val = self.eval_expr(left)?;
+                let right_val = self.eval_expr(right)?;
+                Ok(Value::binary(*op, &left_val, &right_val)?)
+            }
+            ExprKind::UnaryOp(operand, op) => {
+                let val = self.eval_expr(operand)?;
+                Ok(Value::unary(*op, &val)?)
+            }
+            ExprKind::Builtin(builtin, args) => {
+                let mut nums = Vec::new();
+                for arg in args {
+                    nums.push(self.eval_expr(arg)?.as_number()?);
                 }
+                Ok(Value::Number(builtin.call(&nums)))
             }
         }
     }
@@ -136,24 +351,46 @@ impl Parser {
         };
         self.cursor += 1;
         self.line = token.line_number;
+        self.prev_span = token.span;
 
         Ok(token.clone())
     }
-    /// Expect or error on given operation
-    fn expect(&mut self, op: Operations) -> Result<Token, String> {
+    /// Expect or error on given token kind
+    fn expect(&mut self, kind: TokenKind) -> Result<Token, String> {
         let tok = self.advance()?;
 
-        if tok.operation != Some(op) {
+        if tok.kind != kind {
             return Err(format!(
-                "Expected {0:#?} got {1:#?} at {2}",
-                op,
-                tok.operation.unwrap(),
-                self.cursor
+                "Expected {0:?} got {1:?} at {2}",
+                kind, tok.kind, self.cursor
             ));
         }
 
         Ok(tok)
     }
+    /// Consume an identifier, producing `err` with the cursor if there isn't one
+    fn expect_ident(&mut self, err: &str) -> Result<Ident, String> {
+        let tok = self.advance()?;
+        match tok.kind {
+            TokenKind::Identifier(name) => Ok(Ident {
+                name,
+                span: tok.span,
+            }),
+            _ => Err(format!("{0} @ {1}", err, self.cursor)),
+        }
+    }
+    /// Consume an identifier that is about to be declared, builtin function
+    /// names are reserved
+    fn expect_declaration(&mut self, err: &str) -> Result<Ident, String> {
+        let id = self.expect_ident(err)?;
+        if Builtin::from_name(&id.name).is_some() {
+            return Err(format!(
+                "{0} is a builtin and cannot be redefined @ {1}",
+                id.name, self.cursor
+            ));
+        }
+        Ok(id)
+    }
     fn enter_scope(&mut self) {
         self.scopes.push(HashMap::new());
     }
@@ -162,330 +399,457 @@ impl Parser {
             self.scopes.pop();
         }
     }
-    fn get_variable(&self, id: String) -> Option<f64> {
+    fn get_variable(&self, id: &str) -> Option<Value> {
         for scope in self.scopes.iter().rev() {
-            if let Some(&val) = scope.get(&id) {
-                return Some(val);
+            if let Some(binding) = scope.get(id) {
+                return Some(binding.value.clone());
             }
         }
         None
     }
-    fn set_variable(&mut self, id: String, value: f64) {
+    fn set_variable(&mut self, id: String, binding: Binding) {
         if let Some(scope) = self.scopes.last_mut() {
-            scope.insert(id, value);
+            scope.insert(id, binding);
         }
     }
     /// Peek into current cursor element without consuming data
-    fn peek(&mut self) -> Option<Token> {
-        self.tokens.get(self.cursor).cloned()
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.cursor).map(|tok| &tok.kind)
+    }
+    /// Kind of the token after the current one
+    fn peek_second(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.cursor + 1).map(|tok| &tok.kind)
+    }
+    /// Whether the next token is of the given kind
+    fn at(&self, kind: &TokenKind) -> bool {
+        self.peek() == Some(kind)
+    }
+    /// Span of the next token, used as the start of the node about to be parsed
+    fn peek_span(&self) -> Span {
+        self.tokens
+            .get(self.cursor)
+            .map_or(self.prev_span, |tok| tok.span)
+    }
+    /// Span from `start` up to the last consumed token
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.prev_span)
+    }
+    fn binary(left: Expr, op: BinaryOp, right: Expr) -> Expr {
+        let span = left.span.to(right.span);
+        Expr::new(
+            ExprKind::BinaryOp(Box::new(left), op, Box::new(right)),
+            span,
+        )
     }
-    /// Parse tokens into AST nodes
-    pub fn parse_lines(&mut self) -> Result<Vec<Expr>, String> {
+    /// Whether the next token starts a new line outside of any brackets,
+    /// which ends the expression being parsed
+    fn at_line_break(&self) -> bool {
+        self.nesting == 0
+            && self
+                .tokens
+                .get(self.cursor)
+                .is_some_and(|tok| tok.line_number != self.line)
+    }
+    /// Skip any number of `;`
+    fn skip_semicolons(&mut self) -> Result<(), String> {
+        while self.at(&TokenKind::Semicolon) {
+            self.advance()?;
+        }
+        Ok(())
+    }
+    /// Parse tokens into top level statements
+    pub fn parse_lines(&mut self) -> Result<Vec<Stmt>, String> {
         let mut ret = Vec::new();
-        while self.cursor < self.tokens.len() {
+        self.skip_semicolons()?;
+        while self.peek().is_some() {
             ret.push(self.parse_tokens()?);
+            self.skip_semicolons()?;
         }
         Ok(ret)
     }
-    pub fn parse_tokens(&mut self) -> Result<Expr, String> {
-        let start_line = self.line;
-
-        let expr = self.parse_addition_and_subtraction()?;
+    /// Parse a single statement, which has to be followed by `;`, a line
+    /// break, a closing `}` or the end of input
+    pub fn parse_tokens(&mut self) -> Result<Stmt, String> {
+        let stmt = self.parse_statement()?;
 
-        if let Some(tok) = self.peek() {
-            if tok.line_number > start_line {
-                return Ok(expr);
-            }
+        match self.peek() {
+            None | Some(TokenKind::Semicolon | TokenKind::RBrace) => Ok(stmt),
+            Some(_) if self.at_line_break() => Ok(stmt),
+            Some(_) => Err(format!(
+                "Expected ';' or a new line after statement @ {0}",
+                self.cursor
+            )),
         }
-        Ok(expr)
     }
-    /// Handle addition & subtraction
-    fn parse_addition_and_subtraction(&mut self) -> Result<Expr, String> {
-        let mut left = self.parse_multiplication_and_division()?;
+    fn parse_statement(&mut self) -> Result<Stmt, String> {
+        let start = self.peek_span();
+        let Some(tok) = self.peek() else {
+            return Err(format!("Empty input @ {0}", self.cursor));
+        };
 
-        while let Some(parsed_tok) = self.peek() {
-            match parsed_tok.operation {
-                Some(Operations::ADD) | Some(Operations::MINUS) => {
-                    self.advance()?;
-                    let right = self.parse_multiplication_and_division()?;
-                    left = Expr::BinaryOp(
-                        Box::new(left),
-                        parsed_tok.operation.unwrap(),
-                        Box::new(right),
-                    );
+        let kind = match tok {
+            TokenKind::Let => {
+                let (id, expr) = self.parse_binding("let")?;
+                StmtKind::Let(id, expr)
+            }
+            TokenKind::Const => {
+                let (id, expr) = self.parse_binding("const")?;
+                StmtKind::Const(id, expr)
+            }
+            TokenKind::Fn => StmtKind::Fn(self.parse_custom_function()?),
+            TokenKind::Return => {
+                if self.fn_depth == 0 {
+                    return Err(format!("Return outside of a function @ {0}", self.cursor));
+                }
+                self.advance()?;
+                match self.peek() {
+                    None | Some(TokenKind::RBrace | TokenKind::Semicolon) => StmtKind::Return(None),
+                    _ if self.at_line_break() => StmtKind::Return(None),
+                    _ => StmtKind::Return(Some(self.parse_expression()?)),
                 }
-                _ => break,
             }
+            TokenKind::Identifier(_) if self.peek_second() == Some(&TokenKind::Assign) => {
+                let id = self.expect_ident("Expected identifier")?;
+                self.advance()?;
+                StmtKind::Assign(id, self.parse_expression()?)
+            }
+            _ => StmtKind::Expr(self.parse_expression()?),
+        };
+        Ok(Stmt::new(kind, self.span_from(start)))
+    }
+    /// `let`/`const` identifier = expression
+    fn parse_binding(&mut self, keyword: &str) -> Result<(Ident, Expr), String> {
+        self.advance()?;
+        let id = self.expect_declaration(&format!("Expected identifier after {keyword}"))?;
+        self.expect(TokenKind::Assign)?;
+        let expr = self.parse_expression()?;
+        Ok((id, expr))
+    }
+    /// Parse a full expression
+    fn parse_expression(&mut self) -> Result<Expr, String> {
+        self.parse_expr_bp(Precedence::Lowest)
+    }
+    fn infix_op(kind: Option<&TokenKind>) -> Option<BinaryOp> {
+        match kind? {
+            TokenKind::Less => Some(BinaryOp::Less),
+            TokenKind::LessEqual => Some(BinaryOp::LessEqual),
+            TokenKind::Greater => Some(BinaryOp::Greater),
+            TokenKind::GreaterEqual => Some(BinaryOp::GreaterEqual),
+            TokenKind::EqualEqual => Some(BinaryOp::Equal),
+            TokenKind::BangEqual => Some(BinaryOp::NotEqual),
+            TokenKind::Plus => Some(BinaryOp::Add),
+            TokenKind::Minus => Some(BinaryOp::Sub),
+            TokenKind::Star => Some(BinaryOp::Mul),
+            TokenKind::Slash => Some(BinaryOp::Div),
+            TokenKind::Percent => Some(BinaryOp::Mod),
+            TokenKind::Caret => Some(BinaryOp::Pow),
+            _ => None,
         }
-        Ok(left)
     }
-    /// Handle multiplication and division
-    fn parse_multiplication_and_division(&mut self) -> Result<Expr, String> {
-        let mut left = self.parse_power()?;
+    /// Pratt parser, keeps folding operators into `left` while they bind at
+    /// least as tightly as `min`, see `precedence.rs` for the operator table
+    fn parse_expr_bp(&mut self, min: Precedence) -> Result<Expr, String> {
+        let mut left = self.parse_prefix()?;
 
-        while let Some(parsed_tok) = self.peek() {
-            match parsed_tok.operation {
-                Some(Operations::MULTIPLY) | Some(Operations::DIVIDE) => {
-                    self.advance()?;
-                    let right = self.parse_power()?;
-                    left = Expr::BinaryOp(
-                        Box::new(left),
-                        parsed_tok.operation.unwrap(),
-                        Box::new(right),
-                    );
-                }
-                _ => break,
+        loop {
+            if self.at_line_break() {
+                break;
             }
-        }
-        Ok(left)
-    }
-    /// Handle powers
-    fn parse_power(&mut self) -> Result<Expr, String> {
-        let mut left = self.parse_fn()?;
 
-        while let Some(parsed_tok) = self.peek() {
-            match parsed_tok.operation {
-                Some(Operations::POWER) => {
-                    self.advance()?;
-                    let right = self.parse_fn()?;
-                    left = Expr::BinaryOp(Box::new(left), Operations::POWER, Box::new(right));
-                }
-                _ => break,
+            if self.at(&TokenKind::Bang) && Precedence::Postfix >= min {
+                self.advance()?;
+                let span = self.span_from(left.span);
+                left = Expr::new(ExprKind::UnaryOp(Box::new(left), UnaryOp::Factorial), span);
+                continue;
             }
-        }
-        Ok(left)
-    }
-    /// Handle all functions like sin/cos/tan and factorial
-    fn parse_fn(&mut self) -> Result<Expr, String> {
-        if let Some(parsed_tok) = self.peek() {
-            match parsed_tok.operation {
-                Some(Operations::FNCOS)
-                | Some(Operations::FNSIN)
-                | Some(Operations::FNTAN)
-                | Some(Operations::FNABS)
-                | Some(Operations::FNSQRT)
-                | Some(Operations::FNEXP)
-                | Some(Operations::FNASIN)
-                | Some(Operations::FNACOS)
-                | Some(Operations::FNATAN)
-                | Some(Operations::FNSINH)
-                | Some(Operations::FNCOSH)
-                | Some(Operations::FNTANH)
-                | Some(Operations::FNFLOOR)
-                | Some(Operations::FNCEIL)
-                | Some(Operations::FNROUND)
-                | Some(Operations::FNFACT) => {
-                    self.advance()?;
-                    let expr = self.parse_primary()?;
-                    return Ok(Expr::UnaryOp(Box::new(expr), parsed_tok.operation.unwrap()));
+
+            if Precedence::Implicit >= min && self.at_implicit_operand() {
+                if let Some(err) = self.split_number() {
+                    return Err(format!("{err} @ {0}", self.cursor));
                 }
-                Some(Operations::FNLOG) => {
-                    // Consume log
-                    self.advance()?;
-                    self.expect(Operations::LPAREN)?;
-                    let expo = self.parse_addition_and_subtraction()?;
-                    let base = if self
-                        .peek()
-                        .map_or(false, |tok| tok.operation == Some(Operations::COMMA))
-                    {
-                        self.advance()?;
-                        self.parse_addition_and_subtraction()?
-                    } else {
-                        Expr::Number(10.0)
-                    };
-                    self.expect(Operations::RPAREN)?;
-                    return Ok(Expr::BinaryOp(
-                        Box::new(expo),
-                        Operations::FNLOG,
-                        Box::new(base),
+                if !self.dialect.implicit_multiplication {
+                    return Err(format!(
+                        "Implicit multiplication is not allowed in the strict dialect @ {0}",
+                        self.cursor
                     ));
                 }
-                _ => {}
+                let right = self.parse_expr_bp(Precedence::Implicit.tighter())?;
+                left = Self::binary(left, BinaryOp::Mul, right);
+                continue;
             }
-        }
-        let mut expr = self.parse_primary()?;
 
-        if let Some(Token {
-            operation: Some(Operations::FNMOD),
-            ..
-        }) = self.peek()
-        {
+            let Some(op) = Self::infix_op(self.peek()) else {
+                break;
+            };
+            let (prec, assoc) = infix_binding(op);
+            if prec < min {
+                break;
+            }
             self.advance()?;
-            let right_expr = self.parse_primary()?;
-            expr = Expr::BinaryOp(Box::new(expr), Operations::FNMOD, Box::new(right_expr));
-        }
+            let right = match assoc {
+                Assoc::Right => self.parse_expr_bp(prec)?,
+                Assoc::Left | Assoc::None => self.parse_expr_bp(prec.tighter())?,
+            };
+            left = Self::binary(left, op, right);
 
-        if let Some(Token {
-            operation: Some(Operations::FNFACT),
-            ..
-        }) = self.peek()
-        {
-            self.advance()?;
-            expr = Expr::UnaryOp(Box::new(expr), Operations::FNFACT);
-        }
-        Ok(expr)
-    }
-    fn parse_get_or_set(&mut self) -> Option<Expr> {
-        if let Some(Token {
-            value: Some(ValueType::Identifier(id)),
-            ..
-        }) = self.peek()
-        {
-            self.advance().ok()?;
-            if let Some(Token {
-                operation: Some(Operations::VARASSIGN),
-                ..
-            }) = self.peek()
+            if assoc == Assoc::None
+                && Self::infix_op(self.peek()).is_some_and(|next| infix_binding(next).0 == prec)
             {
-                self.advance().ok()?;
-                let expr = self.parse_addition_and_subtraction().ok()?;
-                return Some(Expr::Assignment(Box::new(expr), id));
-            } else {
-                return Some(Expr::Variable(id));
+                return Err(format!("Comparisons cannot be chained @ {0}", self.cursor));
             }
         }
-        None
+        Ok(left)
     }
-    fn parse_scope(&mut self) -> Result<Expr, String> {
-        self.expect(Operations::LBRACE)?;
-        let mut exprs = Vec::new();
-        while self
-            .peek()
-            .map_or(false, |tok| tok.operation != Some(Operations::RBRACE))
-        {
-            exprs.push(self.parse_tokens()?);
-        }
-        self.expect(Operations::RBRACE)?;
-        Ok(Expr::ScopeExp(exprs))
-    }
-    fn parse_assignment(&mut self) -> Result<Expr, String> {
-        self.advance()?;
-        let identifier = match self.advance()?.value {
-            Some(ValueType::Identifier(id)) => id,
-            _ => return Err(format!("Expected identifier after let @ {0}", self.cursor)),
+    /// Whether the next token starts an operand that is implicitly multiplied
+    /// with the one just parsed, which never happens across lines
+    fn at_implicit_operand(&self) -> bool {
+        let Some(tok) = self.tokens.get(self.cursor) else {
+            return false;
         };
-        self.expect(Operations::VARASSIGN)?;
-        let expr = self.parse_addition_and_subtraction()?;
-        Ok(Expr::Assignment(Box::new(expr), identifier))
+        tok.line_number == self.line
+            && matches!(
+                tok.kind,
+                TokenKind::Number(_) | TokenKind::Identifier(_) | TokenKind::LParen
+            )
     }
-    fn parse_unary_minus(&mut self) -> Result<Expr, String> {
-        self.advance()?;
-        let parsed_exp = self.parse_primary()?;
-        Ok(Expr::UnaryOp(Box::new(parsed_exp), Operations::MINUS))
+    /// Why multiplying the number just parsed with the next operand would
+    /// misread what was meant, `2 3` reads as one number and `1e3` as
+    /// scientific notation rather than `1 * e * 3`
+    fn split_number(&self) -> Option<&'static str> {
+        let prev = self.tokens.get(self.cursor.checked_sub(1)?)?;
+        if !matches!(prev.kind, TokenKind::Number(_)) {
+            return None;
+        }
+        let next = self.tokens.get(self.cursor)?;
+        let touching = |before: &Token, after: &Token| before.span.end == after.span.start;
+        match &next.kind {
+            TokenKind::Number(_) => Some("A number can't follow another number"),
+            TokenKind::Identifier(name)
+                if name == "e"
+                    && touching(prev, next)
+                    && self.tokens.get(self.cursor + 1).is_some_and(|exp| {
+                        matches!(exp.kind, TokenKind::Number(_)) && touching(next, exp)
+                    }) =>
+            {
+                Some("Scientific notation isn't supported, write the power of ten with ^")
+            }
+            _ => None,
+        }
     }
-    fn parse_unary_not(&mut self) -> Result<Expr, String> {
+    /// Prefix operators and bracketless builtins, whose operand binds tighter
+    /// than `*` but looser than `^`
+    fn parse_prefix(&mut self) -> Result<Expr, String> {
+        let start = self.peek_span();
+        let op = match self.peek() {
+            Some(TokenKind::Minus) => UnaryOp::Neg,
+            Some(TokenKind::Tilde) => UnaryOp::Not,
+            Some(TokenKind::Identifier(name)) => {
+                if let Some(builtin) = Builtin::from_name(name) {
+                    self.advance()?;
+                    return self.parse_builtin(builtin, start);
+                }
+                return self.parse_primary();
+            }
+            _ => return self.parse_primary(),
+        };
         self.advance()?;
-        let parsed_exp = self.parse_primary()?;
-        Ok(Expr::UnaryOp(Box::new(parsed_exp), Operations::NOT))
+        let operand = self.parse_expr_bp(Precedence::Prefix)?;
+        Ok(Expr::new(
+            ExprKind::UnaryOp(Box::new(operand), op),
+            self.span_from(start),
+        ))
     }
-    fn parse_custom_function(&mut self) -> Result<Expr, String> {
-        self.advance()?;
-        //fn id (param) { body }
-        let fn_name = match self.advance()?.value {
-            Some(ValueType::Identifier(id)) => id,
-            _ => return Err(format!("Expected function name @ {0}", self.cursor)),
+    /// `sin(x)`, `log(x, b)` or the bracketless `sin x`, the brackets of a
+    /// call start on the line of its name
+    fn parse_builtin(&mut self, builtin: Builtin, start: Span) -> Result<Expr, String> {
+        let args = if self.at(&TokenKind::LParen) && !self.at_line_break() {
+            self.parse_arguments()?
+        } else {
+            vec![self.parse_expr_bp(Precedence::Implicit)?]
         };
-        self.expect(Operations::LPAREN)?;
+        let (min, max) = builtin.arity();
+        if args.len() < min || args.len() > max {
+            return Err(format!(
+                "{0} takes {1} argument(s), got {2} @ {3}",
+                builtin,
+                if min == max {
+                    min.to_string()
+                } else {
+                    format!("{min} to {max}")
+                },
+                args.len(),
+                self.cursor
+            ));
+        }
+        Ok(Expr::new(
+            ExprKind::Builtin(builtin, args),
+            self.span_from(start),
+        ))
+    }
+    fn parse_scope(&mut self) -> Result<Expr, String> {
+        let start = self.peek_span();
+        self.expect(TokenKind::LBrace)?;
+        // Line breaks separate statements again inside a block
+        let nesting = std::mem::take(&mut self.nesting);
+        let mut stmts = Vec::new();
+        self.skip_semicolons()?;
+        while self.peek().is_some_and(|kind| *kind != TokenKind::RBrace) {
+            stmts.push(self.parse_tokens()?);
+            self.skip_semicolons()?;
+        }
+        self.nesting = nesting;
+        self.expect(TokenKind::RBrace)?;
+        Ok(Expr::new(ExprKind::Block(stmts), self.span_from(start)))
+    }
+    /// if cond { .. } (else if cond { .. })* (else { .. })?
+    fn parse_if(&mut self) -> Result<Expr, String> {
+        let start = self.peek_span();
+        self.expect(TokenKind::If)?;
+        let cond = self.parse_expression()?;
+        let then_branch = self.parse_scope()?;
+        let else_branch = if self.at(&TokenKind::Else) {
+            self.advance()?;
+            if self.at(&TokenKind::If) {
+                Some(Box::new(self.parse_if()?))
+            } else {
+                Some(Box::new(self.parse_scope()?))
+            }
+        } else {
+            None
+        };
+        Ok(Expr::new(
+            ExprKind::If(Box::new(cond), Box::new(then_branch), else_branch),
+            self.span_from(start),
+        ))
+    }
+    fn parse_custom_function(&mut self) -> Result<FnDecl, String> {
+        self.advance()?;
+        //fn id (param: type) -> type { body }
+        let fn_name = self.expect_declaration("Expected function name")?;
+        self.expect(TokenKind::LParen)?;
         let mut param_vec = Vec::new();
-        while let Some(tok) = self.peek() {
-            if tok.operation == Some(Operations::RPAREN) {
+        while self.peek().is_some() {
+            if self.at(&TokenKind::RParen) {
                 self.advance()?;
                 break;
             }
 
-            match tok.value {
-                Some(ValueType::Identifier(param)) => {
-                    param_vec.push(param);
-                    self.advance()?;
-                    if self
-                        .peek()
-                        .map_or(false, |t| t.operation == Some(Operations::COMMA))
-                    {
-                        self.advance()?;
+            let name = self.expect_declaration("Expected alphanumeric parameter")?;
+            let ty = self.parse_annotation(TokenKind::Colon)?;
+            param_vec.push(Param { name, ty });
+            if self.at(&TokenKind::Comma) {
+                self.advance()?;
+            }
+        }
+        let ret = self.parse_annotation(TokenKind::Arrow)?;
+        self.fn_depth += 1;
+        let fn_body = self.parse_scope();
+        self.fn_depth -= 1;
+        let ExprKind::Block(body) = fn_body?.kind else {
+            unreachable!("parse_scope always produces a block")
+        };
+        Ok(FnDecl {
+            name: fn_name,
+            params: param_vec,
+            ret,
+            body,
+        })
+    }
+    /// A type following `marker`, if the next token is the marker
+    fn parse_annotation(&mut self, marker: TokenKind) -> Result<Option<TypeAnn>, String> {
+        if !self.at(&marker) {
+            return Ok(None);
+        }
+        self.advance()?;
+        Ok(Some(self.parse_type()?))
+    }
+    /// `num`, `bool`, `unit` or `fn(type, ...) -> type`
+    fn parse_type(&mut self) -> Result<TypeAnn, String> {
+        let tok = self.advance()?;
+        match tok.kind {
+            TokenKind::Fn => {
+                self.expect(TokenKind::LParen)?;
+                let mut params = Vec::new();
+                while !self.at(&TokenKind::RParen) {
+                    params.push(self.parse_type()?);
+                    if !self.at(&TokenKind::RParen) {
+                        self.expect(TokenKind::Comma)?;
                     }
                 }
-                _ => {
-                    return Err(format!(
-                        "Expected alphanumeric parameter @ {0}",
-                        self.cursor
-                    ))
-                }
+                self.advance()?;
+                self.expect(TokenKind::Arrow)?;
+                Ok(TypeAnn::Fn(params, Box::new(self.parse_type()?)))
             }
+            TokenKind::Identifier(name) => match name.as_str() {
+                "num" => Ok(TypeAnn::Num),
+                "bool" => Ok(TypeAnn::Bool),
+                "unit" => Ok(TypeAnn::Unit),
+                _ => Err(format!("Unknown type {0} @ {1}", name, self.cursor)),
+            },
+            _ => Err(format!("Expected a type @ {0}", self.cursor)),
         }
-        let fn_body = self.parse_scope()?;
-        Ok(Expr::Function(fn_name, param_vec, Box::new(fn_body)))
     }
-    fn parse_custom_function_call(&mut self, id: String) -> Result<Expr, String> {
-        //(param)
-        self.advance()?;
+    /// `(expr, expr, ...)`
+    fn parse_arguments(&mut self) -> Result<Vec<Expr>, String> {
+        self.expect(TokenKind::LParen)?;
+        self.nesting += 1;
         let mut param_vec = Vec::new();
-        while let Some(tok) = self.peek() {
-            if tok.operation == Some(Operations::RPAREN) {
+        while self.peek().is_some() {
+            if self.at(&TokenKind::RParen) {
                 self.advance()?;
                 break;
             }
 
-            param_vec.push(Box::new(self.parse_addition_and_subtraction()?));
-            if let Some(Token {
-                operation: Some(Operations::COMMA),
-                ..
-            }) = self.peek()
-            {
+            param_vec.push(self.parse_expression()?);
+            if self.at(&TokenKind::Comma) {
                 self.advance()?;
             }
         }
-
-        Ok(Expr::FunctionCall(id, param_vec))
+        self.nesting -= 1;
+        Ok(param_vec)
+    }
+    fn parse_custom_function_call(&mut self, id: Ident) -> Result<Expr, String> {
+        let param_vec = self.parse_arguments()?;
+        let span = self.span_from(id.span);
+        Ok(Expr::new(ExprKind::FunctionCall(id, param_vec), span))
     }
     /// Handle raw value
     fn parse_primary(&mut self) -> Result<Expr, String> {
-        if let Some(tok) = self.peek() {
-            match tok.operation {
-                Some(Operations::FNDEFINE) => return self.parse_custom_function(),
-                _ => {}
-            }
-        }
-        if let Some(expr) = self.parse_get_or_set() {
-            if let Expr::Variable(ref fn_name) = expr {
-                if self
-                    .peek()
-                    .map_or(false, |tok| tok.operation == Some(Operations::LPAREN))
-                {
-                    return self.parse_custom_function_call(fn_name.to_string());
-                }
-            }
-            return Ok(expr);
-        }
-        if let Some(tok) = self.peek() {
-            match tok.operation {
-                Some(Operations::LBRACE) => {
-                    return self.parse_scope();
-                }
-                Some(Operations::VARLET) => {
-                    return self.parse_assignment();
-                }
-                Some(Operations::MINUS) => {
-                    return self.parse_unary_minus();
-                }
-                Some(Operations::NOT) => {
-                    return self.parse_unary_not();
-                }
-                _ => {}
+        match self.peek() {
+            Some(TokenKind::LBrace) => return self.parse_scope(),
+            Some(TokenKind::If) => return self.parse_if(),
+            Some(TokenKind::Let | TokenKind::Const | TokenKind::Fn | TokenKind::Return) => {
+                return Err(format!(
+                    "Definitions are only valid as statements @ {0}",
+                    self.cursor
+                ));
             }
+            _ => {}
         }
 
         let curr_token = self.advance()?;
-        match curr_token.operation {
-            Some(Operations::LPAREN) => {
-                let parsed_exp = self.parse_addition_and_subtraction()?;
-                self.expect(Operations::RPAREN)
+        match curr_token.kind {
+            TokenKind::LParen => {
+                self.nesting += 1;
+                let parsed_exp = self.parse_expression()?;
+                self.nesting -= 1;
+                self.expect(TokenKind::RParen)
                     .map_err(|_| format!("Missing ')' @ {0}", self.cursor))?;
                 Ok(parsed_exp)
             }
-            _ => match curr_token.value {
-                Some(ValueType::Number(val)) => Ok(Expr::Number(val)),
-                _ => Err(format!("Expected number @ {0}", self.cursor)),
-            },
+            TokenKind::Number(val) => Ok(Expr::new(ExprKind::Number(val), curr_token.span)),
+            TokenKind::Bool(val) => Ok(Expr::new(ExprKind::Bool(val), curr_token.span)),
+            TokenKind::Identifier(name) => {
+                // A `(` on the next line starts a statement of its own
+                if self.at(&TokenKind::LParen) && !self.at_line_break() {
+                    return self.parse_custom_function_call(Ident {
+                        name,
+                        span: curr_token.span,
+                    });
+                }
+                Ok(Expr::new(ExprKind::Variable(name), curr_token.span))
+            }
+            _ => Err(format!("Expected number @ {0}", self.cursor)),
         }
     }
 }
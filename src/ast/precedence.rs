@@ -0,0 +1,74 @@
+use crate::ast::ast::BinaryOp;
+
+/// Binding strength of operators, from loosest to tightest
+///
+/// | level          | operators                     | associativity |
+/// |----------------|-------------------------------|---------------|
+/// | Comparison     | `<` `<=` `>` `>=` `==` `!=`   | none          |
+/// | Additive       | `+` `-`                       | left          |
+/// | Multiplicative | `*` `/` `%`                   | left          |
+/// | Implicit       | `2x` `3(x+1)` `(a)(b)`        | left          |
+/// | Prefix         | `-a` `~a` `sin a`             | right         |
+/// | Power          | `^`                           | right         |
+/// | Postfix        | `a!`                          | left          |
+///
+/// So `-2^2` is `-(2^2)`, `2^3^2` is `2^(3^2)`, `2^-1` is `2^(-1)`,
+/// `sin x^2` is `sin(x^2)` and `sin x + 1` is `sin(x) + 1`.
+///
+/// Implicit multiplication binds tighter than `*` and `/` but looser than
+/// `^` and prefix minus, so `1/2x` is `1/(2x)`, `4x^2` is `4(x^2)`, `2^3x` is
+/// `(2^3)x` and `-2x` is `(-2)x`. Bracketless builtins take their operand at
+/// this level so `sin 2x` is `sin(2x)`. It never crosses a line break, and
+/// `name(..)` is always a call rather than `name * (..)`. A number is never
+/// followed by another, so `2 3` and `1e3`, which would be `1 * e * 3`, are
+/// errors rather than silent products
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Precedence {
+    Lowest,
+    Comparison,
+    Additive,
+    Multiplicative,
+    Implicit,
+    Prefix,
+    Power,
+    Postfix,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    Left,
+    Right,
+    /// `a < b < c` is an error rather than picking a grouping
+    None,
+}
+
+impl Precedence {
+    /// The next tighter level, operands of a left associative operator are
+    /// parsed at this level so an operator of equal precedence ends them
+    pub fn tighter(self) -> Precedence {
+        match self {
+            Precedence::Lowest => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Additive,
+            Precedence::Additive => Precedence::Multiplicative,
+            Precedence::Multiplicative => Precedence::Implicit,
+            Precedence::Implicit => Precedence::Prefix,
+            Precedence::Prefix => Precedence::Power,
+            Precedence::Power | Precedence::Postfix => Precedence::Postfix,
+        }
+    }
+}
+
+/// The operator table for infix operators
+pub fn infix_binding(op: BinaryOp) -> (Precedence, Assoc) {
+    match op {
+        BinaryOp::Less
+        | BinaryOp::LessEqual
+        | BinaryOp::Greater
+        | BinaryOp::GreaterEqual
+        | BinaryOp::Equal
+        | BinaryOp::NotEqual => (Precedence::Comparison, Assoc::None),
+        BinaryOp::Add | BinaryOp::Sub => (Precedence::Additive, Assoc::Left),
+        BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => (Precedence::Multiplicative, Assoc::Left),
+        BinaryOp::Pow => (Precedence::Power, Assoc::Right),
+    }
+}
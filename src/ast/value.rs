@@ -0,0 +1,139 @@
+use crate::ast::ast::factorial;
+use crate::ast::ast::BinaryOp;
+use crate::ast::ast::Stmt;
+use crate::ast::ast::UnaryOp;
+use crate::ast::error::RuntimeError;
+use std::fmt;
+use std::rc::Rc;
+
+/// A user defined function, shared between every value referring to it
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<String>,
+    /// Statements run by the tree-walker, empty for compiled functions
+    pub body: Vec<Stmt>,
+    /// Where the virtual machine that defined the function keeps its code
+    pub code: Option<usize>,
+}
+
+/// Runtime value produced by evaluating an expression
+#[derive(Debug, Clone)]
+pub enum Value {
+    Number(f64),
+    Bool(bool),
+    /// Result of things that produce nothing, e.g. an empty scope
+    Unit,
+    Function(Rc<Function>),
+}
+
+impl Value {
+    /// Name of the value's type as used in error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Bool(_) => "bool",
+            Value::Unit => "unit",
+            Value::Function(_) => "function",
+        }
+    }
+    pub fn as_number(&self) -> Result<f64, RuntimeError> {
+        match self {
+            Value::Number(val) => Ok(*val),
+            _ => Err(RuntimeError::TypeMismatch {
+                expected: "number",
+                found: self.type_name(),
+            }),
+        }
+    }
+    pub fn as_bool(&self) -> Result<bool, RuntimeError> {
+        match self {
+            Value::Bool(val) => Ok(*val),
+            _ => Err(RuntimeError::TypeMismatch {
+                expected: "bool",
+                found: self.type_name(),
+            }),
+        }
+    }
+}
+
+impl Value {
+    /// Apply an infix operator, shared by every backend so they agree on
+    /// semantics
+    pub fn binary(op: BinaryOp, left: &Value, right: &Value) -> Result<Value, RuntimeError> {
+        if let BinaryOp::Equal | BinaryOp::NotEqual = op {
+            if left.type_name() != right.type_name() {
+                return Err(RuntimeError::TypeMismatch {
+                    expected: left.type_name(),
+                    found: right.type_name(),
+                });
+            }
+            return Ok(Value::Bool((left == right) == (op == BinaryOp::Equal)));
+        }
+        let left = left.as_number()?;
+        let right = right.as_number()?;
+        Ok(match op {
+            BinaryOp::Add => Value::Number(left + right),
+            BinaryOp::Sub => Value::Number(left - right),
+            BinaryOp::Mul => Value::Number(left * right),
+            BinaryOp::Div => {
+                if right == 0f64 {
+                    return Err(RuntimeError::DivisionByZero);
+                }
+                Value::Number(left / right)
+            }
+            BinaryOp::Pow => Value::Number(f64::powf(left, right)),
+            BinaryOp::Mod => Value::Number(left % right),
+            BinaryOp::Less => Value::Bool(left < right),
+            BinaryOp::LessEqual => Value::Bool(left <= right),
+            BinaryOp::Greater => Value::Bool(left > right),
+            BinaryOp::GreaterEqual => Value::Bool(left >= right),
+            BinaryOp::Equal => Value::Bool(left == right),
+            BinaryOp::NotEqual => Value::Bool(left != right),
+        })
+    }
+    /// Apply a prefix or postfix operator
+    pub fn unary(op: UnaryOp, val: &Value) -> Result<Value, RuntimeError> {
+        let val = val.as_number()?;
+        Ok(Value::Number(match op {
+            UnaryOp::Neg => -val,
+            UnaryOp::Not => -(val + 1.0),
+            UnaryOp::Factorial => factorial(val),
+        }))
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Unit, Value::Unit) => true,
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(val: f64) -> Self {
+        Value::Number(val)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(val: bool) -> Self {
+        Value::Bool(val)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(val) => write!(f, "{val}"),
+            Value::Bool(val) => write!(f, "{val}"),
+            Value::Unit => write!(f, "()"),
+            Value::Function(func) => write!(f, "<fn {0}({1})>", func.name, func.params.join(", ")),
+        }
+    }
+}
@@ -0,0 +1,706 @@
+use crate::ast::ast::BinaryOp;
+use crate::ast::ast::Builtin;
+use crate::ast::ast::UnaryOp;
+use crate::ast::error::RuntimeError;
+use crate::codegen::x86;
+use crate::codegen::x86::Assembly;
+use crate::codegen::x86::Cond;
+use crate::codegen::x86::Opcode;
+use crate::codegen::x86::Operand;
+use crate::codegen::x86::Reg;
+use crate::codegen::x86::ARG_REGS;
+use crate::codegen::x86::ARG_XMMS;
+use crate::ir::ir::BlockId;
+use crate::ir::ir::Constant;
+use crate::ir::ir::Function;
+use crate::ir::ir::Inst;
+use crate::ir::ir::Module;
+use crate::ir::ir::Op;
+use crate::ir::ir::Terminator;
+use crate::ir::ir::Ty;
+use crate::ir::ir::ValueId;
+use crate::ir::ir::MAIN;
+
+/// What the assembly is linked into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// A C program, `cc out.s -lm` links it against libc and libm
+    Linked,
+    /// A static executable entering at `_start` that brings its own maths
+    /// and makes system calls directly, see `elf::executable`
+    Standalone,
+    /// Code run in process by `jit::Jit`, calling back into Rust for maths
+    /// and errors
+    Jit,
+}
+
+/// Compile a verified module to x86-64 assembly following the System V ABI
+///
+/// Every function becomes the exported symbol `ape_<name>`, taking and
+/// returning numbers in xmm registers and booleans as 0 or 1 in general
+/// purpose ones. Each value gets a stack slot and phis are filled in on the
+/// edges into their block. The entry point runs `ape_main` and prints its
+/// result
+pub fn generate(module: &Module, target: Target) -> Assembly {
+    let mut gen = Codegen {
+        asm: Assembly::default(),
+        target,
+        fact: false,
+        print: false,
+        fail: false,
+        math: Vec::new(),
+    };
+    for global in &module.globals {
+        gen.asm.data.push(variable(&global.name));
+        gen.asm.data.push(is_set(&global.name));
+    }
+    for func in &module.functions {
+        gen.function(func);
+    }
+    let ret = module.function(MAIN).map_or(Ty::Unit, |main| main.ret);
+    match target {
+        Target::Linked => gen.entry(ret),
+        Target::Standalone => gen.start(ret),
+        Target::Jit => gen.trampolines(&module.functions),
+    }
+    gen.runtime();
+    gen.asm
+}
+
+/// Symbol of a function, names in the language never hold `_` so these
+/// can't clash with the runtime's
+pub fn symbol(name: &str) -> String {
+    format!("ape_{name}")
+}
+
+pub fn variable(name: &str) -> String {
+    format!("ape_var_{name}")
+}
+
+/// Variable holding 1 once a global has been stored to
+pub fn is_set(name: &str) -> String {
+    format!("ape_var_{name}_set")
+}
+
+fn block_label(func: &Function, block: BlockId) -> String {
+    format!(".L{0}_{block}", func.name)
+}
+
+/// Stack slot of a value
+fn slot(val: ValueId) -> Operand {
+    Operand::Mem(Reg::Rbp, -8 * (val.0 as i32 + 1))
+}
+
+/// Slot after every value's, for intermediate results
+fn scratch(func: &Function) -> Operand {
+    Operand::Mem(Reg::Rbp, -8 * (func.types.len() as i32 + 1))
+}
+
+/// Where an argument is passed
+#[derive(Clone, Copy)]
+enum Loc {
+    Xmm(u8),
+    Reg(Reg),
+    /// Pushed by the caller, counting from the first argument on the stack
+    Stack(usize),
+}
+
+/// Numbers go in xmm registers and the rest in general purpose ones until
+/// each runs out, then on the stack
+fn locations(tys: &[Ty]) -> Vec<Loc> {
+    let (mut xmms, mut regs, mut stack) = (0, 0, 0);
+    tys.iter()
+        .map(|ty| match ty {
+            Ty::Num if xmms < ARG_XMMS => {
+                xmms += 1;
+                Loc::Xmm(xmms - 1)
+            }
+            Ty::Bool | Ty::Unit if regs < ARG_REGS.len() => {
+                regs += 1;
+                Loc::Reg(ARG_REGS[regs - 1])
+            }
+            _ => {
+                stack += 1;
+                Loc::Stack(stack - 1)
+            }
+        })
+        .collect()
+}
+
+pub struct Codegen {
+    pub asm: Assembly,
+    target: Target,
+    /// Runtime routines the program needs
+    fact: bool,
+    pub print: bool,
+    fail: bool,
+    /// libm functions the program calls
+    math: Vec<String>,
+}
+
+impl Codegen {
+    pub fn op(&mut self, opcode: Opcode, operands: Vec<Operand>) {
+        self.asm.text.push(x86::Inst::Op(opcode, operands));
+    }
+    pub fn label(&mut self, label: String) {
+        self.asm.text.push(x86::Inst::Label(label));
+    }
+    pub fn call(&mut self, target: &str) {
+        self.op(Opcode::Call, vec![Operand::Label(target.to_string())]);
+    }
+    /// Put a number in an xmm register through `%rax`
+    pub fn number(&mut self, val: f64, xmm: u8) {
+        self.op(
+            Opcode::Movabs,
+            vec![Operand::Imm(val.to_bits() as i64), Operand::Reg(Reg::Rax)],
+        );
+        self.op(Opcode::Mov, vec![Operand::Reg(Reg::Rax), Operand::Xmm(xmm)]);
+    }
+    pub fn mov(&mut self, src: Operand, dst: Operand) {
+        self.op(Opcode::Mov, vec![src, dst]);
+    }
+    pub fn movsd(&mut self, src: Operand, dst: Operand) {
+        self.op(Opcode::Movsd, vec![src, dst]);
+    }
+    /// Turn the byte in `%al` into a bool in `dst`
+    fn bool_from_al(&mut self, dst: Operand) {
+        self.op(
+            Opcode::Movzb,
+            vec![Operand::Byte(Reg::Rax), Operand::Reg(Reg::Rax)],
+        );
+        self.mov(Operand::Reg(Reg::Rax), dst);
+    }
+    /// Call a libm function, or the runtime's own version of it
+    fn libm(&mut self, name: &str) {
+        match self.target {
+            Target::Linked => self.call(&format!("{name}@PLT")),
+            Target::Standalone | Target::Jit => {
+                self.call(&format!("ape_rt_{name}"));
+                if !self.math.iter().any(|known| known == name) {
+                    self.math.push(name.to_string());
+                }
+            }
+        }
+    }
+    /// Print the error to stderr and exit with status 1, as the interpreter
+    /// would report it
+    fn fail(&mut self, label: String, err: RuntimeError) {
+        let text = format!("error: {err}\n");
+        self.op(
+            Opcode::Lea,
+            vec![Operand::Rip(label.clone()), Operand::Reg(Reg::Rdi)],
+        );
+        self.mov(Operand::Imm(text.len() as i64), Operand::Reg(Reg::Rsi));
+        self.call("ape_rt_fail");
+        if !self.asm.strings.iter().any(|(known, _)| *known == label) {
+            self.asm.strings.push((label, text));
+        }
+        self.fail = true;
+    }
+
+    fn function(&mut self, func: &Function) {
+        let name = symbol(&func.name);
+        self.asm.exports.push(name.clone());
+        self.label(name);
+        self.op(Opcode::Push, vec![Operand::Reg(Reg::Rbp)]);
+        self.mov(Operand::Reg(Reg::Rsp), Operand::Reg(Reg::Rbp));
+        // A slot per value and the scratch slot, keeping the stack aligned
+        let frame = (8 * (func.types.len() + 1)).div_ceil(16) * 16;
+        self.op(
+            Opcode::Sub,
+            vec![Operand::Imm(frame as i64), Operand::Reg(Reg::Rsp)],
+        );
+        for (idx, loc) in locations(&func.params).into_iter().enumerate() {
+            let dst = slot(ValueId(idx as u32));
+            match loc {
+                Loc::Xmm(xmm) => self.movsd(Operand::Xmm(xmm), dst),
+                Loc::Reg(reg) => self.mov(Operand::Reg(reg), dst),
+                Loc::Stack(pos) => {
+                    // Past the saved `%rbp` and the return address
+                    let src = Operand::Mem(Reg::Rbp, 16 + 8 * pos as i32);
+                    self.mov(src, Operand::Reg(Reg::Rax));
+                    self.mov(Operand::Reg(Reg::Rax), dst);
+                }
+            }
+        }
+        for (idx, block) in func.blocks.iter().enumerate() {
+            let here = BlockId(idx as u32);
+            if idx > 0 {
+                self.label(block_label(func, here));
+            }
+            for inst in &block.insts {
+                match inst {
+                    Inst::Define(val, op) => self.define(func, *val, op),
+                    Inst::Store(name, val) => {
+                        self.mov(slot(*val), Operand::Reg(Reg::Rax));
+                        self.mov(Operand::Reg(Reg::Rax), Operand::Rip(variable(name)));
+                        self.mov(Operand::Imm(1), Operand::Rip(is_set(name)));
+                    }
+                }
+            }
+            self.terminator(func, here, block.term);
+        }
+    }
+
+    fn define(&mut self, func: &Function, val: ValueId, op: &Op) {
+        let dst = slot(val);
+        match op {
+            Op::Const(Constant::Num(num)) => {
+                self.op(
+                    Opcode::Movabs,
+                    vec![Operand::Imm(num.to_bits() as i64), Operand::Reg(Reg::Rax)],
+                );
+                self.mov(Operand::Reg(Reg::Rax), dst);
+            }
+            Op::Const(Constant::Bool(b)) => self.mov(Operand::Imm(*b as i64), dst),
+            Op::Const(Constant::Unit) => self.mov(Operand::Imm(0), dst),
+            Op::Binary(op, left, right) => self.binary(func, val, *op, *left, *right),
+            Op::Unary(UnaryOp::Neg, operand) => {
+                self.mov(slot(*operand), Operand::Reg(Reg::Rax));
+                self.op(Opcode::Btc, vec![Operand::Imm(63), Operand::Reg(Reg::Rax)]);
+                self.mov(Operand::Reg(Reg::Rax), dst);
+            }
+            Op::Unary(UnaryOp::Not, operand) => {
+                // `~x` is `-(x + 1)`
+                self.movsd(slot(*operand), Operand::Xmm(0));
+                self.number(1.0, 1);
+                self.op(Opcode::Addsd, vec![Operand::Xmm(1), Operand::Xmm(0)]);
+                self.mov(Operand::Xmm(0), Operand::Reg(Reg::Rax));
+                self.op(Opcode::Btc, vec![Operand::Imm(63), Operand::Reg(Reg::Rax)]);
+                self.mov(Operand::Reg(Reg::Rax), dst);
+            }
+            Op::Unary(UnaryOp::Factorial, operand) => {
+                self.movsd(slot(*operand), Operand::Xmm(0));
+                self.call("ape_rt_fact");
+                self.movsd(Operand::Xmm(0), dst);
+                self.fact = true;
+            }
+            Op::Builtin(builtin, args) => self.builtin(func, val, *builtin, args),
+            Op::Call(name, args) => self.call_function(func, val, name, args),
+            Op::Load(name) => {
+                let ok = format!(".L{0}_ok{1}", func.name, val.0);
+                self.op(
+                    Opcode::Cmp,
+                    vec![Operand::Imm(0), Operand::Rip(is_set(name))],
+                );
+                self.op(Opcode::J(Cond::Ne), vec![Operand::Label(ok.clone())]);
+                self.fail(
+                    format!(".Lmsg_var_{name}"),
+                    RuntimeError::UndeclaredVariable(name.clone()),
+                );
+                self.label(ok);
+                self.mov(Operand::Rip(variable(name)), Operand::Reg(Reg::Rax));
+                self.mov(Operand::Reg(Reg::Rax), dst);
+            }
+            // Filled in on the edges into the block
+            Op::Phi(_) => {}
+        }
+    }
+
+    fn binary(
+        &mut self,
+        func: &Function,
+        val: ValueId,
+        op: BinaryOp,
+        left: ValueId,
+        right: ValueId,
+    ) {
+        let dst = slot(val);
+        let numbers = func.ty(left) == Ty::Num;
+        let (left, right) = (slot(left), slot(right));
+        if !numbers {
+            // Only (in)equality takes booleans and units, both held as integers
+            let cond = match op {
+                BinaryOp::Equal => Cond::E,
+                _ => Cond::Ne,
+            };
+            self.mov(left, Operand::Reg(Reg::Rax));
+            self.op(Opcode::Cmp, vec![right, Operand::Reg(Reg::Rax)]);
+            self.op(Opcode::Set(cond), vec![Operand::Byte(Reg::Rax)]);
+            self.bool_from_al(dst);
+            return;
+        }
+        match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => {
+                let opcode = match op {
+                    BinaryOp::Add => Opcode::Addsd,
+                    BinaryOp::Sub => Opcode::Subsd,
+                    _ => Opcode::Mulsd,
+                };
+                self.movsd(left, Operand::Xmm(0));
+                self.op(opcode, vec![right, Operand::Xmm(0)]);
+                self.movsd(Operand::Xmm(0), dst);
+            }
+            BinaryOp::Div => {
+                let ok = format!(".L{0}_ok{1}", func.name, val.0);
+                self.movsd(right, Operand::Xmm(1));
+                self.op(Opcode::Xorpd, vec![Operand::Xmm(2), Operand::Xmm(2)]);
+                self.op(Opcode::Ucomisd, vec![Operand::Xmm(2), Operand::Xmm(1)]);
+                // Unordered means a NaN divisor, which isn't zero
+                self.op(Opcode::J(Cond::P), vec![Operand::Label(ok.clone())]);
+                self.op(Opcode::J(Cond::Ne), vec![Operand::Label(ok.clone())]);
+                self.fail(String::from(".Lmsg_div"), RuntimeError::DivisionByZero);
+                self.label(ok);
+                self.movsd(left, Operand::Xmm(0));
+                self.op(Opcode::Divsd, vec![Operand::Xmm(1), Operand::Xmm(0)]);
+                self.movsd(Operand::Xmm(0), dst);
+            }
+            BinaryOp::Pow | BinaryOp::Mod => {
+                self.movsd(left, Operand::Xmm(0));
+                self.movsd(right, Operand::Xmm(1));
+                self.libm(match op {
+                    BinaryOp::Pow => "pow",
+                    _ => "fmod",
+                });
+                self.movsd(Operand::Xmm(0), dst);
+            }
+            BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual => {
+                // `a` and `ae` are false when unordered, `<` swaps operands
+                // to use them too
+                let (first, second, cond) = match op {
+                    BinaryOp::Greater => (left, right, Cond::A),
+                    BinaryOp::GreaterEqual => (left, right, Cond::Ae),
+                    BinaryOp::Less => (right, left, Cond::A),
+                    _ => (right, left, Cond::Ae),
+                };
+                self.movsd(first, Operand::Xmm(0));
+                self.op(Opcode::Ucomisd, vec![second, Operand::Xmm(0)]);
+                self.op(Opcode::Set(cond), vec![Operand::Byte(Reg::Rax)]);
+                self.bool_from_al(dst);
+            }
+            BinaryOp::Equal | BinaryOp::NotEqual => {
+                // Equal is ordered and zero, unequal either of the opposites
+                let (zero, parity, combine) = match op {
+                    BinaryOp::Equal => (Cond::E, Cond::Np, Opcode::Andb),
+                    _ => (Cond::Ne, Cond::P, Opcode::Orb),
+                };
+                self.movsd(left, Operand::Xmm(0));
+                self.op(Opcode::Ucomisd, vec![right, Operand::Xmm(0)]);
+                self.op(Opcode::Set(zero), vec![Operand::Byte(Reg::Rax)]);
+                self.op(Opcode::Set(parity), vec![Operand::Byte(Reg::Rcx)]);
+                self.op(
+                    combine,
+                    vec![Operand::Byte(Reg::Rcx), Operand::Byte(Reg::Rax)],
+                );
+                self.bool_from_al(dst);
+            }
+        }
+    }
+
+    fn builtin(&mut self, func: &Function, val: ValueId, builtin: Builtin, args: &[ValueId]) {
+        let dst = slot(val);
+        let arg = slot(args[0]);
+        match builtin {
+            Builtin::Sqrt => {
+                self.op(Opcode::Sqrtsd, vec![arg, Operand::Xmm(0)]);
+                self.movsd(Operand::Xmm(0), dst);
+            }
+            Builtin::Abs => {
+                self.mov(arg, Operand::Reg(Reg::Rax));
+                self.op(Opcode::Btr, vec![Operand::Imm(63), Operand::Reg(Reg::Rax)]);
+                self.mov(Operand::Reg(Reg::Rax), dst);
+            }
+            Builtin::Log => {
+                // ln(x) / ln(base) as the interpreter computes it
+                self.movsd(arg, Operand::Xmm(0));
+                self.libm("log");
+                self.movsd(Operand::Xmm(0), scratch(func));
+                match args.get(1) {
+                    Some(&base) => self.movsd(slot(base), Operand::Xmm(0)),
+                    None => self.number(10.0, 0),
+                }
+                self.libm("log");
+                self.movsd(scratch(func), Operand::Xmm(1));
+                self.op(Opcode::Divsd, vec![Operand::Xmm(0), Operand::Xmm(1)]);
+                self.movsd(Operand::Xmm(1), dst);
+            }
+            Builtin::Exp => {
+                self.number(std::f64::consts::E, 0);
+                self.movsd(arg, Operand::Xmm(1));
+                self.libm("pow");
+                self.movsd(Operand::Xmm(0), dst);
+            }
+            _ => {
+                self.movsd(arg, Operand::Xmm(0));
+                self.libm(&builtin.to_string());
+                self.movsd(Operand::Xmm(0), dst);
+            }
+        }
+    }
+
+    fn call_function(&mut self, func: &Function, val: ValueId, name: &str, args: &[ValueId]) {
+        let tys: Vec<Ty> = args.iter().map(|&arg| func.ty(arg)).collect();
+        let locs = locations(&tys);
+        let on_stack: Vec<ValueId> = args
+            .iter()
+            .zip(&locs)
+            .filter(|(_, loc)| matches!(loc, Loc::Stack(_)))
+            .map(|(&arg, _)| arg)
+            .collect();
+        // The stack is 16 byte aligned at the call
+        let pushed = on_stack.len() + on_stack.len() % 2;
+        if on_stack.len() % 2 == 1 {
+            self.op(Opcode::Sub, vec![Operand::Imm(8), Operand::Reg(Reg::Rsp)]);
+        }
+        for &arg in on_stack.iter().rev() {
+            self.op(Opcode::Push, vec![slot(arg)]);
+        }
+        for (&arg, loc) in args.iter().zip(&locs) {
+            match *loc {
+                Loc::Xmm(xmm) => self.movsd(slot(arg), Operand::Xmm(xmm)),
+                Loc::Reg(reg) => self.mov(slot(arg), Operand::Reg(reg)),
+                Loc::Stack(_) => {}
+            }
+        }
+        self.call(&symbol(name));
+        if pushed > 0 {
+            self.op(
+                Opcode::Add,
+                vec![Operand::Imm(8 * pushed as i64), Operand::Reg(Reg::Rsp)],
+            );
+        }
+        match func.ty(val) {
+            Ty::Num => self.movsd(Operand::Xmm(0), slot(val)),
+            Ty::Bool | Ty::Unit => self.mov(Operand::Reg(Reg::Rax), slot(val)),
+        }
+    }
+
+    fn terminator(&mut self, func: &Function, here: BlockId, term: Terminator) {
+        let next = BlockId(here.0 + 1);
+        match term {
+            Terminator::Jump(to) => {
+                self.edge(func, here, to);
+                if to != next {
+                    self.op(Opcode::Jmp, vec![Operand::Label(block_label(func, to))]);
+                }
+            }
+            Terminator::Branch(cond, then_block, else_block) => {
+                // Phis of the else block are filled in on the way there
+                let else_phis = has_phis(func, else_block);
+                let else_label = match else_phis {
+                    true => format!(".L{0}_{here}_{else_block}", func.name),
+                    false => block_label(func, else_block),
+                };
+                self.op(Opcode::Cmp, vec![Operand::Imm(0), slot(cond)]);
+                self.op(Opcode::J(Cond::E), vec![Operand::Label(else_label.clone())]);
+                self.edge(func, here, then_block);
+                if then_block != next || else_phis {
+                    self.op(
+                        Opcode::Jmp,
+                        vec![Operand::Label(block_label(func, then_block))],
+                    );
+                }
+                if else_phis {
+                    self.label(else_label);
+                    self.edge(func, here, else_block);
+                    if else_block != next {
+                        self.op(
+                            Opcode::Jmp,
+                            vec![Operand::Label(block_label(func, else_block))],
+                        );
+                    }
+                }
+            }
+            Terminator::Return(val) => {
+                match func.ret {
+                    Ty::Num => self.movsd(slot(val), Operand::Xmm(0)),
+                    Ty::Bool | Ty::Unit => self.mov(slot(val), Operand::Reg(Reg::Rax)),
+                }
+                self.op(Opcode::Leave, Vec::new());
+                self.op(Opcode::Ret, Vec::new());
+            }
+        }
+    }
+
+    /// Copy what the phis of `to` take from `from`, all at once through the
+    /// stack so no phi overwrites a value another still needs
+    fn edge(&mut self, func: &Function, from: BlockId, to: BlockId) {
+        let copies: Vec<(ValueId, ValueId)> = func
+            .block(to)
+            .insts
+            .iter()
+            .map_while(|inst| match inst {
+                Inst::Define(dst, Op::Phi(incoming)) => incoming
+                    .iter()
+                    .find(|&&(_, pred)| pred == from)
+                    .map(|&(src, _)| (src, *dst)),
+                _ => None,
+            })
+            .collect();
+        for &(src, _) in &copies {
+            self.op(Opcode::Push, vec![slot(src)]);
+        }
+        for &(_, dst) in copies.iter().rev() {
+            self.op(Opcode::Pop, vec![slot(dst)]);
+        }
+    }
+
+    /// C's `main`, printing what `ape_main` returns like the interpreter
+    fn entry(&mut self, ret: Ty) {
+        self.asm.exports.push(String::from("main"));
+        self.label(String::from("main"));
+        self.op(Opcode::Push, vec![Operand::Reg(Reg::Rbp)]);
+        self.mov(Operand::Reg(Reg::Rsp), Operand::Reg(Reg::Rbp));
+        self.call(&symbol(MAIN));
+        match ret {
+            Ty::Num => {
+                self.call("ape_rt_print");
+                self.print = true;
+            }
+            Ty::Bool => {
+                self.string(".Lrt_true", "true", Reg::Rdi);
+                self.op(Opcode::Cmp, vec![Operand::Imm(0), Operand::Reg(Reg::Rax)]);
+                self.op(
+                    Opcode::J(Cond::Ne),
+                    vec![Operand::Label(String::from(".Lrt_puts"))],
+                );
+                self.string(".Lrt_false", "false", Reg::Rdi);
+                self.label(String::from(".Lrt_puts"));
+                self.call("puts@PLT");
+            }
+            Ty::Unit => {
+                self.string(".Lrt_unit", "()", Reg::Rdi);
+                self.call("puts@PLT");
+            }
+        }
+        self.mov(Operand::Imm(0), Operand::Reg(Reg::Rax));
+        self.op(Opcode::Pop, vec![Operand::Reg(Reg::Rbp)]);
+        self.op(Opcode::Ret, Vec::new());
+    }
+
+    /// Load the address of a constant string into `reg`
+    pub fn string(&mut self, label: &str, text: &str, reg: Reg) {
+        if !self.asm.strings.iter().any(|(known, _)| known == label) {
+            self.asm.strings.push((label.to_string(), text.to_string()));
+        }
+        self.op(
+            Opcode::Lea,
+            vec![Operand::Rip(label.to_string()), Operand::Reg(reg)],
+        );
+    }
+
+    /// Routines the generated code calls, only those it uses
+    fn runtime(&mut self) {
+        let label = |name: &str| Operand::Label(name.to_string());
+        match self.target {
+            Target::Linked if self.print => self.linked_print(),
+            Target::Standalone if self.print => self.bare_print(),
+            _ => {}
+        }
+        if self.fact {
+            // NaN for negative numbers, otherwise the product up to the
+            // number truncated, which is 1 for NaN
+            self.label(String::from("ape_rt_fact"));
+            self.op(Opcode::Ucomisd, vec![Operand::Xmm(0), Operand::Xmm(0)]);
+            self.op(Opcode::J(Cond::P), vec![label(".Lrt_fact_one")]);
+            self.op(Opcode::Xorpd, vec![Operand::Xmm(1), Operand::Xmm(1)]);
+            self.op(Opcode::Ucomisd, vec![Operand::Xmm(1), Operand::Xmm(0)]);
+            self.op(Opcode::J(Cond::B), vec![label(".Lrt_fact_nan")]);
+            self.op(
+                Opcode::Cvttsd2si,
+                vec![Operand::Xmm(0), Operand::Reg(Reg::Rcx)],
+            );
+            // Too big for an integer, the product overflows long before
+            self.op(
+                Opcode::Test,
+                vec![Operand::Reg(Reg::Rcx), Operand::Reg(Reg::Rcx)],
+            );
+            self.op(Opcode::J(Cond::S), vec![label(".Lrt_fact_inf")]);
+            self.number(1.0, 0);
+            self.mov(Operand::Imm(1), Operand::Reg(Reg::Rax));
+            self.label(String::from(".Lrt_fact_loop"));
+            self.op(
+                Opcode::Cmp,
+                vec![Operand::Reg(Reg::Rcx), Operand::Reg(Reg::Rax)],
+            );
+            self.op(Opcode::J(Cond::A), vec![label(".Lrt_fact_done")]);
+            self.op(
+                Opcode::Cvtsi2sd,
+                vec![Operand::Reg(Reg::Rax), Operand::Xmm(1)],
+            );
+            self.op(Opcode::Mulsd, vec![Operand::Xmm(1), Operand::Xmm(0)]);
+            self.op(Opcode::Add, vec![Operand::Imm(1), Operand::Reg(Reg::Rax)]);
+            self.op(Opcode::Jmp, vec![label(".Lrt_fact_loop")]);
+            self.label(String::from(".Lrt_fact_one"));
+            self.number(1.0, 0);
+            self.op(Opcode::Ret, Vec::new());
+            self.label(String::from(".Lrt_fact_nan"));
+            self.number(f64::NAN, 0);
+            self.op(Opcode::Ret, Vec::new());
+            self.label(String::from(".Lrt_fact_inf"));
+            self.number(f64::INFINITY, 0);
+            self.label(String::from(".Lrt_fact_done"));
+            self.op(Opcode::Ret, Vec::new());
+        }
+        match self.target {
+            Target::Linked if self.fail => self.linked_fail(),
+            Target::Standalone if self.fail => self.bare_fail(),
+            Target::Jit if self.fail => self.jit_fail(),
+            _ => {}
+        }
+        for name in std::mem::take(&mut self.math) {
+            match self.target {
+                Target::Jit => self.jit_math(&name),
+                _ => self.bare_math(&name),
+            }
+        }
+    }
+
+    /// The first of `%.15g`, `%.16g` and `%.17g` that reads back as the
+    /// same number, `%g` drops trailing zeros so this is the shortest form
+    /// the interpreter prints too
+    fn linked_print(&mut self) {
+        let rbp = |disp| Operand::Mem(Reg::Rbp, disp);
+        let label = |name: &str| Operand::Label(name.to_string());
+        self.label(String::from("ape_rt_print"));
+        self.op(Opcode::Push, vec![Operand::Reg(Reg::Rbp)]);
+        self.mov(Operand::Reg(Reg::Rsp), Operand::Reg(Reg::Rbp));
+        self.op(Opcode::Sub, vec![Operand::Imm(48), Operand::Reg(Reg::Rsp)]);
+        self.movsd(Operand::Xmm(0), rbp(-40));
+        self.op(Opcode::Ucomisd, vec![Operand::Xmm(0), Operand::Xmm(0)]);
+        self.op(Opcode::J(Cond::P), vec![label(".Lrt_print_nan")]);
+        self.mov(Operand::Imm(15), rbp(-48));
+        self.label(String::from(".Lrt_print_try"));
+        self.op(Opcode::Lea, vec![rbp(-32), Operand::Reg(Reg::Rdi)]);
+        self.mov(Operand::Imm(32), Operand::Reg(Reg::Rsi));
+        self.string(".Lrt_format", "%.*g", Reg::Rdx);
+        self.mov(rbp(-48), Operand::Reg(Reg::Rcx));
+        self.movsd(rbp(-40), Operand::Xmm(0));
+        // Variadic calls count the vector registers used in `%al`
+        self.mov(Operand::Imm(1), Operand::Reg(Reg::Rax));
+        self.call("snprintf@PLT");
+        self.op(Opcode::Lea, vec![rbp(-32), Operand::Reg(Reg::Rdi)]);
+        self.mov(Operand::Imm(0), Operand::Reg(Reg::Rsi));
+        self.call("strtod@PLT");
+        self.op(Opcode::Add, vec![Operand::Imm(1), rbp(-48)]);
+        self.op(Opcode::Ucomisd, vec![rbp(-40), Operand::Xmm(0)]);
+        self.op(Opcode::J(Cond::Ne), vec![label(".Lrt_print_try")]);
+        self.op(Opcode::Lea, vec![rbp(-32), Operand::Reg(Reg::Rdi)]);
+        self.op(Opcode::Jmp, vec![label(".Lrt_print_puts")]);
+        self.label(String::from(".Lrt_print_nan"));
+        self.string(".Lrt_nan", "NaN", Reg::Rdi);
+        self.label(String::from(".Lrt_print_puts"));
+        self.call("puts@PLT");
+        self.op(Opcode::Leave, Vec::new());
+        self.op(Opcode::Ret, Vec::new());
+    }
+
+    /// Write the message in `%rdi` of length `%rsi` to stderr and exit
+    fn linked_fail(&mut self) {
+        self.label(String::from("ape_rt_fail"));
+        self.op(Opcode::Push, vec![Operand::Reg(Reg::Rbp)]);
+        self.mov(Operand::Reg(Reg::Rsi), Operand::Reg(Reg::Rdx));
+        self.mov(Operand::Reg(Reg::Rdi), Operand::Reg(Reg::Rsi));
+        self.mov(Operand::Imm(2), Operand::Reg(Reg::Rdi));
+        self.call("write@PLT");
+        self.mov(Operand::Imm(1), Operand::Reg(Reg::Rdi));
+        self.call("exit@PLT");
+    }
+}
+
+fn has_phis(func: &Function, block: BlockId) -> bool {
+    matches!(
+        func.block(block).insts.first(),
+        Some(Inst::Define(_, Op::Phi(_)))
+    )
+}
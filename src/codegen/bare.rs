@@ -0,0 +1,700 @@
+use crate::codegen::asm::Codegen;
+use crate::codegen::x86::Cond;
+use crate::codegen::x86::Opcode;
+use crate::codegen::x86::Operand;
+use crate::codegen::x86::Reg;
+use crate::ir::ir::Ty;
+
+/// Slot in the red zone below `%rsp`
+fn below(disp: i32) -> Operand {
+    Operand::Mem(Reg::Rsp, disp)
+}
+
+fn st(idx: u8) -> Operand {
+    Operand::St(idx)
+}
+
+fn reg(reg: Reg) -> Operand {
+    Operand::Reg(reg)
+}
+
+fn label(name: &str) -> Operand {
+    Operand::Label(name.to_string())
+}
+
+const SIGN: i64 = i64::MIN;
+const INFINITY: i64 = 0x7FF0_0000_0000_0000;
+
+// Stand-ins for what libc and libm give the linked target, so standalone
+// executables only make system calls
+impl Codegen {
+    fn emit(&mut self, opcode: Opcode) {
+        self.op(opcode, Vec::new());
+    }
+    fn jump(&mut self, cond: Cond, target: &str) {
+        self.op(Opcode::J(cond), vec![label(target)]);
+    }
+    /// Store a number to memory through `%rax`
+    fn constant(&mut self, val: f64, dst: Operand) {
+        self.op(
+            Opcode::Movabs,
+            vec![Operand::Imm(val.to_bits() as i64), reg(Reg::Rax)],
+        );
+        self.mov(reg(Reg::Rax), dst);
+    }
+    /// Compare `%rax` with a 64-bit pattern through `%rcx`
+    fn compare_bits(&mut self, bits: i64) {
+        self.op(Opcode::Movabs, vec![Operand::Imm(bits), reg(Reg::Rcx)]);
+        self.op(Opcode::Cmp, vec![reg(Reg::Rcx), reg(Reg::Rax)]);
+    }
+    /// Set the status flags from the x87 condition code `C2`
+    fn test_c2(&mut self) {
+        self.emit(Opcode::Fnstsw);
+        self.op(Opcode::Test, vec![Operand::Imm(0x400), reg(Reg::Rax)]);
+    }
+    /// Return the top of the x87 stack in `%xmm0`
+    fn x87_return(&mut self) {
+        self.op(Opcode::Fstp, vec![below(-8)]);
+        self.movsd(below(-8), Operand::Xmm(0));
+        self.emit(Opcode::Ret);
+    }
+    /// `write(fd, %rsi, %rdx)`
+    fn write(&mut self, fd: i64) {
+        self.mov(Operand::Imm(fd), reg(Reg::Rdi));
+        self.mov(Operand::Imm(1), reg(Reg::Rax));
+        self.emit(Opcode::Syscall);
+    }
+    fn exit(&mut self, status: i64) {
+        self.mov(Operand::Imm(60), reg(Reg::Rax));
+        self.mov(Operand::Imm(status), reg(Reg::Rdi));
+        self.emit(Opcode::Syscall);
+    }
+
+    /// The entry point, printing what `ape_main` returns like the
+    /// interpreter and exiting
+    pub fn start(&mut self, ret: Ty) {
+        self.asm.exports.push(String::from("_start"));
+        self.label(String::from("_start"));
+        self.call("ape_main");
+        match ret {
+            Ty::Num => {
+                self.call("ape_rt_print");
+                self.print = true;
+            }
+            Ty::Bool => {
+                self.string(".Lrt_true", "true\n", Reg::Rsi);
+                self.mov(Operand::Imm(5), reg(Reg::Rdx));
+                self.op(Opcode::Cmp, vec![Operand::Imm(0), reg(Reg::Rax)]);
+                self.jump(Cond::Ne, ".Lrt_write");
+                self.string(".Lrt_false", "false\n", Reg::Rsi);
+                self.mov(Operand::Imm(6), reg(Reg::Rdx));
+                self.label(String::from(".Lrt_write"));
+                self.write(1);
+            }
+            Ty::Unit => {
+                self.string(".Lrt_unit", "()\n", Reg::Rsi);
+                self.mov(Operand::Imm(3), reg(Reg::Rdx));
+                self.write(1);
+            }
+        }
+        self.exit(0);
+    }
+
+    /// Write the message in `%rdi` of length `%rsi` to stderr and exit
+    pub fn bare_fail(&mut self) {
+        self.label(String::from("ape_rt_fail"));
+        self.mov(reg(Reg::Rsi), reg(Reg::Rdx));
+        self.mov(reg(Reg::Rdi), reg(Reg::Rsi));
+        self.write(2);
+        self.exit(1);
+    }
+
+    /// The libm function `name`, computed with the x87 unit's extended
+    /// precision so it may differ from libm in the last digit. These are
+    /// leaf functions keeping scratch below the stack pointer
+    pub fn bare_math(&mut self, name: &str) {
+        self.label(format!("ape_rt_{name}"));
+        match name {
+            "sin" => self.trig(name, Opcode::Fsin),
+            "cos" => self.trig(name, Opcode::Fcos),
+            "tan" => self.trig(name, Opcode::Fptan),
+            "asin" | "acos" => {
+                // The arctangent of x / sqrt(1 - x^2) or its reciprocal
+                self.movsd(Operand::Xmm(0), below(-8));
+                self.constant(1.0, below(-16));
+                self.op(Opcode::Fld, vec![below(-8)]);
+                self.op(Opcode::Fmul, vec![below(-8)]);
+                self.op(Opcode::Fsubr, vec![below(-16)]);
+                self.emit(Opcode::Fsqrt);
+                self.op(Opcode::Fld, vec![below(-8)]);
+                if name == "asin" {
+                    self.op(Opcode::Fxch, vec![st(1)]);
+                }
+                self.emit(Opcode::Fpatan);
+                self.x87_return();
+            }
+            "atan" => {
+                self.movsd(Operand::Xmm(0), below(-8));
+                self.op(Opcode::Fld, vec![below(-8)]);
+                self.emit(Opcode::Fld1);
+                self.emit(Opcode::Fpatan);
+                self.x87_return();
+            }
+            "log" => {
+                self.movsd(Operand::Xmm(0), below(-8));
+                self.emit(Opcode::Fldln2);
+                self.op(Opcode::Fld, vec![below(-8)]);
+                self.emit(Opcode::Fyl2x);
+                self.x87_return();
+            }
+            "sinh" | "cosh" => self.hyperbolic(name),
+            "tanh" => self.tanh(),
+            "pow" => self.pow(),
+            "fmod" => {
+                self.movsd(Operand::Xmm(1), below(-8));
+                self.movsd(Operand::Xmm(0), below(-16));
+                self.op(Opcode::Fld, vec![below(-8)]);
+                self.op(Opcode::Fld, vec![below(-16)]);
+                self.label(String::from(".Lrt_fmod_loop"));
+                self.emit(Opcode::Fprem);
+                self.test_c2();
+                self.jump(Cond::Ne, ".Lrt_fmod_loop");
+                self.op(Opcode::FstpSt, vec![st(1)]);
+                self.x87_return();
+            }
+            _ => self.rounding(name),
+        }
+    }
+
+    /// Sine, cosine or tangent, reducing arguments the instruction can't
+    /// take by 2pi first
+    fn trig(&mut self, name: &str, opcode: Opcode) {
+        let done = format!(".Lrt_{name}_done");
+        let reduce = format!(".Lrt_{name}_reduce");
+        self.movsd(Operand::Xmm(0), below(-8));
+        self.op(Opcode::Fld, vec![below(-8)]);
+        self.emit(opcode);
+        self.test_c2();
+        self.jump(Cond::E, &done);
+        self.emit(Opcode::Fldpi);
+        self.emit(Opcode::Fldpi);
+        self.op(Opcode::Faddp, vec![st(0), st(1)]);
+        self.op(Opcode::Fxch, vec![st(1)]);
+        self.label(reduce.clone());
+        self.emit(Opcode::Fprem);
+        self.test_c2();
+        self.jump(Cond::Ne, &reduce);
+        self.op(Opcode::FstpSt, vec![st(1)]);
+        self.emit(opcode);
+        self.label(done);
+        if opcode == Opcode::Fptan {
+            // Drop the 1 it pushes
+            self.op(Opcode::FstpSt, vec![st(0)]);
+        }
+        self.x87_return();
+    }
+
+    /// Replace the top of the x87 stack with 2 to its power, for powers
+    /// within the extended range
+    fn exp2(&mut self) {
+        // 2^n * 2^f with n the nearest integer
+        self.op(Opcode::FldSt, vec![st(0)]);
+        self.emit(Opcode::Frndint);
+        self.op(Opcode::Fst, vec![below(-64)]);
+        self.op(Opcode::Fxch, vec![st(1)]);
+        self.op(Opcode::Fsub, vec![below(-64)]);
+        self.emit(Opcode::F2xm1);
+        self.emit(Opcode::Fld1);
+        self.op(Opcode::Faddp, vec![st(0), st(1)]);
+        self.emit(Opcode::Fscale);
+        self.op(Opcode::FstpSt, vec![st(1)]);
+    }
+
+    /// Replace the top of the x87 stack with e to its power less 1, for
+    /// powers up to a half
+    fn expm1(&mut self) {
+        self.emit(Opcode::Fldl2e);
+        self.op(Opcode::Fmulp, vec![st(0), st(1)]);
+        self.emit(Opcode::F2xm1);
+    }
+
+    /// `|x|` into `%xmm1` and whether it's below `small` or above `big`
+    /// through `%xmm2`
+    fn magnitude(&mut self, small: (f64, &str), big: (f64, &str)) {
+        self.mov(Operand::Xmm(0), reg(Reg::Rax));
+        self.op(Opcode::Btr, vec![Operand::Imm(63), reg(Reg::Rax)]);
+        self.mov(reg(Reg::Rax), Operand::Xmm(1));
+        for (limit, cond, target) in [(small.0, Cond::B, small.1), (big.0, Cond::A, big.1)] {
+            if !target.is_empty() {
+                self.number(limit, 2);
+                self.op(Opcode::Ucomisd, vec![Operand::Xmm(2), Operand::Xmm(1)]);
+                // Unordered counts as below, NaN takes the first path
+                self.jump(cond, target);
+            }
+        }
+    }
+
+    /// `(e^x -/+ e^-x) / 2`, or `(m + m / (m + 1)) / 2` with `m = e^x - 1`
+    /// for small `sinh` which would otherwise cancel
+    fn hyperbolic(&mut self, name: &str) {
+        let small = format!(".Lrt_{name}_small");
+        let big = format!(".Lrt_{name}_big");
+        self.movsd(Operand::Xmm(0), below(-8));
+        self.constant(0.5, below(-16));
+        match name {
+            "sinh" => self.magnitude((0.5, &small), (11000.0, &big)),
+            _ => self.magnitude((0.0, ""), (11000.0, &big)),
+        }
+        self.op(Opcode::Fld, vec![below(-8)]);
+        self.emit(Opcode::Fldl2e);
+        self.op(Opcode::Fmulp, vec![st(0), st(1)]);
+        self.exp2();
+        self.emit(Opcode::Fld1);
+        self.op(Opcode::FldSt, vec![st(1)]);
+        self.op(Opcode::Fdivp, vec![st(0), st(1)]);
+        if name == "sinh" {
+            self.emit(Opcode::Fchs);
+        }
+        self.op(Opcode::Faddp, vec![st(0), st(1)]);
+        self.op(Opcode::Fmul, vec![below(-16)]);
+        self.x87_return();
+        if name == "sinh" {
+            self.label(small);
+            self.op(Opcode::Fld, vec![below(-8)]);
+            self.expm1();
+            self.op(Opcode::FldSt, vec![st(0)]);
+            self.op(Opcode::FldSt, vec![st(0)]);
+            self.emit(Opcode::Fld1);
+            self.op(Opcode::Faddp, vec![st(0), st(1)]);
+            self.op(Opcode::Fdivp, vec![st(0), st(1)]);
+            self.op(Opcode::Faddp, vec![st(0), st(1)]);
+            self.op(Opcode::Fmul, vec![below(-16)]);
+            self.x87_return();
+        }
+        // Past the extended range the result is infinite
+        self.label(big);
+        self.number(1e308, 2);
+        match name {
+            "sinh" => self.op(Opcode::Mulsd, vec![Operand::Xmm(2), Operand::Xmm(0)]),
+            _ => {
+                self.op(Opcode::Mulsd, vec![Operand::Xmm(2), Operand::Xmm(1)]);
+                self.movsd(Operand::Xmm(1), Operand::Xmm(0));
+            }
+        }
+        self.emit(Opcode::Ret);
+    }
+
+    /// `(e^2x - 1) / (e^2x + 1)`, or `m / (m + 2)` with `m = e^2x - 1` for
+    /// small `x`
+    fn tanh(&mut self) {
+        self.movsd(Operand::Xmm(0), below(-8));
+        self.magnitude((0.25, ".Lrt_tanh_small"), (22.0, ".Lrt_tanh_big"));
+        self.movsd(below(-8), Operand::Xmm(1));
+        self.op(Opcode::Addsd, vec![Operand::Xmm(1), Operand::Xmm(1)]);
+        self.movsd(Operand::Xmm(1), below(-8));
+        self.constant(1.0, below(-16));
+        self.op(Opcode::Fld, vec![below(-8)]);
+        self.emit(Opcode::Fldl2e);
+        self.op(Opcode::Fmulp, vec![st(0), st(1)]);
+        self.exp2();
+        self.op(Opcode::FldSt, vec![st(0)]);
+        self.op(Opcode::Fsub, vec![below(-16)]);
+        self.op(Opcode::Fxch, vec![st(1)]);
+        self.op(Opcode::Fadd, vec![below(-16)]);
+        self.op(Opcode::Fdivp, vec![st(0), st(1)]);
+        self.x87_return();
+        self.label(String::from(".Lrt_tanh_small"));
+        self.op(Opcode::Addsd, vec![Operand::Xmm(0), Operand::Xmm(0)]);
+        self.movsd(Operand::Xmm(0), below(-8));
+        self.op(Opcode::Fld, vec![below(-8)]);
+        self.expm1();
+        self.op(Opcode::FldSt, vec![st(0)]);
+        self.emit(Opcode::Fld1);
+        self.emit(Opcode::Fld1);
+        self.op(Opcode::Faddp, vec![st(0), st(1)]);
+        self.op(Opcode::Faddp, vec![st(0), st(1)]);
+        self.op(Opcode::Fdivp, vec![st(0), st(1)]);
+        self.x87_return();
+        // 1 with the sign of x
+        self.label(String::from(".Lrt_tanh_big"));
+        self.mov(Operand::Xmm(0), reg(Reg::Rax));
+        self.op(Opcode::Movabs, vec![Operand::Imm(SIGN), reg(Reg::Rcx)]);
+        self.op(Opcode::And, vec![reg(Reg::Rcx), reg(Reg::Rax)]);
+        self.op(
+            Opcode::Movabs,
+            vec![Operand::Imm(1f64.to_bits() as i64), reg(Reg::Rcx)],
+        );
+        self.op(Opcode::Or, vec![reg(Reg::Rcx), reg(Reg::Rax)]);
+        self.mov(reg(Reg::Rax), Operand::Xmm(0));
+        self.emit(Opcode::Ret);
+    }
+
+    /// `x^y` as `2^(y log2 |x|)` with the sign and special cases of C's `pow`
+    fn pow(&mut self) {
+        // x^0 and 1^y are 1 even for NaN, otherwise NaN gives NaN
+        self.op(Opcode::Xorpd, vec![Operand::Xmm(2), Operand::Xmm(2)]);
+        self.op(Opcode::Ucomisd, vec![Operand::Xmm(2), Operand::Xmm(1)]);
+        self.jump(Cond::P, ".Lrt_pow_base");
+        self.jump(Cond::E, ".Lrt_pow_one");
+        self.label(String::from(".Lrt_pow_base"));
+        self.number(1.0, 2);
+        self.op(Opcode::Ucomisd, vec![Operand::Xmm(2), Operand::Xmm(0)]);
+        self.jump(Cond::P, ".Lrt_pow_nan");
+        self.jump(Cond::E, ".Lrt_pow_one");
+        self.op(Opcode::Ucomisd, vec![Operand::Xmm(1), Operand::Xmm(1)]);
+        self.jump(Cond::P, ".Lrt_pow_nan");
+        self.movsd(Operand::Xmm(0), below(-8));
+        self.movsd(Operand::Xmm(1), below(-16));
+        // `%rcx` is 1 for an odd integer y, 0 for an even one and -1 for
+        // the rest, found by rounding y and y / 2
+        self.op(Opcode::Fld, vec![below(-16)]);
+        self.emit(Opcode::Frndint);
+        self.op(Opcode::Fstp, vec![below(-24)]);
+        self.mov(Operand::Imm(-1), reg(Reg::Rcx));
+        self.op(Opcode::Ucomisd, vec![below(-24), Operand::Xmm(1)]);
+        self.jump(Cond::Ne, ".Lrt_pow_sign");
+        self.number(0.5, 2);
+        self.op(Opcode::Mulsd, vec![Operand::Xmm(1), Operand::Xmm(2)]);
+        self.movsd(Operand::Xmm(2), below(-32));
+        self.op(Opcode::Fld, vec![below(-32)]);
+        self.emit(Opcode::Frndint);
+        self.op(Opcode::Fstp, vec![below(-24)]);
+        self.mov(Operand::Imm(0), reg(Reg::Rcx));
+        self.op(Opcode::Ucomisd, vec![below(-24), Operand::Xmm(2)]);
+        self.jump(Cond::E, ".Lrt_pow_sign");
+        self.mov(Operand::Imm(1), reg(Reg::Rcx));
+        // `%r9` is 1 when the result is negative
+        self.label(String::from(".Lrt_pow_sign"));
+        self.mov(Operand::Imm(0), reg(Reg::R9));
+        self.mov(below(-8), reg(Reg::Rax));
+        self.op(Opcode::Btr, vec![Operand::Imm(63), reg(Reg::Rax)]);
+        self.jump(Cond::Ae, ".Lrt_pow_magnitude");
+        // (-1)^±inf is 1
+        self.mov(below(-16), reg(Reg::Rdx));
+        self.op(Opcode::Btr, vec![Operand::Imm(63), reg(Reg::Rdx)]);
+        self.op(Opcode::Movabs, vec![Operand::Imm(INFINITY), reg(Reg::Rsi)]);
+        self.op(Opcode::Cmp, vec![reg(Reg::Rsi), reg(Reg::Rdx)]);
+        self.jump(Cond::Ne, ".Lrt_pow_finite");
+        self.op(
+            Opcode::Movabs,
+            vec![Operand::Imm(1f64.to_bits() as i64), reg(Reg::Rsi)],
+        );
+        self.op(Opcode::Cmp, vec![reg(Reg::Rsi), reg(Reg::Rax)]);
+        self.jump(Cond::E, ".Lrt_pow_one");
+        self.label(String::from(".Lrt_pow_finite"));
+        self.op(Opcode::Cmp, vec![Operand::Imm(1), reg(Reg::Rcx)]);
+        self.jump(Cond::Ne, ".Lrt_pow_even");
+        self.mov(Operand::Imm(1), reg(Reg::R9));
+        self.label(String::from(".Lrt_pow_even"));
+        // A negative finite non-zero x to a fractional power has no result
+        self.op(Opcode::Test, vec![reg(Reg::Rcx), reg(Reg::Rcx)]);
+        self.jump(Cond::Ns, ".Lrt_pow_magnitude");
+        self.op(Opcode::Test, vec![reg(Reg::Rax), reg(Reg::Rax)]);
+        self.jump(Cond::E, ".Lrt_pow_magnitude");
+        self.op(Opcode::Movabs, vec![Operand::Imm(INFINITY), reg(Reg::Rsi)]);
+        self.op(Opcode::Cmp, vec![reg(Reg::Rsi), reg(Reg::Rax)]);
+        self.jump(Cond::Ne, ".Lrt_pow_nan");
+        self.label(String::from(".Lrt_pow_magnitude"));
+        self.mov(reg(Reg::Rax), below(-8));
+        self.op(Opcode::Fld, vec![below(-16)]);
+        self.op(Opcode::Fld, vec![below(-8)]);
+        self.emit(Opcode::Fyl2x);
+        // Powers past the extended range overflow or underflow a double
+        self.op(Opcode::Fst, vec![below(-24)]);
+        self.movsd(below(-24), Operand::Xmm(2));
+        self.number(2000.0, 3);
+        self.op(Opcode::Ucomisd, vec![Operand::Xmm(3), Operand::Xmm(2)]);
+        self.jump(Cond::A, ".Lrt_pow_huge");
+        self.number(-2000.0, 3);
+        self.op(Opcode::Ucomisd, vec![Operand::Xmm(2), Operand::Xmm(3)]);
+        self.jump(Cond::A, ".Lrt_pow_tiny");
+        self.exp2();
+        self.op(Opcode::Fstp, vec![below(-8)]);
+        self.movsd(below(-8), Operand::Xmm(0));
+        self.op(Opcode::Jmp, vec![label(".Lrt_pow_apply")]);
+        self.label(String::from(".Lrt_pow_huge"));
+        self.op(Opcode::FstpSt, vec![st(0)]);
+        self.number(f64::INFINITY, 0);
+        self.op(Opcode::Jmp, vec![label(".Lrt_pow_apply")]);
+        self.label(String::from(".Lrt_pow_tiny"));
+        self.op(Opcode::FstpSt, vec![st(0)]);
+        self.op(Opcode::Xorpd, vec![Operand::Xmm(0), Operand::Xmm(0)]);
+        self.label(String::from(".Lrt_pow_apply"));
+        self.op(Opcode::Test, vec![reg(Reg::R9), reg(Reg::R9)]);
+        self.jump(Cond::E, ".Lrt_pow_done");
+        self.mov(Operand::Xmm(0), reg(Reg::Rax));
+        self.op(Opcode::Btc, vec![Operand::Imm(63), reg(Reg::Rax)]);
+        self.mov(reg(Reg::Rax), Operand::Xmm(0));
+        self.label(String::from(".Lrt_pow_done"));
+        self.emit(Opcode::Ret);
+        self.label(String::from(".Lrt_pow_one"));
+        self.number(1.0, 0);
+        self.emit(Opcode::Ret);
+        self.label(String::from(".Lrt_pow_nan"));
+        self.number(f64::NAN, 0);
+        self.emit(Opcode::Ret);
+    }
+
+    /// `floor`, `ceil` or `round` from the truncated number, keeping the
+    /// sign of x so -0.5 rounds to -0
+    fn rounding(&mut self, name: &str) {
+        let sign = format!(".Lrt_{name}_sign");
+        let done = format!(".Lrt_{name}_done");
+        // From 2^52 on, and for infinities and NaN, x is already whole
+        self.mov(Operand::Xmm(0), reg(Reg::Rax));
+        self.op(Opcode::Btr, vec![Operand::Imm(63), reg(Reg::Rax)]);
+        self.compare_bits(4503599627370496f64.to_bits() as i64);
+        self.jump(Cond::Ae, &done);
+        if name == "round" {
+            // Half away from zero, on |x|
+            self.mov(reg(Reg::Rax), Operand::Xmm(3));
+            self.op(Opcode::Cvttsd2si, vec![Operand::Xmm(3), reg(Reg::Rax)]);
+            self.op(Opcode::Cvtsi2sd, vec![reg(Reg::Rax), Operand::Xmm(1)]);
+            self.op(Opcode::Subsd, vec![Operand::Xmm(1), Operand::Xmm(3)]);
+            self.number(0.5, 2);
+            self.op(Opcode::Ucomisd, vec![Operand::Xmm(2), Operand::Xmm(3)]);
+            self.jump(Cond::B, &sign);
+        } else {
+            self.op(Opcode::Cvttsd2si, vec![Operand::Xmm(0), reg(Reg::Rax)]);
+            self.op(Opcode::Cvtsi2sd, vec![reg(Reg::Rax), Operand::Xmm(1)]);
+            match name {
+                "floor" => self.op(Opcode::Ucomisd, vec![Operand::Xmm(0), Operand::Xmm(1)]),
+                _ => self.op(Opcode::Ucomisd, vec![Operand::Xmm(1), Operand::Xmm(0)]),
+            }
+            self.jump(Cond::Be, &sign);
+        }
+        self.number(1.0, 2);
+        match name {
+            "floor" => self.op(Opcode::Subsd, vec![Operand::Xmm(2), Operand::Xmm(1)]),
+            _ => self.op(Opcode::Addsd, vec![Operand::Xmm(2), Operand::Xmm(1)]),
+        }
+        self.label(sign);
+        self.mov(Operand::Xmm(0), reg(Reg::Rax));
+        self.op(Opcode::Movabs, vec![Operand::Imm(SIGN), reg(Reg::Rcx)]);
+        self.op(Opcode::And, vec![reg(Reg::Rcx), reg(Reg::Rax)]);
+        self.mov(Operand::Xmm(1), reg(Reg::Rdx));
+        self.op(Opcode::Or, vec![reg(Reg::Rax), reg(Reg::Rdx)]);
+        self.mov(reg(Reg::Rdx), Operand::Xmm(0));
+        self.label(done);
+        self.emit(Opcode::Ret);
+    }
+
+    /// Print the number in `%xmm0` as the interpreter does, the fewest
+    /// significant digits reading back as the same number written out
+    /// without an exponent
+    pub fn bare_print(&mut self) {
+        let rbp = |disp| Operand::Mem(Reg::Rbp, disp);
+        // x at -8, its decimal exponent at -16, the digits at -24 and the
+        // power of ten they're scaled by at -32. The line is built from
+        // -1024 on at `%rdi`, digits are written backwards from -576
+        self.label(String::from("ape_rt_print"));
+        self.op(Opcode::Push, vec![reg(Reg::Rbp)]);
+        self.mov(reg(Reg::Rsp), reg(Reg::Rbp));
+        self.op(Opcode::Sub, vec![Operand::Imm(1024), reg(Reg::Rsp)]);
+        self.op(Opcode::Lea, vec![rbp(-1024), reg(Reg::Rdi)]);
+        self.mov(Operand::Xmm(0), reg(Reg::Rax));
+        self.mov(reg(Reg::Rax), reg(Reg::Rdx));
+        self.op(Opcode::Btr, vec![Operand::Imm(63), reg(Reg::Rdx)]);
+        self.op(Opcode::Movabs, vec![Operand::Imm(INFINITY), reg(Reg::Rcx)]);
+        self.op(Opcode::Cmp, vec![reg(Reg::Rcx), reg(Reg::Rdx)]);
+        self.jump(Cond::A, ".Lrt_print_nan");
+        self.op(Opcode::Test, vec![reg(Reg::Rax), reg(Reg::Rax)]);
+        self.jump(Cond::Ns, ".Lrt_print_positive");
+        self.put(b'-');
+        self.label(String::from(".Lrt_print_positive"));
+        self.op(Opcode::Cmp, vec![reg(Reg::Rcx), reg(Reg::Rdx)]);
+        self.jump(Cond::E, ".Lrt_print_inf");
+        self.op(Opcode::Test, vec![reg(Reg::Rdx), reg(Reg::Rdx)]);
+        self.jump(Cond::E, ".Lrt_print_zero");
+        self.mov(reg(Reg::Rdx), rbp(-8));
+        // Start from the nearest power of ten, then move it until x has 17
+        // digits before the point
+        self.emit(Opcode::Fldlg2);
+        self.op(Opcode::Fld, vec![rbp(-8)]);
+        self.emit(Opcode::Fyl2x);
+        self.op(Opcode::Fistp, vec![rbp(-16)]);
+        self.label(String::from(".Lrt_print_exponent"));
+        self.op(Opcode::Fld, vec![rbp(-8)]);
+        self.mov(Operand::Imm(16), reg(Reg::Rax));
+        self.op(Opcode::Sub, vec![rbp(-16), reg(Reg::Rax)]);
+        self.call("ape_rt_scale");
+        self.op(Opcode::Fistp, vec![rbp(-56)]);
+        self.mov(rbp(-56), reg(Reg::Rax));
+        self.compare_bits(100_000_000_000_000_000);
+        self.jump(Cond::B, ".Lrt_print_below");
+        self.op(Opcode::Add, vec![Operand::Imm(1), rbp(-16)]);
+        self.op(Opcode::Jmp, vec![label(".Lrt_print_exponent")]);
+        self.label(String::from(".Lrt_print_below"));
+        self.compare_bits(10_000_000_000_000_000);
+        self.jump(Cond::Ae, ".Lrt_print_shortest");
+        self.op(Opcode::Sub, vec![Operand::Imm(1), rbp(-16)]);
+        self.op(Opcode::Jmp, vec![label(".Lrt_print_exponent")]);
+        // Try fewer digits first, scaling them back to compare with x
+        self.label(String::from(".Lrt_print_shortest"));
+        self.mov(Operand::Imm(1), rbp(-48));
+        self.label(String::from(".Lrt_print_try"));
+        self.op(Opcode::Fld, vec![rbp(-8)]);
+        self.mov(rbp(-48), reg(Reg::Rax));
+        self.op(Opcode::Sub, vec![Operand::Imm(1), reg(Reg::Rax)]);
+        self.op(Opcode::Sub, vec![rbp(-16), reg(Reg::Rax)]);
+        self.call("ape_rt_scale");
+        self.op(Opcode::Fistp, vec![rbp(-24)]);
+        self.op(Opcode::Fild, vec![rbp(-24)]);
+        self.mov(rbp(-16), reg(Reg::Rax));
+        self.op(Opcode::Add, vec![Operand::Imm(1), reg(Reg::Rax)]);
+        self.op(Opcode::Sub, vec![rbp(-48), reg(Reg::Rax)]);
+        self.mov(reg(Reg::Rax), rbp(-32));
+        self.call("ape_rt_scale");
+        self.op(Opcode::Fstp, vec![rbp(-40)]);
+        self.mov(rbp(-40), reg(Reg::Rax));
+        self.op(Opcode::Cmp, vec![rbp(-8), reg(Reg::Rax)]);
+        self.jump(Cond::E, ".Lrt_print_found");
+        self.op(Opcode::Add, vec![Operand::Imm(1), rbp(-48)]);
+        self.op(Opcode::Cmp, vec![Operand::Imm(17), rbp(-48)]);
+        self.jump(Cond::B, ".Lrt_print_try");
+        self.mov(rbp(-56), reg(Reg::Rax));
+        self.mov(reg(Reg::Rax), rbp(-24));
+        self.mov(rbp(-16), reg(Reg::Rax));
+        self.op(Opcode::Sub, vec![Operand::Imm(16), reg(Reg::Rax)]);
+        self.mov(reg(Reg::Rax), rbp(-32));
+        // Drop trailing zeros, keeping the digits in `%r8`
+        self.label(String::from(".Lrt_print_found"));
+        self.mov(rbp(-24), reg(Reg::Rax));
+        self.mov(Operand::Imm(10), reg(Reg::Rcx));
+        self.label(String::from(".Lrt_print_trim"));
+        self.mov(reg(Reg::Rax), reg(Reg::R8));
+        self.mov(Operand::Imm(0), reg(Reg::Rdx));
+        self.op(Opcode::Div, vec![reg(Reg::Rcx)]);
+        self.op(Opcode::Test, vec![reg(Reg::Rdx), reg(Reg::Rdx)]);
+        self.jump(Cond::Ne, ".Lrt_print_trimmed");
+        self.op(Opcode::Add, vec![Operand::Imm(1), rbp(-32)]);
+        self.op(Opcode::Jmp, vec![label(".Lrt_print_trim")]);
+        self.label(String::from(".Lrt_print_trimmed"));
+        self.mov(reg(Reg::R8), reg(Reg::Rax));
+        self.op(Opcode::Lea, vec![rbp(-576), reg(Reg::Rsi)]);
+        self.label(String::from(".Lrt_print_digit"));
+        self.mov(Operand::Imm(0), reg(Reg::Rdx));
+        self.op(Opcode::Div, vec![reg(Reg::Rcx)]);
+        self.op(Opcode::Add, vec![Operand::Imm(b'0' as i64), reg(Reg::Rdx)]);
+        self.op(Opcode::Sub, vec![Operand::Imm(1), reg(Reg::Rsi)]);
+        self.op(
+            Opcode::Movb,
+            vec![Operand::Byte(Reg::Rdx), Operand::Mem(Reg::Rsi, 0)],
+        );
+        self.op(Opcode::Test, vec![reg(Reg::Rax), reg(Reg::Rax)]);
+        self.jump(Cond::Ne, ".Lrt_print_digit");
+        // `%r8` digits from `%rsi`, the last one scaled by 10^q
+        self.op(Opcode::Lea, vec![rbp(-576), reg(Reg::R8)]);
+        self.op(Opcode::Sub, vec![reg(Reg::Rsi), reg(Reg::R8)]);
+        self.mov(rbp(-32), reg(Reg::Rax));
+        self.op(Opcode::Test, vec![reg(Reg::Rax), reg(Reg::Rax)]);
+        self.jump(Cond::S, ".Lrt_print_fraction");
+        self.mov(reg(Reg::R8), reg(Reg::Rcx));
+        self.copy(".Lrt_print_whole");
+        self.mov(rbp(-32), reg(Reg::Rcx));
+        self.zeros(".Lrt_print_tens");
+        self.op(Opcode::Jmp, vec![label(".Lrt_print_end")]);
+        self.label(String::from(".Lrt_print_fraction"));
+        self.mov(reg(Reg::R8), reg(Reg::Rcx));
+        self.op(Opcode::Add, vec![reg(Reg::Rax), reg(Reg::Rcx)]);
+        self.op(Opcode::Cmp, vec![Operand::Imm(0), reg(Reg::Rcx)]);
+        self.jump(Cond::Le, ".Lrt_print_small");
+        self.copy(".Lrt_print_integer");
+        self.put(b'.');
+        self.mov(rbp(-32), reg(Reg::Rcx));
+        self.op(Opcode::Neg, vec![reg(Reg::Rcx)]);
+        self.copy(".Lrt_print_decimals");
+        self.op(Opcode::Jmp, vec![label(".Lrt_print_end")]);
+        self.label(String::from(".Lrt_print_small"));
+        self.put(b'0');
+        self.put(b'.');
+        self.op(Opcode::Neg, vec![reg(Reg::Rcx)]);
+        self.zeros(".Lrt_print_leading");
+        self.mov(reg(Reg::R8), reg(Reg::Rcx));
+        self.copy(".Lrt_print_significant");
+        self.op(Opcode::Jmp, vec![label(".Lrt_print_end")]);
+        for (name, text) in [("nan", "NaN"), ("inf", "inf"), ("zero", "0")] {
+            self.label(format!(".Lrt_print_{name}"));
+            for byte in text.bytes() {
+                self.put(byte);
+            }
+            self.op(Opcode::Jmp, vec![label(".Lrt_print_end")]);
+        }
+        self.label(String::from(".Lrt_print_end"));
+        self.put(b'\n');
+        self.op(Opcode::Lea, vec![rbp(-1024), reg(Reg::Rsi)]);
+        self.mov(reg(Reg::Rdi), reg(Reg::Rdx));
+        self.op(Opcode::Sub, vec![reg(Reg::Rsi), reg(Reg::Rdx)]);
+        self.write(1);
+        self.emit(Opcode::Leave);
+        self.emit(Opcode::Ret);
+
+        // The top of the x87 stack times 10 to the power of `%rax`, exact
+        // while that power fits the 64-bit mantissa
+        self.label(String::from("ape_rt_scale"));
+        self.mov(reg(Reg::Rax), reg(Reg::Rdx));
+        self.mov(reg(Reg::Rax), reg(Reg::Rcx));
+        self.op(Opcode::Test, vec![reg(Reg::Rcx), reg(Reg::Rcx)]);
+        self.jump(Cond::Ns, ".Lrt_scale_up");
+        self.op(Opcode::Neg, vec![reg(Reg::Rcx)]);
+        self.label(String::from(".Lrt_scale_up"));
+        self.constant(10.0, below(-8));
+        self.emit(Opcode::Fld1);
+        self.label(String::from(".Lrt_scale_loop"));
+        self.op(Opcode::Test, vec![reg(Reg::Rcx), reg(Reg::Rcx)]);
+        self.jump(Cond::E, ".Lrt_scale_apply");
+        self.op(Opcode::Fmul, vec![below(-8)]);
+        self.op(Opcode::Sub, vec![Operand::Imm(1), reg(Reg::Rcx)]);
+        self.op(Opcode::Jmp, vec![label(".Lrt_scale_loop")]);
+        self.label(String::from(".Lrt_scale_apply"));
+        self.op(Opcode::Test, vec![reg(Reg::Rdx), reg(Reg::Rdx)]);
+        self.jump(Cond::S, ".Lrt_scale_down");
+        self.op(Opcode::Fmulp, vec![st(0), st(1)]);
+        self.emit(Opcode::Ret);
+        self.label(String::from(".Lrt_scale_down"));
+        self.op(Opcode::Fdivp, vec![st(0), st(1)]);
+        self.emit(Opcode::Ret);
+    }
+
+    /// Append a byte to the line at `%rdi`
+    fn put(&mut self, byte: u8) {
+        self.op(
+            Opcode::Movb,
+            vec![Operand::Imm(byte as i64), Operand::Mem(Reg::Rdi, 0)],
+        );
+        self.op(Opcode::Add, vec![Operand::Imm(1), reg(Reg::Rdi)]);
+    }
+
+    /// Append `%rcx` bytes from `%rsi`
+    fn copy(&mut self, name: &str) {
+        let done = format!("{name}_done");
+        self.label(name.to_string());
+        self.op(Opcode::Test, vec![reg(Reg::Rcx), reg(Reg::Rcx)]);
+        self.jump(Cond::E, &done);
+        self.op(
+            Opcode::Movb,
+            vec![Operand::Mem(Reg::Rsi, 0), Operand::Byte(Reg::Rax)],
+        );
+        self.op(
+            Opcode::Movb,
+            vec![Operand::Byte(Reg::Rax), Operand::Mem(Reg::Rdi, 0)],
+        );
+        self.op(Opcode::Add, vec![Operand::Imm(1), reg(Reg::Rsi)]);
+        self.op(Opcode::Add, vec![Operand::Imm(1), reg(Reg::Rdi)]);
+        self.op(Opcode::Sub, vec![Operand::Imm(1), reg(Reg::Rcx)]);
+        self.op(Opcode::Jmp, vec![label(name)]);
+        self.label(done);
+    }
+
+    /// Append `%rcx` zeros
+    fn zeros(&mut self, name: &str) {
+        let done = format!("{name}_done");
+        self.label(name.to_string());
+        self.op(Opcode::Test, vec![reg(Reg::Rcx), reg(Reg::Rcx)]);
+        self.jump(Cond::E, &done);
+        self.put(b'0');
+        self.op(Opcode::Sub, vec![Operand::Imm(1), reg(Reg::Rcx)]);
+        self.op(Opcode::Jmp, vec![label(name)]);
+        self.label(done);
+    }
+}
@@ -0,0 +1,1015 @@
+use crate::ast::ast::constant;
+use crate::ast::ast::BinaryOp;
+use crate::ast::ast::Builtin;
+use crate::ast::ast::Expr;
+use crate::ast::ast::ExprKind;
+use crate::ast::ast::FnDecl;
+use crate::ast::ast::Stmt;
+use crate::ast::ast::StmtKind;
+use crate::ast::ast::UnaryOp;
+use crate::ast::error::RuntimeError;
+use crate::ir::ir::Ty;
+use crate::ir::lower::declarations;
+use crate::ir::lower::Declarations;
+use crate::ir::lower::LowerError;
+use crate::semantic::resolver::DeclId;
+use crate::semantic::resolver::Resolved;
+use crate::semantic::typeck::Type;
+use crate::tokeniser::token_enum::Span;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// Keywords of C and names `math.h`, `stdbool.h`, `stdio.h` and `stdlib.h`
+/// may declare or define, which the program's names are kept clear of
+const RESERVED: &[&str] = &[
+    "auto",
+    "break",
+    "case",
+    "char",
+    "const",
+    "continue",
+    "default",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "extern",
+    "float",
+    "for",
+    "goto",
+    "if",
+    "inline",
+    "int",
+    "long",
+    "register",
+    "restrict",
+    "return",
+    "short",
+    "signed",
+    "sizeof",
+    "static",
+    "struct",
+    "switch",
+    "typedef",
+    "union",
+    "unsigned",
+    "void",
+    "volatile",
+    "while",
+    "bool",
+    "true",
+    "false",
+    "main",
+    "acos",
+    "asin",
+    "atan",
+    "cos",
+    "sin",
+    "tan",
+    "acosh",
+    "asinh",
+    "atanh",
+    "cosh",
+    "sinh",
+    "tanh",
+    "exp",
+    "expm",
+    "frexp",
+    "ilogb",
+    "ldexp",
+    "log",
+    "logb",
+    "modf",
+    "scalbn",
+    "scalbln",
+    "log1p",
+    "cbrt",
+    "fabs",
+    "hypot",
+    "pow",
+    "sqrt",
+    "erf",
+    "erfc",
+    "lgamma",
+    "tgamma",
+    "gamma",
+    "ceil",
+    "floor",
+    "nearbyint",
+    "rint",
+    "lrint",
+    "llrint",
+    "round",
+    "lround",
+    "llround",
+    "trunc",
+    "fmod",
+    "remainder",
+    "remquo",
+    "copysign",
+    "nan",
+    "nextafter",
+    "nexttoward",
+    "fdim",
+    "fmax",
+    "fmin",
+    "fma",
+    "drem",
+    "finite",
+    "significand",
+    "j",
+    "jn",
+    "y",
+    "yn",
+    "fpclassify",
+    "isfinite",
+    "isinf",
+    "isnan",
+    "isnormal",
+    "signbit",
+    "isgreater",
+    "isgreaterequal",
+    "isless",
+    "islessequal",
+    "islessgreater",
+    "isunordered",
+    "NAN",
+    "INFINITY",
+    "EOF",
+    "NULL",
+    "FILE",
+    "printf",
+    "fprintf",
+    "sprintf",
+    "snprintf",
+    "puts",
+    "fputs",
+    "putchar",
+    "getchar",
+    "stdin",
+    "stdout",
+    "stderr",
+    "exit",
+    "abort",
+    "strtod",
+    "atof",
+    "atoi",
+    "malloc",
+    "free",
+    "errno",
+];
+
+/// Translate a checked program to a self-contained C file
+///
+/// `types` are those the type checker inferred, as for `lower`, and the
+/// same programs are refused. Functions become C functions of the same
+/// name, numbers are `double`, booleans `bool` and units `void`, left out
+/// of parameter lists. Blocks become blocks, `if`s with only expressions in
+/// their branches become conditional expressions and other `if`s become
+/// statements. Calls and anything else that may have an effect are given a
+/// variable of their own so C evaluates them in the order the interpreter
+/// would. Names that C or its headers reserve have `_` added, and shadowed
+/// names a number.
+///
+/// The top level statements become `ape_main` and a `main` prints its
+/// result, runtime errors are written to stderr and exit with status 1.
+/// Defining `APE_NO_MAIN` leaves `main` out for embedding
+pub fn to_c(resolved: &Resolved, types: &HashMap<DeclId, Type>) -> Result<String, LowerError> {
+    let Declarations {
+        functions,
+        signatures,
+        globals,
+    } = declarations(resolved, types)?;
+    let mut c = C {
+        names: HashMap::new(),
+        globals: HashMap::new(),
+        signatures,
+        taken: HashSet::new(),
+        scopes: Vec::new(),
+        lines: Vec::new(),
+        depth: 1,
+        temps: 0,
+        func: String::new(),
+        ret: Ty::Unit,
+        span: Span::default(),
+        div: false,
+        fact: false,
+        fail: false,
+    };
+    for decl in &functions {
+        let name = c.fresh(&decl.name.name);
+        c.names.insert(decl.name.name.clone(), name);
+    }
+    let mut out = String::from(
+        "#include <math.h>\n#include <stdbool.h>\n#include <stdio.h>\n#include <stdlib.h>\n",
+    );
+    if !globals.is_empty() {
+        out.push('\n');
+    }
+    for global in &globals {
+        let name = c.fresh(&global.name);
+        if global.ty != Ty::Unit {
+            writeln!(out, "static {0} {name};", ctype(global.ty)).unwrap();
+        }
+        writeln!(out, "static bool {name}_set;").unwrap();
+        c.globals.insert(global.name.clone(), (global.ty, name));
+    }
+
+    let mut definitions = Vec::new();
+    let mut prototypes = Vec::new();
+    for decl in &functions {
+        let (header, body) = c.function(decl)?;
+        prototypes.push(format!("{header};"));
+        definitions.push(format!("{header}\n{{\n{body}}}\n"));
+    }
+    let (ret, body) = c.top_level(&resolved.stmts)?;
+    prototypes.push(format!("{0} ape_main(void);", ctype(ret)));
+    definitions.push(format!("{0} ape_main(void)\n{{\n{body}}}\n", ctype(ret)));
+
+    out.push('\n');
+    for prototype in prototypes {
+        writeln!(out, "{prototype}").unwrap();
+    }
+    c.runtime(&mut out);
+    for definition in definitions {
+        write!(out, "\n{definition}").unwrap();
+    }
+    out.push_str("\n#ifndef APE_NO_MAIN\n");
+    if ret == Ty::Num {
+        // The first of `%.15g`, `%.16g` and `%.17g` that reads back as the
+        // same number
+        out.push_str(
+            "static void ape_print(double x)\n\
+             {\n    \
+                 if (isnan(x)) {\n        \
+                     puts(\"NaN\");\n        \
+                     return;\n    \
+                 }\n    \
+                 char buffer[32];\n    \
+                 for (int precision = 15; precision <= 17; precision++) {\n        \
+                     snprintf(buffer, sizeof buffer, \"%.*g\", precision, x);\n        \
+                     if (strtod(buffer, NULL) == x) {\n            \
+                         break;\n        \
+                     }\n    \
+                 }\n    \
+                 puts(buffer);\n\
+             }\n\n",
+        );
+    }
+    out.push_str("int main(void)\n{\n");
+    match ret {
+        Ty::Num => out.push_str("    ape_print(ape_main());\n"),
+        Ty::Bool => out.push_str("    puts(ape_main() ? \"true\" : \"false\");\n"),
+        Ty::Unit => out.push_str("    ape_main();\n    puts(\"()\");\n"),
+    }
+    out.push_str("    return 0;\n}\n#endif\n");
+    Ok(out)
+}
+
+fn ctype(ty: Ty) -> &'static str {
+    match ty {
+        Ty::Num => "double",
+        Ty::Bool => "bool",
+        Ty::Unit => "void",
+    }
+}
+
+fn is_reserved(name: &str) -> bool {
+    // `sinf`, `sinl`, `log10` and the like are variants of the same names
+    let base = name.trim_end_matches(|ch: char| ch.is_ascii_digit());
+    let base = match base.strip_suffix(['f', 'l']) {
+        Some(stripped) if RESERVED.contains(&stripped) => stripped,
+        _ => base,
+    };
+    RESERVED.contains(&base)
+}
+
+/// A double as a C constant reading back exactly
+fn number(val: f64) -> String {
+    let text = match val {
+        _ if val.is_nan() => String::from("NAN"),
+        _ if val.is_infinite() => String::from("INFINITY"),
+        _ => format!("{0:?}", val.abs()),
+    };
+    match val.is_sign_negative() && !val.is_nan() {
+        true => format!("(-{text})"),
+        false => text,
+    }
+}
+
+/// A C string literal of the text
+fn string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for ch in text.chars() {
+        match ch {
+            '"' | '\\' => write!(out, "\\{ch}").unwrap(),
+            '\n' => out.push_str("\\n"),
+            _ => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A translated expression. `code` has no effects, so it can be written
+/// anywhere, but may stop the program if `fallible`. It's empty for units
+struct Val {
+    code: String,
+    ty: Ty,
+    fallible: bool,
+    /// A literal or a variable only assigned once, which nothing that runs
+    /// before it's read can change
+    constant: bool,
+}
+
+impl Val {
+    fn unit() -> Self {
+        Val {
+            code: String::new(),
+            ty: Ty::Unit,
+            fallible: false,
+            constant: true,
+        }
+    }
+    fn constant(code: String, ty: Ty) -> Self {
+        Val {
+            code,
+            ty,
+            fallible: false,
+            constant: true,
+        }
+    }
+    fn temp(name: String, ty: Ty) -> Self {
+        Val {
+            code: name,
+            ty,
+            fallible: false,
+            constant: true,
+        }
+    }
+    fn expr(code: String, ty: Ty, fallible: bool) -> Self {
+        Val {
+            code,
+            ty,
+            fallible,
+            constant: false,
+        }
+    }
+}
+
+/// `None` when control never gets past the code because of a `return`
+type Translated<T> = Result<Option<T>, LowerError>;
+
+struct C {
+    /// C name of every function
+    names: HashMap<String, String>,
+    /// Type and C name of every global
+    globals: HashMap<String, (Ty, String)>,
+    signatures: HashMap<String, (Vec<Ty>, Ty)>,
+    /// C names of the functions, globals and the current function's locals,
+    /// every declaration gets its own
+    taken: HashSet<String>,
+    /// C names and types of the locals in scope, no name for units,
+    /// innermost last and empty at the top level
+    scopes: Vec<HashMap<String, (Option<String>, Ty)>>,
+    /// Statements of the function being translated
+    lines: Vec<String>,
+    depth: usize,
+    temps: usize,
+    /// Name and return type of the function being translated
+    func: String,
+    ret: Ty,
+    /// Source being translated, for errors
+    span: Span,
+    /// Runtime functions the program needs
+    div: bool,
+    fact: bool,
+    fail: bool,
+}
+
+impl C {
+    fn error(&self, reason: String) -> LowerError {
+        LowerError {
+            reason,
+            span: self.span,
+        }
+    }
+    /// A C name for a declaration of `name` no other declaration has
+    fn fresh(&mut self, name: &str) -> String {
+        let base = match is_reserved(name) {
+            true => format!("{name}_"),
+            false => name.to_string(),
+        };
+        let mut fresh = base.clone();
+        let mut count = 1;
+        while self.taken.contains(&fresh) {
+            fresh = format!("{base}_{count}");
+            count += 1;
+        }
+        self.taken.insert(fresh.clone());
+        fresh
+    }
+    fn temp(&mut self) -> String {
+        self.temps += 1;
+        format!("t{0}", self.temps)
+    }
+    fn line(&mut self, line: String) {
+        self.lines
+            .push(format!("{0}{line}", "    ".repeat(self.depth)));
+    }
+    /// Keep a fallible value that's otherwise unused, so it still fails
+    fn discard(&mut self, val: Val) {
+        if val.fallible {
+            self.line(format!("(void){0};", val.code));
+        }
+    }
+    fn fail(&mut self, err: RuntimeError) -> String {
+        self.fail = true;
+        format!("ape_fail({0});", string(&format!("error: {err}\n")))
+    }
+    /// The statements of the function so far, indented by `depth`
+    fn take_body(&mut self) -> String {
+        let mut body = String::new();
+        for line in self.lines.drain(..) {
+            writeln!(body, "{line}").unwrap();
+        }
+        body
+    }
+
+    /// The function's header and body
+    fn function(&mut self, decl: &FnDecl) -> Result<(String, String), LowerError> {
+        let (params, ret) = self.signatures[&decl.name.name].clone();
+        let outer = self.taken.clone();
+        self.temps = 0;
+        self.func = decl.name.name.clone();
+        self.ret = ret;
+        self.span = decl.name.span;
+        let mut scope = HashMap::new();
+        let mut header = Vec::new();
+        for (param, &ty) in decl.params.iter().zip(&params) {
+            let name = match ty {
+                Ty::Unit => None,
+                _ => {
+                    let name = self.fresh(&param.name.name);
+                    header.push(format!("{0} {name}", ctype(ty)));
+                    Some(name)
+                }
+            };
+            scope.insert(param.name.name.clone(), (name, ty));
+        }
+        if header.is_empty() {
+            header.push(String::from("void"));
+        }
+        self.scopes = vec![scope];
+        if let Some(val) = self.stmts(&decl.body)? {
+            self.ret(val)?;
+        }
+        self.scopes.clear();
+        self.taken = outer;
+        let header = format!(
+            "{0} {1}({2})",
+            ctype(ret),
+            self.names[&decl.name.name],
+            header.join(", ")
+        );
+        Ok((header, self.take_body()))
+    }
+    /// `ape_main`'s return type and body, the value of the last expression
+    /// statement
+    fn top_level(&mut self, stmts: &[Stmt]) -> Result<(Ty, String), LowerError> {
+        self.temps = 0;
+        let last = stmts
+            .iter()
+            .rposition(|stmt| matches!(stmt.kind, StmtKind::Expr(_)));
+        let mut result = Val::unit();
+        for (idx, stmt) in stmts.iter().enumerate() {
+            let val = self
+                .stmt(stmt)?
+                .expect("only functions return, the top level always continues");
+            match val {
+                // Read now, what follows may change it
+                Some(val) if Some(idx) == last && idx + 1 < stmts.len() && !val.constant => {
+                    let temp = self.temp();
+                    self.line(format!("const {0} {temp} = {1};", ctype(val.ty), val.code));
+                    result = Val::temp(temp, val.ty);
+                }
+                Some(val) if Some(idx) == last => result = val,
+                Some(val) => self.discard(val),
+                None => {}
+            }
+        }
+        if result.ty != Ty::Unit {
+            self.line(format!("return {0};", result.code));
+        }
+        Ok((result.ty, self.take_body()))
+    }
+    fn ret(&mut self, val: Val) -> Result<(), LowerError> {
+        if val.ty != self.ret {
+            return Err(self.error(format!(
+                "{0} returns {1} here but {2} elsewhere",
+                self.func, self.ret, val.ty
+            )));
+        }
+        match val.ty {
+            Ty::Unit => self.line(String::from("return;")),
+            _ => self.line(format!("return {0};", val.code)),
+        }
+        Ok(())
+    }
+
+    /// The value of an expression statement, `None` for anything else
+    fn stmt(&mut self, stmt: &Stmt) -> Translated<Option<Val>> {
+        let outer = std::mem::replace(&mut self.span, stmt.span);
+        let val = self.stmt_kind(stmt);
+        self.span = outer;
+        val
+    }
+    fn stmt_kind(&mut self, stmt: &Stmt) -> Translated<Option<Val>> {
+        match &stmt.kind {
+            StmtKind::Let(id, expr) | StmtKind::Const(id, expr) => {
+                let Some(val) = self.expr(expr)? else {
+                    return Ok(None);
+                };
+                if self.scopes.is_empty() {
+                    return self.store(&id.name, val).map(|()| Some(None));
+                }
+                let ty = val.ty;
+                let name = match ty {
+                    Ty::Unit => {
+                        self.discard(val);
+                        None
+                    }
+                    _ => {
+                        let name = self.fresh(&id.name);
+                        self.line(format!("{0} {name} = {1};", ctype(ty), val.code));
+                        Some(name)
+                    }
+                };
+                let scope = self.scopes.last_mut().expect("checked above");
+                scope.insert(id.name.clone(), (name, ty));
+            }
+            StmtKind::Assign(id, expr) => {
+                let Some(val) = self.expr(expr)? else {
+                    return Ok(None);
+                };
+                match self.local(&id.name) {
+                    Some((_, ty)) if ty != val.ty => {
+                        return Err(self.error(format!(
+                            "{0} changes type from {ty} to {1}",
+                            id.name, val.ty
+                        )))
+                    }
+                    Some((Some(name), _)) => self.line(format!("{name} = {0};", val.code)),
+                    Some((None, _)) => self.discard(val),
+                    None => self.store(&id.name, val)?,
+                }
+            }
+            // Already translated on its own
+            StmtKind::Fn(_) => {}
+            StmtKind::Expr(expr) => return Ok(self.expr(expr)?.map(Some)),
+            StmtKind::Return(expr) => {
+                let val = match expr {
+                    Some(expr) => match self.expr(expr)? {
+                        Some(val) => val,
+                        None => return Ok(None),
+                    },
+                    None => Val::unit(),
+                };
+                self.ret(val)?;
+                return Ok(None);
+            }
+        }
+        Ok(Some(None))
+    }
+    fn store(&mut self, name: &str, val: Val) -> Result<(), LowerError> {
+        let (ty, global) = self
+            .globals
+            .get(name)
+            .cloned()
+            .ok_or_else(|| self.error(format!("{name} isn't a global of this program")))?;
+        if ty != val.ty {
+            return Err(self.error(format!("{name} changes type from {ty} to {0}", val.ty)));
+        }
+        match ty {
+            Ty::Unit => self.discard(val),
+            _ => self.line(format!("{global} = {0};", val.code)),
+        }
+        self.line(format!("{global}_set = true;"));
+        Ok(())
+    }
+    /// Statements in a scope of their own, giving the value of a trailing
+    /// expression statement or else unit
+    fn stmts(&mut self, stmts: &[Stmt]) -> Translated<Val> {
+        self.scopes.push(HashMap::new());
+        let mut val: Option<Val> = None;
+        for stmt in stmts {
+            match self.stmt(stmt)? {
+                Some(stmt_val) => {
+                    if let Some(unused) = val.take() {
+                        self.discard(unused);
+                    }
+                    val = stmt_val;
+                }
+                None => {
+                    self.scopes.pop();
+                    return Ok(None);
+                }
+            }
+        }
+        self.scopes.pop();
+        Ok(Some(val.unwrap_or_else(Val::unit)))
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Translated<Val> {
+        let outer = std::mem::replace(&mut self.span, expr.span);
+        let val = self.expr_kind(expr);
+        self.span = outer;
+        val
+    }
+    /// Translate the expressions in order, stopping if one returns. Values
+    /// are read into variables before any later one runs a statement
+    fn operands(&mut self, exprs: &[Expr]) -> Translated<Vec<Val>> {
+        let mut vals: Vec<Val> = Vec::new();
+        for expr in exprs {
+            let mark = self.lines.len();
+            let Some(val) = self.expr(expr)? else {
+                return Ok(None);
+            };
+            if self.lines.len() > mark {
+                let mut reads = Vec::new();
+                for earlier in vals.iter_mut().filter(|earlier| !earlier.constant) {
+                    let temp = self.temp();
+                    reads.push(format!(
+                        "{0}const {1} {temp} = {2};",
+                        "    ".repeat(self.depth),
+                        ctype(earlier.ty),
+                        earlier.code
+                    ));
+                    *earlier = Val::temp(temp, earlier.ty);
+                }
+                self.lines.splice(mark..mark, reads);
+            }
+            vals.push(val);
+        }
+        Ok(Some(vals))
+    }
+    fn expr_kind(&mut self, expr: &Expr) -> Translated<Val> {
+        let val = match &expr.kind {
+            ExprKind::Number(val) => Val::constant(number(*val), Ty::Num),
+            ExprKind::Bool(val) => Val::constant(val.to_string(), Ty::Bool),
+            ExprKind::Variable(name) => {
+                if let Some((local, ty)) = self.local(name) {
+                    match local {
+                        Some(local) => Val::expr(local, ty, false),
+                        None => Val::unit(),
+                    }
+                } else if let Some((ty, global)) = self.globals.get(name).cloned() {
+                    let fail = self.fail(RuntimeError::UndeclaredVariable(name.clone()));
+                    self.line(format!("if (!{global}_set) {{"));
+                    self.depth += 1;
+                    self.line(fail);
+                    self.depth -= 1;
+                    self.line(String::from("}"));
+                    match ty {
+                        Ty::Unit => Val::unit(),
+                        _ => Val::expr(global, ty, false),
+                    }
+                } else if self.signatures.contains_key(name) {
+                    return Err(self.error(String::from("Functions can't be used as values")));
+                } else if let Some(val) = constant(name) {
+                    Val::constant(number(val), Ty::Num)
+                } else {
+                    return Err(self.error(format!("{name} isn't defined in this program")));
+                }
+            }
+            ExprKind::BinaryOp(left, op, right) => {
+                let Some(vals) = self.operands(&[(**left).clone(), (**right).clone()])? else {
+                    return Ok(None);
+                };
+                let (left, right) = (&vals[0], &vals[1]);
+                let fallible = left.fallible || right.fallible;
+                let (a, b) = (&left.code, &right.code);
+                match left.ty {
+                    // Units are all equal
+                    Ty::Unit => Val::constant((*op == BinaryOp::Equal).to_string(), Ty::Bool),
+                    Ty::Bool => {
+                        Val::expr(format!("({a} {0} {b})", op.symbol()), Ty::Bool, fallible)
+                    }
+                    Ty::Num => match op {
+                        BinaryOp::Div => {
+                            let divisor = match right.constant {
+                                true => constant_value(&right.code),
+                                false => None,
+                            };
+                            match divisor {
+                                Some(divisor) if divisor != 0.0 && !divisor.is_nan() => {
+                                    Val::expr(format!("({a} / {b})"), Ty::Num, fallible)
+                                }
+                                _ => {
+                                    self.div = true;
+                                    Val::expr(format!("ape_div({a}, {b})"), Ty::Num, true)
+                                }
+                            }
+                        }
+                        BinaryOp::Mod => Val::expr(format!("fmod({a}, {b})"), Ty::Num, fallible),
+                        BinaryOp::Pow => Val::expr(format!("pow({a}, {b})"), Ty::Num, fallible),
+                        _ => {
+                            let ty = match op.is_comparison() {
+                                true => Ty::Bool,
+                                false => Ty::Num,
+                            };
+                            Val::expr(format!("({a} {0} {b})", op.symbol()), ty, fallible)
+                        }
+                    },
+                }
+            }
+            ExprKind::UnaryOp(operand, op) => {
+                let Some(val) = self.expr(operand)? else {
+                    return Ok(None);
+                };
+                let a = &val.code;
+                let code = match op {
+                    UnaryOp::Neg => format!("(-{a})"),
+                    // `~x` is `-(x + 1)`
+                    UnaryOp::Not => format!("(-({a} + 1.0))"),
+                    UnaryOp::Factorial => {
+                        self.fact = true;
+                        format!("ape_fact({a})")
+                    }
+                };
+                Val::expr(code, Ty::Num, val.fallible)
+            }
+            ExprKind::Builtin(builtin, args) => {
+                let Some(vals) = self.operands(args)? else {
+                    return Ok(None);
+                };
+                let fallible = vals.iter().any(|val| val.fallible);
+                let args: Vec<&str> = vals.iter().map(|val| val.code.as_str()).collect();
+                let code = match builtin {
+                    // ln(x) / ln(base) as the interpreter computes it
+                    Builtin::Log => format!(
+                        "(log({0}) / log({1}))",
+                        args[0],
+                        args.get(1).copied().unwrap_or("10.0")
+                    ),
+                    Builtin::Exp => format!("pow({0}, {1})", number(std::f64::consts::E), args[0]),
+                    Builtin::Abs => format!("fabs({0})", args[0]),
+                    _ => format!("{0}({1})", builtin.name(), args.join(", ")),
+                };
+                Val::expr(code, Ty::Num, fallible)
+            }
+            ExprKind::Block(stmts) => return self.block(stmts),
+            ExprKind::FunctionCall(id, args) => {
+                if self.local(&id.name).is_some() || self.globals.contains_key(&id.name) {
+                    return Err(
+                        self.error(String::from("Only functions defined with fn can be called"))
+                    );
+                }
+                let Some((params, ret)) = self.signatures.get(&id.name).cloned() else {
+                    return Err(self.error(format!("{0} isn't defined in this program", id.name)));
+                };
+                let Some(vals) = self.operands(args)? else {
+                    return Ok(None);
+                };
+                for (idx, (val, param)) in vals.iter().zip(&params).enumerate() {
+                    if val.ty != *param {
+                        return Err(self.error(format!(
+                            "Argument {0} of {1} is {2}, the IR only has a version taking {param}",
+                            idx + 1,
+                            id.name,
+                            val.ty
+                        )));
+                    }
+                }
+                let args: Vec<&str> = vals
+                    .iter()
+                    .filter(|val| val.ty != Ty::Unit)
+                    .map(|val| val.code.as_str())
+                    .collect();
+                let call = format!("{0}({1})", self.names[&id.name], args.join(", "));
+                match ret {
+                    Ty::Unit => {
+                        self.line(format!("{call};"));
+                        Val::unit()
+                    }
+                    _ => {
+                        let temp = self.temp();
+                        self.line(format!("const {0} {temp} = {call};", ctype(ret)));
+                        Val::temp(temp, ret)
+                    }
+                }
+            }
+            ExprKind::If(cond, then_branch, else_branch) => {
+                return self.translate_if(cond, then_branch, else_branch.as_deref())
+            }
+        };
+        Ok(Some(val))
+    }
+    fn local(&self, name: &str) -> Option<(Option<String>, Ty)> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+    }
+    /// A block expression, left out when it's only an expression
+    fn block(&mut self, stmts: &[Stmt]) -> Translated<Val> {
+        let mark = self.lines.len();
+        self.line(String::from("{"));
+        self.depth += 1;
+        let val = self.stmts(stmts)?;
+        self.depth -= 1;
+        let Some(val) = val else {
+            self.line(String::from("}"));
+            return Ok(None);
+        };
+        if self.lines.len() == mark + 1 {
+            self.lines.pop();
+            return Ok(Some(val));
+        }
+        if val.ty == Ty::Unit {
+            self.line(String::from("}"));
+            return Ok(Some(Val::unit()));
+        }
+        let temp = self.temp();
+        let declaration = format!("{0}{1} {temp};", "    ".repeat(self.depth), ctype(val.ty));
+        self.lines.insert(mark, declaration);
+        self.depth += 1;
+        self.line(format!("{temp} = {0};", val.code));
+        self.depth -= 1;
+        self.line(String::from("}"));
+        Ok(Some(Val::temp(temp, val.ty)))
+    }
+    /// A branch of an `if`, a block's statements go straight in it
+    fn branch(&mut self, branch: &Expr) -> Translated<Val> {
+        match &branch.kind {
+            ExprKind::Block(stmts) => {
+                let outer = std::mem::replace(&mut self.span, branch.span);
+                let val = self.stmts(stmts);
+                self.span = outer;
+                val
+            }
+            _ => self.expr(branch),
+        }
+    }
+    fn translate_if(
+        &mut self,
+        cond: &Expr,
+        then_branch: &Expr,
+        else_branch: Option<&Expr>,
+    ) -> Translated<Val> {
+        let Some(cond) = self.expr(cond)? else {
+            return Ok(None);
+        };
+        let mark = self.lines.len();
+        self.depth += 1;
+        let then_val = self.branch(then_branch)?;
+        let then_lines = self.lines.split_off(mark);
+        let else_val = match else_branch {
+            Some(branch) => self.branch(branch)?,
+            None => Some(Val::unit()),
+        };
+        let else_lines = self.lines.split_off(mark);
+        self.depth -= 1;
+
+        let ty = match (&then_val, &else_val) {
+            (Some(then_val), Some(else_val)) if then_val.ty != else_val.ty => {
+                return Err(self.error(String::from("Branches of this if have different types")))
+            }
+            (Some(val), _) | (None, Some(val)) => Some(val.ty),
+            (None, None) => None,
+        };
+        if then_lines.is_empty() && else_lines.is_empty() {
+            if let (Some(then_val), Some(else_val)) = (&then_val, &else_val) {
+                if then_val.ty == Ty::Unit {
+                    return Ok(Some(Val::unit()));
+                }
+                return Ok(Some(Val::expr(
+                    format!("({0} ? {1} : {2})", cond.code, then_val.code, else_val.code),
+                    then_val.ty,
+                    cond.fallible || then_val.fallible || else_val.fallible,
+                )));
+            }
+        }
+
+        let temp = match ty {
+            Some(Ty::Unit) | None => None,
+            Some(ty) => {
+                let temp = self.temp();
+                self.line(format!("{0} {temp};", ctype(ty)));
+                Some(temp)
+            }
+        };
+        self.line(format!("if ({0}) {{", unwrapped(&cond.code)));
+        self.lines.extend(then_lines);
+        self.depth += 1;
+        self.assign(&temp, then_val);
+        self.depth -= 1;
+        let mark = self.lines.len();
+        self.lines.extend(else_lines);
+        self.depth += 1;
+        self.assign(&temp, else_val);
+        self.depth -= 1;
+        if self.lines.len() > mark {
+            let else_line = format!("{0}}} else {{", "    ".repeat(self.depth));
+            self.lines.insert(mark, else_line);
+        }
+        self.line(String::from("}"));
+        Ok(ty.map(|ty| match temp {
+            Some(temp) => Val::temp(temp, ty),
+            None => Val::unit(),
+        }))
+    }
+    /// Give the `if`'s variable the branch's value, unless it returned
+    fn assign(&mut self, temp: &Option<String>, val: Option<Val>) {
+        match (temp, val) {
+            (Some(temp), Some(val)) => self.line(format!("{temp} = {0};", val.code)),
+            (None, Some(val)) => self.discard(val),
+            (_, None) => {}
+        }
+    }
+
+    /// Functions the translated code calls, only those it uses
+    fn runtime(&mut self, out: &mut String) {
+        // Dividing fails on zero
+        if self.fail || self.div {
+            out.push_str(
+                "\nstatic void ape_fail(const char *message)\n\
+                 {\n    \
+                     fputs(message, stderr);\n    \
+                     exit(1);\n\
+                 }\n",
+            );
+        }
+        if self.div {
+            let fail = self.fail(RuntimeError::DivisionByZero);
+            write!(
+                out,
+                "\nstatic double ape_div(double a, double b)\n\
+                 {{\n    \
+                     if (b == 0.0) {{\n        \
+                         {fail}\n    \
+                     }}\n    \
+                     return a / b;\n\
+                 }}\n"
+            )
+            .unwrap();
+        }
+        if self.fact {
+            // NaN for negative numbers, otherwise the product up to the
+            // number truncated, which is 1 for NaN and overflows past 170
+            out.push_str(
+                "\nstatic double ape_fact(double n)\n\
+                 {\n    \
+                     if (n < 0.0) {\n        \
+                         return NAN;\n    \
+                     }\n    \
+                     if (n >= 171.0) {\n        \
+                         return INFINITY;\n    \
+                     }\n    \
+                     double product = 1.0;\n    \
+                     for (double factor = 1.0; factor <= n; factor += 1.0) {\n        \
+                         product *= factor;\n    \
+                     }\n    \
+                     return product;\n\
+                 }\n",
+            );
+        }
+    }
+}
+
+/// The number a constant's code stands for
+fn constant_value(code: &str) -> Option<f64> {
+    match code {
+        "NAN" => Some(f64::NAN),
+        "INFINITY" => Some(f64::INFINITY),
+        "(-INFINITY)" => Some(f64::NEG_INFINITY),
+        _ => match code
+            .strip_prefix("(-")
+            .and_then(|code| code.strip_suffix(')'))
+        {
+            Some(code) => code.parse::<f64>().ok().map(|val| -val),
+            None => code.parse().ok(),
+        },
+    }
+}
+
+/// The code without the parentheses around all of it, if it has them
+fn unwrapped(code: &str) -> &str {
+    let Some(inner) = code
+        .strip_prefix('(')
+        .and_then(|code| code.strip_suffix(')'))
+    else {
+        return code;
+    };
+    let mut depth = 0;
+    for ch in inner.chars() {
+        match ch {
+            '(' => depth += 1,
+            ')' if depth == 0 => return code,
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    inner
+}
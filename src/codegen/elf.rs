@@ -0,0 +1,117 @@
+use crate::codegen::encode::encode;
+use crate::codegen::x86::Assembly;
+use std::collections::HashMap;
+
+/// Address the executable is loaded at
+const BASE: u64 = 0x400000;
+const PAGE: u64 = 0x1000;
+const HEADER_SIZE: u64 = 64;
+const PROGRAM_HEADER_SIZE: u64 = 56;
+
+const PT_LOAD: u32 = 1;
+const PT_GNU_STACK: u32 = 0x6474_e551;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+/// Segment of the file mapped into memory
+struct Segment {
+    kind: u32,
+    flags: u32,
+    offset: u64,
+    addr: u64,
+    file_size: u64,
+    mem_size: u64,
+}
+
+/// A static ELF64 executable for x86-64 Linux entering at `_start`
+///
+/// The headers, code and strings share one read only segment and the
+/// variables are zeroed memory in a writable one after it. Nothing is
+/// linked in, so every symbol the code uses has to be defined in `asm`
+pub fn executable(asm: &Assembly) -> Result<Vec<u8>, String> {
+    let mut code = encode(&asm.text)?;
+    let entry = *code
+        .labels
+        .get("_start")
+        .ok_or_else(|| String::from("Undefined symbol _start"))?;
+    let segments = if asm.data.is_empty() { 2 } else { 3 };
+    let text_addr = BASE + HEADER_SIZE + segments * PROGRAM_HEADER_SIZE;
+
+    let mut symbols = HashMap::new();
+    let mut rodata = Vec::new();
+    let rodata_addr = text_addr + code.bytes.len() as u64;
+    for (label, text) in &asm.strings {
+        symbols.insert(label.clone(), rodata_addr + rodata.len() as u64);
+        rodata.extend_from_slice(text.as_bytes());
+        rodata.push(0);
+    }
+    let file_size = rodata_addr + rodata.len() as u64 - BASE;
+    // On a page of its own so it can be writable
+    let data_addr = (BASE + file_size).next_multiple_of(PAGE);
+    for (idx, label) in asm.data.iter().enumerate() {
+        symbols.insert(label.clone(), data_addr + 8 * idx as u64);
+    }
+    code.resolve(text_addr, |symbol| symbols.get(symbol).copied())?;
+
+    let mut program = vec![Segment {
+        kind: PT_LOAD,
+        flags: PF_R | PF_X,
+        offset: 0,
+        addr: BASE,
+        file_size,
+        mem_size: file_size,
+    }];
+    if !asm.data.is_empty() {
+        program.push(Segment {
+            kind: PT_LOAD,
+            flags: PF_R | PF_W,
+            offset: data_addr - BASE,
+            addr: data_addr,
+            file_size: 0,
+            mem_size: 8 * asm.data.len() as u64,
+        });
+    }
+    // Without it the stack would be executable
+    program.push(Segment {
+        kind: PT_GNU_STACK,
+        flags: PF_R | PF_W,
+        offset: 0,
+        addr: 0,
+        file_size: 0,
+        mem_size: 0,
+    });
+
+    let mut out = Vec::with_capacity(file_size as usize);
+    // Identification: 64-bit, little endian, version 1, System V
+    out.extend_from_slice(&[0x7F, b'E', b'L', b'F', 2, 1, 1, 0]);
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(&2u16.to_le_bytes()); // Executable
+    out.extend_from_slice(&0x3Eu16.to_le_bytes()); // x86-64
+    out.extend_from_slice(&1u32.to_le_bytes());
+    out.extend_from_slice(&(text_addr + entry as u64).to_le_bytes());
+    out.extend_from_slice(&HEADER_SIZE.to_le_bytes()); // Program headers
+    out.extend_from_slice(&0u64.to_le_bytes()); // No section headers
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+    out.extend_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+    out.extend_from_slice(&(program.len() as u16).to_le_bytes());
+    out.extend_from_slice(&[0; 6]);
+    for segment in &program {
+        out.extend_from_slice(&segment.kind.to_le_bytes());
+        out.extend_from_slice(&segment.flags.to_le_bytes());
+        out.extend_from_slice(&segment.offset.to_le_bytes());
+        out.extend_from_slice(&segment.addr.to_le_bytes());
+        out.extend_from_slice(&segment.addr.to_le_bytes());
+        out.extend_from_slice(&segment.file_size.to_le_bytes());
+        out.extend_from_slice(&segment.mem_size.to_le_bytes());
+        let align = match segment.kind {
+            PT_LOAD => PAGE,
+            _ => 16,
+        };
+        out.extend_from_slice(&align.to_le_bytes());
+    }
+    out.extend_from_slice(&code.bytes);
+    out.extend_from_slice(&rodata);
+    Ok(out)
+}
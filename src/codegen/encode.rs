@@ -0,0 +1,396 @@
+use crate::codegen::x86::Cond;
+use crate::codegen::x86::Inst;
+use crate::codegen::x86::Opcode;
+use crate::codegen::x86::Operand;
+use crate::codegen::x86::Reg;
+use std::collections::HashMap;
+
+/// Machine code for a text section, its references to symbols still to be
+/// filled in once its address is known
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Code {
+    pub bytes: Vec<u8>,
+    /// Offset of every label
+    pub labels: HashMap<String, usize>,
+    pub fixups: Vec<Fixup>,
+}
+
+/// A 32-bit field holding the distance to a symbol from the end of the
+/// instruction it's in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fixup {
+    pub at: usize,
+    pub end: usize,
+    pub symbol: String,
+}
+
+/// Encode the instructions as GNU `as` would, except jumps always take a
+/// 32-bit displacement
+pub fn encode(text: &[Inst]) -> Result<Code, String> {
+    let mut encoder = Encoder {
+        code: Code::default(),
+        byte_rex: false,
+    };
+    for inst in text {
+        match inst {
+            Inst::Label(label) => {
+                let offset = encoder.code.bytes.len();
+                if encoder.code.labels.insert(label.clone(), offset).is_some() {
+                    return Err(format!("Label {label} is defined twice"));
+                }
+            }
+            Inst::Op(opcode, operands) => {
+                let fixups = encoder.code.fixups.len();
+                encoder.byte_rex = operands
+                    .iter()
+                    .any(|op| matches!(op, Operand::Byte(reg) if (4..8).contains(&(*reg as u8))));
+                encoder
+                    .inst(*opcode, operands)
+                    .ok_or_else(|| format!("Can't encode `{0}`", inst.to_string().trim()))?;
+                let end = encoder.code.bytes.len();
+                encoder.code.fixups[fixups..]
+                    .iter_mut()
+                    .for_each(|fixup| fixup.end = end);
+            }
+        }
+    }
+    Ok(encoder.code)
+}
+
+impl Code {
+    /// Fill in every fixup for the code placed at `base`, symbols outside
+    /// it found with `lookup`
+    pub fn resolve(
+        &mut self,
+        base: u64,
+        lookup: impl Fn(&str) -> Option<u64>,
+    ) -> Result<(), String> {
+        for fixup in &self.fixups {
+            let target = match self.labels.get(&fixup.symbol) {
+                Some(&offset) => base + offset as u64,
+                None => lookup(&fixup.symbol)
+                    .ok_or_else(|| format!("Undefined symbol {0}", fixup.symbol))?,
+            };
+            let distance = target.wrapping_sub(base + fixup.end as u64) as i64;
+            let distance = i32::try_from(distance)
+                .map_err(|_| format!("{0} is out of reach", fixup.symbol))?;
+            self.bytes[fixup.at..fixup.at + 4].copy_from_slice(&distance.to_le_bytes());
+        }
+        Ok(())
+    }
+}
+
+/// Number of a condition in `jcc` and `setcc` opcodes
+fn cond_code(cond: Cond) -> u8 {
+    match cond {
+        Cond::B => 0x2,
+        Cond::Ae => 0x3,
+        Cond::E => 0x4,
+        Cond::Ne => 0x5,
+        Cond::Be => 0x6,
+        Cond::A => 0x7,
+        Cond::S => 0x8,
+        Cond::Ns => 0x9,
+        Cond::P => 0xA,
+        Cond::Np => 0xB,
+        Cond::Le => 0xE,
+    }
+}
+
+fn is_mem(op: &Operand) -> bool {
+    matches!(op, Operand::Mem(..) | Operand::Rip(_))
+}
+
+/// Registers and memory, what the ModRM byte can address
+fn is_rm(op: &Operand) -> bool {
+    matches!(op, Operand::Reg(_) | Operand::Byte(_) | Operand::Xmm(_)) || is_mem(op)
+}
+
+struct Encoder {
+    code: Code,
+    /// Whether the instruction names the low byte of `%rsp` to `%rdi`,
+    /// which needs a REX prefix
+    byte_rex: bool,
+}
+
+impl Encoder {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.code.bytes.extend_from_slice(bytes);
+    }
+    fn imm32(&mut self, val: i64) -> Option<()> {
+        let val = i32::try_from(val).ok()?;
+        self.bytes(&val.to_le_bytes());
+        Some(())
+    }
+    fn imm8(&mut self, val: i64) -> Option<()> {
+        let val = i8::try_from(val).ok()?;
+        self.bytes(&val.to_le_bytes());
+        Some(())
+    }
+    /// Four bytes to be filled in with the distance to `symbol`
+    fn fixup(&mut self, symbol: &str) {
+        self.code.fixups.push(Fixup {
+            at: self.code.bytes.len(),
+            end: 0,
+            symbol: symbol.to_string(),
+        });
+        self.bytes(&[0; 4]);
+    }
+    /// An opcode with the register in its low bits
+    fn short(&mut self, wide: bool, opcode: u8, reg: Reg) {
+        let rex = 0x40 | (wide as u8) << 3 | (reg as u8) >> 3;
+        if rex != 0x40 {
+            self.bytes(&[rex]);
+        }
+        self.bytes(&[opcode | (reg as u8 & 7)]);
+    }
+    /// `prefix`, a REX prefix if needed, `opcode` and the ModRM byte with
+    /// `reg` in its middle field addressing `rm`, then any SIB byte and
+    /// displacement
+    fn modrm(
+        &mut self,
+        prefix: Option<u8>,
+        wide: bool,
+        opcode: &[u8],
+        reg: u8,
+        rm: &Operand,
+    ) -> Option<()> {
+        let base = match rm {
+            Operand::Reg(reg) | Operand::Byte(reg) | Operand::Mem(reg, _) => *reg as u8,
+            Operand::Xmm(idx) => *idx,
+            Operand::Rip(_) => 0,
+            _ => return None,
+        };
+        if let Some(prefix) = prefix {
+            self.bytes(&[prefix]);
+        }
+        let rex = 0x40 | (wide as u8) << 3 | (reg >> 3) << 2 | base >> 3;
+        if rex != 0x40 || self.byte_rex {
+            self.bytes(&[rex]);
+        }
+        self.bytes(opcode);
+        let (reg, base) = ((reg & 7) << 3, base & 7);
+        match rm {
+            Operand::Mem(_, disp) => {
+                // A base of `%rbp` without displacement means `%rip`
+                let mode = match *disp {
+                    0 if base != 5 => 0x00,
+                    -128..=127 => 0x40,
+                    _ => 0x80,
+                };
+                self.bytes(&[mode | reg | base]);
+                // `%rsp` as the base needs a SIB byte
+                if base == 4 {
+                    self.bytes(&[0x24]);
+                }
+                match mode {
+                    0x40 => self.bytes(&[*disp as i8 as u8]),
+                    0x80 => self.bytes(&disp.to_le_bytes()),
+                    _ => {}
+                }
+            }
+            Operand::Rip(symbol) => {
+                self.bytes(&[reg | 5]);
+                self.fixup(symbol);
+            }
+            _ => self.bytes(&[0xC0 | reg | base]),
+        }
+        Some(())
+    }
+    /// `add`, `or`, `and`, `sub` or `cmp`, told apart by `digit`
+    fn alu(&mut self, digit: u8, operands: &[Operand]) -> Option<()> {
+        match operands {
+            [Operand::Imm(val), dst] if i8::try_from(*val).is_ok() => {
+                self.modrm(None, true, &[0x83], digit, dst)?;
+                self.imm8(*val)
+            }
+            // `%rax` has a shorter form
+            [Operand::Imm(val), Operand::Reg(Reg::Rax)] => {
+                self.bytes(&[0x48, digit << 3 | 5]);
+                self.imm32(*val)
+            }
+            [Operand::Imm(val), dst] => {
+                self.modrm(None, true, &[0x81], digit, dst)?;
+                self.imm32(*val)
+            }
+            [Operand::Reg(src), dst] => self.modrm(None, true, &[digit << 3 | 1], *src as u8, dst),
+            [src, Operand::Reg(dst)] if is_mem(src) => {
+                self.modrm(None, true, &[digit << 3 | 3], *dst as u8, src)
+            }
+            _ => None,
+        }
+    }
+    /// A double precision SSE instruction reading `src` into `dst`
+    fn sse(&mut self, prefix: u8, opcode: u8, src: &Operand, dst: &Operand) -> Option<()> {
+        match dst {
+            Operand::Xmm(dst) => self.modrm(Some(prefix), false, &[0x0F, opcode], *dst, src),
+            _ => None,
+        }
+    }
+    /// An x87 instruction with a double or 64-bit integer in memory
+    fn x87_mem(&mut self, opcode: u8, digit: u8, operands: &[Operand]) -> Option<()> {
+        match operands {
+            [mem] if is_mem(mem) => self.modrm(None, false, &[opcode], digit, mem),
+            _ => None,
+        }
+    }
+
+    fn inst(&mut self, opcode: Opcode, operands: &[Operand]) -> Option<()> {
+        match (opcode, operands) {
+            (Opcode::Push, [Operand::Reg(reg)]) => self.short(false, 0x50, *reg),
+            (Opcode::Push, [src]) if is_mem(src) => self.modrm(None, false, &[0xFF], 6, src)?,
+            (Opcode::Pop, [Operand::Reg(reg)]) => self.short(false, 0x58, *reg),
+            (Opcode::Pop, [dst]) if is_mem(dst) => self.modrm(None, false, &[0x8F], 0, dst)?,
+            (Opcode::Mov, [Operand::Xmm(src), dst]) if is_rm(dst) => {
+                self.modrm(Some(0x66), true, &[0x0F, 0x7E], *src, dst)?
+            }
+            (Opcode::Mov, [src, Operand::Xmm(dst)]) if is_rm(src) => {
+                self.modrm(Some(0x66), true, &[0x0F, 0x6E], *dst, src)?
+            }
+            (Opcode::Mov, [Operand::Reg(src), dst]) => {
+                self.modrm(None, true, &[0x89], *src as u8, dst)?
+            }
+            (Opcode::Mov, [src, Operand::Reg(dst)]) if is_mem(src) => {
+                self.modrm(None, true, &[0x8B], *dst as u8, src)?
+            }
+            (Opcode::Mov, [Operand::Imm(val), dst]) => {
+                self.modrm(None, true, &[0xC7], 0, dst)?;
+                self.imm32(*val)?
+            }
+            (Opcode::Movabs, [Operand::Imm(val), Operand::Reg(dst)]) => {
+                self.short(true, 0xB8, *dst);
+                self.bytes(&val.to_le_bytes());
+            }
+            (Opcode::Lea, [src, Operand::Reg(dst)]) if is_mem(src) => {
+                self.modrm(None, true, &[0x8D], *dst as u8, src)?
+            }
+            (Opcode::Add, _) => self.alu(0, operands)?,
+            (Opcode::Or, _) => self.alu(1, operands)?,
+            (Opcode::And, _) => self.alu(4, operands)?,
+            (Opcode::Sub, _) => self.alu(5, operands)?,
+            (Opcode::Cmp, _) => self.alu(7, operands)?,
+            (Opcode::Neg, [dst]) => self.modrm(None, true, &[0xF7], 3, dst)?,
+            (Opcode::Div, [src]) => self.modrm(None, true, &[0xF7], 6, src)?,
+            (Opcode::Test, [Operand::Reg(src), dst]) => {
+                self.modrm(None, true, &[0x85], *src as u8, dst)?
+            }
+            (Opcode::Test, [Operand::Imm(val), Operand::Reg(Reg::Rax)]) => {
+                self.bytes(&[0x48, 0xA9]);
+                self.imm32(*val)?
+            }
+            (Opcode::Test, [Operand::Imm(val), dst]) => {
+                self.modrm(None, true, &[0xF7], 0, dst)?;
+                self.imm32(*val)?
+            }
+            (Opcode::Btc | Opcode::Btr, [Operand::Imm(bit), dst]) => {
+                let digit = match opcode {
+                    Opcode::Btc => 7,
+                    _ => 6,
+                };
+                self.modrm(None, true, &[0x0F, 0xBA], digit, dst)?;
+                self.imm8(*bit)?
+            }
+            (Opcode::Andb, [Operand::Byte(src), dst]) => {
+                self.modrm(None, false, &[0x20], *src as u8, dst)?
+            }
+            (Opcode::Orb, [Operand::Byte(src), dst]) => {
+                self.modrm(None, false, &[0x08], *src as u8, dst)?
+            }
+            (Opcode::Movb, [Operand::Imm(val), dst]) => {
+                self.modrm(None, false, &[0xC6], 0, dst)?;
+                self.imm8(*val)?
+            }
+            (Opcode::Movb, [Operand::Byte(src), dst]) => {
+                self.modrm(None, false, &[0x88], *src as u8, dst)?
+            }
+            (Opcode::Movb, [src, Operand::Byte(dst)]) if is_mem(src) => {
+                self.modrm(None, false, &[0x8A], *dst as u8, src)?
+            }
+            (Opcode::Movzb, [src, Operand::Reg(dst)]) => {
+                self.modrm(None, true, &[0x0F, 0xB6], *dst as u8, src)?
+            }
+            (Opcode::Set(cond), [dst]) => {
+                self.modrm(None, false, &[0x0F, 0x90 | cond_code(cond)], 0, dst)?
+            }
+            (Opcode::Jmp, [Operand::Label(label)]) => {
+                self.bytes(&[0xE9]);
+                self.fixup(label);
+            }
+            (Opcode::Jmp, [target @ Operand::Reg(_)]) => {
+                self.modrm(None, false, &[0xFF], 4, target)?
+            }
+            (Opcode::J(cond), [Operand::Label(label)]) => {
+                self.bytes(&[0x0F, 0x80 | cond_code(cond)]);
+                self.fixup(label);
+            }
+            (Opcode::Call, [Operand::Label(label)]) => {
+                self.bytes(&[0xE8]);
+                self.fixup(label);
+            }
+            (Opcode::Call, [target @ Operand::Reg(_)]) => {
+                self.modrm(None, false, &[0xFF], 2, target)?
+            }
+            (Opcode::Leave, []) => self.bytes(&[0xC9]),
+            (Opcode::Ret, []) => self.bytes(&[0xC3]),
+            (Opcode::Syscall, []) => self.bytes(&[0x0F, 0x05]),
+            (Opcode::Movsd, [Operand::Xmm(src), dst]) if is_mem(dst) => {
+                self.modrm(Some(0xF2), false, &[0x0F, 0x11], *src, dst)?
+            }
+            (Opcode::Movsd, [src, dst]) => self.sse(0xF2, 0x10, src, dst)?,
+            (Opcode::Addsd, [src, dst]) => self.sse(0xF2, 0x58, src, dst)?,
+            (Opcode::Mulsd, [src, dst]) => self.sse(0xF2, 0x59, src, dst)?,
+            (Opcode::Subsd, [src, dst]) => self.sse(0xF2, 0x5C, src, dst)?,
+            (Opcode::Divsd, [src, dst]) => self.sse(0xF2, 0x5E, src, dst)?,
+            (Opcode::Sqrtsd, [src, dst]) => self.sse(0xF2, 0x51, src, dst)?,
+            (Opcode::Ucomisd, [src, dst]) => self.sse(0x66, 0x2E, src, dst)?,
+            (Opcode::Xorpd, [src, dst]) => self.sse(0x66, 0x57, src, dst)?,
+            (Opcode::Cvttsd2si, [src, Operand::Reg(dst)]) => {
+                self.modrm(Some(0xF2), true, &[0x0F, 0x2C], *dst as u8, src)?
+            }
+            (Opcode::Cvtsi2sd, [src, Operand::Xmm(dst)]) => {
+                self.modrm(Some(0xF2), true, &[0x0F, 0x2A], *dst, src)?
+            }
+            (Opcode::Fld, _) => self.x87_mem(0xDD, 0, operands)?,
+            (Opcode::Fst, _) => self.x87_mem(0xDD, 2, operands)?,
+            (Opcode::Fstp, _) => self.x87_mem(0xDD, 3, operands)?,
+            (Opcode::Fild, _) => self.x87_mem(0xDF, 5, operands)?,
+            (Opcode::Fistp, _) => self.x87_mem(0xDF, 7, operands)?,
+            (Opcode::Fadd, _) => self.x87_mem(0xDC, 0, operands)?,
+            (Opcode::Fmul, _) => self.x87_mem(0xDC, 1, operands)?,
+            (Opcode::Fsub, _) => self.x87_mem(0xDC, 4, operands)?,
+            (Opcode::Fsubr, _) => self.x87_mem(0xDC, 5, operands)?,
+            (Opcode::Fdiv, _) => self.x87_mem(0xDC, 6, operands)?,
+            (Opcode::FldSt, [Operand::St(idx @ 0..=7)]) => self.bytes(&[0xD9, 0xC0 | idx]),
+            (Opcode::FstpSt, [Operand::St(idx @ 0..=7)]) => self.bytes(&[0xDD, 0xD8 | idx]),
+            (Opcode::Fxch, [Operand::St(idx @ 0..=7)]) => self.bytes(&[0xD9, 0xC8 | idx]),
+            (Opcode::Faddp, [Operand::St(0), Operand::St(1)]) => self.bytes(&[0xDE, 0xC1]),
+            (Opcode::Fmulp, [Operand::St(0), Operand::St(1)]) => self.bytes(&[0xDE, 0xC9]),
+            (Opcode::Fdivp, [Operand::St(0), Operand::St(1)]) => self.bytes(&[0xDE, 0xF9]),
+            (Opcode::Fnstsw, []) => self.bytes(&[0xDF, 0xE0]),
+            (_, []) => {
+                let op = match opcode {
+                    Opcode::F2xm1 => 0xF0,
+                    Opcode::Fyl2x => 0xF1,
+                    Opcode::Fptan => 0xF2,
+                    Opcode::Fpatan => 0xF3,
+                    Opcode::Fchs => 0xE0,
+                    Opcode::Fld1 => 0xE8,
+                    Opcode::Fldl2e => 0xEA,
+                    Opcode::Fldpi => 0xEB,
+                    Opcode::Fldlg2 => 0xEC,
+                    Opcode::Fldln2 => 0xED,
+                    Opcode::Fprem => 0xF8,
+                    Opcode::Fsqrt => 0xFA,
+                    Opcode::Frndint => 0xFC,
+                    Opcode::Fscale => 0xFD,
+                    Opcode::Fsin => 0xFE,
+                    Opcode::Fcos => 0xFF,
+                    _ => return None,
+                };
+                self.bytes(&[0xD9, op]);
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+}
@@ -0,0 +1,382 @@
+use crate::ast::value::Value;
+use crate::codegen::asm::generate;
+use crate::codegen::asm::is_set;
+use crate::codegen::asm::symbol;
+use crate::codegen::asm::variable;
+use crate::codegen::asm::Codegen;
+use crate::codegen::asm::Target;
+use crate::codegen::encode::encode;
+use crate::codegen::x86::Opcode;
+use crate::codegen::x86::Operand;
+use crate::codegen::x86::Reg;
+use crate::codegen::x86::ARG_XMMS;
+use crate::ir::ir::Function;
+use crate::ir::ir::Module;
+use crate::ir::ir::Ty;
+use crate::ir::ir::MAIN;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+const PAGE: usize = 0x1000;
+
+thread_local! {
+    /// Message of the runtime error that stopped the last call on this thread
+    static ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Called by the code with the message a compiled program would print
+extern "C" fn record_error(text: *const u8, len: usize) {
+    // SAFETY: the code passes one of its own strings and its length
+    let text = unsafe { std::slice::from_raw_parts(text, len) };
+    let text = String::from_utf8_lossy(text);
+    let text = text.trim_end();
+    let message = text.strip_prefix("error: ").unwrap_or(text).to_string();
+    ERROR.with(|error| *error.borrow_mut() = Some(message));
+}
+
+/// The runtime error that stopped the last compiled call on this thread
+pub fn take_error() -> Option<String> {
+    ERROR.with(|error| error.borrow_mut().take())
+}
+
+/// Wrap the `f64` methods behind the libm functions the code calls as C
+/// functions, returning the address of `$name`'s
+macro_rules! routines {
+    ($name:expr, $($routine:ident($($arg:ident),+) => $body:expr),+ $(,)?) => {
+        match $name {
+            $(stringify!($routine) => {
+                extern "C" fn $routine($($arg: f64),+) -> f64 {
+                    $body
+                }
+                $routine as *const () as usize
+            })+
+            _ => unreachable!("{0} isn't a libm function", $name),
+        }
+    };
+}
+
+/// Address of the maths the interpreter does for a libm function, so
+/// compiled code gets the same results to the bit
+fn routine(name: &str) -> usize {
+    routines!(
+        name,
+        sin(x) => x.sin(),
+        cos(x) => x.cos(),
+        tan(x) => x.tan(),
+        asin(x) => x.asin(),
+        acos(x) => x.acos(),
+        atan(x) => x.atan(),
+        sinh(x) => x.sinh(),
+        cosh(x) => x.cosh(),
+        tanh(x) => x.tanh(),
+        floor(x) => x.floor(),
+        ceil(x) => x.ceil(),
+        round(x) => x.round(),
+        log(x) => x.ln(),
+        pow(x, y) => x.powf(y),
+        fmod(x, y) => x % y,
+    )
+}
+
+fn trampoline(name: &str) -> String {
+    format!("ape_jit_{name}")
+}
+
+/// Whether Rust can call the function, taking numbers in registers and
+/// returning one
+fn callable(func: &Function) -> bool {
+    func.name != MAIN
+        && func.ret == Ty::Num
+        && func.params.len() <= ARG_XMMS as usize
+        && func.params.iter().all(|&param| param == Ty::Num)
+}
+
+// Entry points and runtime for code run in process, errors unwind to the
+// entry point and maths calls back into Rust
+impl Codegen {
+    /// `ape_jit_<name>` for `main` and every callable function, keeping
+    /// `%rsp` in `%rbx` for `jit_fail` to unwind to. Nothing else touches
+    /// `%rbx` and calls preserve it
+    pub fn trampolines(&mut self, functions: &[Function]) {
+        for func in functions {
+            if func.name != MAIN && !callable(func) {
+                continue;
+            }
+            self.label(trampoline(&func.name));
+            self.op(Opcode::Push, vec![Operand::Reg(Reg::Rbx)]);
+            self.mov(Operand::Reg(Reg::Rsp), Operand::Reg(Reg::Rbx));
+            self.call(&symbol(&func.name));
+            self.op(Opcode::Pop, vec![Operand::Reg(Reg::Rbx)]);
+            self.op(Opcode::Ret, Vec::new());
+        }
+    }
+    /// Hand the message in `%rdi` of length `%rsi` to `record_error` and
+    /// return NaN from the trampoline
+    pub fn jit_fail(&mut self) {
+        self.label(String::from("ape_rt_fail"));
+        self.mov(Operand::Reg(Reg::Rbx), Operand::Reg(Reg::Rsp));
+        self.op(
+            Opcode::Movabs,
+            vec![
+                Operand::Imm(record_error as *const () as usize as i64),
+                Operand::Reg(Reg::Rax),
+            ],
+        );
+        self.op(Opcode::Call, vec![Operand::Reg(Reg::Rax)]);
+        self.number(f64::NAN, 0);
+        self.op(Opcode::Pop, vec![Operand::Reg(Reg::Rbx)]);
+        self.op(Opcode::Ret, Vec::new());
+    }
+    /// Jump to Rust's version of a libm function, which may be too far
+    /// away for a call to reach
+    pub fn jit_math(&mut self, name: &str) {
+        self.label(format!("ape_rt_{name}"));
+        self.op(
+            Opcode::Movabs,
+            vec![Operand::Imm(routine(name) as i64), Operand::Reg(Reg::Rax)],
+        );
+        self.op(Opcode::Jmp, vec![Operand::Reg(Reg::Rax)]);
+    }
+}
+
+/// A C function pointer taking and returning numbers
+pub trait NativeFn: Copy {
+    const ARITY: usize;
+    /// # Safety
+    /// `address` has to be the entry of a function with this signature
+    unsafe fn from_address(address: usize) -> Self;
+}
+
+macro_rules! native_fn {
+    ($arity:literal $(, $arg:ty)*) => {
+        impl NativeFn for extern "C" fn($($arg),*) -> f64 {
+            const ARITY: usize = $arity;
+            unsafe fn from_address(address: usize) -> Self {
+                std::mem::transmute::<usize, Self>(address)
+            }
+        }
+    };
+}
+
+native_fn!(0);
+native_fn!(1, f64);
+native_fn!(2, f64, f64);
+native_fn!(3, f64, f64, f64);
+native_fn!(4, f64, f64, f64, f64);
+native_fn!(5, f64, f64, f64, f64, f64);
+native_fn!(6, f64, f64, f64, f64, f64, f64);
+native_fn!(7, f64, f64, f64, f64, f64, f64, f64);
+native_fn!(8, f64, f64, f64, f64, f64, f64, f64, f64);
+
+/// A module compiled into executable memory of this process
+///
+/// The code and its strings are mapped read only, the variables writable
+/// after them. Calls to maths and runtime errors go through Rust, so
+/// results agree with the interpreter's
+pub struct Jit {
+    memory: *mut u8,
+    size: usize,
+    /// Address of every label and variable
+    symbols: HashMap<String, usize>,
+    module: Module,
+}
+
+impl Jit {
+    pub fn new(module: &Module) -> Result<Jit, String> {
+        let asm = generate(module, Target::Jit);
+        let mut code = encode(&asm.text)?;
+        let mut offsets = HashMap::new();
+        let mut rodata = Vec::new();
+        for (label, text) in &asm.strings {
+            offsets.insert(label.clone(), code.bytes.len() + rodata.len());
+            rodata.extend_from_slice(text.as_bytes());
+            rodata.push(0);
+        }
+        let text_size = (code.bytes.len() + rodata.len()).next_multiple_of(PAGE);
+        for (idx, label) in asm.data.iter().enumerate() {
+            offsets.insert(label.clone(), text_size + 8 * idx);
+        }
+        let size = text_size + (8 * asm.data.len()).next_multiple_of(PAGE);
+
+        let memory = map(size)?;
+        let mut jit = Jit {
+            memory,
+            size,
+            symbols: HashMap::new(),
+            module: module.clone(),
+        };
+        let base = memory as usize;
+        code.resolve(base as u64, |symbol| {
+            offsets.get(symbol).map(|&offset| (base + offset) as u64)
+        })?;
+        // SAFETY: both fit in the text pages just mapped
+        unsafe {
+            std::ptr::copy_nonoverlapping(code.bytes.as_ptr(), memory, code.bytes.len());
+            std::ptr::copy_nonoverlapping(
+                rodata.as_ptr(),
+                memory.add(code.bytes.len()),
+                rodata.len(),
+            );
+        }
+        protect(memory, text_size)?;
+        jit.symbols = code
+            .labels
+            .into_iter()
+            .chain(offsets)
+            .map(|(label, offset)| (label, base + offset))
+            .collect();
+        Ok(jit)
+    }
+    /// Run the top level statements, giving what the interpreter would or
+    /// the runtime error it would stop with
+    pub fn run(&self) -> Result<Value, String> {
+        let entry = self.symbols[&trampoline(MAIN)];
+        let ret = self.module.function(MAIN).map_or(Ty::Unit, |main| main.ret);
+        take_error();
+        // SAFETY: `main` takes nothing and returns a value of type `ret`
+        let val = unsafe {
+            match ret {
+                Ty::Num => {
+                    let main = std::mem::transmute::<usize, extern "C" fn() -> f64>(entry);
+                    Value::Number(main())
+                }
+                Ty::Bool => {
+                    let main = std::mem::transmute::<usize, extern "C" fn() -> u64>(entry);
+                    Value::Bool(main() != 0)
+                }
+                Ty::Unit => {
+                    let main = std::mem::transmute::<usize, extern "C" fn()>(entry);
+                    main();
+                    Value::Unit
+                }
+            }
+        };
+        match take_error() {
+            Some(err) => Err(err),
+            None => Ok(val),
+        }
+    }
+    /// Value of a global once the code has stored to it
+    pub fn global(&self, name: &str) -> Option<Value> {
+        let global = self.module.global(name)?;
+        // SAFETY: variables are 8 bytes in the writable pages
+        let read = |label: String| unsafe { *(self.symbols[&label] as *const u64) };
+        if read(is_set(name)) == 0 {
+            return None;
+        }
+        let bits = read(variable(name));
+        Some(match global.ty {
+            Ty::Num => Value::Number(f64::from_bits(bits)),
+            Ty::Bool => Value::Bool(bits != 0),
+            Ty::Unit => Value::Unit,
+        })
+    }
+    /// A function as a C function pointer
+    ///
+    /// A runtime error makes it return NaN, `take_error` then gives the
+    /// message
+    ///
+    /// # Safety
+    /// The pointer points into memory this owns, it mustn't be called once
+    /// this is dropped
+    pub unsafe fn function<F: NativeFn>(&self, name: &str) -> Result<F, String> {
+        let func = self
+            .module
+            .function(name)
+            .filter(|func| func.name != MAIN)
+            .ok_or_else(|| format!("{name} isn't defined in this program"))?;
+        if !callable(func) {
+            return Err(format!(
+                "{name} can't be called natively, only functions taking and returning numbers can"
+            ));
+        }
+        if func.params.len() != F::ARITY {
+            return Err(format!(
+                "{name} takes {0} arguments, not {1}",
+                func.params.len(),
+                F::ARITY
+            ));
+        }
+        // SAFETY: the trampoline takes `ARITY` numbers and returns one
+        Ok(unsafe { F::from_address(self.symbols[&trampoline(name)]) })
+    }
+}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        unmap(self.memory, self.size);
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+extern "C" {
+    fn mmap(addr: *mut u8, len: usize, prot: i32, flags: i32, fd: i32, offset: i64) -> *mut u8;
+    fn mprotect(addr: *mut u8, len: usize, prot: i32) -> i32;
+    fn munmap(addr: *mut u8, len: usize) -> i32;
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+const PROT_READ: i32 = 1;
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+const PROT_WRITE: i32 = 2;
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+const PROT_EXEC: i32 = 4;
+
+/// Zeroed pages that can be read and written
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn map(size: usize) -> Result<*mut u8, String> {
+    const MAP_PRIVATE: i32 = 0x02;
+    const MAP_ANONYMOUS: i32 = 0x20;
+    // SAFETY: a new anonymous mapping doesn't alias anything
+    let memory = unsafe {
+        mmap(
+            std::ptr::null_mut(),
+            size,
+            PROT_READ | PROT_WRITE,
+            MAP_PRIVATE | MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+    };
+    match memory as isize {
+        -1 => Err(format!(
+            "Failed to map memory: {0}",
+            std::io::Error::last_os_error()
+        )),
+        _ => Ok(memory),
+    }
+}
+
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+fn map(_size: usize) -> Result<*mut u8, String> {
+    Err(String::from("Native code is only run on x86-64 Linux"))
+}
+
+/// Make the first `size` bytes executable and no longer writable
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn protect(memory: *mut u8, size: usize) -> Result<(), String> {
+    // SAFETY: the pages belong to a mapping made by `map`
+    match unsafe { mprotect(memory, size, PROT_READ | PROT_EXEC) } {
+        0 => Ok(()),
+        _ => Err(format!(
+            "Failed to protect memory: {0}",
+            std::io::Error::last_os_error()
+        )),
+    }
+}
+
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+fn protect(_memory: *mut u8, _size: usize) -> Result<(), String> {
+    unreachable!("nothing is mapped")
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn unmap(memory: *mut u8, size: usize) {
+    // SAFETY: nothing refers to the mapping once its `Jit` is dropped
+    unsafe {
+        munmap(memory, size);
+    }
+}
+
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+fn unmap(_memory: *mut u8, _size: usize) {}
@@ -0,0 +1,519 @@
+use crate::ast::ast::BinaryOp;
+use crate::ast::ast::Builtin;
+use crate::ast::ast::UnaryOp;
+use crate::ast::error::RuntimeError;
+use crate::codegen::asm::is_set;
+use crate::codegen::asm::symbol;
+use crate::codegen::asm::variable;
+use crate::ir::ir::Constant;
+use crate::ir::ir::Function;
+use crate::ir::ir::Inst;
+use crate::ir::ir::Module;
+use crate::ir::ir::Op;
+use crate::ir::ir::Terminator;
+use crate::ir::ir::Ty;
+use crate::ir::ir::ValueId;
+use crate::ir::ir::MAIN;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Compile a verified module to textual LLVM IR
+///
+/// Functions keep their `ape_<name>` symbols and calling convention of the
+/// assembly backend, and `main` prints the result like it does. Numbers are
+/// `double`, booleans `i1` and units `{}`. Builtins become intrinsics where
+/// LLVM has them and libm calls otherwise
+pub fn to_llvm(module: &Module) -> String {
+    let mut gen = Llvm {
+        out: String::new(),
+        strings: Vec::new(),
+        declarations: BTreeSet::new(),
+        fact: false,
+        check: false,
+        print: false,
+    };
+    for global in &module.globals {
+        if global.ty != Ty::Unit {
+            let zero = match global.ty {
+                Ty::Num => "0.0",
+                _ => "false",
+            };
+            gen.line(&format!(
+                "@{0} = internal global {1} {zero}",
+                variable(&global.name),
+                ty(global.ty)
+            ));
+        }
+        gen.line(&format!(
+            "@{0} = internal global i1 false",
+            is_set(&global.name)
+        ));
+    }
+    if !module.globals.is_empty() {
+        gen.line("");
+    }
+    for func in &module.functions {
+        gen.function(func);
+    }
+    let ret = module.function(MAIN).map_or(Ty::Unit, |main| main.ret);
+    gen.entry(ret);
+    gen.runtime();
+
+    let mut text = String::new();
+    for (label, bytes) in &gen.strings {
+        writeln!(
+            text,
+            "@{label} = private unnamed_addr constant [{0} x i8] c\"{1}\"",
+            bytes.len(),
+            escaped(bytes)
+        )
+        .unwrap();
+    }
+    if !gen.strings.is_empty() {
+        text.push('\n');
+    }
+    text.push_str(&gen.out);
+    for declaration in &gen.declarations {
+        writeln!(text, "{declaration}").unwrap();
+    }
+    text
+}
+
+fn ty(ty: Ty) -> &'static str {
+    match ty {
+        Ty::Num => "double",
+        Ty::Bool => "i1",
+        Ty::Unit => "{}",
+    }
+}
+
+/// A double as LLVM reads it back exactly, in hex unless it's a small
+/// whole number
+fn number(val: f64) -> String {
+    if val.fract() == 0.0 && val.abs() < 1e15 && !(val == 0.0 && val.is_sign_negative()) {
+        format!("{val:.1}")
+    } else {
+        format!("0x{0:016X}", val.to_bits())
+    }
+}
+
+/// Bytes of a `c"..."` string, anything but printable ASCII as `\XX`
+fn escaped(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => write!(out, "\\{byte:02X}").unwrap(),
+            b' '..=b'~' => out.push(byte as char),
+            _ => write!(out, "\\{byte:02X}").unwrap(),
+        }
+    }
+    out
+}
+
+/// Name of the intrinsic for a builtin, `None` for those only libm has
+fn intrinsic(builtin: Builtin) -> Option<&'static str> {
+    match builtin {
+        Builtin::Sin => Some("llvm.sin.f64"),
+        Builtin::Cos => Some("llvm.cos.f64"),
+        Builtin::Sqrt => Some("llvm.sqrt.f64"),
+        Builtin::Abs => Some("llvm.fabs.f64"),
+        Builtin::Floor => Some("llvm.floor.f64"),
+        Builtin::Ceil => Some("llvm.ceil.f64"),
+        Builtin::Round => Some("llvm.round.f64"),
+        _ => None,
+    }
+}
+
+struct Llvm {
+    out: String,
+    /// Constant byte strings by label
+    strings: Vec<(String, Vec<u8>)>,
+    /// External functions and intrinsics called
+    declarations: BTreeSet<String>,
+    /// Runtime functions the program needs
+    fact: bool,
+    check: bool,
+    print: bool,
+}
+
+/// Operand text of each value, constants and units written in place
+struct Values {
+    names: HashMap<ValueId, String>,
+}
+
+impl Values {
+    fn get(&self, val: ValueId) -> String {
+        self.names
+            .get(&val)
+            .cloned()
+            .unwrap_or_else(|| format!("%v{0}", val.0))
+    }
+}
+
+impl Llvm {
+    fn line(&mut self, line: &str) {
+        self.out.push_str(line);
+        self.out.push('\n');
+    }
+    fn inst(&mut self, inst: &str) {
+        self.line(&format!("  {inst}"));
+    }
+    fn declare(&mut self, declaration: &str) {
+        self.declarations.insert(declaration.to_string());
+    }
+    fn string(&mut self, label: &str, bytes: &[u8]) {
+        if !self.strings.iter().any(|(known, _)| known == label) {
+            self.strings.push((label.to_string(), bytes.to_vec()));
+        }
+    }
+    /// Stop with the error unless `ok` holds, as the interpreter would
+    fn check(&mut self, ok: &str, label: String, err: RuntimeError) {
+        let text = format!("error: {err}\n");
+        self.inst(&format!(
+            "call void @ape_rt_check(i1 {ok}, ptr @{label}, i64 {0})",
+            text.len()
+        ));
+        self.string(&label, text.as_bytes());
+        self.check = true;
+    }
+    /// Call an intrinsic or libm function on doubles
+    fn call_math(&mut self, dst: &str, name: &str, args: &[String]) {
+        let params = vec!["double"; args.len()].join(", ");
+        self.declare(&format!("declare double @{name}({params})"));
+        let args: Vec<String> = args.iter().map(|arg| format!("double {arg}")).collect();
+        self.inst(&format!(
+            "{dst} = call double @{name}({0})",
+            args.join(", ")
+        ));
+    }
+
+    fn function(&mut self, func: &Function) {
+        let mut values = Values {
+            names: HashMap::new(),
+        };
+        for (idx, &param) in func.params.iter().enumerate() {
+            if param == Ty::Unit {
+                values
+                    .names
+                    .insert(ValueId(idx as u32), String::from("zeroinitializer"));
+            }
+        }
+        for block in &func.blocks {
+            for inst in &block.insts {
+                if let Inst::Define(val, op) = inst {
+                    let name = match op {
+                        _ if func.ty(*val) == Ty::Unit => String::from("zeroinitializer"),
+                        Op::Const(Constant::Num(num)) => number(*num),
+                        Op::Const(Constant::Bool(b)) => b.to_string(),
+                        // Units are all equal
+                        Op::Binary(op, left, _) if func.ty(*left) == Ty::Unit => {
+                            (*op == BinaryOp::Equal).to_string()
+                        }
+                        _ => continue,
+                    };
+                    values.names.insert(*val, name);
+                }
+            }
+        }
+
+        let params: Vec<String> = func
+            .params
+            .iter()
+            .enumerate()
+            .map(|(idx, &param)| format!("{0} %v{idx}", ty(param)))
+            .collect();
+        self.line(&format!(
+            "define {0} @{1}({2}) {{",
+            ty(func.ret),
+            symbol(&func.name),
+            params.join(", ")
+        ));
+        for (idx, block) in func.blocks.iter().enumerate() {
+            self.line(&format!("b{idx}:"));
+            for inst in &block.insts {
+                match inst {
+                    Inst::Define(val, op) => self.define(func, &values, *val, op),
+                    Inst::Store(name, val) => {
+                        let val_ty = func.ty(*val);
+                        if val_ty != Ty::Unit {
+                            self.inst(&format!(
+                                "store {0} {1}, ptr @{2}",
+                                ty(val_ty),
+                                values.get(*val),
+                                variable(name)
+                            ));
+                        }
+                        self.inst(&format!("store i1 true, ptr @{0}", is_set(name)));
+                    }
+                }
+            }
+            let term = match block.term {
+                Terminator::Jump(to) => format!("br label %{to}"),
+                Terminator::Branch(cond, then_block, else_block) => format!(
+                    "br i1 {0}, label %{then_block}, label %{else_block}",
+                    values.get(cond)
+                ),
+                Terminator::Return(val) => {
+                    format!("ret {0} {1}", ty(func.ret), values.get(val))
+                }
+            };
+            self.inst(&term);
+        }
+        self.line("}");
+        self.line("");
+    }
+
+    fn define(&mut self, func: &Function, values: &Values, val: ValueId, op: &Op) {
+        let dst = format!("%v{0}", val.0);
+        let val_ty = func.ty(val);
+        match op {
+            Op::Const(_) => {}
+            Op::Binary(op, left, right) => {
+                let operand_ty = func.ty(*left);
+                let (left, right) = (values.get(*left), values.get(*right));
+                match operand_ty {
+                    Ty::Unit => {}
+                    Ty::Bool => {
+                        let cond = match op {
+                            BinaryOp::Equal => "eq",
+                            _ => "ne",
+                        };
+                        self.inst(&format!("{dst} = icmp {cond} i1 {left}, {right}"));
+                    }
+                    Ty::Num => self.binary(&dst, *op, &left, &right),
+                }
+            }
+            Op::Unary(op, operand) => {
+                let operand = values.get(*operand);
+                match op {
+                    UnaryOp::Neg => self.inst(&format!("{dst} = fneg double {operand}")),
+                    UnaryOp::Not => {
+                        // `~x` is `-(x + 1)`
+                        self.inst(&format!("{dst}.inc = fadd double {operand}, 1.0"));
+                        self.inst(&format!("{dst} = fneg double {dst}.inc"));
+                    }
+                    UnaryOp::Factorial => {
+                        self.inst(&format!(
+                            "{dst} = call double @ape_rt_fact(double {operand})"
+                        ));
+                        self.fact = true;
+                    }
+                }
+            }
+            Op::Builtin(builtin, args) => {
+                let args: Vec<String> = args.iter().map(|&arg| values.get(arg)).collect();
+                self.builtin(&dst, *builtin, &args);
+            }
+            Op::Call(name, args) => {
+                let args: Vec<String> = args
+                    .iter()
+                    .map(|&arg| format!("{0} {1}", ty(func.ty(arg)), values.get(arg)))
+                    .collect();
+                let call = format!(
+                    "call {0} @{1}({2})",
+                    ty(val_ty),
+                    symbol(name),
+                    args.join(", ")
+                );
+                match val_ty {
+                    Ty::Unit => self.inst(&call),
+                    _ => self.inst(&format!("{dst} = {call}")),
+                }
+            }
+            Op::Load(name) => {
+                self.inst(&format!("{dst}.set = load i1, ptr @{0}", is_set(name)));
+                self.check(
+                    &format!("{dst}.set"),
+                    format!(".msg_var_{name}"),
+                    RuntimeError::UndeclaredVariable(name.clone()),
+                );
+                if val_ty != Ty::Unit {
+                    self.inst(&format!(
+                        "{dst} = load {0}, ptr @{1}",
+                        ty(val_ty),
+                        variable(name)
+                    ));
+                }
+            }
+            Op::Phi(incoming) => {
+                if val_ty != Ty::Unit {
+                    let incoming: Vec<String> = incoming
+                        .iter()
+                        .map(|&(src, block)| format!("[ {0}, %{block} ]", values.get(src)))
+                        .collect();
+                    self.inst(&format!(
+                        "{dst} = phi {0} {1}",
+                        ty(val_ty),
+                        incoming.join(", ")
+                    ));
+                }
+            }
+        }
+    }
+
+    fn binary(&mut self, dst: &str, op: BinaryOp, left: &str, right: &str) {
+        let inst = match op {
+            BinaryOp::Add => "fadd double",
+            BinaryOp::Sub => "fsub double",
+            BinaryOp::Mul => "fmul double",
+            BinaryOp::Div => {
+                // Unordered means a NaN divisor, which isn't zero
+                self.inst(&format!("{dst}.ok = fcmp une double {right}, 0.0"));
+                self.check(
+                    &format!("{dst}.ok"),
+                    String::from(".msg_div"),
+                    RuntimeError::DivisionByZero,
+                );
+                "fdiv double"
+            }
+            BinaryOp::Mod => "frem double",
+            BinaryOp::Pow => {
+                self.call_math(dst, "llvm.pow.f64", &[left.to_string(), right.to_string()]);
+                return;
+            }
+            BinaryOp::Less => "fcmp olt double",
+            BinaryOp::LessEqual => "fcmp ole double",
+            BinaryOp::Greater => "fcmp ogt double",
+            BinaryOp::GreaterEqual => "fcmp oge double",
+            BinaryOp::Equal => "fcmp oeq double",
+            BinaryOp::NotEqual => "fcmp une double",
+        };
+        self.inst(&format!("{dst} = {inst} {left}, {right}"));
+    }
+
+    fn builtin(&mut self, dst: &str, builtin: Builtin, args: &[String]) {
+        match builtin {
+            Builtin::Log => {
+                // ln(x) / ln(base) as the interpreter computes it
+                let base = args.get(1).cloned().unwrap_or_else(|| number(10.0));
+                self.call_math(&format!("{dst}.num"), "llvm.log.f64", &args[..1]);
+                self.call_math(&format!("{dst}.den"), "llvm.log.f64", &[base]);
+                self.inst(&format!("{dst} = fdiv double {dst}.num, {dst}.den"));
+            }
+            Builtin::Exp => {
+                let e = number(std::f64::consts::E);
+                self.call_math(dst, "llvm.pow.f64", &[e, args[0].clone()]);
+            }
+            _ => match intrinsic(builtin) {
+                Some(name) => self.call_math(dst, name, args),
+                None => self.call_math(dst, builtin.name(), args),
+            },
+        }
+    }
+
+    /// C's `main`, printing what `ape_main` returns like the interpreter
+    fn entry(&mut self, ret: Ty) {
+        self.line("define i32 @main() {");
+        self.line("entry:");
+        match ret {
+            Ty::Num => {
+                self.inst("%result = call double @ape_main()");
+                self.inst("call void @ape_rt_print(double %result)");
+                self.print = true;
+            }
+            Ty::Bool => {
+                self.string(".true", b"true\0");
+                self.string(".false", b"false\0");
+                self.inst("%result = call i1 @ape_main()");
+                self.inst("%text = select i1 %result, ptr @.true, ptr @.false");
+                self.inst("%written = call i32 @puts(ptr %text)");
+                self.declare("declare i32 @puts(ptr)");
+            }
+            Ty::Unit => {
+                self.string(".unit", b"()\0");
+                self.inst("call {} @ape_main()");
+                self.inst("%written = call i32 @puts(ptr @.unit)");
+                self.declare("declare i32 @puts(ptr)");
+            }
+        }
+        self.inst("ret i32 0");
+        self.line("}");
+    }
+
+    /// Functions the generated code calls, only those it uses
+    fn runtime(&mut self) {
+        if self.print {
+            // The first of `%.15g`, `%.16g` and `%.17g` that reads back as
+            // the same number
+            self.string(".format", b"%.*g\0");
+            self.string(".nan", b"NaN\0");
+            self.line("");
+            self.line("define internal void @ape_rt_print(double %x) {");
+            self.line("entry:");
+            self.inst("%buffer = alloca [32 x i8]");
+            self.inst("%nan = fcmp uno double %x, 0.0");
+            self.inst("br i1 %nan, label %print_nan, label %try");
+            self.line("try:");
+            self.inst("%precision = phi i32 [ 15, %entry ], [ %next, %try ]");
+            self.inst("%length = call i32 (ptr, i64, ptr, ...) @snprintf(ptr %buffer, i64 32, ptr @.format, i32 %precision, double %x)");
+            self.inst("%back = call double @strtod(ptr %buffer, ptr null)");
+            self.inst("%next = add i32 %precision, 1");
+            self.inst("%same = fcmp oeq double %back, %x");
+            self.inst("br i1 %same, label %print, label %try");
+            self.line("print:");
+            self.inst("%written = call i32 @puts(ptr %buffer)");
+            self.inst("ret void");
+            self.line("print_nan:");
+            self.inst("%written_nan = call i32 @puts(ptr @.nan)");
+            self.inst("ret void");
+            self.line("}");
+            self.declare("declare i32 @puts(ptr)");
+            self.declare("declare i32 @snprintf(ptr, i64, ptr, ...)");
+            self.declare("declare double @strtod(ptr, ptr)");
+        }
+        if self.fact {
+            // NaN for negative numbers, otherwise the product up to the
+            // number truncated, which is 1 for NaN and overflows past 170
+            self.line("");
+            self.line("define internal double @ape_rt_fact(double %n) {");
+            self.line("entry:");
+            self.inst("%negative = fcmp olt double %n, 0.0");
+            self.inst("br i1 %negative, label %nan, label %check");
+            self.line("nan:");
+            self.inst(&format!("ret double {0}", number(f64::NAN)));
+            self.line("check:");
+            self.inst("%huge = fcmp oge double %n, 171.0");
+            self.inst("br i1 %huge, label %infinite, label %start");
+            self.line("infinite:");
+            self.inst(&format!("ret double {0}", number(f64::INFINITY)));
+            self.line("start:");
+            self.inst("%ordered = fcmp ord double %n, 0.0");
+            self.inst("%count = select i1 %ordered, double %n, double 0.0");
+            self.inst("%last = fptoui double %count to i64");
+            self.inst("br label %loop");
+            self.line("loop:");
+            self.inst("%factor = phi i64 [ 1, %start ], [ %next, %multiply ]");
+            self.inst("%product = phi double [ 1.0, %start ], [ %times, %multiply ]");
+            self.inst("%done = icmp ugt i64 %factor, %last");
+            self.inst("br i1 %done, label %exit, label %multiply");
+            self.line("multiply:");
+            self.inst("%float = uitofp i64 %factor to double");
+            self.inst("%times = fmul double %product, %float");
+            self.inst("%next = add i64 %factor, 1");
+            self.inst("br label %loop");
+            self.line("exit:");
+            self.inst("ret double %product");
+            self.line("}");
+        }
+        if self.check {
+            // Write the message to stderr and exit with status 1
+            self.line("");
+            self.line("define internal void @ape_rt_check(i1 %ok, ptr %message, i64 %length) {");
+            self.line("entry:");
+            self.inst("br i1 %ok, label %done, label %fail");
+            self.line("fail:");
+            self.inst("%written = call i64 @write(i32 2, ptr %message, i64 %length)");
+            self.inst("call void @exit(i32 1)");
+            self.inst("unreachable");
+            self.line("done:");
+            self.inst("ret void");
+            self.line("}");
+            self.declare("declare i64 @write(i32, ptr, i64)");
+            self.declare("declare void @exit(i32)");
+        }
+        if !self.declarations.is_empty() {
+            self.line("");
+        }
+    }
+}
@@ -0,0 +1,9 @@
+pub mod asm;
+pub mod bare;
+pub mod c;
+pub mod elf;
+pub mod encode;
+pub mod jit;
+pub mod llvm;
+pub mod wasm;
+pub mod x86;
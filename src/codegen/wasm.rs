@@ -0,0 +1,709 @@
+use crate::ast::ast::BinaryOp;
+use crate::ast::ast::Builtin;
+use crate::ast::ast::UnaryOp;
+use crate::ast::error::RuntimeError;
+use crate::ir::ir::Constant;
+use crate::ir::ir::Function;
+use crate::ir::ir::Inst;
+use crate::ir::ir::Module;
+use crate::ir::ir::Op;
+use crate::ir::ir::Terminator;
+use crate::ir::ir::Ty;
+use crate::ir::ir::ValueId;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+/// Module maths functions are imported from
+pub const MATH: &str = "math";
+/// Module and name of the function a program calls with the offset and
+/// length in memory of a runtime error's message, it doesn't return
+pub const ERROR: (&str, &str) = ("env", "error");
+/// Name the module's memory is exported under
+pub const MEMORY: &str = "memory";
+
+const I32: u8 = 0x7F;
+const F64: u8 = 0x7C;
+const EMPTY: u8 = 0x40;
+
+const UNREACHABLE: u8 = 0x00;
+const BLOCK: u8 = 0x02;
+const LOOP: u8 = 0x03;
+const IF: u8 = 0x04;
+const ELSE: u8 = 0x05;
+const END: u8 = 0x0B;
+const BR: u8 = 0x0C;
+const BR_IF: u8 = 0x0D;
+const BR_TABLE: u8 = 0x0E;
+const RETURN: u8 = 0x0F;
+const CALL: u8 = 0x10;
+const LOCAL_GET: u8 = 0x20;
+const LOCAL_SET: u8 = 0x21;
+const GLOBAL_GET: u8 = 0x23;
+const GLOBAL_SET: u8 = 0x24;
+const I32_CONST: u8 = 0x41;
+const F64_CONST: u8 = 0x44;
+const I32_EQZ: u8 = 0x45;
+const I32_EQ: u8 = 0x46;
+const I32_NE: u8 = 0x47;
+const F64_EQ: u8 = 0x61;
+const F64_NE: u8 = 0x62;
+const F64_LT: u8 = 0x63;
+const F64_GT: u8 = 0x64;
+const F64_LE: u8 = 0x65;
+const F64_GE: u8 = 0x66;
+const F64_ABS: u8 = 0x99;
+const F64_NEG: u8 = 0x9A;
+const F64_CEIL: u8 = 0x9B;
+const F64_FLOOR: u8 = 0x9C;
+const F64_SQRT: u8 = 0x9F;
+const F64_ADD: u8 = 0xA0;
+const F64_SUB: u8 = 0xA1;
+const F64_MUL: u8 = 0xA2;
+const F64_DIV: u8 = 0xA3;
+
+/// Compile a verified module to a binary WebAssembly module
+///
+/// Every function is exported under its own name, the top level as
+/// `main`. Numbers are `f64` and booleans `i32`, units have no value at
+/// all so they're left out of signatures. Builtins wasm has instructions
+/// for use them, the rest are imported from `math` along with `pow` and
+/// `fmod`, and `log` there is the natural logarithm. The memory only holds
+/// the messages of runtime errors
+pub fn to_wasm(module: &Module) -> Result<Vec<u8>, String> {
+    if module.function(MEMORY).is_some() {
+        return Err(format!("{MEMORY} is reserved for the module's memory"));
+    }
+    let mut imports = BTreeSet::new();
+    let mut fact = false;
+    for func in &module.functions {
+        for block in &func.blocks {
+            for inst in &block.insts {
+                if let Inst::Define(_, op) = inst {
+                    match op {
+                        Op::Binary(BinaryOp::Pow, _, _) => {
+                            imports.insert("pow");
+                        }
+                        Op::Binary(BinaryOp::Mod, _, _) => {
+                            imports.insert("fmod");
+                        }
+                        Op::Unary(UnaryOp::Factorial, _) => fact = true,
+                        Op::Builtin(builtin, _) => {
+                            imports.extend(import(*builtin));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    let mut gen = Wasm {
+        types: Vec::new(),
+        imports: Vec::new(),
+        functions: HashMap::new(),
+        globals: HashMap::new(),
+        messages: Vec::new(),
+        fact: None,
+    };
+    for name in imports {
+        let index = gen.signature(vec![F64; arity(name)], vec![F64]);
+        gen.imports.push((MATH, name, index));
+    }
+    let index = gen.signature(vec![I32, I32], Vec::new());
+    gen.imports.push((ERROR.0, ERROR.1, index));
+    let first = gen.imports.len() as u32;
+    for (idx, func) in module.functions.iter().enumerate() {
+        gen.functions.insert(func.name.clone(), first + idx as u32);
+    }
+    if fact {
+        gen.fact = Some(first + module.functions.len() as u32);
+    }
+
+    let mut global_section = Vec::new();
+    for global in &module.globals {
+        let value = match valtype(global.ty) {
+            Some(ty) => {
+                global_section.push(global_def(ty));
+                Some(global_section.len() as u32 - 1)
+            }
+            None => None,
+        };
+        global_section.push(global_def(I32));
+        gen.globals.insert(
+            global.name.clone(),
+            (value, global_section.len() as u32 - 1),
+        );
+    }
+
+    let mut function_section = Vec::new();
+    let mut bodies = Vec::new();
+    for func in &module.functions {
+        let params = func.params.iter().filter_map(|&ty| valtype(ty)).collect();
+        let ret = valtype(func.ret).into_iter().collect();
+        function_section.push(leb(gen.signature(params, ret) as u64));
+        bodies.push(gen.body(func));
+    }
+    if gen.fact.is_some() {
+        function_section.push(leb(gen.signature(vec![F64], vec![F64]) as u64));
+        bodies.push(fact_body());
+    }
+
+    let mut out = b"\0asm".to_vec();
+    out.extend_from_slice(&1u32.to_le_bytes());
+    let types = gen
+        .types
+        .iter()
+        .map(|(params, results)| {
+            let mut ty = vec![0x60];
+            ty.extend(vector(params.iter().map(|&ty| vec![ty]).collect()));
+            ty.extend(vector(results.iter().map(|&ty| vec![ty]).collect()));
+            ty
+        })
+        .collect();
+    section(&mut out, 1, vector(types));
+    let imports = gen
+        .imports
+        .iter()
+        .map(|&(module, field, index)| {
+            let mut import = name(module);
+            import.extend(name(field));
+            import.push(0x00);
+            import.extend(leb(index as u64));
+            import
+        })
+        .collect();
+    section(&mut out, 2, vector(imports));
+    section(&mut out, 3, vector(function_section));
+    let text: String = gen.messages.iter().map(|(_, text)| text.as_str()).collect();
+    let pages = text.len().div_ceil(0x10000).max(1);
+    section(
+        &mut out,
+        5,
+        vector(vec![[vec![0x00], leb(pages as u64)].concat()]),
+    );
+    if !global_section.is_empty() {
+        section(&mut out, 6, vector(global_section));
+    }
+    let mut exports: Vec<Vec<u8>> = module
+        .functions
+        .iter()
+        .enumerate()
+        .map(|(idx, func)| {
+            let mut export = name(&func.name);
+            export.push(0x00);
+            export.extend(leb(first as u64 + idx as u64));
+            export
+        })
+        .collect();
+    let mut memory = name(MEMORY);
+    memory.extend([0x02, 0x00]);
+    exports.push(memory);
+    section(&mut out, 7, vector(exports));
+    section(&mut out, 10, vector(bodies));
+    if !text.is_empty() {
+        let mut data = vec![0x00, I32_CONST, 0x00, END];
+        data.extend(name(&text));
+        section(&mut out, 11, vector(vec![data]));
+    }
+    Ok(out)
+}
+
+fn valtype(ty: Ty) -> Option<u8> {
+    match ty {
+        Ty::Num => Some(F64),
+        Ty::Bool => Some(I32),
+        Ty::Unit => None,
+    }
+}
+
+/// Host functions a builtin calls, those wasm has an instruction for need
+/// none
+fn import(builtin: Builtin) -> Vec<&'static str> {
+    match builtin {
+        Builtin::Abs | Builtin::Sqrt | Builtin::Floor | Builtin::Ceil => Vec::new(),
+        Builtin::Exp => vec!["pow"],
+        // `f64.nearest` rounds halves to even rather than away from zero
+        _ => vec![builtin.name()],
+    }
+}
+
+fn arity(import: &str) -> usize {
+    match import {
+        "pow" | "fmod" => 2,
+        _ => 1,
+    }
+}
+
+/// Unsigned LEB128
+fn leb(mut val: u64) -> Vec<u8> {
+    let mut out = Vec::new();
+    loop {
+        let byte = (val & 0x7F) as u8;
+        val >>= 7;
+        if val == 0 {
+            out.push(byte);
+            return out;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Signed LEB128
+fn sleb(mut val: i64) -> Vec<u8> {
+    let mut out = Vec::new();
+    loop {
+        let byte = (val & 0x7F) as u8;
+        val >>= 7;
+        if (val == 0 && byte & 0x40 == 0) || (val == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return out;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn vector(items: Vec<Vec<u8>>) -> Vec<u8> {
+    let mut out = leb(items.len() as u64);
+    items.into_iter().for_each(|item| out.extend(item));
+    out
+}
+
+fn name(text: &str) -> Vec<u8> {
+    let mut out = leb(text.len() as u64);
+    out.extend_from_slice(text.as_bytes());
+    out
+}
+
+fn section(out: &mut Vec<u8>, id: u8, contents: Vec<u8>) {
+    out.push(id);
+    out.extend(leb(contents.len() as u64));
+    out.extend(contents);
+}
+
+/// A mutable global starting at zero
+fn global_def(ty: u8) -> Vec<u8> {
+    let mut out = vec![ty, 0x01];
+    match ty {
+        F64 => {
+            out.push(F64_CONST);
+            out.extend_from_slice(&0f64.to_le_bytes());
+        }
+        _ => out.extend([I32_CONST, 0x00]),
+    }
+    out.push(END);
+    out
+}
+
+/// NaN for negative numbers, otherwise the product up to the number
+/// truncated, which is 1 for NaN and overflows past 170
+fn fact_body() -> Vec<u8> {
+    let (n, factor, product) = (0, 1, 2);
+    let mut code = Code::default();
+    code.get(n);
+    code.f64(0.0);
+    code.op(F64_LT);
+    code.op(IF);
+    code.op(EMPTY);
+    code.f64(f64::NAN);
+    code.op(RETURN);
+    code.op(END);
+    code.get(n);
+    code.f64(171.0);
+    code.op(F64_GE);
+    code.op(IF);
+    code.op(EMPTY);
+    code.f64(f64::INFINITY);
+    code.op(RETURN);
+    code.op(END);
+    code.f64(1.0);
+    code.set(factor);
+    code.f64(1.0);
+    code.set(product);
+    code.op(BLOCK);
+    code.op(EMPTY);
+    code.op(LOOP);
+    code.op(EMPTY);
+    // Unordered with NaN, so it stops straight away
+    code.get(factor);
+    code.get(n);
+    code.op(F64_LE);
+    code.op(I32_EQZ);
+    code.op(BR_IF);
+    code.index(1);
+    code.get(product);
+    code.get(factor);
+    code.op(F64_MUL);
+    code.set(product);
+    code.get(factor);
+    code.f64(1.0);
+    code.op(F64_ADD);
+    code.set(factor);
+    code.op(BR);
+    code.index(0);
+    code.op(END);
+    code.op(END);
+    code.get(product);
+    code.op(END);
+    let mut body = vector(vec![[leb(2), vec![F64]].concat()]);
+    body.extend(code.bytes);
+    [leb(body.len() as u64), body].concat()
+}
+
+#[derive(Default)]
+struct Code {
+    bytes: Vec<u8>,
+}
+
+impl Code {
+    fn op(&mut self, op: u8) {
+        self.bytes.push(op);
+    }
+    fn index(&mut self, index: u32) {
+        self.bytes.extend(leb(index as u64));
+    }
+    fn get(&mut self, local: u32) {
+        self.op(LOCAL_GET);
+        self.index(local);
+    }
+    fn set(&mut self, local: u32) {
+        self.op(LOCAL_SET);
+        self.index(local);
+    }
+    fn f64(&mut self, val: f64) {
+        self.op(F64_CONST);
+        self.bytes.extend_from_slice(&val.to_le_bytes());
+    }
+    fn i32(&mut self, val: i32) {
+        self.op(I32_CONST);
+        self.bytes.extend(sleb(val as i64));
+    }
+    fn call(&mut self, func: u32) {
+        self.op(CALL);
+        self.index(func);
+    }
+}
+
+struct Wasm {
+    /// Distinct signatures, parameter and result types
+    types: Vec<(Vec<u8>, Vec<u8>)>,
+    /// Module, name and type of each imported function
+    imports: Vec<(&'static str, &'static str, u32)>,
+    /// Index of every function of the module
+    functions: HashMap<String, u32>,
+    /// Indices of each global's value, if it has one, and of the flag
+    /// that's set once it's stored to
+    globals: HashMap<String, (Option<u32>, u32)>,
+    /// Error messages and their offsets in memory
+    messages: Vec<(u32, String)>,
+    fact: Option<u32>,
+}
+
+/// Where each value is kept in a function's body
+struct Frame {
+    /// Local of every value with a type wasm has
+    locals: HashMap<ValueId, u32>,
+    /// Local holding the block to go to next
+    label: u32,
+    blocks: u32,
+}
+
+impl Wasm {
+    fn signature(&mut self, params: Vec<u8>, results: Vec<u8>) -> u32 {
+        let ty = (params, results);
+        match self.types.iter().position(|known| *known == ty) {
+            Some(index) => index as u32,
+            None => {
+                self.types.push(ty);
+                self.types.len() as u32 - 1
+            }
+        }
+    }
+    fn import(&self, name: &str) -> u32 {
+        self.imports
+            .iter()
+            .position(|&(module, field, _)| module == MATH && field == name)
+            .expect("imports are collected up front") as u32
+    }
+    /// Call the host with the message then trap, it shouldn't return
+    fn fail(&mut self, code: &mut Code, err: RuntimeError) {
+        let text = err.to_string();
+        let offset = match self.messages.iter().find(|(_, known)| *known == text) {
+            Some(&(offset, _)) => offset,
+            None => {
+                let offset = self
+                    .messages
+                    .last()
+                    .map_or(0, |(offset, text)| offset + text.len() as u32);
+                self.messages.push((offset, text.clone()));
+                offset
+            }
+        };
+        code.i32(offset as i32);
+        code.i32(text.len() as i32);
+        code.call(self.imports.len() as u32 - 1);
+        code.op(UNREACHABLE);
+    }
+
+    /// The function's locals and code, blocks run by a loop around a
+    /// `br_table` on the next block's number since wasm has no gotos
+    ///
+    /// ```text
+    /// loop
+    ///   block ... block
+    ///     br_table 0 1 ... n-1 (label)
+    ///   end
+    ///   b0 ...
+    ///   end
+    ///   bn-1
+    /// end
+    /// ```
+    fn body(&mut self, func: &Function) -> Vec<u8> {
+        let mut frame = Frame {
+            locals: HashMap::new(),
+            label: 0,
+            blocks: func.blocks.len() as u32,
+        };
+        let mut count = 0;
+        for (idx, &param) in func.params.iter().enumerate() {
+            if valtype(param).is_some() {
+                frame.locals.insert(ValueId(idx as u32), count);
+                count += 1;
+            }
+        }
+        let mut locals: Vec<u8> = Vec::new();
+        for block in &func.blocks {
+            for inst in &block.insts {
+                if let Inst::Define(val, _) = inst {
+                    if let Some(ty) = valtype(func.ty(*val)) {
+                        frame.locals.insert(*val, count + locals.len() as u32);
+                        locals.push(ty);
+                    }
+                }
+            }
+        }
+        frame.label = count + locals.len() as u32;
+        locals.push(I32);
+
+        let mut code = Code::default();
+        code.op(LOOP);
+        code.op(EMPTY);
+        for _ in 0..frame.blocks {
+            code.op(BLOCK);
+            code.op(EMPTY);
+        }
+        code.get(frame.label);
+        code.op(BR_TABLE);
+        code.index(frame.blocks);
+        for block in 0..frame.blocks {
+            code.index(block);
+        }
+        code.index(frame.blocks - 1);
+        for (idx, block) in func.blocks.iter().enumerate() {
+            code.op(END);
+            for inst in &block.insts {
+                match inst {
+                    Inst::Define(val, op) => self.define(&mut code, func, &frame, *val, op),
+                    Inst::Store(name, val) => {
+                        let (value, set) = self.globals[name];
+                        if let Some(value) = value {
+                            code.get(frame.locals[val]);
+                            code.op(GLOBAL_SET);
+                            code.index(value);
+                        }
+                        code.i32(1);
+                        code.op(GLOBAL_SET);
+                        code.index(set);
+                    }
+                }
+            }
+            // Branches out of the current block reach the loop
+            let depth = frame.blocks - 1 - idx as u32;
+            match block.term {
+                Terminator::Jump(to) => jump(&mut code, func, &frame, idx, to.0, depth),
+                Terminator::Branch(cond, then_block, else_block) => {
+                    code.get(frame.locals[&cond]);
+                    code.op(IF);
+                    code.op(EMPTY);
+                    jump(&mut code, func, &frame, idx, then_block.0, depth + 1);
+                    code.op(ELSE);
+                    jump(&mut code, func, &frame, idx, else_block.0, depth + 1);
+                    code.op(END);
+                    code.op(UNREACHABLE);
+                }
+                Terminator::Return(val) => {
+                    if let Some(&local) = frame.locals.get(&val) {
+                        code.get(local);
+                    }
+                    code.op(RETURN);
+                }
+            }
+        }
+        code.op(END);
+        code.op(UNREACHABLE);
+        code.op(END);
+
+        // Locals are declared in runs of one type
+        let mut runs: Vec<(u32, u8)> = Vec::new();
+        for ty in locals {
+            match runs.last_mut() {
+                Some((count, last)) if *last == ty => *count += 1,
+                _ => runs.push((1, ty)),
+            }
+        }
+        let mut body = vector(
+            runs.into_iter()
+                .map(|(count, ty)| [leb(count as u64), vec![ty]].concat())
+                .collect(),
+        );
+        body.extend(code.bytes);
+        [leb(body.len() as u64), body].concat()
+    }
+
+    fn define(&mut self, code: &mut Code, func: &Function, frame: &Frame, val: ValueId, op: &Op) {
+        let get = |code: &mut Code, val: &ValueId| {
+            if let Some(&local) = frame.locals.get(val) {
+                code.get(local);
+            }
+        };
+        match op {
+            Op::Const(Constant::Num(num)) => code.f64(*num),
+            Op::Const(Constant::Bool(b)) => code.i32(*b as i32),
+            Op::Const(Constant::Unit) => {}
+            Op::Binary(op, left, right) => match func.ty(*left) {
+                // Units are all equal
+                Ty::Unit => code.i32((*op == BinaryOp::Equal) as i32),
+                Ty::Bool => {
+                    get(code, left);
+                    get(code, right);
+                    code.op(match op {
+                        BinaryOp::Equal => I32_EQ,
+                        _ => I32_NE,
+                    });
+                }
+                Ty::Num => {
+                    if *op == BinaryOp::Div {
+                        // A NaN divisor isn't zero
+                        get(code, right);
+                        code.f64(0.0);
+                        code.op(F64_EQ);
+                        code.op(IF);
+                        code.op(EMPTY);
+                        self.fail(code, RuntimeError::DivisionByZero);
+                        code.op(END);
+                    }
+                    get(code, left);
+                    get(code, right);
+                    match op {
+                        BinaryOp::Pow => code.call(self.import("pow")),
+                        BinaryOp::Mod => code.call(self.import("fmod")),
+                        _ => code.op(match op {
+                            BinaryOp::Add => F64_ADD,
+                            BinaryOp::Sub => F64_SUB,
+                            BinaryOp::Mul => F64_MUL,
+                            BinaryOp::Div => F64_DIV,
+                            BinaryOp::Less => F64_LT,
+                            BinaryOp::LessEqual => F64_LE,
+                            BinaryOp::Greater => F64_GT,
+                            BinaryOp::GreaterEqual => F64_GE,
+                            BinaryOp::Equal => F64_EQ,
+                            _ => F64_NE,
+                        }),
+                    }
+                }
+            },
+            Op::Unary(op, operand) => {
+                get(code, operand);
+                match op {
+                    UnaryOp::Neg => code.op(F64_NEG),
+                    UnaryOp::Not => {
+                        // `~x` is `-(x + 1)`
+                        code.f64(1.0);
+                        code.op(F64_ADD);
+                        code.op(F64_NEG);
+                    }
+                    UnaryOp::Factorial => code.call(self.fact.expect("collected up front")),
+                }
+            }
+            Op::Builtin(builtin, args) => match builtin {
+                Builtin::Log => {
+                    // ln(x) / ln(base) as the interpreter computes it
+                    let log = self.import("log");
+                    get(code, &args[0]);
+                    code.call(log);
+                    match args.get(1) {
+                        Some(base) => get(code, base),
+                        None => code.f64(10.0),
+                    }
+                    code.call(log);
+                    code.op(F64_DIV);
+                }
+                Builtin::Exp => {
+                    code.f64(std::f64::consts::E);
+                    get(code, &args[0]);
+                    code.call(self.import("pow"));
+                }
+                _ => {
+                    args.iter().for_each(|arg| get(code, arg));
+                    match builtin {
+                        Builtin::Abs => code.op(F64_ABS),
+                        Builtin::Sqrt => code.op(F64_SQRT),
+                        Builtin::Floor => code.op(F64_FLOOR),
+                        Builtin::Ceil => code.op(F64_CEIL),
+                        _ => code.call(self.import(builtin.name())),
+                    }
+                }
+            },
+            Op::Call(name, args) => {
+                args.iter().for_each(|arg| get(code, arg));
+                code.call(self.functions[name]);
+            }
+            Op::Load(name) => {
+                let (value, set) = self.globals[name];
+                code.op(GLOBAL_GET);
+                code.index(set);
+                code.op(I32_EQZ);
+                code.op(IF);
+                code.op(EMPTY);
+                self.fail(code, RuntimeError::UndeclaredVariable(name.clone()));
+                code.op(END);
+                if let Some(value) = value {
+                    code.op(GLOBAL_GET);
+                    code.index(value);
+                }
+            }
+            // Set by the jumps into the block
+            Op::Phi(_) => {}
+        }
+        if !matches!(op, Op::Phi(_)) {
+            if let Some(&local) = frame.locals.get(&val) {
+                code.set(local);
+            }
+        }
+    }
+}
+
+/// Give the phis of block `to` their values from block `from` and branch
+/// back to the loop, `depth` blocks out
+fn jump(code: &mut Code, func: &Function, frame: &Frame, from: usize, to: u32, depth: u32) {
+    // Every value is read before any phi is written
+    let mut phis = Vec::new();
+    for inst in &func.blocks[to as usize].insts {
+        if let Inst::Define(val, Op::Phi(incoming)) = inst {
+            if let Some(&local) = frame.locals.get(val) {
+                let &(src, _) = incoming
+                    .iter()
+                    .find(|&&(_, pred)| pred.0 as usize == from)
+                    .expect("verified phis cover every predecessor");
+                code.get(frame.locals[&src]);
+                phis.push(local);
+            }
+        }
+    }
+    for local in phis.into_iter().rev() {
+        code.set(local);
+    }
+    code.i32(to as i32);
+    code.set(frame.label);
+    code.op(BR);
+    code.index(depth);
+}
@@ -0,0 +1,387 @@
+use std::fmt;
+
+/// A 64-bit general purpose register, numbered as instructions encode it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reg {
+    Rax = 0,
+    Rcx = 1,
+    Rdx = 2,
+    Rbx = 3,
+    Rsp = 4,
+    Rbp = 5,
+    Rsi = 6,
+    Rdi = 7,
+    R8 = 8,
+    R9 = 9,
+}
+
+/// Registers carrying integer arguments under the System V ABI, in order
+pub const ARG_REGS: [Reg; 6] = [Reg::Rdi, Reg::Rsi, Reg::Rdx, Reg::Rcx, Reg::R8, Reg::R9];
+
+/// Number of xmm registers carrying floating point arguments
+pub const ARG_XMMS: u8 = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Reg(Reg),
+    /// The low byte of a register, e.g. `%al`
+    Byte(Reg),
+    Xmm(u8),
+    Imm(i64),
+    /// An x87 stack register, `%st(0)` is the top
+    St(u8),
+    /// `disp(%base)`
+    Mem(Reg, i32),
+    /// `symbol(%rip)`, the address of data next to the code
+    Rip(String),
+    /// Jump or call target
+    Label(String),
+}
+
+/// Condition of a jump or `set`, named after its suffix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cond {
+    E,
+    Ne,
+    /// Above, unsigned or after `ucomisd`
+    A,
+    Ae,
+    B,
+    /// Parity set, an unordered `ucomisd` involving NaN
+    P,
+    Np,
+    S,
+    Ns,
+    /// Below or equal, unsigned or after `ucomisd`
+    Be,
+    /// Less or equal, signed
+    Le,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Push,
+    Pop,
+    /// `movq` between registers, memory, immediates and xmm registers
+    Mov,
+    /// 64-bit immediate into a register
+    Movabs,
+    Lea,
+    Add,
+    Sub,
+    And,
+    Or,
+    Neg,
+    /// Unsigned divide of `%rdx:%rax`, the quotient in `%rax` and the
+    /// remainder in `%rdx`
+    Div,
+    Cmp,
+    Test,
+    /// Complement a bit of a register
+    Btc,
+    /// Clear a bit of a register
+    Btr,
+    Andb,
+    Orb,
+    Movb,
+    /// Zero extend a byte register
+    Movzb,
+    Set(Cond),
+    Jmp,
+    J(Cond),
+    Call,
+    Leave,
+    Ret,
+    Syscall,
+    Movsd,
+    Addsd,
+    Subsd,
+    Mulsd,
+    Divsd,
+    Sqrtsd,
+    Ucomisd,
+    Xorpd,
+    /// Truncate a double to a 64-bit integer
+    Cvttsd2si,
+    Cvtsi2sd,
+    /// Push a double from memory onto the x87 stack
+    Fld,
+    /// Push a copy of an x87 register
+    FldSt,
+    /// Store the top of the x87 stack to memory as a double
+    Fst,
+    /// Store and pop
+    Fstp,
+    /// Copy the top into an x87 register and pop
+    FstpSt,
+    /// Push a 64-bit integer from memory
+    Fild,
+    /// Store the top rounded to a 64-bit integer and pop
+    Fistp,
+    /// Arithmetic on the top and a double in memory, the result on top
+    Fadd,
+    Fmul,
+    Fsub,
+    /// Subtract the top from the double in memory
+    Fsubr,
+    Fdiv,
+    Fld1,
+    Fldl2e,
+    Fldlg2,
+    Fldln2,
+    Fldpi,
+    Fxch,
+    Fchs,
+    Fsin,
+    Fcos,
+    /// Tangent of the top, pushing 1 after it
+    Fptan,
+    /// Arctangent of `%st(1) / %st(0)`, popping once
+    Fpatan,
+    Fsqrt,
+    Frndint,
+    /// Multiply the top by 2 to the power of `%st(1)` truncated
+    Fscale,
+    /// 2 to the power of the top less 1, the top between -1 and 1
+    F2xm1,
+    /// `%st(1)` times the base 2 logarithm of the top, popping once
+    Fyl2x,
+    /// Partial remainder of the top by `%st(1)`, `C2` is set while unfinished
+    Fprem,
+    /// Arithmetic into `%st(1)` popping the top
+    Faddp,
+    Fmulp,
+    /// `%st(1) / %st(0)`, which GNU `as` spells `fdivrp`
+    Fdivp,
+    /// Store the x87 status word in `%ax`
+    Fnstsw,
+}
+
+/// A line of the text section
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inst {
+    Label(String),
+    /// An instruction, its operands in AT&T order with the destination last
+    Op(Opcode, Vec<Operand>),
+}
+
+/// A whole translation unit
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Assembly {
+    /// Symbols visible to other object files
+    pub exports: Vec<String>,
+    pub text: Vec<Inst>,
+    /// Labels of zero initialised 8 byte variables
+    pub data: Vec<String>,
+    /// Labelled nul terminated strings
+    pub strings: Vec<(String, String)>,
+}
+
+impl Reg {
+    pub fn name(self) -> &'static str {
+        match self {
+            Reg::Rax => "rax",
+            Reg::Rcx => "rcx",
+            Reg::Rdx => "rdx",
+            Reg::Rbx => "rbx",
+            Reg::Rsp => "rsp",
+            Reg::Rbp => "rbp",
+            Reg::Rsi => "rsi",
+            Reg::Rdi => "rdi",
+            Reg::R8 => "r8",
+            Reg::R9 => "r9",
+        }
+    }
+    /// Name of the register's low byte
+    pub fn byte_name(self) -> &'static str {
+        match self {
+            Reg::Rax => "al",
+            Reg::Rcx => "cl",
+            Reg::Rdx => "dl",
+            Reg::Rbx => "bl",
+            Reg::Rsp => "spl",
+            Reg::Rbp => "bpl",
+            Reg::Rsi => "sil",
+            Reg::Rdi => "dil",
+            Reg::R8 => "r8b",
+            Reg::R9 => "r9b",
+        }
+    }
+}
+
+impl Cond {
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Cond::E => "e",
+            Cond::Ne => "ne",
+            Cond::A => "a",
+            Cond::Ae => "ae",
+            Cond::B => "b",
+            Cond::P => "p",
+            Cond::Np => "np",
+            Cond::S => "s",
+            Cond::Ns => "ns",
+            Cond::Be => "be",
+            Cond::Le => "le",
+        }
+    }
+}
+
+impl Opcode {
+    pub fn mnemonic(self) -> String {
+        let name = match self {
+            Opcode::Push => "pushq",
+            Opcode::Pop => "popq",
+            Opcode::Mov => "movq",
+            Opcode::Movabs => "movabsq",
+            Opcode::Lea => "leaq",
+            Opcode::Add => "addq",
+            Opcode::Sub => "subq",
+            Opcode::And => "andq",
+            Opcode::Or => "orq",
+            Opcode::Neg => "negq",
+            Opcode::Div => "divq",
+            Opcode::Cmp => "cmpq",
+            Opcode::Test => "testq",
+            Opcode::Btc => "btcq",
+            Opcode::Btr => "btrq",
+            Opcode::Andb => "andb",
+            Opcode::Orb => "orb",
+            Opcode::Movb => "movb",
+            Opcode::Movzb => "movzbq",
+            Opcode::Set(cond) => return format!("set{0}", cond.suffix()),
+            Opcode::Jmp => "jmp",
+            Opcode::J(cond) => return format!("j{0}", cond.suffix()),
+            Opcode::Call => "call",
+            Opcode::Leave => "leave",
+            Opcode::Ret => "ret",
+            Opcode::Syscall => "syscall",
+            Opcode::Movsd => "movsd",
+            Opcode::Addsd => "addsd",
+            Opcode::Subsd => "subsd",
+            Opcode::Mulsd => "mulsd",
+            Opcode::Divsd => "divsd",
+            Opcode::Sqrtsd => "sqrtsd",
+            Opcode::Ucomisd => "ucomisd",
+            Opcode::Xorpd => "xorpd",
+            Opcode::Cvttsd2si => "cvttsd2si",
+            Opcode::Cvtsi2sd => "cvtsi2sd",
+            Opcode::Fld => "fldl",
+            Opcode::FldSt => "fld",
+            Opcode::Fst => "fstl",
+            Opcode::Fstp => "fstpl",
+            Opcode::FstpSt => "fstp",
+            Opcode::Fild => "fildq",
+            Opcode::Fistp => "fistpq",
+            Opcode::Fadd => "faddl",
+            Opcode::Fmul => "fmull",
+            Opcode::Fsub => "fsubl",
+            Opcode::Fsubr => "fsubrl",
+            Opcode::Fdiv => "fdivl",
+            Opcode::Fld1 => "fld1",
+            Opcode::Fldl2e => "fldl2e",
+            Opcode::Fldlg2 => "fldlg2",
+            Opcode::Fldln2 => "fldln2",
+            Opcode::Fldpi => "fldpi",
+            Opcode::Fxch => "fxch",
+            Opcode::Fchs => "fchs",
+            Opcode::Fsin => "fsin",
+            Opcode::Fcos => "fcos",
+            Opcode::Fptan => "fptan",
+            Opcode::Fpatan => "fpatan",
+            Opcode::Fsqrt => "fsqrt",
+            Opcode::Frndint => "frndint",
+            Opcode::Fscale => "fscale",
+            Opcode::F2xm1 => "f2xm1",
+            Opcode::Fyl2x => "fyl2x",
+            Opcode::Fprem => "fprem",
+            Opcode::Faddp => "faddp",
+            Opcode::Fmulp => "fmulp",
+            Opcode::Fdivp => "fdivrp",
+            // Its only form
+            Opcode::Fnstsw => "fnstsw\t%ax",
+        };
+        name.to_string()
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Reg(reg) => write!(f, "%{0}", reg.name()),
+            Operand::Byte(reg) => write!(f, "%{0}", reg.byte_name()),
+            Operand::Xmm(idx) => write!(f, "%xmm{idx}"),
+            Operand::Imm(val) => write!(f, "${val}"),
+            Operand::St(idx) => write!(f, "%st({idx})"),
+            Operand::Mem(base, 0) => write!(f, "(%{0})", base.name()),
+            Operand::Mem(base, disp) => write!(f, "{disp}(%{0})", base.name()),
+            Operand::Rip(symbol) => write!(f, "{symbol}(%rip)"),
+            Operand::Label(label) => write!(f, "{label}"),
+        }
+    }
+}
+
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Inst::Label(label) => write!(f, "{label}:"),
+            Inst::Op(opcode, operands) if operands.is_empty() => {
+                write!(f, "\t{0}", opcode.mnemonic())
+            }
+            Inst::Op(opcode, operands) => {
+                let operands: Vec<String> = operands
+                    .iter()
+                    .map(|op| match (opcode, op) {
+                        // Bit patterns of doubles read better in hex
+                        (Opcode::Movabs, Operand::Imm(val)) => format!("$0x{val:x}"),
+                        // Indirect through a register
+                        (Opcode::Jmp | Opcode::Call, Operand::Reg(_)) => format!("*{op}"),
+                        _ => op.to_string(),
+                    })
+                    .collect();
+                write!(f, "\t{0}\t{1}", opcode.mnemonic(), operands.join(", "))
+            }
+        }
+    }
+}
+
+/// Quote a string for `.asciz`
+fn quoted(text: &str) -> String {
+    let mut out = String::from("\"");
+    for ch in text.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            _ => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl fmt::Display for Assembly {
+    /// GNU `as` syntax
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "\t.text")?;
+        for symbol in &self.exports {
+            writeln!(f, "\t.globl\t{symbol}")?;
+        }
+        for inst in &self.text {
+            writeln!(f, "{inst}")?;
+        }
+        if !self.data.is_empty() {
+            writeln!(f, "\t.data")?;
+            for label in &self.data {
+                writeln!(f, "{label}:\n\t.quad\t0")?;
+            }
+        }
+        if !self.strings.is_empty() {
+            writeln!(f, "\t.section\t.rodata")?;
+            for (label, text) in &self.strings {
+                writeln!(f, "{label}:\n\t.asciz\t{0}", quoted(text))?;
+            }
+        }
+        writeln!(f, "\t.section\t.note.GNU-stack,\"\",@progbits")
+    }
+}
@@ -0,0 +1,168 @@
+use crate::ast::ast::BinaryOp;
+use crate::ast::ast::UnaryOp;
+use crate::ir::ir::Block;
+use crate::ir::ir::BlockId;
+use crate::ir::ir::Constant;
+use crate::ir::ir::Function;
+use crate::ir::ir::Inst;
+use crate::ir::ir::Module;
+use crate::ir::ir::Op;
+use crate::ir::ir::Terminator;
+use crate::ir::ir::Ty;
+use crate::ir::ir::ValueId;
+use std::fmt;
+
+/// Mnemonics of the binary operators, also read by the parser
+pub const BINARY_NAMES: [(BinaryOp, &str); 12] = [
+    (BinaryOp::Add, "add"),
+    (BinaryOp::Sub, "sub"),
+    (BinaryOp::Mul, "mul"),
+    (BinaryOp::Div, "div"),
+    (BinaryOp::Pow, "pow"),
+    (BinaryOp::Mod, "mod"),
+    (BinaryOp::Less, "lt"),
+    (BinaryOp::LessEqual, "le"),
+    (BinaryOp::Greater, "gt"),
+    (BinaryOp::GreaterEqual, "ge"),
+    (BinaryOp::Equal, "eq"),
+    (BinaryOp::NotEqual, "ne"),
+];
+
+pub const UNARY_NAMES: [(UnaryOp, &str); 3] = [
+    (UnaryOp::Neg, "neg"),
+    (UnaryOp::Not, "not"),
+    (UnaryOp::Factorial, "fact"),
+];
+
+fn mnemonic<T: PartialEq>(names: &[(T, &'static str)], op: T) -> &'static str {
+    names
+        .iter()
+        .find(|(candidate, _)| *candidate == op)
+        .map(|&(_, name)| name)
+        .expect("every operator has a mnemonic")
+}
+
+fn list(vals: &[ValueId]) -> String {
+    vals.iter()
+        .map(|val| val.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ty::Num => write!(f, "num"),
+            Ty::Bool => write!(f, "bool"),
+            Ty::Unit => write!(f, "unit"),
+        }
+    }
+}
+
+impl fmt::Display for ValueId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{0}", self.0)
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "b{0}", self.0)
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // Debug formatting round trips, including -0, inf and NaN
+            Constant::Num(val) => write!(f, "{val:?}"),
+            Constant::Bool(val) => write!(f, "{val}"),
+            Constant::Unit => write!(f, "()"),
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Const(val) => write!(f, "const {val}"),
+            Op::Binary(op, left, right) => {
+                write!(f, "{0} {left}, {right}", mnemonic(&BINARY_NAMES, *op))
+            }
+            Op::Unary(op, operand) => write!(f, "{0} {operand}", mnemonic(&UNARY_NAMES, *op)),
+            Op::Builtin(builtin, args) => write!(f, "{builtin} {0}", list(args)),
+            Op::Call(name, args) => write!(f, "call @{name}({0})", list(args)),
+            Op::Load(name) => write!(f, "load @{name}"),
+            Op::Phi(incoming) => {
+                let incoming: Vec<String> = incoming
+                    .iter()
+                    .map(|(val, block)| format!("[{val}, {block}]"))
+                    .collect();
+                write!(f, "phi {0}", incoming.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Terminator::Jump(to) => write!(f, "jmp {to}"),
+            Terminator::Branch(cond, then_block, else_block) => {
+                write!(f, "br {cond}, {then_block}, {else_block}")
+            }
+            Terminator::Return(val) => write!(f, "ret {val}"),
+        }
+    }
+}
+
+impl Function {
+    fn write_block(&self, f: &mut fmt::Formatter<'_>, block: &Block) -> fmt::Result {
+        for inst in &block.insts {
+            match inst {
+                Inst::Define(val, op) => writeln!(f, "  {val}: {0} = {op}", self.ty(*val))?,
+                Inst::Store(name, val) => writeln!(f, "  store @{name}, {val}")?,
+            }
+        }
+        writeln!(f, "  {0}", block.term)
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<String> = self
+            .params
+            .iter()
+            .enumerate()
+            .map(|(idx, ty)| format!("%{idx}: {ty}"))
+            .collect();
+        writeln!(
+            f,
+            "fn @{0}({1}) -> {2} {{",
+            self.name,
+            params.join(", "),
+            self.ret
+        )?;
+        for (idx, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "b{idx}:")?;
+            self.write_block(f, block)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// The textual form read back by `parse`
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for global in &self.globals {
+            writeln!(f, "global @{0}: {1}", global.name, global.ty)?;
+        }
+        for (idx, func) in self.functions.iter().enumerate() {
+            if idx > 0 || !self.globals.is_empty() {
+                writeln!(f)?;
+            }
+            writeln!(f, "{func}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+use crate::ast::ast::BinaryOp;
+use crate::ast::ast::Builtin;
+use crate::ast::ast::UnaryOp;
+
+/// Type of an SSA value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Ty {
+    Num,
+    Bool,
+    Unit,
+}
+
+/// An SSA value, written `%n`, numbered within its function
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ValueId(pub u32);
+
+/// A basic block, written `bn`, its index in `Function::blocks`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+#[derive(Debug, Clone, Copy)]
+pub enum Constant {
+    Num(f64),
+    Bool(bool),
+    Unit,
+}
+
+/// Computation producing a value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Const(Constant),
+    /// Arithmetic on numbers, comparisons produce a bool and `div` fails on a
+    /// zero divisor as it does in the language
+    Binary(BinaryOp, ValueId, ValueId),
+    Unary(UnaryOp, ValueId),
+    Builtin(Builtin, Vec<ValueId>),
+    /// Call a function of the module by name
+    Call(String, Vec<ValueId>),
+    /// Read a global, which fails if nothing has been stored to it yet
+    Load(String),
+    /// The value coming from whichever predecessor control arrived from
+    Phi(Vec<(ValueId, BlockId)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inst {
+    /// `%n: ty = op`, the type is kept in `Function::types`
+    Define(ValueId, Op),
+    /// Write a global
+    Store(String, ValueId),
+}
+
+/// How control leaves a block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    /// Go to the first block if the bool is true, otherwise the second
+    Branch(ValueId, BlockId, BlockId),
+    Return(ValueId),
+}
+
+/// Instructions run in order, phis come first
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub insts: Vec<Inst>,
+    pub term: Terminator,
+}
+
+/// A function in SSA form, control enters at the first block
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    /// Parameters are the values `%0` up to the number of parameters
+    pub params: Vec<Ty>,
+    pub ret: Ty,
+    pub blocks: Vec<Block>,
+    /// Type of every value, indexed by its number
+    pub types: Vec<Ty>,
+}
+
+/// A variable shared by every function, read and written by name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub ty: Ty,
+}
+
+/// A whole program, its top level statements make up `main`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Module {
+    pub globals: Vec<Global>,
+    pub functions: Vec<Function>,
+}
+
+/// Name of the function holding a program's top level statements
+pub const MAIN: &str = "main";
+
+impl PartialEq for Constant {
+    /// Numbers compare by their bits, telling -0 from 0, but every NaN is
+    /// equal so printed and parsed modules are
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Constant::Num(a), Constant::Num(b)) => {
+                a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan())
+            }
+            (Constant::Bool(a), Constant::Bool(b)) => a == b,
+            (Constant::Unit, Constant::Unit) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Constant {
+    pub fn ty(self) -> Ty {
+        match self {
+            Constant::Num(_) => Ty::Num,
+            Constant::Bool(_) => Ty::Bool,
+            Constant::Unit => Ty::Unit,
+        }
+    }
+}
+
+impl Op {
+    /// Values the operation reads
+    pub fn operands(&self) -> Vec<ValueId> {
+        match self {
+            Op::Const(_) | Op::Load(_) => Vec::new(),
+            Op::Binary(_, left, right) => vec![*left, *right],
+            Op::Unary(_, operand) => vec![*operand],
+            Op::Builtin(_, args) | Op::Call(_, args) => args.clone(),
+            Op::Phi(incoming) => incoming.iter().map(|&(val, _)| val).collect(),
+        }
+    }
+}
+
+impl Terminator {
+    /// Blocks control may go to next
+    pub fn successors(self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(to) => vec![to],
+            Terminator::Branch(_, then_block, else_block) => vec![then_block, else_block],
+            Terminator::Return(_) => Vec::new(),
+        }
+    }
+}
+
+impl Function {
+    pub fn ty(&self, val: ValueId) -> Ty {
+        self.types[val.0 as usize]
+    }
+    pub fn block(&self, block: BlockId) -> &Block {
+        &self.blocks[block.0 as usize]
+    }
+    /// Rename every value, definitions and uses alike, leaving `types` as is
+    pub fn rename_values(&mut self, rename: impl Fn(ValueId) -> ValueId) {
+        for block in &mut self.blocks {
+            for inst in &mut block.insts {
+                let op = match inst {
+                    Inst::Define(val, op) => {
+                        *val = rename(*val);
+                        op
+                    }
+                    Inst::Store(_, val) => {
+                        *val = rename(*val);
+                        continue;
+                    }
+                };
+                match op {
+                    Op::Const(_) | Op::Load(_) => {}
+                    Op::Binary(_, left, right) => {
+                        *left = rename(*left);
+                        *right = rename(*right);
+                    }
+                    Op::Unary(_, operand) => *operand = rename(*operand),
+                    Op::Builtin(_, args) | Op::Call(_, args) => {
+                        args.iter_mut().for_each(|arg| *arg = rename(*arg))
+                    }
+                    Op::Phi(incoming) => {
+                        incoming.iter_mut().for_each(|(val, _)| *val = rename(*val))
+                    }
+                }
+            }
+            match &mut block.term {
+                Terminator::Jump(_) => {}
+                Terminator::Branch(val, _, _) | Terminator::Return(val) => *val = rename(*val),
+            }
+        }
+    }
+    /// Blocks that may jump to each block, in block order
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut preds = vec![Vec::new(); self.blocks.len()];
+        for (idx, block) in self.blocks.iter().enumerate() {
+            for succ in block.term.successors() {
+                if let Some(preds) = preds.get_mut(succ.0 as usize) {
+                    if !preds.contains(&BlockId(idx as u32)) {
+                        preds.push(BlockId(idx as u32));
+                    }
+                }
+            }
+        }
+        preds
+    }
+}
+
+impl Module {
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|func| func.name == name)
+    }
+    pub fn global(&self, name: &str) -> Option<&Global> {
+        self.globals.iter().find(|global| global.name == name)
+    }
+}
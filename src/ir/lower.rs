@@ -0,0 +1,632 @@
+use crate::ast::ast::constant;
+use crate::ast::ast::Expr;
+use crate::ast::ast::ExprKind;
+use crate::ast::ast::FnDecl;
+use crate::ast::ast::Stmt;
+use crate::ast::ast::StmtKind;
+use crate::ir::ir::Block;
+use crate::ir::ir::BlockId;
+use crate::ir::ir::Constant;
+use crate::ir::ir::Function;
+use crate::ir::ir::Global;
+use crate::ir::ir::Inst;
+use crate::ir::ir::Module;
+use crate::ir::ir::Op;
+use crate::ir::ir::Terminator;
+use crate::ir::ir::Ty;
+use crate::ir::ir::ValueId;
+use crate::ir::ir::MAIN;
+use crate::semantic::resolver::DeclId;
+use crate::semantic::resolver::Resolved;
+use crate::semantic::typeck::Type;
+use crate::tokeniser::token_enum::Span;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A program the IR can't represent
+#[derive(Debug, Clone, PartialEq)]
+pub struct LowerError {
+    pub reason: String,
+    pub span: Span,
+}
+
+impl fmt::Display for LowerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{0} @ {1}", self.reason, self.span.start)
+    }
+}
+
+impl std::error::Error for LowerError {}
+
+/// Lower a checked program to SSA form
+///
+/// `types` are those the type checker inferred for the program, before any
+/// optimisation. Every function becomes a function of the module, however
+/// deeply it was nested, and the top level statements become `main`, which
+/// returns the value of the last expression statement. Top level `let`s are
+/// globals, locals of blocks and functions are SSA values with phis where
+/// branches of an `if` assign them differently.
+///
+/// Functions are bound before the program starts rather than when their
+/// definition runs, so each name may only be defined once. Functions can't
+/// be used as values, and type variables left generic by inference become
+/// numbers
+pub fn lower(resolved: &Resolved, types: &HashMap<DeclId, Type>) -> Result<Module, LowerError> {
+    let Declarations {
+        functions: decls,
+        signatures,
+        globals,
+    } = declarations(resolved, types)?;
+
+    let mut module = Module {
+        globals,
+        functions: Vec::new(),
+    };
+    for decl in &decls {
+        let (params, ret) = signatures[&decl.name.name].clone();
+        let mut builder = Builder::new(&decl.name.name, params, ret, &module, &signatures);
+        let scope = decl
+            .params
+            .iter()
+            .enumerate()
+            .map(|(idx, param)| (param.name.name.clone(), ValueId(idx as u32)))
+            .collect();
+        builder.scopes.push(scope);
+        builder.span = decl.name.span;
+        if let Some(val) = builder.block(&decl.body)? {
+            builder.ret(val)?;
+        }
+        module.functions.push(renumber(builder.func));
+    }
+
+    let mut builder = Builder::new(MAIN, Vec::new(), Ty::Unit, &module, &signatures);
+    let mut result = None;
+    for stmt in &resolved.stmts {
+        let val = builder
+            .stmt(stmt)?
+            .expect("only functions return, the top level always continues");
+        if val.is_some() {
+            result = val;
+        }
+    }
+    let result = match result {
+        Some(val) => val,
+        None => builder.unit(),
+    };
+    builder.func.ret = builder.func.ty(result);
+    builder.ret(result)?;
+    module.functions.push(renumber(builder.func));
+    Ok(module)
+}
+
+/// The functions and globals of a checked program, typed as the IR types
+/// them
+pub struct Declarations<'a> {
+    /// Every function declaration in order of appearance, including nested
+    /// ones
+    pub functions: Vec<&'a FnDecl>,
+    /// Parameter and return types of each function by name
+    pub signatures: HashMap<String, (Vec<Ty>, Ty)>,
+    /// Every name a top level `let` defines
+    pub globals: Vec<Global>,
+}
+
+/// Type the program's functions and globals, checking each function is
+/// defined once and each global keeps its type
+pub fn declarations<'a>(
+    resolved: &'a Resolved,
+    types: &HashMap<DeclId, Type>,
+) -> Result<Declarations<'a>, LowerError> {
+    let declared: HashMap<Span, DeclId> = resolved
+        .resolution
+        .declarations
+        .iter()
+        .enumerate()
+        .filter_map(|(id, decl)| decl.span.map(|span| (span, id)))
+        .collect();
+    let type_at = |span: Span, name: &str| {
+        declared
+            .get(&span)
+            .and_then(|id| types.get(id))
+            .ok_or_else(|| LowerError {
+                reason: format!("{name} has no inferred type"),
+                span,
+            })
+    };
+
+    let mut decls = Vec::new();
+    collect_functions(&resolved.stmts, &mut decls);
+    let mut signatures = HashMap::new();
+    for decl in &decls {
+        let span = decl.name.span;
+        let name = &decl.name.name;
+        if name == MAIN {
+            return Err(LowerError {
+                reason: format!("{MAIN} is reserved for the top level"),
+                span,
+            });
+        }
+        let Type::Fn(params, ret) = type_at(span, name)? else {
+            unreachable!("functions have function types");
+        };
+        let params = params
+            .iter()
+            .map(|param| ty(param, span))
+            .collect::<Result<Vec<Ty>, LowerError>>()?;
+        let signature = (params, ty(ret, span)?);
+        if signatures.insert(name.clone(), signature).is_some() {
+            return Err(LowerError {
+                reason: format!("{name} is defined more than once"),
+                span,
+            });
+        }
+    }
+
+    let mut globals: Vec<Global> = Vec::new();
+    for stmt in &resolved.stmts {
+        let (StmtKind::Let(id, _) | StmtKind::Const(id, _)) = &stmt.kind else {
+            continue;
+        };
+        let found = ty(type_at(id.span, &id.name)?, id.span)?;
+        match globals.iter().find(|global| global.name == id.name) {
+            Some(global) if global.ty != found => {
+                return Err(LowerError {
+                    reason: format!("{0} changes type from {1} to {found}", id.name, global.ty),
+                    span: id.span,
+                })
+            }
+            Some(_) => {}
+            None => globals.push(Global {
+                name: id.name.clone(),
+                ty: found,
+            }),
+        }
+    }
+
+    Ok(Declarations {
+        functions: decls,
+        signatures,
+        globals,
+    })
+}
+
+/// Number values in the order they're defined, parameters first
+fn renumber(mut func: Function) -> Function {
+    let mut order: Vec<usize> = (0..func.params.len()).collect();
+    for block in &func.blocks {
+        for inst in &block.insts {
+            if let Inst::Define(val, _) = inst {
+                order.push(val.0 as usize);
+            }
+        }
+    }
+    let mut new = vec![ValueId(0); func.types.len()];
+    for (idx, &old) in order.iter().enumerate() {
+        new[old] = ValueId(idx as u32);
+    }
+    func.types = order.iter().map(|&old| func.types[old]).collect();
+    func.rename_values(|val| new[val.0 as usize]);
+    func
+}
+
+fn ty(ty: &Type, span: Span) -> Result<Ty, LowerError> {
+    match ty {
+        Type::Num | Type::Var(_) => Ok(Ty::Num),
+        Type::Bool => Ok(Ty::Bool),
+        Type::Unit => Ok(Ty::Unit),
+        Type::Fn(..) => Err(LowerError {
+            reason: String::from("Functions can't be used as values"),
+            span,
+        }),
+    }
+}
+
+/// Every function declaration in order of appearance, including nested ones
+fn collect_functions<'a>(stmts: &'a [Stmt], out: &mut Vec<&'a FnDecl>) {
+    for stmt in stmts {
+        match &stmt.kind {
+            StmtKind::Fn(decl) => {
+                out.push(decl);
+                collect_functions(&decl.body, out);
+            }
+            StmtKind::Let(_, expr)
+            | StmtKind::Const(_, expr)
+            | StmtKind::Assign(_, expr)
+            | StmtKind::Expr(expr)
+            | StmtKind::Return(Some(expr)) => collect_in_expr(expr, out),
+            StmtKind::Return(None) => {}
+        }
+    }
+}
+
+fn collect_in_expr<'a>(expr: &'a Expr, out: &mut Vec<&'a FnDecl>) {
+    match &expr.kind {
+        ExprKind::Block(stmts) => collect_functions(stmts, out),
+        ExprKind::BinaryOp(left, _, right) => {
+            collect_in_expr(left, out);
+            collect_in_expr(right, out);
+        }
+        ExprKind::UnaryOp(operand, _) => collect_in_expr(operand, out),
+        ExprKind::Builtin(_, args) | ExprKind::FunctionCall(_, args) => {
+            args.iter().for_each(|arg| collect_in_expr(arg, out))
+        }
+        ExprKind::If(cond, then_branch, else_branch) => {
+            collect_in_expr(cond, out);
+            collect_in_expr(then_branch, out);
+            if let Some(else_branch) = else_branch {
+                collect_in_expr(else_branch, out);
+            }
+        }
+        ExprKind::Number(_) | ExprKind::Bool(_) | ExprKind::Variable(_) => {}
+    }
+}
+
+/// `None` when control never gets past the code because of a `return`
+type Lowered<T> = Result<Option<T>, LowerError>;
+
+/// Lexical scopes of the function being built, innermost last
+type Scopes = Vec<HashMap<String, ValueId>>;
+
+/// Builds one function
+struct Builder<'a> {
+    func: Function,
+    /// Block instructions are added to
+    current: BlockId,
+    /// Empty at the top level, where `let` defines globals
+    scopes: Scopes,
+    module: &'a Module,
+    signatures: &'a HashMap<String, (Vec<Ty>, Ty)>,
+    /// Source being lowered, for errors
+    span: Span,
+    unit: Option<ValueId>,
+}
+
+impl<'a> Builder<'a> {
+    fn new(
+        name: &str,
+        params: Vec<Ty>,
+        ret: Ty,
+        module: &'a Module,
+        signatures: &'a HashMap<String, (Vec<Ty>, Ty)>,
+    ) -> Self {
+        let func = Function {
+            name: name.to_string(),
+            types: params.clone(),
+            params,
+            ret,
+            blocks: vec![Block {
+                insts: Vec::new(),
+                term: Terminator::Return(ValueId(0)),
+            }],
+        };
+        Self {
+            func,
+            current: BlockId(0),
+            scopes: Vec::new(),
+            module,
+            signatures,
+            span: Span::default(),
+            unit: None,
+        }
+    }
+    fn error(&self, reason: String) -> LowerError {
+        LowerError {
+            reason,
+            span: self.span,
+        }
+    }
+    /// A block whose terminator is filled in when it's finished
+    fn new_block(&mut self) -> BlockId {
+        self.func.blocks.push(Block {
+            insts: Vec::new(),
+            term: Terminator::Return(ValueId(0)),
+        });
+        BlockId(self.func.blocks.len() as u32 - 1)
+    }
+    fn define(&mut self, ty: Ty, op: Op) -> ValueId {
+        let val = ValueId(self.func.types.len() as u32);
+        self.func.types.push(ty);
+        self.func.blocks[self.current.0 as usize]
+            .insts
+            .push(Inst::Define(val, op));
+        val
+    }
+    fn constant(&mut self, val: Constant) -> ValueId {
+        self.define(val.ty(), Op::Const(val))
+    }
+    /// The function's only unit value, defined in the entry block the first
+    /// time it's needed so it's available everywhere after
+    fn unit(&mut self) -> ValueId {
+        if let Some(val) = self.unit {
+            return val;
+        }
+        let val = ValueId(self.func.types.len() as u32);
+        self.func.types.push(Ty::Unit);
+        self.func.blocks[0]
+            .insts
+            .push(Inst::Define(val, Op::Const(Constant::Unit)));
+        self.unit = Some(val);
+        val
+    }
+    fn finish(&mut self, term: Terminator) {
+        self.func.blocks[self.current.0 as usize].term = term;
+    }
+    fn ret(&mut self, val: ValueId) -> Result<(), LowerError> {
+        let found = self.func.ty(val);
+        if found != self.func.ret {
+            return Err(self.error(format!(
+                "{0} returns {1} here but {found} elsewhere",
+                self.func.name, self.func.ret
+            )));
+        }
+        self.finish(Terminator::Return(val));
+        Ok(())
+    }
+    fn local(&self, name: &str) -> Option<ValueId> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+    /// The value of an expression statement, unit for anything else
+    fn stmt(&mut self, stmt: &Stmt) -> Lowered<Option<ValueId>> {
+        let outer = std::mem::replace(&mut self.span, stmt.span);
+        let val = self.stmt_kind(stmt);
+        self.span = outer;
+        val
+    }
+    fn stmt_kind(&mut self, stmt: &Stmt) -> Lowered<Option<ValueId>> {
+        match &stmt.kind {
+            StmtKind::Let(id, expr) | StmtKind::Const(id, expr) => {
+                let Some(val) = self.expr(expr)? else {
+                    return Ok(None);
+                };
+                match self.scopes.last_mut() {
+                    Some(scope) => {
+                        scope.insert(id.name.clone(), val);
+                    }
+                    None => self.store(&id.name, val)?,
+                }
+            }
+            StmtKind::Assign(id, expr) => {
+                let Some(val) = self.expr(expr)? else {
+                    return Ok(None);
+                };
+                match self
+                    .scopes
+                    .iter_mut()
+                    .rev()
+                    .find(|scope| scope.contains_key(&id.name))
+                {
+                    Some(scope) => {
+                        scope.insert(id.name.clone(), val);
+                    }
+                    None => self.store(&id.name, val)?,
+                }
+            }
+            // Already lowered on its own
+            StmtKind::Fn(_) => {}
+            StmtKind::Expr(expr) => return Ok(self.expr(expr)?.map(Some)),
+            StmtKind::Return(expr) => {
+                let val = match expr {
+                    Some(expr) => match self.expr(expr)? {
+                        Some(val) => val,
+                        None => return Ok(None),
+                    },
+                    None => self.unit(),
+                };
+                self.ret(val)?;
+                return Ok(None);
+            }
+        }
+        Ok(Some(None))
+    }
+    fn store(&mut self, name: &str, val: ValueId) -> Result<(), LowerError> {
+        let global = self
+            .module
+            .global(name)
+            .ok_or_else(|| self.error(format!("{name} isn't a global of this program")))?;
+        let found = self.func.ty(val);
+        if global.ty != found {
+            return Err(self.error(format!(
+                "{name} changes type from {0} to {found}",
+                global.ty
+            )));
+        }
+        self.func.blocks[self.current.0 as usize]
+            .insts
+            .push(Inst::Store(name.to_string(), val));
+        Ok(())
+    }
+    /// The value of a trailing expression statement, or else unit
+    fn block(&mut self, stmts: &[Stmt]) -> Lowered<ValueId> {
+        self.scopes.push(HashMap::new());
+        let mut val = None;
+        for stmt in stmts {
+            match self.stmt(stmt)? {
+                Some(stmt_val) => val = stmt_val,
+                None => {
+                    self.scopes.pop();
+                    return Ok(None);
+                }
+            }
+        }
+        self.scopes.pop();
+        Ok(Some(val.unwrap_or_else(|| self.unit())))
+    }
+    fn expr(&mut self, expr: &Expr) -> Lowered<ValueId> {
+        let outer = std::mem::replace(&mut self.span, expr.span);
+        let val = self.expr_kind(expr);
+        self.span = outer;
+        val
+    }
+    /// Lower the expressions in order, stopping if one returns
+    fn exprs(&mut self, exprs: &[Expr]) -> Lowered<Vec<ValueId>> {
+        let mut vals = Vec::new();
+        for expr in exprs {
+            match self.expr(expr)? {
+                Some(val) => vals.push(val),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(vals))
+    }
+    fn expr_kind(&mut self, expr: &Expr) -> Lowered<ValueId> {
+        let val = match &expr.kind {
+            ExprKind::Number(val) => self.constant(Constant::Num(*val)),
+            ExprKind::Bool(val) => self.constant(Constant::Bool(*val)),
+            ExprKind::Variable(name) => {
+                if let Some(val) = self.local(name) {
+                    return Ok(Some(val));
+                }
+                if let Some(global) = self.module.global(name) {
+                    self.define(global.ty, Op::Load(name.clone()))
+                } else if self.signatures.contains_key(name) {
+                    return Err(self.error(String::from("Functions can't be used as values")));
+                } else if let Some(val) = constant(name) {
+                    self.constant(Constant::Num(val))
+                } else {
+                    return Err(self.error(format!("{name} isn't defined in this program")));
+                }
+            }
+            ExprKind::BinaryOp(left, op, right) => {
+                let Some(left) = self.expr(left)? else {
+                    return Ok(None);
+                };
+                let Some(right) = self.expr(right)? else {
+                    return Ok(None);
+                };
+                let ty = match op.is_comparison() {
+                    true => Ty::Bool,
+                    false => Ty::Num,
+                };
+                self.define(ty, Op::Binary(*op, left, right))
+            }
+            ExprKind::UnaryOp(operand, op) => {
+                let Some(val) = self.expr(operand)? else {
+                    return Ok(None);
+                };
+                self.define(Ty::Num, Op::Unary(*op, val))
+            }
+            ExprKind::Builtin(builtin, args) => {
+                let Some(args) = self.exprs(args)? else {
+                    return Ok(None);
+                };
+                self.define(Ty::Num, Op::Builtin(*builtin, args))
+            }
+            ExprKind::Block(stmts) => return self.block(stmts),
+            ExprKind::FunctionCall(id, args) => {
+                if self.local(&id.name).is_some() || self.module.global(&id.name).is_some() {
+                    return Err(
+                        self.error(String::from("Only functions defined with fn can be called"))
+                    );
+                }
+                let Some((params, ret)) = self.signatures.get(&id.name).cloned() else {
+                    return Err(self.error(format!("{0} isn't defined in this program", id.name)));
+                };
+                let Some(args) = self.exprs(args)? else {
+                    return Ok(None);
+                };
+                for (idx, (&arg, param)) in args.iter().zip(&params).enumerate() {
+                    let found = self.func.ty(arg);
+                    if found != *param {
+                        return Err(self.error(format!(
+                            "Argument {0} of {1} is {found}, the IR only has a version taking {param}",
+                            idx + 1,
+                            id.name
+                        )));
+                    }
+                }
+                self.define(ret, Op::Call(id.name.clone(), args))
+            }
+            ExprKind::If(cond, then_branch, else_branch) => {
+                return self.lower_if(cond, then_branch, else_branch.as_deref())
+            }
+        };
+        Ok(Some(val))
+    }
+    /// Branch on the condition, joining with phis for the value and for every
+    /// local the branches leave different
+    fn lower_if(
+        &mut self,
+        cond: &Expr,
+        then_branch: &Expr,
+        else_branch: Option<&Expr>,
+    ) -> Lowered<ValueId> {
+        let Some(cond) = self.expr(cond)? else {
+            return Ok(None);
+        };
+        let then_block = self.new_block();
+        let else_block = self.new_block();
+        self.finish(Terminator::Branch(cond, then_block, else_block));
+        let scopes = self.scopes.clone();
+
+        let mut ends: Vec<(BlockId, ValueId, Scopes)> = Vec::new();
+        for (block, branch) in [(then_block, Some(then_branch)), (else_block, else_branch)] {
+            self.current = block;
+            self.scopes = scopes.clone();
+            let val = match branch {
+                Some(branch) => self.expr(branch)?,
+                None => Some(self.unit()),
+            };
+            if let Some(val) = val {
+                ends.push((self.current, val, std::mem::take(&mut self.scopes)));
+            }
+        }
+
+        match ends.len() {
+            0 => Ok(None),
+            1 => {
+                let (block, val, scopes) = ends.pop().expect("one branch");
+                self.current = block;
+                self.scopes = scopes;
+                Ok(Some(val))
+            }
+            _ => {
+                let join = self.new_block();
+                for &(block, _, _) in &ends {
+                    self.current = block;
+                    self.finish(Terminator::Jump(join));
+                }
+                self.current = join;
+                let (then_end, else_end) = (&ends[0], &ends[1]);
+                let val = self.phi(then_end, else_end, then_end.1, else_end.1)?;
+                let mut scopes = then_end.2.clone();
+                for (depth, scope) in scopes.iter_mut().enumerate() {
+                    let mut names: Vec<&String> = scope.keys().collect();
+                    names.sort();
+                    let mut joined = HashMap::new();
+                    for name in names {
+                        let (then_val, else_val) = (scope[name], else_end.2[depth][name]);
+                        joined.insert(
+                            name.clone(),
+                            self.phi(then_end, else_end, then_val, else_val)?,
+                        );
+                    }
+                    *scope = joined;
+                }
+                self.scopes = scopes;
+                Ok(Some(val))
+            }
+        }
+    }
+    fn phi(
+        &mut self,
+        then_end: &(BlockId, ValueId, Scopes),
+        else_end: &(BlockId, ValueId, Scopes),
+        then_val: ValueId,
+        else_val: ValueId,
+    ) -> Result<ValueId, LowerError> {
+        if then_val == else_val {
+            return Ok(then_val);
+        }
+        let ty = self.func.ty(then_val);
+        if self.func.ty(else_val) != ty {
+            return Err(self.error(String::from("Branches of this if have different types")));
+        }
+        Ok(self.define(
+            ty,
+            Op::Phi(vec![(then_val, then_end.0), (else_val, else_end.0)]),
+        ))
+    }
+}
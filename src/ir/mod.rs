@@ -0,0 +1,6 @@
+pub mod display;
+#[allow(clippy::module_inception)]
+pub mod ir;
+pub mod lower;
+pub mod parse;
+pub mod verify;
@@ -0,0 +1,368 @@
+use crate::ast::ast::Builtin;
+use crate::ir::display::BINARY_NAMES;
+use crate::ir::display::UNARY_NAMES;
+use crate::ir::ir::Block;
+use crate::ir::ir::BlockId;
+use crate::ir::ir::Constant;
+use crate::ir::ir::Function;
+use crate::ir::ir::Global;
+use crate::ir::ir::Inst;
+use crate::ir::ir::Module;
+use crate::ir::ir::Op;
+use crate::ir::ir::Terminator;
+use crate::ir::ir::Ty;
+use crate::ir::ir::ValueId;
+
+/// Read the textual form written by `Module`'s `Display`
+///
+/// Only the syntax is checked here, `verify` checks the result makes sense.
+/// Text after a `;` is a comment
+pub fn parse(text: &str) -> Result<Module, String> {
+    let mut parser = Parser {
+        tokens: lex(text),
+        pos: 0,
+    };
+    let mut module = Module::default();
+    loop {
+        parser.newlines();
+        match parser.peek() {
+            Tok::End => return Ok(module),
+            Tok::Word(word) if word == "global" => {
+                parser.pos += 1;
+                let name = parser.global_name()?;
+                parser.expect(Tok::Sym(':'))?;
+                let ty = parser.ty()?;
+                parser.end_of_line()?;
+                module.globals.push(Global { name, ty });
+            }
+            Tok::Word(word) if word == "fn" => {
+                parser.pos += 1;
+                module.functions.push(parser.function()?);
+            }
+            _ => return Err(parser.unexpected("global or fn")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    /// Names, numbers and keywords
+    Word(String),
+    Sym(char),
+    Arrow,
+    Newline,
+    End,
+}
+
+fn lex(text: &str) -> Vec<(Tok, usize)> {
+    let mut tokens = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let line_number = idx + 1;
+        let code = line.split(';').next().unwrap_or("");
+        let mut chars = code.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c.is_whitespace() {
+                continue;
+            }
+            if c == '-' && chars.peek() == Some(&'>') {
+                chars.next();
+                tokens.push((Tok::Arrow, line_number));
+            } else if c.is_alphanumeric() || "_.-+".contains(c) {
+                let mut word = String::from(c);
+                while let Some(&next) = chars.peek() {
+                    if !(next.is_alphanumeric() || "_.-+".contains(next)) {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                tokens.push((Tok::Word(word), line_number));
+            } else {
+                tokens.push((Tok::Sym(c), line_number));
+            }
+        }
+        tokens.push((Tok::Newline, line_number));
+    }
+    let last = tokens.last().map_or(1, |&(_, line)| line);
+    tokens.push((Tok::End, last));
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<(Tok, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Tok {
+        &self.tokens[self.pos].0
+    }
+    fn next(&mut self) -> Tok {
+        let tok = self.tokens[self.pos].0.clone();
+        if tok != Tok::End {
+            self.pos += 1;
+        }
+        tok
+    }
+    fn unexpected(&self, expected: &str) -> String {
+        let (tok, line) = &self.tokens[self.pos];
+        let found = match tok {
+            Tok::Word(word) => word.clone(),
+            Tok::Sym(c) => c.to_string(),
+            Tok::Arrow => String::from("->"),
+            Tok::Newline => String::from("end of line"),
+            Tok::End => String::from("end of input"),
+        };
+        format!("Expected {expected}, found {found} on line {line}")
+    }
+    fn expect(&mut self, tok: Tok) -> Result<(), String> {
+        if *self.peek() != tok {
+            let expected = match &tok {
+                Tok::Sym(c) => c.to_string(),
+                Tok::Arrow => String::from("->"),
+                _ => format!("{tok:?}"),
+            };
+            return Err(self.unexpected(&expected));
+        }
+        self.next();
+        Ok(())
+    }
+    fn eat(&mut self, tok: Tok) -> bool {
+        let found = *self.peek() == tok;
+        if found {
+            self.next();
+        }
+        found
+    }
+    fn newlines(&mut self) {
+        while self.eat(Tok::Newline) {}
+    }
+    fn end_of_line(&mut self) -> Result<(), String> {
+        match self.peek() {
+            Tok::Newline | Tok::End => {
+                self.newlines();
+                Ok(())
+            }
+            _ => Err(self.unexpected("end of line")),
+        }
+    }
+    fn word(&mut self, expected: &str) -> Result<String, String> {
+        match self.peek() {
+            Tok::Word(word) => {
+                let word = word.clone();
+                self.next();
+                Ok(word)
+            }
+            _ => Err(self.unexpected(expected)),
+        }
+    }
+    /// `@name`
+    fn global_name(&mut self) -> Result<String, String> {
+        self.expect(Tok::Sym('@'))?;
+        self.word("name")
+    }
+    fn ty(&mut self) -> Result<Ty, String> {
+        let ty = match self.peek() {
+            Tok::Word(word) if word == "num" => Ty::Num,
+            Tok::Word(word) if word == "bool" => Ty::Bool,
+            Tok::Word(word) if word == "unit" => Ty::Unit,
+            _ => return Err(self.unexpected("type")),
+        };
+        self.next();
+        Ok(ty)
+    }
+    /// `%n`
+    fn value(&mut self) -> Result<ValueId, String> {
+        if *self.peek() != Tok::Sym('%') {
+            return Err(self.unexpected("value"));
+        }
+        self.next();
+        match self.peek() {
+            Tok::Word(word) => match word.parse() {
+                Ok(idx) => {
+                    self.next();
+                    Ok(ValueId(idx))
+                }
+                Err(_) => Err(self.unexpected("value number")),
+            },
+            _ => Err(self.unexpected("value number")),
+        }
+    }
+    /// `bn`
+    fn block(&mut self) -> Result<BlockId, String> {
+        match self.peek() {
+            Tok::Word(word) => match word.strip_prefix('b').map(str::parse) {
+                Some(Ok(idx)) => {
+                    self.next();
+                    Ok(BlockId(idx))
+                }
+                _ => Err(self.unexpected("block")),
+            },
+            _ => Err(self.unexpected("block")),
+        }
+    }
+    /// Values separated by commas until the end of the line or `)`
+    fn values(&mut self) -> Result<Vec<ValueId>, String> {
+        let mut vals = vec![self.value()?];
+        while self.eat(Tok::Sym(',')) {
+            vals.push(self.value()?);
+        }
+        Ok(vals)
+    }
+    fn function(&mut self) -> Result<Function, String> {
+        let name = self.global_name()?;
+        self.expect(Tok::Sym('('))?;
+        let mut params = Vec::new();
+        if !self.eat(Tok::Sym(')')) {
+            loop {
+                let param = self.value()?;
+                if param.0 as usize != params.len() {
+                    self.pos -= 1;
+                    return Err(self.unexpected(&format!("parameter %{0}", params.len())));
+                }
+                self.expect(Tok::Sym(':'))?;
+                params.push(self.ty()?);
+                if self.eat(Tok::Sym(')')) {
+                    break;
+                }
+                self.expect(Tok::Sym(','))?;
+            }
+        }
+        self.expect(Tok::Arrow)?;
+        let ret = self.ty()?;
+        self.expect(Tok::Sym('{'))?;
+        self.end_of_line()?;
+
+        let mut func = Function {
+            name,
+            types: params.clone(),
+            params,
+            ret,
+            blocks: Vec::new(),
+        };
+        while !self.eat(Tok::Sym('}')) {
+            let label = self.block()?;
+            if label.0 as usize != func.blocks.len() {
+                self.pos -= 1;
+                return Err(self.unexpected(&format!("block b{0}", func.blocks.len())));
+            }
+            self.expect(Tok::Sym(':'))?;
+            self.end_of_line()?;
+            let block = self.block_body(&mut func.types)?;
+            func.blocks.push(block);
+        }
+        self.end_of_line()?;
+        Ok(func)
+    }
+    /// Instructions up to and including the terminator
+    fn block_body(&mut self, types: &mut Vec<Ty>) -> Result<Block, String> {
+        let mut insts = Vec::new();
+        loop {
+            let inst = match self.peek().clone() {
+                Tok::Sym('%') => {
+                    let val = self.value()?;
+                    self.expect(Tok::Sym(':'))?;
+                    let ty = self.ty()?;
+                    self.expect(Tok::Sym('='))?;
+                    let op = self.op()?;
+                    let idx = val.0 as usize;
+                    if types.len() <= idx {
+                        types.resize(idx + 1, Ty::Unit);
+                    }
+                    types[idx] = ty;
+                    Inst::Define(val, op)
+                }
+                Tok::Word(word) if word == "store" => {
+                    self.next();
+                    let name = self.global_name()?;
+                    self.expect(Tok::Sym(','))?;
+                    Inst::Store(name, self.value()?)
+                }
+                Tok::Word(word) if ["jmp", "br", "ret"].contains(&word.as_str()) => {
+                    self.next();
+                    let term = match word.as_str() {
+                        "jmp" => Terminator::Jump(self.block()?),
+                        "br" => {
+                            let cond = self.value()?;
+                            self.expect(Tok::Sym(','))?;
+                            let then_block = self.block()?;
+                            self.expect(Tok::Sym(','))?;
+                            Terminator::Branch(cond, then_block, self.block()?)
+                        }
+                        _ => Terminator::Return(self.value()?),
+                    };
+                    self.end_of_line()?;
+                    return Ok(Block { insts, term });
+                }
+                _ => return Err(self.unexpected("instruction")),
+            };
+            self.end_of_line()?;
+            insts.push(inst);
+        }
+    }
+    fn op(&mut self) -> Result<Op, String> {
+        let word = self.word("operation")?;
+        if let Some(&(op, _)) = BINARY_NAMES.iter().find(|(_, name)| *name == word) {
+            let left = self.value()?;
+            self.expect(Tok::Sym(','))?;
+            return Ok(Op::Binary(op, left, self.value()?));
+        }
+        if let Some(&(op, _)) = UNARY_NAMES.iter().find(|(_, name)| *name == word) {
+            return Ok(Op::Unary(op, self.value()?));
+        }
+        if let Some(builtin) = Builtin::from_name(&word) {
+            return Ok(Op::Builtin(builtin, self.values()?));
+        }
+        match word.as_str() {
+            "const" => {
+                let val = match self.peek() {
+                    Tok::Sym('(') => {
+                        self.next();
+                        self.expect(Tok::Sym(')'))?;
+                        return Ok(Op::Const(Constant::Unit));
+                    }
+                    Tok::Word(word) if word == "true" => Constant::Bool(true),
+                    Tok::Word(word) if word == "false" => Constant::Bool(false),
+                    Tok::Word(word) => match word.parse() {
+                        Ok(val) => Constant::Num(val),
+                        Err(_) => return Err(self.unexpected("constant")),
+                    },
+                    _ => return Err(self.unexpected("constant")),
+                };
+                self.next();
+                Ok(Op::Const(val))
+            }
+            "call" => {
+                let name = self.global_name()?;
+                self.expect(Tok::Sym('('))?;
+                let args = match self.eat(Tok::Sym(')')) {
+                    true => Vec::new(),
+                    false => {
+                        let args = self.values()?;
+                        self.expect(Tok::Sym(')'))?;
+                        args
+                    }
+                };
+                Ok(Op::Call(name, args))
+            }
+            "load" => Ok(Op::Load(self.global_name()?)),
+            "phi" => {
+                let mut incoming = Vec::new();
+                loop {
+                    self.expect(Tok::Sym('['))?;
+                    let val = self.value()?;
+                    self.expect(Tok::Sym(','))?;
+                    incoming.push((val, self.block()?));
+                    self.expect(Tok::Sym(']'))?;
+                    if !self.eat(Tok::Sym(',')) {
+                        return Ok(Op::Phi(incoming));
+                    }
+                }
+            }
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected("operation"))
+            }
+        }
+    }
+}
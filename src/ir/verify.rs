@@ -0,0 +1,304 @@
+use crate::ast::ast::BinaryOp;
+use crate::ir::ir::BlockId;
+use crate::ir::ir::Function;
+use crate::ir::ir::Inst;
+use crate::ir::ir::Module;
+use crate::ir::ir::Op;
+use crate::ir::ir::Terminator;
+use crate::ir::ir::Ty;
+use crate::ir::ir::ValueId;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/// Check a module is well formed, returning the first problem found
+///
+/// Every value is defined once and before every use along every path, so
+/// its definition dominates its uses. Operands have the types their
+/// operation needs, phis start their block and have one incoming value per
+/// predecessor, every block is reachable and names refer to globals and
+/// functions that exist
+pub fn verify(module: &Module) -> Result<(), String> {
+    let mut names = HashSet::new();
+    for global in &module.globals {
+        if !names.insert(&global.name) {
+            return Err(format!("Global @{0} is declared twice", global.name));
+        }
+    }
+    let mut names = HashSet::new();
+    for func in &module.functions {
+        if !names.insert(&func.name) {
+            return Err(format!("Function @{0} is defined twice", func.name));
+        }
+        verify_function(module, func).map_err(|err| format!("{err} in @{0}", func.name))?;
+    }
+    Ok(())
+}
+
+/// Where a value is defined, parameters come before the first block
+#[derive(Clone, Copy)]
+enum Def {
+    Param,
+    Inst(BlockId, usize),
+}
+
+fn verify_function(module: &Module, func: &Function) -> Result<(), String> {
+    if func.blocks.is_empty() {
+        return Err(String::from("No blocks"));
+    }
+    if func.types.len() < func.params.len() || func.types[..func.params.len()] != func.params {
+        return Err(String::from("Parameter types don't match the signature"));
+    }
+
+    let mut defs: HashMap<ValueId, Def> = (0..func.params.len())
+        .map(|idx| (ValueId(idx as u32), Def::Param))
+        .collect();
+    for (idx, block) in func.blocks.iter().enumerate() {
+        for (pos, inst) in block.insts.iter().enumerate() {
+            if let Inst::Define(val, _) = inst {
+                if defs
+                    .insert(*val, Def::Inst(BlockId(idx as u32), pos))
+                    .is_some()
+                {
+                    return Err(format!("{val} is defined twice"));
+                }
+            }
+        }
+    }
+    for idx in 0..func.types.len() {
+        if !defs.contains_key(&ValueId(idx as u32)) {
+            return Err(format!("%{idx} is never defined"));
+        }
+    }
+    if defs.len() != func.types.len() {
+        return Err(String::from("Values aren't numbered consecutively"));
+    }
+
+    for (idx, block) in func.blocks.iter().enumerate() {
+        for succ in block.term.successors() {
+            if succ.0 as usize >= func.blocks.len() {
+                return Err(format!("{succ} doesn't exist, jumped to from b{idx}"));
+            }
+        }
+    }
+    let preds = func.predecessors();
+    if !preds[0].is_empty() {
+        return Err(String::from("The entry block b0 is jumped to"));
+    }
+    let idom = dominators(func, &preds);
+    if let Some(idx) = idom.iter().position(Option::is_none) {
+        return Err(format!("b{idx} is unreachable"));
+    }
+    let dominates = |a: BlockId, b: BlockId| {
+        let mut block = b;
+        loop {
+            if block == a {
+                return true;
+            }
+            match idom[block.0 as usize] {
+                Some(up) if up != block => block = up,
+                _ => return false,
+            }
+        }
+    };
+    // Whether `val` is available at position `pos` of `block`
+    let available = |val: ValueId, block: BlockId, pos: usize| match defs.get(&val) {
+        None => Err(format!("{val} is never defined")),
+        Some(Def::Param) => Ok(()),
+        Some(&Def::Inst(def_block, def_pos)) => {
+            let ok = match def_block == block {
+                true => def_pos < pos,
+                false => dominates(def_block, block),
+            };
+            match ok {
+                true => Ok(()),
+                false => Err(format!(
+                    "{val} is used in {block} where it may not be defined"
+                )),
+            }
+        }
+    };
+    let expect = |val: ValueId, ty: Ty, what: &str| match func.ty(val) == ty {
+        true => Ok(()),
+        false => Err(format!("{what} must be {ty}, {val} is {0}", func.ty(val))),
+    };
+
+    for (idx, block) in func.blocks.iter().enumerate() {
+        let here = BlockId(idx as u32);
+        let mut phis_done = false;
+        for (pos, inst) in block.insts.iter().enumerate() {
+            let (result, op) = match inst {
+                Inst::Define(val, op) => (Some(*val), op),
+                Inst::Store(name, val) => {
+                    phis_done = true;
+                    available(*val, here, pos)?;
+                    let global = module
+                        .global(name)
+                        .ok_or_else(|| format!("Global @{name} doesn't exist"))?;
+                    expect(*val, global.ty, &format!("Storing to @{name}"))?;
+                    continue;
+                }
+            };
+            let result = result.expect("definitions have a result");
+            let ty = func.ty(result);
+            if let Op::Phi(incoming) = op {
+                if phis_done || idx == 0 {
+                    return Err(format!("Phi {result} isn't at the start of {here}"));
+                }
+                let mut from: Vec<BlockId> = incoming.iter().map(|&(_, block)| block).collect();
+                from.sort();
+                if from != preds[idx] {
+                    return Err(format!(
+                        "Phi {result} doesn't have one value for each predecessor of {here}"
+                    ));
+                }
+                for &(val, pred) in incoming {
+                    let end = func.block(pred).insts.len();
+                    available(val, pred, end)?;
+                    expect(val, ty, &format!("Phi {result}"))?;
+                }
+                continue;
+            }
+            phis_done = true;
+            for val in op.operands() {
+                available(val, here, pos)?;
+            }
+            let found = match op {
+                Op::Const(val) => val.ty(),
+                Op::Binary(BinaryOp::Equal | BinaryOp::NotEqual, left, right) => {
+                    expect(*right, func.ty(*left), &format!("Comparing with {left}"))?;
+                    Ty::Bool
+                }
+                Op::Binary(op, left, right) => {
+                    expect(*left, Ty::Num, &format!("Operand of {op}"))?;
+                    expect(*right, Ty::Num, &format!("Operand of {op}"))?;
+                    match op.is_comparison() {
+                        true => Ty::Bool,
+                        false => Ty::Num,
+                    }
+                }
+                Op::Unary(op, operand) => {
+                    expect(*operand, Ty::Num, &format!("Operand of {op}"))?;
+                    Ty::Num
+                }
+                Op::Builtin(builtin, args) => {
+                    let (min, max) = builtin.arity();
+                    if !(min..=max).contains(&args.len()) {
+                        return Err(format!(
+                            "{builtin} takes {min} to {max} arguments, found {0}",
+                            args.len()
+                        ));
+                    }
+                    for &arg in args {
+                        expect(arg, Ty::Num, &format!("Argument of {builtin}"))?;
+                    }
+                    Ty::Num
+                }
+                Op::Call(name, args) => {
+                    let callee = module
+                        .function(name)
+                        .ok_or_else(|| format!("Function @{name} doesn't exist"))?;
+                    if callee.params.len() != args.len() {
+                        return Err(format!(
+                            "@{name} takes {0} arguments, found {1}",
+                            callee.params.len(),
+                            args.len()
+                        ));
+                    }
+                    for (&arg, &param) in args.iter().zip(&callee.params) {
+                        expect(arg, param, &format!("Argument of @{name}"))?;
+                    }
+                    callee.ret
+                }
+                Op::Load(name) => {
+                    module
+                        .global(name)
+                        .ok_or_else(|| format!("Global @{name} doesn't exist"))?
+                        .ty
+                }
+                Op::Phi(_) => unreachable!("handled above"),
+            };
+            if found != ty {
+                return Err(format!("{result} is declared {ty} but {op} is {found}"));
+            }
+        }
+
+        let end = block.insts.len();
+        match block.term {
+            Terminator::Jump(_) => {}
+            Terminator::Branch(cond, _, _) => {
+                available(cond, here, end)?;
+                expect(cond, Ty::Bool, "A branch condition")?;
+            }
+            Terminator::Return(val) => {
+                available(val, here, end)?;
+                expect(val, func.ret, "The returned value")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Immediate dominator of each block, the entry is its own and unreachable
+/// blocks have none
+fn dominators(func: &Function, preds: &[Vec<BlockId>]) -> Vec<Option<BlockId>> {
+    // Reverse postorder from the entry
+    let mut order = Vec::new();
+    let mut seen = vec![false; func.blocks.len()];
+    let mut stack = vec![(BlockId(0), false)];
+    while let Some((block, done)) = stack.pop() {
+        if done {
+            order.push(block);
+            continue;
+        }
+        if std::mem::replace(&mut seen[block.0 as usize], true) {
+            continue;
+        }
+        stack.push((block, true));
+        for succ in func.block(block).term.successors().into_iter().rev() {
+            if !seen[succ.0 as usize] {
+                stack.push((succ, false));
+            }
+        }
+    }
+    order.reverse();
+    let mut rank = vec![usize::MAX; func.blocks.len()];
+    for (idx, block) in order.iter().enumerate() {
+        rank[block.0 as usize] = idx;
+    }
+
+    // Cooper, Harvey and Kennedy's iterative algorithm
+    let mut idom: Vec<Option<BlockId>> = vec![None; func.blocks.len()];
+    idom[0] = Some(BlockId(0));
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in order.iter().skip(1) {
+            let mut new = None;
+            for &pred in &preds[block.0 as usize] {
+                if idom[pred.0 as usize].is_none() {
+                    continue;
+                }
+                new = Some(match new {
+                    None => pred,
+                    Some(other) => {
+                        let (mut a, mut b) = (pred, other);
+                        while a != b {
+                            while rank[a.0 as usize] > rank[b.0 as usize] {
+                                a = idom[a.0 as usize].expect("processed");
+                            }
+                            while rank[b.0 as usize] > rank[a.0 as usize] {
+                                b = idom[b.0 as usize].expect("processed");
+                            }
+                        }
+                        a
+                    }
+                });
+            }
+            if new.is_some() && idom[block.0 as usize] != new {
+                idom[block.0 as usize] = new;
+                changed = true;
+            }
+        }
+    }
+    idom
+}
@@ -1,21 +1,237 @@
-use crate::ast::parser::Parser;
-use crate::tokeniser::tokeniser::Tokeniser;
+use crate::ast::parser::Dialect;
+use crate::optimiser::pipeline::Pipeline;
+use crate::repl::session::Emit;
+use crate::repl::session::Session;
+use crate::semantic::lint::LintConfig;
+use crate::vm::cache::encode;
+use crate::vm::cache::is_bytecode;
+use std::io::Read;
+use std::path::Path;
 pub mod ast;
+pub mod codegen;
+pub mod ir;
+pub mod optimiser;
+pub mod repl;
+pub mod semantic;
 pub mod tokeniser;
+pub mod vm;
 
 #[cfg(test)]
 pub mod tests;
 
 fn main() {
-    let input = r#"
-        fn test(x, y) {
-            x + y
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    // `-o file` names the executable `build` or the C file `emit-c` writes
+    let written = match args.iter().position(|arg| arg == "-o") {
+        Some(idx) if idx + 1 < args.len() => {
+            let file = args.remove(idx + 1);
+            args.remove(idx);
+            Some(file)
         }
-        test(5,2) + test(5,2)
-        "#;
-    let tokens = Tokeniser::new(input.to_string()).to_tokens().unwrap();
-    let mut parser = Parser::new(tokens);
-    let expressions = parser.parse_lines().unwrap();
-    let evaluated_result = parser.evaluate(&expressions);
-    println!("{evaluated_result}");
+        Some(_) => fail("-o needs a file"),
+        None => None,
+    };
+    let (flags, mut paths): (Vec<String>, Vec<String>) =
+        args.into_iter().partition(|arg| arg.starts_with('-'));
+    // `parser_1 build script -o out` compiles the script to an executable,
+    // `parser_1 emit-c script -o out.c` translates it to C
+    let build = paths.first().is_some_and(|first| first == "build");
+    let emit_c = paths.first().is_some_and(|first| first == "emit-c");
+    if build || emit_c {
+        paths.remove(0);
+    } else if written.is_some() {
+        fail("-o is only for build and emit-c");
+    }
+    let mut dialect = Dialect::default();
+    let mut lints = LintConfig::default();
+    let mut pipeline = Pipeline::default();
+    let mut emit = None;
+    let mut vm = false;
+    let mut jit = false;
+    let mut output = None;
+    let mut wasm = None;
+    for flag in &flags {
+        let applied = if flag == "--strict" {
+            dialect = Dialect::strict();
+            Ok(true)
+        } else if flag == "--vm" {
+            vm = true;
+            Ok(true)
+        } else if flag == "--jit" {
+            jit = true;
+            Ok(true)
+        } else if let Some(file) = flag.strip_prefix("--compile=") {
+            output = Some(file.to_string());
+            Ok(true)
+        } else if let Some(file) = flag.strip_prefix("--wasm=") {
+            wasm = Some(file.to_string());
+            Ok(true)
+        } else if let Some(stage) = flag.strip_prefix("--emit=") {
+            Emit::from_name(stage)
+                .map(|stage| {
+                    emit = Some(stage);
+                    true
+                })
+                .ok_or_else(|| format!("Unknown stage: {stage}"))
+        } else {
+            pipeline
+                .apply_flag(flag)
+                .and_then(|applied| Ok(applied || lints.apply_flag(flag)?))
+        };
+        match applied {
+            Ok(true) => {}
+            Ok(false) => fail(&format!("Unknown flag: {flag}")),
+            Err(err) => fail(&err),
+        }
+    }
+
+    // `parser_1 [flags] script` evaluates a file, no arguments starts the REPL
+    let path = paths.into_iter().next();
+    match path {
+        Some(_) => pipeline.keep_globals = false,
+        None if build => fail("build needs a script"),
+        None if emit_c => fail("emit-c needs a script"),
+        None if emit.is_some() => fail("--emit needs a script"),
+        None if output.is_some() => fail("--compile needs a script"),
+        None if wasm.is_some() => fail("--wasm needs a script"),
+        None => lints.incremental = true,
+    }
+    let mut session = Session::with_dialect(dialect)
+        .with_lints(lints)
+        .with_pipeline(pipeline);
+    // Compiled scripts only run on the virtual machine
+    if vm || path.as_deref().is_some_and(starts_as_bytecode) {
+        session = session.with_vm();
+    }
+    if jit {
+        session = session.with_jit();
+    }
+
+    match path {
+        Some(path) => {
+            let result = match (emit, &output) {
+                _ if build => {
+                    let file = written.clone().unwrap_or_else(|| stem(&path));
+                    if file == path {
+                        fail("build would overwrite the script, name the executable with -o");
+                    }
+                    read_source(&path)
+                        .and_then(|source| session.compile_executable(&source))
+                        .and_then(|bytes| write_executable(&file, &bytes))
+                        .map(|_| None)
+                }
+                // Printed unless `-o` names a file
+                _ if emit_c => read_source(&path)
+                    .and_then(|source| session.compile_c(&source))
+                    .and_then(|text| match &written {
+                        Some(file) => std::fs::write(file, text)
+                            .map_err(|err| format!("Failed to write {0}: {1}", file, err)),
+                        None => {
+                            print!("{text}");
+                            Ok(())
+                        }
+                    })
+                    .map(|_| None),
+                _ if wasm.is_some() => {
+                    let file = wasm.as_deref().unwrap_or_default();
+                    read_source(&path)
+                        .and_then(|source| session.compile_wasm(&source))
+                        .and_then(|bytes| {
+                            std::fs::write(file, bytes)
+                                .map_err(|err| format!("Failed to write {0}: {1}", file, err))
+                        })
+                        .map(|_| None)
+                }
+                (Some(stage), _) if starts_as_bytecode(&path) => std::fs::read(&path)
+                    .map_err(|err| format!("Failed to read {0}: {1}", path, err))
+                    .and_then(|bytes| session.emit_compiled(&bytes, stage))
+                    .map(|text| {
+                        println!("{text}");
+                        None
+                    }),
+                (Some(stage), _) => read_source(&path)
+                    .and_then(|source| session.emit(&source, stage))
+                    .map(|text| {
+                        println!("{text}");
+                        None
+                    }),
+                (None, Some(output)) => read_source(&path)
+                    .and_then(|source| session.compile_bytecode(&source))
+                    .and_then(|program| {
+                        std::fs::write(output, encode(&program))
+                            .map_err(|err| format!("Failed to write {0}: {1}", output, err))
+                    })
+                    .map(|_| None),
+                (None, None) => session.load(&path),
+            };
+            for warning in session.take_warnings() {
+                eprintln!("warning: {warning}");
+            }
+            match result {
+                Ok(Some(val)) => println!("{val}"),
+                Ok(None) => {}
+                Err(err) => fail(&err),
+            }
+        }
+        None => {
+            let stdin = std::io::stdin();
+            if let Err(err) = session.run(stdin.lock(), std::io::stdout()) {
+                fail(&err.to_string());
+            }
+        }
+    }
+}
+
+/// The script's text, files written by `--compile` can only be run or
+/// disassembled
+fn read_source(path: &str) -> Result<String, String> {
+    let bytes = std::fs::read(path).map_err(|err| format!("Failed to read {0}: {1}", path, err))?;
+    if is_bytecode(&bytes) {
+        return Err(format!(
+            "{path} is compiled bytecode, which can only be run or shown with --emit=bytecode"
+        ));
+    }
+    String::from_utf8(bytes).map_err(|err| format!("Failed to read {0}: {1}", path, err))
+}
+
+/// The script's name without its directory or extension
+fn stem(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map_or(String::from("a.out"), |stem| {
+            stem.to_string_lossy().to_string()
+        })
+}
+
+/// Write a file anyone may run, elsewhere than Unix the executable bit
+/// doesn't exist and it's written as is
+fn write_executable(path: &str, bytes: &[u8]) -> Result<(), String> {
+    std::fs::write(path, bytes)
+        .and_then(|_| make_executable(path))
+        .map_err(|err| format!("Failed to write {0}: {1}", path, err))
+}
+
+#[cfg(unix)]
+fn make_executable(path: &str) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &str) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Whether the file was written by `--compile`, unreadable files are left
+/// for loading to report
+fn starts_as_bytecode(path: &str) -> bool {
+    let mut magic = [0; 4];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|_| is_bytecode(&magic))
+}
+
+fn fail(err: &str) -> ! {
+    eprintln!("error: {err}");
+    std::process::exit(1);
 }
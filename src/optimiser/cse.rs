@@ -0,0 +1,395 @@
+use crate::ast::ast::BinaryOp;
+use crate::ast::ast::Builtin;
+use crate::ast::ast::Expr;
+use crate::ast::ast::ExprKind;
+use crate::ast::ast::FnDecl;
+use crate::ast::ast::Ident;
+use crate::ast::ast::Stmt;
+use crate::ast::ast::StmtKind;
+use crate::ast::ast::UnaryOp;
+use crate::semantic::resolver::DeclId;
+use crate::semantic::resolver::DeclKind;
+use crate::semantic::resolver::Declaration;
+use crate::semantic::resolver::Resolution;
+use crate::semantic::resolver::Resolved;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/// Compute structurally equal subexpressions once
+///
+/// Each statement's expression is hash-consed into a DAG and every node
+/// reached more than once is bound to a temporary in a block wrapped around
+/// the expression, so `sin(x)*sin(x) + cos(x)*cos(x)` becomes
+/// `{ let cse0 = sin(x); let cse1 = cos(x); cse0*cse0 + cse1*cse1 }`.
+/// Identifiers can't contain digits so the temporaries never clash with a
+/// user's names.
+///
+/// Only the part of an expression that always runs is shared, blocks and
+/// the branches of an `if` are handled on their own. Temporaries are
+/// evaluated before the rest of the expression, so when it also calls a
+/// function or runs a block only subexpressions that can't fail and read
+/// no variable that could be reassigned are moved
+pub fn eliminate_common_subexpressions(resolved: Resolved) -> Resolved {
+    let Resolved { stmts, resolution } = resolved;
+    let assigned = assigned(&stmts, &resolution);
+    let mut cse = Cse {
+        resolution,
+        assigned,
+        next: 0,
+    };
+    let stmts = cse.stmts(stmts);
+    Resolved {
+        stmts,
+        resolution: cse.resolution,
+    }
+}
+
+/// Declarations that are the target of an assignment anywhere in the program
+fn assigned(stmts: &[Stmt], resolution: &Resolution) -> HashSet<DeclId> {
+    let mut assigned = HashSet::new();
+    let mut pending: Vec<&Stmt> = stmts.iter().collect();
+    while let Some(stmt) = pending.pop() {
+        let expr = match &stmt.kind {
+            StmtKind::Assign(id, expr) => {
+                assigned.extend(resolution.uses.get(&id.span));
+                expr
+            }
+            StmtKind::Let(_, expr)
+            | StmtKind::Const(_, expr)
+            | StmtKind::Expr(expr)
+            | StmtKind::Return(Some(expr)) => expr,
+            StmtKind::Fn(decl) => {
+                pending.extend(&decl.body);
+                continue;
+            }
+            StmtKind::Return(None) => continue,
+        };
+        pending.extend(statements_in(expr));
+    }
+    assigned
+}
+
+/// Statements of every block nested in the expression
+fn statements_in(expr: &Expr) -> Vec<&Stmt> {
+    match &expr.kind {
+        ExprKind::Block(stmts) => stmts.iter().collect(),
+        ExprKind::BinaryOp(left, _, right) => {
+            let mut stmts = statements_in(left);
+            stmts.extend(statements_in(right));
+            stmts
+        }
+        ExprKind::UnaryOp(operand, _) => statements_in(operand),
+        ExprKind::Builtin(_, args) | ExprKind::FunctionCall(_, args) => {
+            args.iter().flat_map(statements_in).collect()
+        }
+        ExprKind::If(cond, then_branch, else_branch) => {
+            let mut stmts = statements_in(cond);
+            stmts.extend(statements_in(then_branch));
+            stmts.extend(else_branch.iter().flat_map(|branch| statements_in(branch)));
+            stmts
+        }
+        ExprKind::Number(_) | ExprKind::Bool(_) | ExprKind::Variable(_) => Vec::new(),
+    }
+}
+
+/// Structure of a subexpression with its operands replaced by node ids
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    /// Compared by bits so 0 and -0 stay apart
+    Number(u64),
+    Bool(bool),
+    Variable(String),
+    Binary(BinaryOp, usize, usize),
+    Unary(UnaryOp, usize),
+    Builtin(Builtin, Vec<usize>),
+}
+
+struct Node {
+    /// First occurrence of the subexpression
+    expr: Expr,
+    operands: Vec<usize>,
+    /// How many times the value is needed once shared nodes are computed once
+    uses: usize,
+}
+
+/// The hash-consed DAG of one expression, operands get lower ids than the
+/// nodes using them
+#[derive(Default)]
+struct Dag {
+    ids: HashMap<Key, usize>,
+    nodes: Vec<Node>,
+    /// Whether the expression calls a function or runs a block
+    effects: bool,
+}
+
+impl Dag {
+    fn node(&mut self, key: Key, expr: &Expr, operands: Vec<usize>) -> usize {
+        if let Some(&id) = self.ids.get(&key) {
+            return id;
+        }
+        self.nodes.push(Node {
+            expr: expr.clone(),
+            operands,
+            uses: 0,
+        });
+        self.ids.insert(key, self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+    /// A value needed by something that isn't part of the DAG
+    fn root(&mut self, id: Option<usize>) {
+        if let Some(id) = id {
+            self.nodes[id].uses += 1;
+        }
+    }
+    /// Add the expression, `None` if it can't be shared
+    fn add(&mut self, expr: &Expr) -> Option<usize> {
+        let (key, operands) = match &expr.kind {
+            ExprKind::Number(val) => (Key::Number(val.to_bits()), Vec::new()),
+            ExprKind::Bool(val) => (Key::Bool(*val), Vec::new()),
+            ExprKind::Variable(name) => (Key::Variable(name.clone()), Vec::new()),
+            ExprKind::BinaryOp(left, op, right) => match (self.add(left), self.add(right)) {
+                (Some(left), Some(right)) => (Key::Binary(*op, left, right), vec![left, right]),
+                (left, right) => {
+                    self.root(left);
+                    self.root(right);
+                    return None;
+                }
+            },
+            ExprKind::UnaryOp(operand, op) => match self.add(operand) {
+                Some(operand) => (Key::Unary(*op, operand), vec![operand]),
+                None => return None,
+            },
+            ExprKind::Builtin(builtin, args) => {
+                let args: Vec<Option<usize>> = args.iter().map(|arg| self.add(arg)).collect();
+                match args.iter().copied().collect::<Option<Vec<usize>>>() {
+                    Some(args) => (Key::Builtin(*builtin, args.clone()), args),
+                    None => {
+                        args.into_iter().for_each(|arg| self.root(arg));
+                        return None;
+                    }
+                }
+            }
+            ExprKind::FunctionCall(_, args) => {
+                self.effects = true;
+                for arg in args {
+                    let arg = self.add(arg);
+                    self.root(arg);
+                }
+                return None;
+            }
+            ExprKind::If(cond, _, _) => {
+                self.effects = true;
+                let cond = self.add(cond);
+                self.root(cond);
+                return None;
+            }
+            ExprKind::Block(_) => {
+                self.effects = true;
+                return None;
+            }
+        };
+        Some(self.node(key, expr, operands))
+    }
+    /// Id of an expression already added
+    fn id(&self, expr: &Expr) -> Option<usize> {
+        let key = match &expr.kind {
+            ExprKind::Number(val) => Key::Number(val.to_bits()),
+            ExprKind::Bool(val) => Key::Bool(*val),
+            ExprKind::Variable(name) => Key::Variable(name.clone()),
+            ExprKind::BinaryOp(left, op, right) => {
+                Key::Binary(*op, self.id(left)?, self.id(right)?)
+            }
+            ExprKind::UnaryOp(operand, op) => Key::Unary(*op, self.id(operand)?),
+            ExprKind::Builtin(builtin, args) => Key::Builtin(
+                *builtin,
+                args.iter().map(|arg| self.id(arg)).collect::<Option<_>>()?,
+            ),
+            ExprKind::FunctionCall(..) | ExprKind::If(..) | ExprKind::Block(_) => return None,
+        };
+        self.ids.get(&key).copied()
+    }
+}
+
+/// Division is the only operator that can fail at runtime
+fn cannot_fail(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::BinaryOp(left, BinaryOp::Div, right) => {
+            matches!(right.kind, ExprKind::Number(val) if val != 0.0) && cannot_fail(left)
+        }
+        ExprKind::BinaryOp(left, _, right) => cannot_fail(left) && cannot_fail(right),
+        ExprKind::UnaryOp(operand, _) => cannot_fail(operand),
+        ExprKind::Builtin(_, args) => args.iter().all(cannot_fail),
+        _ => true,
+    }
+}
+
+struct Cse {
+    resolution: Resolution,
+    assigned: HashSet<DeclId>,
+    /// Number of the next temporary
+    next: usize,
+}
+
+impl Cse {
+    fn stmts(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        stmts.into_iter().map(|stmt| self.stmt(stmt)).collect()
+    }
+    fn stmt(&mut self, stmt: Stmt) -> Stmt {
+        let kind = match stmt.kind {
+            StmtKind::Let(id, expr) => StmtKind::Let(id, self.expr(expr)),
+            StmtKind::Const(id, expr) => StmtKind::Const(id, self.expr(expr)),
+            StmtKind::Assign(id, expr) => StmtKind::Assign(id, self.expr(expr)),
+            StmtKind::Fn(decl) => StmtKind::Fn(FnDecl {
+                body: self.stmts(decl.body),
+                ..decl
+            }),
+            StmtKind::Expr(expr) => StmtKind::Expr(self.expr(expr)),
+            StmtKind::Return(expr) => StmtKind::Return(expr.map(|expr| self.expr(expr))),
+        };
+        Stmt::new(kind, stmt.span)
+    }
+    /// Share the subexpressions of an expression that always runs as a whole
+    fn expr(&mut self, expr: Expr) -> Expr {
+        let expr = self.nested(expr);
+        let mut dag = Dag::default();
+        let root = dag.add(&expr);
+        dag.root(root);
+
+        // Parents come after their operands, so walking backwards sees every
+        // use of a node before deciding whether to share it
+        let mut shared = Vec::new();
+        for id in (0..dag.nodes.len()).rev() {
+            let node = &dag.nodes[id];
+            let share = node.uses > 1 && !node.operands.is_empty() && self.movable(&dag, id);
+            let uses = if share { 1 } else { node.uses };
+            for operand in node.operands.clone() {
+                dag.nodes[operand].uses += uses;
+            }
+            if share {
+                shared.push(id);
+            }
+        }
+        if shared.is_empty() {
+            return expr;
+        }
+
+        shared.reverse();
+        let mut temps = HashMap::new();
+        for &id in &shared {
+            temps.insert(id, format!("cse{0}", self.next));
+            self.next += 1;
+        }
+        let mut stmts = Vec::new();
+        let mut decls = HashMap::new();
+        for id in shared {
+            let node = &dag.nodes[id];
+            let name = Ident {
+                name: temps[&id].clone(),
+                span: node.expr.span,
+            };
+            let value = self.replace(&dag, &temps, &decls, node.expr.clone(), false);
+            self.resolution.declarations.push(Declaration {
+                name: name.name.clone(),
+                kind: DeclKind::Let,
+                span: Some(name.span),
+            });
+            decls.insert(id, self.resolution.declarations.len() - 1);
+            stmts.push(Stmt::new(StmtKind::Let(name, value), node.expr.span));
+        }
+        let span = expr.span;
+        let body = self.replace(&dag, &temps, &decls, expr, true);
+        stmts.push(Stmt::new(StmtKind::Expr(body), span));
+        Expr::new(ExprKind::Block(stmts), span)
+    }
+    /// Whether a node can be computed ahead of the rest of the expression
+    fn movable(&self, dag: &Dag, id: usize) -> bool {
+        let expr = &dag.nodes[id].expr;
+        !dag.effects || (cannot_fail(expr) && self.reads_fixed_variables(expr))
+    }
+    /// No variable read can change while the expression runs
+    fn reads_fixed_variables(&self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::Variable(_) => match self.resolution.uses.get(&expr.span) {
+                Some(&id) => match self.resolution.declarations[id].kind {
+                    DeclKind::Const | DeclKind::Constant | DeclKind::Fn(_) => true,
+                    // Globals from earlier input may be assigned by a function
+                    // defined with them
+                    DeclKind::Let | DeclKind::Param => {
+                        self.resolution.declarations[id].span.is_some()
+                            && !self.assigned.contains(&id)
+                    }
+                },
+                None => false,
+            },
+            ExprKind::BinaryOp(left, _, right) => {
+                self.reads_fixed_variables(left) && self.reads_fixed_variables(right)
+            }
+            ExprKind::UnaryOp(operand, _) => self.reads_fixed_variables(operand),
+            ExprKind::Builtin(_, args) => args.iter().all(|arg| self.reads_fixed_variables(arg)),
+            _ => true,
+        }
+    }
+    /// Replace shared subexpressions with their temporaries, the expression
+    /// itself is only replaced when `whole` is set
+    fn replace(
+        &mut self,
+        dag: &Dag,
+        temps: &HashMap<usize, String>,
+        decls: &HashMap<usize, DeclId>,
+        expr: Expr,
+        whole: bool,
+    ) -> Expr {
+        if whole {
+            if let Some(id) = dag.id(&expr).filter(|id| temps.contains_key(id)) {
+                self.resolution.uses.insert(expr.span, decls[&id]);
+                return Expr::new(ExprKind::Variable(temps[&id].clone()), expr.span);
+            }
+        }
+        let mut replace = |expr: Expr| self.replace(dag, temps, decls, expr, true);
+        let kind = match expr.kind {
+            ExprKind::BinaryOp(left, op, right) => {
+                ExprKind::BinaryOp(Box::new(replace(*left)), op, Box::new(replace(*right)))
+            }
+            ExprKind::UnaryOp(operand, op) => ExprKind::UnaryOp(Box::new(replace(*operand)), op),
+            ExprKind::Builtin(builtin, args) => {
+                ExprKind::Builtin(builtin, args.into_iter().map(replace).collect())
+            }
+            ExprKind::FunctionCall(id, args) => {
+                ExprKind::FunctionCall(id, args.into_iter().map(replace).collect())
+            }
+            ExprKind::If(cond, then_branch, else_branch) => {
+                ExprKind::If(Box::new(replace(*cond)), then_branch, else_branch)
+            }
+            kind => kind,
+        };
+        Expr::new(kind, expr.span)
+    }
+    /// Handle the blocks and branches inside an expression on their own
+    fn nested(&mut self, expr: Expr) -> Expr {
+        let kind = match expr.kind {
+            ExprKind::Block(stmts) => ExprKind::Block(self.stmts(stmts)),
+            ExprKind::If(cond, then_branch, else_branch) => ExprKind::If(
+                Box::new(self.nested(*cond)),
+                Box::new(self.expr(*then_branch)),
+                else_branch.map(|branch| Box::new(self.expr(*branch))),
+            ),
+            ExprKind::BinaryOp(left, op, right) => ExprKind::BinaryOp(
+                Box::new(self.nested(*left)),
+                op,
+                Box::new(self.nested(*right)),
+            ),
+            ExprKind::UnaryOp(operand, op) => {
+                ExprKind::UnaryOp(Box::new(self.nested(*operand)), op)
+            }
+            ExprKind::Builtin(builtin, args) => ExprKind::Builtin(
+                builtin,
+                args.into_iter().map(|arg| self.nested(arg)).collect(),
+            ),
+            ExprKind::FunctionCall(id, args) => {
+                ExprKind::FunctionCall(id, args.into_iter().map(|arg| self.nested(arg)).collect())
+            }
+            kind => kind,
+        };
+        Expr::new(kind, expr.span)
+    }
+}
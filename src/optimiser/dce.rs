@@ -0,0 +1,202 @@
+use crate::ast::ast::BinaryOp;
+use crate::ast::ast::Expr;
+use crate::ast::ast::ExprKind;
+use crate::ast::ast::FnDecl;
+use crate::ast::ast::Stmt;
+use crate::ast::ast::StmtKind;
+use crate::semantic::resolver::DeclId;
+use crate::semantic::resolver::Resolution;
+use crate::semantic::resolver::Resolved;
+use crate::tokeniser::token_enum::Span;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/// Remove `let` and `const` bindings and function definitions that nothing
+/// refers to, repeating until removing one doesn't leave another unused
+///
+/// A function calling itself doesn't keep itself alive. A binding is only
+/// removed when computing its value can't fail or call anything, and the
+/// last statement of a block is kept since it decides the block's value.
+/// With `keep_globals` top level bindings and every function stay, as later
+/// input may still use them
+pub fn eliminate_dead_code(resolved: Resolved, keep_globals: bool) -> Resolved {
+    let Resolved {
+        mut stmts,
+        resolution,
+    } = resolved;
+    let mut dce = Dce {
+        resolution: &resolution,
+        decls: resolution
+            .declarations
+            .iter()
+            .enumerate()
+            .filter_map(|(id, decl)| Some((decl.span?, id)))
+            .collect(),
+        used: HashSet::new(),
+        keep_globals,
+        removed: false,
+    };
+    loop {
+        dce.used.clear();
+        dce.mark(&stmts, &mut Vec::new());
+        dce.removed = false;
+        stmts = dce.sweep(stmts, true);
+        if !dce.removed {
+            break;
+        }
+    }
+    Resolved { stmts, resolution }
+}
+
+struct Dce<'a> {
+    resolution: &'a Resolution,
+    /// Declaration made at each span
+    decls: HashMap<Span, DeclId>,
+    /// Declarations referred to by the remaining statements
+    used: HashSet<DeclId>,
+    keep_globals: bool,
+    /// Whether the last sweep removed anything
+    removed: bool,
+}
+
+impl Dce<'_> {
+    fn use_at(&mut self, span: Span, functions: &[DeclId]) {
+        if let Some(&id) = self.resolution.uses.get(&span) {
+            if !functions.contains(&id) {
+                self.used.insert(id);
+            }
+        }
+    }
+    /// Record every use, `functions` are the definitions being walked
+    fn mark(&mut self, stmts: &[Stmt], functions: &mut Vec<DeclId>) {
+        for stmt in stmts {
+            match &stmt.kind {
+                StmtKind::Assign(id, expr) => {
+                    self.use_at(id.span, functions);
+                    self.mark_expr(expr, functions);
+                }
+                StmtKind::Let(_, expr)
+                | StmtKind::Const(_, expr)
+                | StmtKind::Expr(expr)
+                | StmtKind::Return(Some(expr)) => self.mark_expr(expr, functions),
+                StmtKind::Fn(decl) => {
+                    functions.push(self.decls[&decl.name.span]);
+                    self.mark(&decl.body, functions);
+                    functions.pop();
+                }
+                StmtKind::Return(None) => {}
+            }
+        }
+    }
+    fn mark_expr(&mut self, expr: &Expr, functions: &mut Vec<DeclId>) {
+        match &expr.kind {
+            ExprKind::Variable(_) => self.use_at(expr.span, functions),
+            ExprKind::FunctionCall(id, args) => {
+                self.use_at(id.span, functions);
+                args.iter().for_each(|arg| self.mark_expr(arg, functions));
+            }
+            ExprKind::BinaryOp(left, _, right) => {
+                self.mark_expr(left, functions);
+                self.mark_expr(right, functions);
+            }
+            ExprKind::UnaryOp(operand, _) => self.mark_expr(operand, functions),
+            ExprKind::Builtin(_, args) => {
+                args.iter().for_each(|arg| self.mark_expr(arg, functions))
+            }
+            ExprKind::Block(stmts) => self.mark(stmts, functions),
+            ExprKind::If(cond, then_branch, else_branch) => {
+                self.mark_expr(cond, functions);
+                self.mark_expr(then_branch, functions);
+                if let Some(else_branch) = else_branch {
+                    self.mark_expr(else_branch, functions);
+                }
+            }
+            ExprKind::Number(_) | ExprKind::Bool(_) => {}
+        }
+    }
+    /// Whether evaluating the expression does nothing but produce a value
+    fn removable(&self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::Number(_) | ExprKind::Bool(_) => true,
+            // Function bodies may read a global that isn't defined yet when
+            // they run, names declared before the read always exist
+            ExprKind::Variable(_) => self.resolution.uses.get(&expr.span).is_some_and(|&id| {
+                self.resolution.declarations[id]
+                    .span
+                    .is_none_or(|decl| decl.start < expr.span.start)
+            }),
+            ExprKind::BinaryOp(left, BinaryOp::Div, right) => {
+                matches!(right.kind, ExprKind::Number(val) if val != 0.0) && self.removable(left)
+            }
+            ExprKind::BinaryOp(left, _, right) => self.removable(left) && self.removable(right),
+            ExprKind::UnaryOp(operand, _) => self.removable(operand),
+            ExprKind::Builtin(_, args) => args.iter().all(|arg| self.removable(arg)),
+            ExprKind::Block(_) | ExprKind::FunctionCall(..) | ExprKind::If(..) => false,
+        }
+    }
+    fn dead(&self, stmt: &Stmt, top: bool) -> bool {
+        match &stmt.kind {
+            StmtKind::Let(_, _) | StmtKind::Const(_, _) if top && self.keep_globals => false,
+            StmtKind::Let(id, expr) | StmtKind::Const(id, expr) => {
+                !self.used.contains(&self.decls[&id.span]) && self.removable(expr)
+            }
+            // Functions live in one global table however deeply they're nested
+            StmtKind::Fn(decl) => {
+                !self.keep_globals && !self.used.contains(&self.decls[&decl.name.span])
+            }
+            _ => false,
+        }
+    }
+    /// Drop dead definitions from a block, or the program when `top` is set
+    fn sweep(&mut self, stmts: Vec<Stmt>, top: bool) -> Vec<Stmt> {
+        let last = stmts.len().saturating_sub(1);
+        let mut kept = Vec::new();
+        for (idx, stmt) in stmts.into_iter().enumerate() {
+            if (top || idx != last) && self.dead(&stmt, top) {
+                self.removed = true;
+                continue;
+            }
+            let kind = match stmt.kind {
+                StmtKind::Let(id, expr) => StmtKind::Let(id, self.sweep_expr(expr)),
+                StmtKind::Const(id, expr) => StmtKind::Const(id, self.sweep_expr(expr)),
+                StmtKind::Assign(id, expr) => StmtKind::Assign(id, self.sweep_expr(expr)),
+                StmtKind::Fn(decl) => StmtKind::Fn(FnDecl {
+                    body: self.sweep(decl.body, false),
+                    ..decl
+                }),
+                StmtKind::Expr(expr) => StmtKind::Expr(self.sweep_expr(expr)),
+                StmtKind::Return(expr) => StmtKind::Return(expr.map(|expr| self.sweep_expr(expr))),
+            };
+            kept.push(Stmt::new(kind, stmt.span));
+        }
+        kept
+    }
+    fn sweep_expr(&mut self, expr: Expr) -> Expr {
+        let kind = match expr.kind {
+            ExprKind::Block(stmts) => ExprKind::Block(self.sweep(stmts, false)),
+            ExprKind::BinaryOp(left, op, right) => ExprKind::BinaryOp(
+                Box::new(self.sweep_expr(*left)),
+                op,
+                Box::new(self.sweep_expr(*right)),
+            ),
+            ExprKind::UnaryOp(operand, op) => {
+                ExprKind::UnaryOp(Box::new(self.sweep_expr(*operand)), op)
+            }
+            ExprKind::Builtin(builtin, args) => ExprKind::Builtin(
+                builtin,
+                args.into_iter().map(|arg| self.sweep_expr(arg)).collect(),
+            ),
+            ExprKind::FunctionCall(id, args) => ExprKind::FunctionCall(
+                id,
+                args.into_iter().map(|arg| self.sweep_expr(arg)).collect(),
+            ),
+            ExprKind::If(cond, then_branch, else_branch) => ExprKind::If(
+                Box::new(self.sweep_expr(*cond)),
+                Box::new(self.sweep_expr(*then_branch)),
+                else_branch.map(|branch| Box::new(self.sweep_expr(*branch))),
+            ),
+            kind => kind,
+        };
+        Expr::new(kind, expr.span)
+    }
+}
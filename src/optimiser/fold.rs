@@ -0,0 +1,186 @@
+use crate::ast::ast::constant;
+use crate::ast::ast::BinaryOp;
+use crate::ast::ast::Builtin;
+use crate::ast::ast::Expr;
+use crate::ast::ast::ExprKind;
+use crate::ast::ast::FnDecl;
+use crate::ast::ast::Stmt;
+use crate::ast::ast::StmtKind;
+use crate::ast::ast::UnaryOp;
+use crate::ast::value::Value;
+use crate::semantic::resolver::DeclKind;
+use crate::semantic::resolver::Resolution;
+use crate::semantic::resolver::Resolved;
+use crate::tokeniser::token_enum::Span;
+
+/// Fold constant subexpressions and apply algebraic identities
+///
+/// Runs on type checked programs, so arithmetic operands are known to be
+/// numbers. Every rewrite gives a bit for bit identical result: constants are
+/// evaluated with the interpreter's own operators, anything that would fail at
+/// runtime (`1 / 0`) is left for the interpreter to report, and identities
+/// that don't hold for every float are skipped. `x * 0` isn't 0 when `x` is
+/// infinite or NaN, `(a + 1) + 2` isn't `a + 3` after rounding, and `x + 0`
+/// turns -0 into 0, so it is only dropped when `x` can't be -0
+pub fn fold(resolved: Resolved) -> Resolved {
+    let Resolved { stmts, resolution } = resolved;
+    let stmts = Folder {
+        resolution: &resolution,
+    }
+    .stmts(stmts);
+    Resolved { stmts, resolution }
+}
+
+struct Folder<'a> {
+    resolution: &'a Resolution,
+}
+
+/// Value of a literal
+fn literal(expr: &Expr) -> Option<Value> {
+    match expr.kind {
+        ExprKind::Number(val) => Some(Value::Number(val)),
+        ExprKind::Bool(val) => Some(Value::Bool(val)),
+        _ => None,
+    }
+}
+
+/// A literal producing the value, functions and unit have no literal
+fn from_value(value: Value, span: Span) -> Option<Expr> {
+    match value {
+        Value::Number(val) => Some(Expr::new(ExprKind::Number(val), span)),
+        Value::Bool(val) => Some(Expr::new(ExprKind::Bool(val), span)),
+        Value::Unit | Value::Function(_) => None,
+    }
+}
+
+fn number(expr: &Expr) -> Option<f64> {
+    match expr.kind {
+        ExprKind::Number(val) => Some(val),
+        _ => None,
+    }
+}
+
+/// Whether the expression can evaluate to -0, a sum is only -0 when both
+/// operands are
+fn may_be_negative_zero(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Number(val) => *val == 0.0 && val.is_sign_negative(),
+        ExprKind::Builtin(Builtin::Abs | Builtin::Exp | Builtin::Cosh, _) => false,
+        ExprKind::UnaryOp(_, UnaryOp::Factorial) => false,
+        ExprKind::BinaryOp(left, BinaryOp::Add, right) => {
+            may_be_negative_zero(left) && may_be_negative_zero(right)
+        }
+        _ => true,
+    }
+}
+
+impl Folder<'_> {
+    fn stmts(&self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        stmts.into_iter().map(|stmt| self.stmt(stmt)).collect()
+    }
+    fn stmt(&self, stmt: Stmt) -> Stmt {
+        let kind = match stmt.kind {
+            StmtKind::Let(id, expr) => StmtKind::Let(id, self.expr(expr)),
+            StmtKind::Const(id, expr) => StmtKind::Const(id, self.expr(expr)),
+            StmtKind::Assign(id, expr) => StmtKind::Assign(id, self.expr(expr)),
+            StmtKind::Fn(decl) => StmtKind::Fn(FnDecl {
+                body: self.stmts(decl.body),
+                ..decl
+            }),
+            StmtKind::Expr(expr) => StmtKind::Expr(self.expr(expr)),
+            StmtKind::Return(expr) => StmtKind::Return(expr.map(|expr| self.expr(expr))),
+        };
+        Stmt::new(kind, stmt.span)
+    }
+    fn expr(&self, expr: Expr) -> Expr {
+        let span = expr.span;
+        let kind = match expr.kind {
+            ExprKind::Variable(name) => {
+                let is_constant = self
+                    .resolution
+                    .lookup(span)
+                    .is_some_and(|decl| decl.kind == DeclKind::Constant);
+                match constant(&name) {
+                    Some(val) if is_constant => ExprKind::Number(val),
+                    _ => ExprKind::Variable(name),
+                }
+            }
+            ExprKind::BinaryOp(left, op, right) => {
+                return self.binary(self.expr(*left), op, self.expr(*right), span)
+            }
+            ExprKind::UnaryOp(operand, op) => return self.unary(self.expr(*operand), op, span),
+            ExprKind::Builtin(builtin, args) => {
+                let args: Vec<Expr> = args.into_iter().map(|arg| self.expr(arg)).collect();
+                match args.iter().map(number).collect::<Option<Vec<f64>>>() {
+                    Some(nums) => ExprKind::Number(builtin.call(&nums)),
+                    None => ExprKind::Builtin(builtin, args),
+                }
+            }
+            ExprKind::Block(stmts) => ExprKind::Block(self.stmts(stmts)),
+            ExprKind::FunctionCall(id, args) => {
+                ExprKind::FunctionCall(id, args.into_iter().map(|arg| self.expr(arg)).collect())
+            }
+            ExprKind::If(cond, then_branch, else_branch) => {
+                // Branches are blocks or ifs, so taking one keeps its scoping,
+                // and the one that can't run isn't folded at all
+                let cond = self.expr(*cond);
+                match (cond.kind, else_branch) {
+                    (ExprKind::Bool(true), _) => return self.expr(*then_branch),
+                    (ExprKind::Bool(false), Some(else_branch)) => return self.expr(*else_branch),
+                    (ExprKind::Bool(false), None) => ExprKind::Block(Vec::new()),
+                    (kind, else_branch) => ExprKind::If(
+                        Box::new(Expr::new(kind, cond.span)),
+                        Box::new(self.expr(*then_branch)),
+                        else_branch.map(|branch| Box::new(self.expr(*branch))),
+                    ),
+                }
+            }
+            kind @ (ExprKind::Number(_) | ExprKind::Bool(_)) => kind,
+        };
+        Expr::new(kind, span)
+    }
+    fn binary(&self, left: Expr, op: BinaryOp, right: Expr, span: Span) -> Expr {
+        if let (Some(left), Some(right)) = (literal(&left), literal(&right)) {
+            if let Some(folded) = Value::binary(op, &left, &right)
+                .ok()
+                .and_then(|val| from_value(val, span))
+            {
+                return folded;
+            }
+        }
+        match (op, number(&left), number(&right)) {
+            // x * 1, x / 1, x ^ 1, x - 0, x + -0
+            (BinaryOp::Mul | BinaryOp::Div | BinaryOp::Pow, _, Some(1.0)) => left,
+            (BinaryOp::Sub, _, Some(val)) if val == 0.0 && val.is_sign_positive() => left,
+            (BinaryOp::Add, _, Some(val)) if val == 0.0 && val.is_sign_negative() => left,
+            (BinaryOp::Add, _, Some(val)) if val == 0.0 && !may_be_negative_zero(&left) => left,
+            // 1 * x, -0 + x
+            (BinaryOp::Mul, Some(1.0), _) => right,
+            (BinaryOp::Add, Some(val), _) if val == 0.0 && val.is_sign_negative() => right,
+            (BinaryOp::Add, Some(val), _) if val == 0.0 && !may_be_negative_zero(&right) => right,
+            // x * -1, x / -1, -1 * x
+            (BinaryOp::Mul | BinaryOp::Div, _, Some(-1.0)) => self.unary(left, UnaryOp::Neg, span),
+            (BinaryOp::Mul, Some(-1.0), _) => self.unary(right, UnaryOp::Neg, span),
+            _ => Expr::new(
+                ExprKind::BinaryOp(Box::new(left), op, Box::new(right)),
+                span,
+            ),
+        }
+    }
+    fn unary(&self, operand: Expr, op: UnaryOp, span: Span) -> Expr {
+        if let Some(folded) = literal(&operand)
+            .and_then(|val| Value::unary(op, &val).ok())
+            .and_then(|val| from_value(val, span))
+        {
+            return folded;
+        }
+        match (op, operand.kind) {
+            // --x
+            (UnaryOp::Neg, ExprKind::UnaryOp(inner, UnaryOp::Neg)) => *inner,
+            (op, kind) => Expr::new(
+                ExprKind::UnaryOp(Box::new(Expr::new(kind, operand.span)), op),
+                span,
+            ),
+        }
+    }
+}
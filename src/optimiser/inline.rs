@@ -0,0 +1,430 @@
+use crate::ast::ast::Expr;
+use crate::ast::ast::ExprKind;
+use crate::ast::ast::FnDecl;
+use crate::ast::ast::Ident;
+use crate::ast::ast::Stmt;
+use crate::ast::ast::StmtKind;
+use crate::semantic::resolver::DeclId;
+use crate::semantic::resolver::DeclKind;
+use crate::semantic::resolver::Resolution;
+use crate::semantic::resolver::Resolved;
+use crate::tokeniser::token_enum::Span;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/// Largest body, counted in statements and expressions, inlined by default
+pub const DEFAULT_THRESHOLD: usize = 16;
+
+/// Replace calls to small non-recursive functions with their bodies
+///
+/// `fn sq(x) { x * x }` turns `sq(3)` into `{ let x_0 = 3; x_0 * x_0 }`.
+/// Parameters are renamed to names a user can't write, so an argument such
+/// as `x` in `f(y, x)` can't be captured, and calls are left alone where a
+/// local would hide a global the body uses. Only functions defined once, at
+/// the top level, before the call and without a `return` other than a
+/// trailing one are inlined. Copies keep the spans of the body they came
+/// from so they resolve like the original
+pub fn inline_functions(resolved: Resolved, threshold: usize) -> Resolved {
+    let Resolved { stmts, resolution } = resolved;
+    let candidates = candidates(&stmts, &resolution, threshold);
+    let mut inliner = Inliner {
+        resolution: &resolution,
+        candidates,
+        locals: Vec::new(),
+        next: 0,
+    };
+    let stmts = inliner.stmts(stmts);
+    Resolved { stmts, resolution }
+}
+
+/// A function that may be inlined
+struct Candidate {
+    decl: FnDecl,
+    /// Where the definition ends, calls after it always see it defined
+    defined: usize,
+    /// Names the body uses without declaring them
+    free: HashSet<String>,
+}
+
+/// Statements and expressions in a body
+fn size(stmts: &[Stmt]) -> usize {
+    stmts
+        .iter()
+        .map(|stmt| 1 + stmt_exprs(stmt).map(expr_size).sum::<usize>())
+        .sum()
+}
+
+fn expr_size(expr: &Expr) -> usize {
+    1 + match &expr.kind {
+        ExprKind::BinaryOp(left, _, right) => expr_size(left) + expr_size(right),
+        ExprKind::UnaryOp(operand, _) => expr_size(operand),
+        ExprKind::Builtin(_, args) | ExprKind::FunctionCall(_, args) => {
+            args.iter().map(expr_size).sum()
+        }
+        ExprKind::Block(stmts) => size(stmts),
+        ExprKind::If(cond, then_branch, else_branch) => {
+            expr_size(cond) + expr_size(then_branch) + else_branch.as_deref().map_or(0, expr_size)
+        }
+        ExprKind::Number(_) | ExprKind::Bool(_) | ExprKind::Variable(_) => 0,
+    }
+}
+
+/// Expression held directly by a statement
+fn stmt_exprs(stmt: &Stmt) -> impl Iterator<Item = &Expr> {
+    match &stmt.kind {
+        StmtKind::Let(_, expr)
+        | StmtKind::Const(_, expr)
+        | StmtKind::Assign(_, expr)
+        | StmtKind::Expr(expr)
+        | StmtKind::Return(Some(expr)) => Some(expr),
+        StmtKind::Fn(_) | StmtKind::Return(None) => None,
+    }
+    .into_iter()
+}
+
+/// Bodies that define functions or return early can't be inlined, a
+/// trailing `return` becomes the block's value
+fn inlinable_body(stmts: &[Stmt], top: bool) -> bool {
+    stmts
+        .iter()
+        .enumerate()
+        .all(|(idx, stmt)| match &stmt.kind {
+            StmtKind::Fn(_) => false,
+            StmtKind::Return(_) if !(top && idx + 1 == stmts.len()) => false,
+            _ => stmt_exprs(stmt).all(inlinable_expr),
+        })
+}
+
+fn inlinable_expr(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Block(stmts) => inlinable_body(stmts, false),
+        ExprKind::BinaryOp(left, _, right) => inlinable_expr(left) && inlinable_expr(right),
+        ExprKind::UnaryOp(operand, _) => inlinable_expr(operand),
+        ExprKind::Builtin(_, args) | ExprKind::FunctionCall(_, args) => {
+            args.iter().all(inlinable_expr)
+        }
+        ExprKind::If(cond, then_branch, else_branch) => {
+            inlinable_expr(cond)
+                && inlinable_expr(then_branch)
+                && else_branch.as_deref().is_none_or(inlinable_expr)
+        }
+        ExprKind::Number(_) | ExprKind::Bool(_) | ExprKind::Variable(_) => true,
+    }
+}
+
+/// Every name written in the statements with the span it was written at,
+/// collecting the functions they define along the way
+fn names<'a>(stmts: &'a [Stmt], found: &mut Vec<(String, Span)>, fns: &mut Vec<&'a Stmt>) {
+    for stmt in stmts {
+        match &stmt.kind {
+            StmtKind::Fn(_) => fns.push(stmt),
+            StmtKind::Assign(id, _) => found.push((id.name.clone(), id.span)),
+            _ => {}
+        }
+        for expr in stmt_exprs(stmt) {
+            expr_names(expr, found, fns);
+        }
+    }
+}
+
+fn expr_names<'a>(expr: &'a Expr, found: &mut Vec<(String, Span)>, fns: &mut Vec<&'a Stmt>) {
+    match &expr.kind {
+        ExprKind::Variable(name) => found.push((name.clone(), expr.span)),
+        ExprKind::FunctionCall(id, args) => {
+            found.push((id.name.clone(), id.span));
+            args.iter().for_each(|arg| expr_names(arg, found, fns));
+        }
+        ExprKind::BinaryOp(left, _, right) => {
+            expr_names(left, found, fns);
+            expr_names(right, found, fns);
+        }
+        ExprKind::UnaryOp(operand, _) => expr_names(operand, found, fns),
+        ExprKind::Builtin(_, args) => args.iter().for_each(|arg| expr_names(arg, found, fns)),
+        ExprKind::Block(stmts) => names(stmts, found, fns),
+        ExprKind::If(cond, then_branch, else_branch) => {
+            expr_names(cond, found, fns);
+            expr_names(then_branch, found, fns);
+            if let Some(else_branch) = else_branch {
+                expr_names(else_branch, found, fns);
+            }
+        }
+        ExprKind::Number(_) | ExprKind::Bool(_) => {}
+    }
+}
+
+/// Top level functions small enough to inline that can't reach themselves
+fn candidates(
+    stmts: &[Stmt],
+    resolution: &Resolution,
+    threshold: usize,
+) -> HashMap<DeclId, Candidate> {
+    let decl_at: HashMap<Span, DeclId> = resolution
+        .declarations
+        .iter()
+        .enumerate()
+        .filter_map(|(id, decl)| Some((decl.span?, id)))
+        .collect();
+    let definitions = |name: &str| {
+        resolution
+            .declarations
+            .iter()
+            .filter(|decl| decl.name == name && matches!(decl.kind, DeclKind::Fn(_)))
+            .count()
+    };
+
+    // Functions each function refers to, by call or as a value. Functions
+    // from earlier input were checked before anything here existed, so they
+    // can't refer back to it
+    let mut refers: HashMap<DeclId, HashSet<DeclId>> = HashMap::new();
+    let mut found = HashMap::new();
+    let mut pending: Vec<&Stmt> = Vec::new();
+    names(stmts, &mut Vec::new(), &mut pending);
+    let top: HashSet<Span> = stmts
+        .iter()
+        .filter(|stmt| matches!(stmt.kind, StmtKind::Fn(_)))
+        .map(|stmt| stmt.span)
+        .collect();
+    while let Some(stmt) = pending.pop() {
+        let StmtKind::Fn(decl) = &stmt.kind else {
+            continue;
+        };
+        let id = decl_at[&decl.name.span];
+        let mut used = Vec::new();
+        names(&decl.body, &mut used, &mut pending);
+        let inside = |span: Span| span.start >= stmt.span.start && span.end <= stmt.span.end;
+        let mut free = HashSet::new();
+        for (name, span) in used {
+            let Some(&decl) = resolution.uses.get(&span) else {
+                continue;
+            };
+            if matches!(resolution.declarations[decl].kind, DeclKind::Fn(_)) {
+                refers.entry(id).or_default().insert(decl);
+            }
+            if !resolution.declarations[decl].span.is_some_and(inside) {
+                free.insert(name);
+            }
+        }
+        if top.contains(&stmt.span)
+            && inlinable_body(&decl.body, true)
+            && size(&decl.body) <= threshold
+            && definitions(&decl.name.name) == 1
+        {
+            found.insert(
+                id,
+                Candidate {
+                    decl: decl.clone(),
+                    defined: stmt.span.end,
+                    free,
+                },
+            );
+        }
+    }
+
+    let reaches_itself = |start: DeclId| {
+        let mut seen = HashSet::new();
+        let mut pending = vec![start];
+        while let Some(id) = pending.pop() {
+            for &next in refers.get(&id).into_iter().flatten() {
+                if next == start {
+                    return true;
+                }
+                if seen.insert(next) {
+                    pending.push(next);
+                }
+            }
+        }
+        false
+    };
+    found.retain(|&id, _| !reaches_itself(id));
+    found
+}
+
+struct Inliner<'a> {
+    resolution: &'a Resolution,
+    candidates: HashMap<DeclId, Candidate>,
+    /// Names declared in each enclosing non-global scope
+    locals: Vec<HashSet<String>>,
+    /// Number of the next renamed parameter
+    next: usize,
+}
+
+impl Inliner<'_> {
+    fn declare(&mut self, name: &str) {
+        if let Some(scope) = self.locals.last_mut() {
+            scope.insert(name.to_string());
+        }
+    }
+    fn stmts(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        stmts.into_iter().map(|stmt| self.stmt(stmt)).collect()
+    }
+    fn stmt(&mut self, stmt: Stmt) -> Stmt {
+        let kind = match stmt.kind {
+            StmtKind::Let(id, expr) => {
+                let expr = self.expr(expr);
+                self.declare(&id.name);
+                StmtKind::Let(id, expr)
+            }
+            StmtKind::Const(id, expr) => {
+                let expr = self.expr(expr);
+                self.declare(&id.name);
+                StmtKind::Const(id, expr)
+            }
+            StmtKind::Assign(id, expr) => StmtKind::Assign(id, self.expr(expr)),
+            StmtKind::Fn(decl) => {
+                // A body sees the globals and its parameters only
+                let outer = std::mem::take(&mut self.locals);
+                self.locals.push(
+                    decl.params
+                        .iter()
+                        .map(|param| param.name.name.clone())
+                        .collect(),
+                );
+                let body = self.block(decl.body);
+                self.locals = outer;
+                StmtKind::Fn(FnDecl { body, ..decl })
+            }
+            StmtKind::Expr(expr) => StmtKind::Expr(self.expr(expr)),
+            StmtKind::Return(expr) => StmtKind::Return(expr.map(|expr| self.expr(expr))),
+        };
+        Stmt::new(kind, stmt.span)
+    }
+    fn block(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        self.locals.push(HashSet::new());
+        let stmts = self.stmts(stmts);
+        self.locals.pop();
+        stmts
+    }
+    fn expr(&mut self, expr: Expr) -> Expr {
+        let kind = match expr.kind {
+            ExprKind::FunctionCall(id, args) => match self.inlinable(&id, expr.span) {
+                Some(callee) => return self.inline(callee, args, expr.span),
+                None => {
+                    ExprKind::FunctionCall(id, args.into_iter().map(|arg| self.expr(arg)).collect())
+                }
+            },
+            ExprKind::Block(stmts) => ExprKind::Block(self.block(stmts)),
+            ExprKind::BinaryOp(left, op, right) => {
+                ExprKind::BinaryOp(Box::new(self.expr(*left)), op, Box::new(self.expr(*right)))
+            }
+            ExprKind::UnaryOp(operand, op) => ExprKind::UnaryOp(Box::new(self.expr(*operand)), op),
+            ExprKind::Builtin(builtin, args) => ExprKind::Builtin(
+                builtin,
+                args.into_iter().map(|arg| self.expr(arg)).collect(),
+            ),
+            ExprKind::If(cond, then_branch, else_branch) => ExprKind::If(
+                Box::new(self.expr(*cond)),
+                Box::new(self.expr(*then_branch)),
+                else_branch.map(|branch| Box::new(self.expr(*branch))),
+            ),
+            kind => kind,
+        };
+        Expr::new(kind, expr.span)
+    }
+    /// The callee's definition if this call can be replaced by its body
+    fn inlinable(&self, id: &Ident, call: Span) -> Option<FnDecl> {
+        let callee = self.resolution.uses.get(&id.span)?;
+        let candidate = self.candidates.get(callee)?;
+        let hidden = candidate
+            .free
+            .iter()
+            .any(|name| self.locals.iter().any(|scope| scope.contains(name)));
+        (candidate.defined <= call.start && !hidden).then(|| candidate.decl.clone())
+    }
+    /// `{ let a_0 = arg; ..; body }`, calls in the arguments and the body are
+    /// inlined in turn
+    fn inline(&mut self, callee: FnDecl, args: Vec<Expr>, span: Span) -> Expr {
+        let mut renamed = HashMap::new();
+        let mut stmts = Vec::new();
+        for (param, arg) in callee.params.iter().zip(args) {
+            let name = format!("{0}_{1}", param.name.name, self.next);
+            self.next += 1;
+            if let Some(id) = self
+                .resolution
+                .declarations
+                .iter()
+                .position(|decl| decl.span == Some(param.name.span))
+            {
+                renamed.insert(id, name.clone());
+            }
+            let binding = Ident {
+                name,
+                span: param.name.span,
+            };
+            stmts.push(Stmt::new(StmtKind::Let(binding, arg), span));
+        }
+        let mut body = callee.body;
+        if let Some(last) = body.pop() {
+            match last.kind {
+                StmtKind::Return(Some(value)) => {
+                    body.push(Stmt::new(StmtKind::Expr(value), last.span))
+                }
+                // A bare `return` leaves the block's value unit
+                StmtKind::Return(None) => {}
+                _ => body.push(last),
+            }
+        }
+        stmts.extend(body.into_iter().map(|stmt| self.rename(stmt, &renamed)));
+        let block = Expr::new(ExprKind::Block(stmts), span);
+        self.expr(block)
+    }
+    fn renamed(&self, name: String, span: Span, renamed: &HashMap<DeclId, String>) -> String {
+        self.resolution
+            .uses
+            .get(&span)
+            .and_then(|id| renamed.get(id))
+            .cloned()
+            .unwrap_or(name)
+    }
+    /// Refer to parameters by their new names
+    fn rename(&self, stmt: Stmt, renamed: &HashMap<DeclId, String>) -> Stmt {
+        let rename = |expr: Expr| self.rename_expr(expr, renamed);
+        let kind = match stmt.kind {
+            StmtKind::Let(id, expr) => StmtKind::Let(id, rename(expr)),
+            StmtKind::Const(id, expr) => StmtKind::Const(id, rename(expr)),
+            StmtKind::Assign(id, expr) => StmtKind::Assign(
+                Ident {
+                    name: self.renamed(id.name, id.span, renamed),
+                    span: id.span,
+                },
+                rename(expr),
+            ),
+            StmtKind::Expr(expr) => StmtKind::Expr(rename(expr)),
+            StmtKind::Return(expr) => StmtKind::Return(expr.map(rename)),
+            kind @ StmtKind::Fn(_) => kind,
+        };
+        Stmt::new(kind, stmt.span)
+    }
+    fn rename_expr(&self, expr: Expr, renamed: &HashMap<DeclId, String>) -> Expr {
+        let rename = |expr: Expr| self.rename_expr(expr, renamed);
+        let kind = match expr.kind {
+            ExprKind::Variable(name) => ExprKind::Variable(self.renamed(name, expr.span, renamed)),
+            ExprKind::FunctionCall(id, args) => ExprKind::FunctionCall(
+                Ident {
+                    name: self.renamed(id.name, id.span, renamed),
+                    span: id.span,
+                },
+                args.into_iter().map(rename).collect(),
+            ),
+            ExprKind::BinaryOp(left, op, right) => {
+                ExprKind::BinaryOp(Box::new(rename(*left)), op, Box::new(rename(*right)))
+            }
+            ExprKind::UnaryOp(operand, op) => ExprKind::UnaryOp(Box::new(rename(*operand)), op),
+            ExprKind::Builtin(builtin, args) => {
+                ExprKind::Builtin(builtin, args.into_iter().map(rename).collect())
+            }
+            ExprKind::Block(stmts) => ExprKind::Block(
+                stmts
+                    .into_iter()
+                    .map(|stmt| self.rename(stmt, renamed))
+                    .collect(),
+            ),
+            ExprKind::If(cond, then_branch, else_branch) => ExprKind::If(
+                Box::new(rename(*cond)),
+                Box::new(rename(*then_branch)),
+                else_branch.map(|branch| Box::new(rename(*branch))),
+            ),
+            kind => kind,
+        };
+        Expr::new(kind, expr.span)
+    }
+}
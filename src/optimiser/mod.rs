@@ -0,0 +1,5 @@
+pub mod cse;
+pub mod dce;
+pub mod fold;
+pub mod inline;
+pub mod pipeline;
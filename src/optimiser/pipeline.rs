@@ -0,0 +1,171 @@
+use crate::optimiser::cse::eliminate_common_subexpressions;
+use crate::optimiser::dce::eliminate_dead_code;
+use crate::optimiser::fold::fold;
+use crate::optimiser::inline::inline_functions;
+use crate::optimiser::inline::DEFAULT_THRESHOLD;
+use crate::semantic::resolver::Resolved;
+use std::fmt;
+
+/// Inline threshold used at `-O3`
+pub const AGGRESSIVE_THRESHOLD: usize = 64;
+
+/// A transformation over a resolved program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    /// Constant folding and algebraic identities
+    Fold,
+    /// Inlining of small functions
+    Inline,
+    /// Removal of unused definitions
+    DeadCode,
+    /// Common subexpression elimination
+    CommonSubexpressions,
+}
+
+impl Pass {
+    pub const ALL: [Pass; 4] = [
+        Pass::Fold,
+        Pass::Inline,
+        Pass::DeadCode,
+        Pass::CommonSubexpressions,
+    ];
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::Fold => "fold",
+            Pass::Inline => "inline",
+            Pass::DeadCode => "dce",
+            Pass::CommonSubexpressions => "cse",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Pass> {
+        Pass::ALL.into_iter().find(|pass| pass.name() == name)
+    }
+}
+
+impl fmt::Display for Pass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{0}", self.name())
+    }
+}
+
+/// Ordered passes run between type checking and evaluation
+///
+/// | level | passes                     |
+/// |-------|----------------------------|
+/// | `-O0` | none                       |
+/// | `-O1` | fold                       |
+/// | `-O2` | fold, inline, dce, cse     |
+/// | `-O3` | as `-O2` inlining up to `AGGRESSIVE_THRESHOLD`, then fold again |
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    passes: Vec<Pass>,
+    /// Inline threshold of the level
+    level_threshold: usize,
+    /// Inline threshold given on the command line, whatever the level
+    threshold: Option<usize>,
+    /// Top level names may still be used by later input, as in the REPL, so
+    /// they aren't removed
+    pub keep_globals: bool,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self::level(2).expect("-O2 exists")
+    }
+}
+
+impl Pipeline {
+    /// Run exactly these passes in this order
+    pub fn new(passes: Vec<Pass>) -> Self {
+        Self {
+            passes,
+            level_threshold: DEFAULT_THRESHOLD,
+            threshold: None,
+            keep_globals: true,
+        }
+    }
+    /// Passes for an optimisation level from 0 to 3
+    pub fn level(level: u8) -> Result<Self, String> {
+        let (passes, level_threshold) = match level {
+            0 => (Vec::new(), DEFAULT_THRESHOLD),
+            1 => (vec![Pass::Fold], DEFAULT_THRESHOLD),
+            2 => (
+                vec![
+                    Pass::Fold,
+                    Pass::Inline,
+                    Pass::DeadCode,
+                    Pass::CommonSubexpressions,
+                ],
+                DEFAULT_THRESHOLD,
+            ),
+            3 => (
+                vec![
+                    Pass::Fold,
+                    Pass::Inline,
+                    Pass::Fold,
+                    Pass::DeadCode,
+                    Pass::CommonSubexpressions,
+                ],
+                AGGRESSIVE_THRESHOLD,
+            ),
+            _ => return Err(format!("Unknown optimisation level: {level}")),
+        };
+        Ok(Self {
+            level_threshold,
+            ..Self::new(passes)
+        })
+    }
+    /// Inline functions whose bodies have at most `threshold` statements and
+    /// expressions whatever the level, 0 turns inlining off
+    pub fn with_inline_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = Some(threshold);
+        self
+    }
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+    pub fn inline_threshold(&self) -> usize {
+        self.threshold.unwrap_or(self.level_threshold)
+    }
+    /// Apply a command line flag such as `-O2`, `--inline-threshold=32` or
+    /// `--passes=fold,cse`, returns false if the flag isn't a pipeline flag
+    /// at all. The last of `-O` and `--passes` picks the passes
+    pub fn apply_flag(&mut self, flag: &str) -> Result<bool, String> {
+        if let Some(level) = flag.strip_prefix("-O") {
+            let level = level
+                .parse()
+                .map_err(|_| format!("Unknown optimisation level: {level}"))?;
+            *self = Self {
+                threshold: self.threshold,
+                keep_globals: self.keep_globals,
+                ..Self::level(level)?
+            };
+        } else if let Some(threshold) = flag.strip_prefix("--inline-threshold=") {
+            let threshold = threshold
+                .parse()
+                .map_err(|_| format!("Invalid inline threshold: {threshold}"))?;
+            self.threshold = Some(threshold);
+        } else if let Some(names) = flag.strip_prefix("--passes=") {
+            self.passes = names
+                .split(',')
+                .filter(|name| !name.is_empty())
+                .map(|name| Pass::from_name(name).ok_or_else(|| format!("Unknown pass: {name}")))
+                .collect::<Result<_, _>>()?;
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+    /// Run every pass in order
+    pub fn run(&self, mut resolved: Resolved) -> Resolved {
+        for pass in &self.passes {
+            resolved = match pass {
+                Pass::Fold => fold(resolved),
+                Pass::Inline => inline_functions(resolved, self.inline_threshold()),
+                Pass::DeadCode => eliminate_dead_code(resolved, self.keep_globals),
+                Pass::CommonSubexpressions => eliminate_common_subexpressions(resolved),
+            };
+        }
+        resolved
+    }
+}
@@ -0,0 +1,62 @@
+/// Check whether the buffered input still has unclosed '(' or '{', or ends
+/// in an operator that continues onto the next line, and the REPL should keep
+/// reading lines before evaluating it
+pub fn is_incomplete(input: &str) -> bool {
+    let mut depth = 0i32;
+
+    for ch in input.chars() {
+        match ch {
+            '(' | '{' => depth += 1,
+            ')' | '}' => depth -= 1,
+            _ => {}
+        }
+        // More closers than openers is an error for the parser to report,
+        // waiting for more input would never fix it
+        if depth < 0 {
+            return false;
+        }
+    }
+
+    depth > 0
+        || input
+            .trim_end()
+            .ends_with(['+', '-', '*', '/', '^', '%', '=', '<', '>', '~', ','])
+}
+
+/// Meta commands understood by the REPL, all prefixed with ':'
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Vars,
+    Funcs,
+    Reset,
+    History,
+    Load(String),
+    Help,
+    Quit,
+}
+
+impl Command {
+    /// Parse a line starting with ':' into a meta command
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let line = line.trim();
+        let body = line
+            .strip_prefix(':')
+            .ok_or_else(|| format!("Not a command: {0}", line))?;
+        let (name, arg) = match body.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (body, ""),
+        };
+
+        match name {
+            "vars" => Ok(Command::Vars),
+            "funcs" => Ok(Command::Funcs),
+            "reset" => Ok(Command::Reset),
+            "history" => Ok(Command::History),
+            "load" if arg.is_empty() => Err(String::from("Usage: :load <file>")),
+            "load" => Ok(Command::Load(arg.to_string())),
+            "help" => Ok(Command::Help),
+            "quit" | "q" => Ok(Command::Quit),
+            _ => Err(format!("Unknown command: :{0} (try :help)", name)),
+        }
+    }
+}
@@ -0,0 +1,636 @@
+use crate::ast::ast::Expr;
+use crate::ast::ast::ExprKind;
+use crate::ast::ast::Stmt;
+use crate::ast::ast::StmtKind;
+use crate::ast::evaluation::Evaluation;
+use crate::ast::parser::Dialect;
+use crate::ast::parser::Parser;
+use crate::ast::value::Value;
+use crate::codegen::asm::generate;
+use crate::codegen::asm::Target;
+use crate::codegen::c::to_c;
+use crate::codegen::elf::executable;
+use crate::codegen::jit::Jit;
+use crate::codegen::jit::NativeFn;
+use crate::codegen::llvm::to_llvm;
+use crate::codegen::wasm::to_wasm;
+use crate::ir::ir::Module;
+use crate::ir::ir::MAIN;
+use crate::ir::lower::lower;
+use crate::ir::verify::verify;
+use crate::optimiser::pipeline::Pipeline;
+use crate::repl::input::is_incomplete;
+use crate::repl::input::Command;
+use crate::semantic::lint::lint;
+use crate::semantic::lint::Level;
+use crate::semantic::lint::LintConfig;
+use crate::semantic::lint::Warning;
+use crate::semantic::resolver::DeclId;
+use crate::semantic::resolver::DeclKind;
+use crate::semantic::resolver::Resolved;
+use crate::semantic::resolver::Resolver;
+use crate::semantic::typeck::Type;
+use crate::semantic::typeck::TypeChecker;
+use crate::tokeniser::token_enum::Span;
+use crate::tokeniser::tokeniser::Tokeniser;
+use crate::vm::bytecode::Program;
+use crate::vm::cache::decode;
+use crate::vm::cache::is_bytecode;
+use crate::vm::compiler::compile;
+use crate::vm::disassemble::disassemble;
+use crate::vm::vm::Vm;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::fmt::Display;
+use std::io::BufRead;
+use std::io::Write;
+
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = ". ";
+const HELP: &str = "\
+:vars          list global variables
+:funcs         list user defined functions
+:reset         forget every variable and function
+:history       show previous inputs
+:load <file>   evaluate a file in this session
+:help          show this message
+:quit          leave the repl";
+
+/// What the REPL should do after being handed a line
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    /// Input evaluated to a value, which is now also stored in `ans`
+    Value(Value),
+    /// Input was complete but only defined things
+    Nothing,
+    /// Input has unclosed brackets, more lines are needed
+    Continue,
+    /// Text produced by a meta command
+    Output(String),
+    Error(String),
+    Quit,
+}
+
+/// Intermediate stage printed by `--emit` instead of evaluating
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    Tokens,
+    /// Statements as parsed
+    Ast,
+    /// Statements after resolution, type checking and the optimisation passes
+    Resolved,
+    Ir,
+    Bytecode,
+    Asm,
+    /// Textual LLVM IR
+    Llvm,
+}
+
+impl Emit {
+    pub const ALL: [Emit; 7] = [
+        Emit::Tokens,
+        Emit::Ast,
+        Emit::Resolved,
+        Emit::Ir,
+        Emit::Bytecode,
+        Emit::Asm,
+        Emit::Llvm,
+    ];
+    pub fn name(self) -> &'static str {
+        match self {
+            Emit::Tokens => "tokens",
+            Emit::Ast => "ast",
+            Emit::Resolved => "resolved",
+            Emit::Ir => "ir",
+            Emit::Bytecode => "bytecode",
+            Emit::Asm => "asm",
+            Emit::Llvm => "llvm",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Emit> {
+        Emit::ALL.into_iter().find(|stage| stage.name() == name)
+    }
+}
+
+impl fmt::Display for Emit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{0}", self.name())
+    }
+}
+
+/// A REPL session, variables and functions survive between inputs
+pub struct Session {
+    parser: Parser,
+    history: Vec<String>,
+    buffer: String,
+    lints: LintConfig,
+    /// Lint warnings from evaluated input that haven't been shown yet
+    warnings: Vec<Warning>,
+    /// Inferred type of every function defined so far
+    fn_types: HashMap<String, Type>,
+    /// Optimisation passes run before evaluation
+    pipeline: Pipeline,
+    /// Runs input when set, otherwise the parser walks the tree
+    vm: Option<Vm>,
+    /// Whether input is compiled to native code when it can be
+    jit: bool,
+    /// Compiled inputs that define functions, kept for as long as the
+    /// session so their functions stay callable
+    compiled: Vec<Jit>,
+    /// Which of `compiled` holds the current definition of each function
+    natives: HashMap<String, usize>,
+}
+
+/// An expression evaluating to the value, placed at the statement
+fn literal(val: Value, stmt: &Stmt) -> Expr {
+    let kind = match val {
+        Value::Number(val) => ExprKind::Number(val),
+        Value::Bool(val) => ExprKind::Bool(val),
+        _ => ExprKind::Block(Vec::new()),
+    };
+    Expr::new(kind, stmt.span)
+}
+
+/// One line per error
+fn report<T: Display>(errs: &[T]) -> String {
+    errs.iter()
+        .map(|err| err.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The checked program lowered to IR that has been verified
+fn verified(resolved: &Resolved, types: &HashMap<DeclId, Type>) -> Result<Module, String> {
+    let module = lower(resolved, types).map_err(|err| err.to_string())?;
+    verify(&module).map_err(|err| format!("Invalid IR: {err}"))?;
+    Ok(module)
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::with_dialect(Dialect::default())
+    }
+    pub fn with_dialect(dialect: Dialect) -> Self {
+        Self {
+            parser: Parser::new(Vec::new()).with_dialect(dialect),
+            history: Vec::new(),
+            buffer: String::new(),
+            lints: LintConfig::repl(),
+            warnings: Vec::new(),
+            fn_types: HashMap::new(),
+            pipeline: Pipeline::default(),
+            vm: None,
+            jit: false,
+            compiled: Vec::new(),
+            natives: HashMap::new(),
+        }
+    }
+    /// Lint input with the given configuration instead of warning about everything
+    pub fn with_lints(mut self, lints: LintConfig) -> Self {
+        self.lints = lints;
+        self
+    }
+    /// Optimise input with the given passes instead of those of `-O2`
+    pub fn with_pipeline(mut self, pipeline: Pipeline) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+    /// Compile input to bytecode and run it on the virtual machine instead of
+    /// walking the tree
+    pub fn with_vm(mut self) -> Self {
+        self.vm = Some(Vm::new());
+        self
+    }
+    /// Compile input to native code and run it in process, input using
+    /// something the IR doesn't support or defined by earlier input is
+    /// evaluated instead
+    pub fn with_jit(mut self) -> Self {
+        self.jit = true;
+        self
+    }
+    /// Previously submitted inputs, multi-line inputs are kept as one entry
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+    /// Lint warnings produced since this was last called
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+    /// Hand a single line of user input to the session
+    pub fn submit(&mut self, line: &str) -> Response {
+        if self.buffer.is_empty() && line.trim_start().starts_with(':') {
+            self.history.push(line.trim().to_string());
+            return match Command::parse(line) {
+                Ok(cmd) => self.run_command(cmd),
+                Err(err) => Response::Error(err),
+            };
+        }
+
+        self.buffer.push_str(line);
+        self.buffer.push('\n');
+        if is_incomplete(&self.buffer) {
+            return Response::Continue;
+        }
+
+        let source = std::mem::take(&mut self.buffer);
+        if source.trim().is_empty() {
+            return Response::Nothing;
+        }
+        self.history.push(source.trim_end().to_string());
+
+        match self.eval_source(&source) {
+            Ok(Some(val)) => Response::Value(val),
+            Ok(None) => Response::Nothing,
+            Err(err) => Response::Error(err),
+        }
+    }
+    /// Evaluate a whole source text against the session state, returning the
+    /// value of the last expression statement
+    pub fn eval_source(&mut self, source: &str) -> Result<Option<Value>, String> {
+        let (resolved, types) = self.compile_typed(source)?;
+        if self.jit {
+            if let Some(result) = self.run_native(&resolved, &types) {
+                self.record_fn_types(&resolved, &types, None);
+                return Ok(result);
+            }
+        }
+        for stmt in &resolved.stmts {
+            if let StmtKind::Fn(decl) = &stmt.kind {
+                self.natives.remove(&decl.name.name);
+            }
+        }
+        let evaluation = self.evaluate(&resolved.stmts);
+        let failed = evaluation.error().map(|(_, span)| span);
+        self.record_fn_types(&resolved, &types, failed);
+        self.finish(evaluation)
+    }
+    fn evaluate(&mut self, stmts: &[Stmt]) -> Evaluation {
+        match &mut self.vm {
+            Some(vm) => vm.evaluate(&compile(stmts)),
+            None => self.parser.evaluate(stmts),
+        }
+    }
+    /// Run the input as native code, or `None` when it can't be compiled or
+    /// stops with an error, which evaluating it again reports and leaves the
+    /// state behind that it would
+    fn run_native(
+        &mut self,
+        resolved: &Resolved,
+        types: &HashMap<DeclId, Type>,
+    ) -> Option<Option<Value>> {
+        let module = lower(resolved, types).ok()?;
+        verify(&module).ok()?;
+        let jit = Jit::new(&module).ok()?;
+        let val = jit.run().ok()?;
+
+        // Bind what the input defined as evaluating it would have
+        let definitions: Vec<Stmt> = resolved
+            .stmts
+            .iter()
+            .filter_map(|stmt| {
+                let kind = match &stmt.kind {
+                    StmtKind::Fn(_) => stmt.kind.clone(),
+                    StmtKind::Let(id, _) => {
+                        StmtKind::Let(id.clone(), literal(jit.global(&id.name)?, stmt))
+                    }
+                    StmtKind::Const(id, _) => {
+                        StmtKind::Const(id.clone(), literal(jit.global(&id.name)?, stmt))
+                    }
+                    _ => return None,
+                };
+                Some(Stmt::new(kind, stmt.span))
+            })
+            .collect();
+        self.evaluate(&definitions);
+        if module.functions.len() > 1 {
+            for func in module.functions.iter().filter(|func| func.name != MAIN) {
+                self.natives.insert(func.name.clone(), self.compiled.len());
+            }
+            self.compiled.push(jit);
+        }
+
+        let result = resolved
+            .stmts
+            .iter()
+            .any(|stmt| !stmt.is_definition())
+            .then_some(val);
+        if let Some(val) = &result {
+            self.set_ans(val);
+        }
+        Some(result)
+    }
+    /// A function compiled by a session run `with_jit` as a C function
+    /// pointer
+    ///
+    /// `let f: extern "C" fn(f64, f64) -> f64 = unsafe { session.compile_fn("f")? }`.
+    /// A runtime error makes it return NaN, `jit::take_error` then gives the
+    /// message
+    ///
+    /// # Safety
+    /// The code lives as long as the session, the pointer mustn't be called
+    /// once the session is dropped
+    pub unsafe fn compile_fn<F: NativeFn>(&self, name: &str) -> Result<F, String> {
+        let idx = self.natives.get(name).ok_or_else(|| {
+            format!("{name} isn't compiled, only functions defined by input the JIT ran are")
+        })?;
+        // SAFETY: passed on to the caller
+        unsafe { self.compiled[*idx].function(name) }
+    }
+    /// Run a program compiled earlier, which needs the virtual machine
+    pub fn run_bytecode(&mut self, program: &Program) -> Result<Option<Value>, String> {
+        let vm = self
+            .vm
+            .as_mut()
+            .ok_or("Bytecode only runs on the virtual machine, pass --vm")?;
+        let evaluation = vm.evaluate(program);
+        self.finish(evaluation)
+    }
+    /// Disassemble a file written by `--compile`, bytecode being the only
+    /// stage it still has
+    pub fn emit_compiled(&self, bytes: &[u8], stage: Emit) -> Result<String, String> {
+        let program = decode(bytes).map_err(|err| err.to_string())?;
+        match stage {
+            Emit::Bytecode => Ok(disassemble(&program, "")),
+            _ => Err(format!(
+                "Compiled bytecode has no {stage} stage, only bytecode can be emitted"
+            )),
+        }
+    }
+    /// Check and compile the source to bytecode without running it
+    pub fn compile_bytecode(&mut self, source: &str) -> Result<Program, String> {
+        Ok(compile(&self.compile(source)?.stmts))
+    }
+    /// Check and compile the source to a static x86-64 Linux executable
+    /// printing its result
+    pub fn compile_executable(&mut self, source: &str) -> Result<Vec<u8>, String> {
+        executable(&generate(&self.compile_ir(source)?, Target::Standalone))
+    }
+    /// Check and compile the source to a WebAssembly module exporting every
+    /// function it defines
+    pub fn compile_wasm(&mut self, source: &str) -> Result<Vec<u8>, String> {
+        to_wasm(&self.compile_library(source)?)
+    }
+    /// Check and translate the source to a C file defining every function
+    /// it defines
+    pub fn compile_c(&mut self, source: &str) -> Result<String, String> {
+        let (resolved, types) = self.compile_typed_keeping_globals(source)?;
+        to_c(&resolved, &types).map_err(|err| err.to_string())
+    }
+    /// Check and lower the source to SSA form without running it
+    pub fn compile_ir(&mut self, source: &str) -> Result<Module, String> {
+        let (resolved, types) = self.compile_typed(source)?;
+        verified(&resolved, &types)
+    }
+    /// Lower the source for other code to call into, every function it
+    /// defines stays even when the script doesn't use it
+    fn compile_library(&mut self, source: &str) -> Result<Module, String> {
+        let (resolved, types) = self.compile_typed_keeping_globals(source)?;
+        verified(&resolved, &types)
+    }
+    /// The final value, which becomes `ans`, or the first error
+    fn finish(&mut self, evaluation: Evaluation) -> Result<Option<Value>, String> {
+        let result = evaluation.into_result().map_err(|err| err.to_string())?;
+        if let Some(val) = &result {
+            self.set_ans(val);
+        }
+        Ok(result)
+    }
+    /// Remember the types of the functions the input defined, those declared
+    /// after the statement that `failed` never were
+    fn record_fn_types(
+        &mut self,
+        resolved: &Resolved,
+        types: &HashMap<DeclId, Type>,
+        failed: Option<Span>,
+    ) {
+        let defined: HashSet<String> = self.functions().into_iter().map(|(name, _)| name).collect();
+        for (&id, ty) in types {
+            let decl = &resolved.resolution.declarations[id];
+            let ran = match (decl.span, failed) {
+                (Some(span), Some(failed)) => span.start < failed.start,
+                _ => true,
+            };
+            if matches!(decl.kind, DeclKind::Fn(_)) && ran && defined.contains(&decl.name) {
+                self.fn_types.insert(decl.name.clone(), ty.clone());
+            }
+        }
+    }
+    fn set_ans(&mut self, val: &Value) {
+        match &mut self.vm {
+            Some(vm) => vm.set_global("ans", val.clone()),
+            None => self.parser.set_global("ans", val.clone()),
+        }
+    }
+    /// Print a stage of compiling the source instead of evaluating it
+    pub fn emit(&mut self, source: &str, stage: Emit) -> Result<String, String> {
+        let lines: Vec<String> = match stage {
+            Emit::Tokens => Tokeniser::new(source.to_string())
+                .to_tokens()?
+                .iter()
+                .map(|tok| {
+                    format!(
+                        "{0} {1}..{2} {3:?}",
+                        tok.line_number, tok.span.start, tok.span.end, tok.kind
+                    )
+                })
+                .collect(),
+            Emit::Ast => self
+                .parse(source)?
+                .iter()
+                .map(|stmt| stmt.to_string())
+                .collect(),
+            Emit::Resolved => self
+                .compile(source)?
+                .stmts
+                .iter()
+                .map(|stmt| stmt.to_string())
+                .collect(),
+            Emit::Bytecode => return Ok(disassemble(&self.compile_bytecode(source)?, source)),
+            Emit::Ir => return Ok(self.compile_ir(source)?.to_string().trim_end().to_string()),
+            Emit::Asm => {
+                return Ok(generate(&self.compile_library(source)?, Target::Linked)
+                    .to_string()
+                    .trim_end()
+                    .to_string())
+            }
+            Emit::Llvm => {
+                return Ok(to_llvm(&self.compile_library(source)?)
+                    .trim_end()
+                    .to_string())
+            }
+        };
+        Ok(lines.join("\n"))
+    }
+    fn parse(&mut self, source: &str) -> Result<Vec<Stmt>, String> {
+        let tokens = Tokeniser::new(source.to_string()).to_tokens()?;
+        self.parser.feed(tokens);
+        self.parser.parse_lines()
+    }
+    /// Parse, check and optimise the source
+    fn compile(&mut self, source: &str) -> Result<Resolved, String> {
+        self.compile_typed(source).map(|(resolved, _)| resolved)
+    }
+    /// Parse, check and optimise the source alongside the types inferred
+    /// before optimising, queueing lint warnings
+    fn compile_typed(&mut self, source: &str) -> Result<(Resolved, HashMap<DeclId, Type>), String> {
+        let stmts = self.parse(source)?;
+        let resolved = self
+            .resolver()
+            .resolve(stmts)
+            .map_err(|errs| report(&errs))?;
+
+        let types = self
+            .type_checker()
+            .check(&resolved)
+            .map_err(|errs| report(&errs))?;
+
+        let (denied, warnings): (Vec<Warning>, Vec<Warning>) = lint(&resolved, &self.lints)
+            .into_iter()
+            .partition(|warning| warning.level == Level::Deny);
+        if !denied.is_empty() {
+            return Err(report(&denied));
+        }
+        self.warnings.extend(warnings);
+
+        Ok((self.pipeline.run(resolved), types))
+    }
+    /// `compile_typed` without removing unused functions or variables, as a
+    /// script does
+    fn compile_typed_keeping_globals(
+        &mut self,
+        source: &str,
+    ) -> Result<(Resolved, HashMap<DeclId, Type>), String> {
+        let keep_globals = std::mem::replace(&mut self.pipeline.keep_globals, true);
+        let compiled = self.compile_typed(source);
+        self.pipeline.keep_globals = keep_globals;
+        compiled
+    }
+    /// Global variables sorted by name
+    fn variables(&self) -> Vec<(String, Value)> {
+        match &self.vm {
+            Some(vm) => vm.variables(),
+            None => self.parser.variables(),
+        }
+    }
+    /// User defined functions and their parameters sorted by name
+    fn functions(&self) -> Vec<(String, Vec<String>)> {
+        match &self.vm {
+            Some(vm) => vm.functions(),
+            None => self.parser.functions(),
+        }
+    }
+    fn resolver(&self) -> Resolver {
+        match &self.vm {
+            Some(vm) => vm.resolver(),
+            None => self.parser.resolver(),
+        }
+    }
+    /// A type checker that knows the types of everything defined so far
+    fn type_checker(&self) -> TypeChecker {
+        let mut checker = TypeChecker::new();
+        for (name, value) in self.variables() {
+            let ty = match &value {
+                Value::Function(func) => self.fn_types.get(&func.name).cloned(),
+                _ => None,
+            };
+            checker.declare_global(&name, ty.unwrap_or_else(|| Type::of_value(&value)));
+        }
+        for (name, ty) in &self.fn_types {
+            checker.declare_function(name, ty.clone());
+        }
+        checker
+    }
+    /// Evaluate a file against the session state, files written by
+    /// `--compile` run without being parsed again
+    pub fn load(&mut self, path: &str) -> Result<Option<Value>, String> {
+        let bytes =
+            std::fs::read(path).map_err(|err| format!("Failed to read {0}: {1}", path, err))?;
+        if is_bytecode(&bytes) {
+            let program = decode(&bytes).map_err(|err| err.to_string())?;
+            return self.run_bytecode(&program);
+        }
+        let source = String::from_utf8(bytes)
+            .map_err(|err| format!("Failed to read {0}: {1}", path, err))?;
+        self.eval_source(&source)
+    }
+    fn run_command(&mut self, cmd: Command) -> Response {
+        match cmd {
+            Command::Vars => Response::Output(
+                self.variables()
+                    .iter()
+                    .map(|(id, val)| format!("{id} = {val}"))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            Command::Funcs => Response::Output(
+                self.functions()
+                    .iter()
+                    .map(|(name, params)| format!("{name}({0})", params.join(", ")))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            Command::Reset => {
+                self.parser.reset();
+                if let Some(vm) = &mut self.vm {
+                    vm.reset();
+                }
+                self.fn_types.clear();
+                self.natives.clear();
+                Response::Nothing
+            }
+            Command::History => Response::Output(
+                self.history
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, entry)| format!("{0:>4}  {entry}", idx + 1))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            Command::Load(path) => match self.load(&path) {
+                Ok(Some(val)) => Response::Value(val),
+                Ok(None) => Response::Nothing,
+                Err(err) => Response::Error(err),
+            },
+            Command::Help => Response::Output(HELP.to_string()),
+            Command::Quit => Response::Quit,
+        }
+    }
+    /// Drive the session from a line based reader until EOF or :quit
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> std::io::Result<()> {
+        write!(output, "{PROMPT}")?;
+        output.flush()?;
+
+        for line in input.lines() {
+            let response = self.submit(&line?);
+            for warning in self.take_warnings() {
+                writeln!(output, "warning: {warning}")?;
+            }
+            match response {
+                Response::Value(val) => writeln!(output, "{val}")?,
+                Response::Output(text) if !text.is_empty() => writeln!(output, "{text}")?,
+                Response::Error(err) => writeln!(output, "error: {err}")?,
+                Response::Quit => return Ok(()),
+                _ => {}
+            }
+            let prompt = if self.buffer.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            };
+            write!(output, "{prompt}")?;
+            output.flush()?;
+        }
+
+        if !self.buffer.is_empty() {
+            writeln!(output, "error: unexpected end of input")?;
+        }
+        Ok(())
+    }
+}
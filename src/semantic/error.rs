@@ -0,0 +1,95 @@
+use crate::tokeniser::token_enum::Span;
+use std::fmt;
+
+/// Problems found before evaluation
+#[derive(Debug, Clone, PartialEq)]
+pub enum SemanticErrorKind {
+    UndeclaredVariable(String),
+    UndefinedFunction(String),
+    AssignToConst(String),
+    ArityMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    DuplicateParameter {
+        function: String,
+        param: String,
+    },
+    /// Types are printed as they would be written in an annotation
+    TypeMismatch {
+        expected: String,
+        found: String,
+    },
+    /// A type that would have to contain itself, such as `f(f)`
+    InfiniteType {
+        var: String,
+        ty: String,
+    },
+    /// A function from earlier input redefined with a type its callers
+    /// weren't checked against
+    Redefinition {
+        name: String,
+        earlier: String,
+        found: String,
+    },
+}
+
+/// A semantic error and the source it points at
+#[derive(Debug, Clone, PartialEq)]
+pub struct SemanticError {
+    pub kind: SemanticErrorKind,
+    pub span: Span,
+    /// A similarly named declaration the user may have meant
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for SemanticErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SemanticErrorKind::UndeclaredVariable(id) => write!(f, "Undeclared Variable: {id}"),
+            SemanticErrorKind::UndefinedFunction(name) => write!(f, "Undefined Function: {name}"),
+            SemanticErrorKind::AssignToConst(id) => write!(f, "Cannot assign to const: {id}"),
+            SemanticErrorKind::ArityMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Function {name} called with wrong # of params, expected {expected} got {found}"
+            ),
+            SemanticErrorKind::DuplicateParameter { function, param } => {
+                write!(f, "Duplicate parameter {param} in function {function}")
+            }
+            SemanticErrorKind::TypeMismatch { expected, found } => {
+                write!(f, "Type error: expected {expected}, found {found}")
+            }
+            SemanticErrorKind::InfiniteType { var, ty } => {
+                write!(f, "Type error: infinite type {var} = {ty}")
+            }
+            SemanticErrorKind::Redefinition {
+                name,
+                earlier,
+                found,
+            } => write!(
+                f,
+                "Type error: {name} was defined as {earlier}, it can't be redefined as {found}"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.suggestion {
+            Some(name) => write!(
+                f,
+                "{0} (did you mean {1}?) @ {2}",
+                self.kind, name, self.span.start
+            ),
+            None => write!(f, "{0} @ {1}", self.kind, self.span.start),
+        }
+    }
+}
+
+impl std::error::Error for SemanticError {}
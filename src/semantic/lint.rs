@@ -0,0 +1,309 @@
+use crate::ast::ast::constant;
+use crate::ast::ast::Expr;
+use crate::ast::ast::ExprKind;
+use crate::ast::ast::FnDecl;
+use crate::ast::ast::Ident;
+use crate::ast::ast::Stmt;
+use crate::ast::ast::StmtKind;
+use crate::semantic::resolver::DeclId;
+use crate::semantic::resolver::DeclKind;
+use crate::semantic::resolver::Resolved;
+use crate::tokeniser::token_enum::Span;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Every check the lint pass knows about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// A `let` or `const` that is never read
+    UnusedVariable,
+    /// A function parameter that is never read
+    UnusedParameter,
+    /// A `let` or `const` in a block that hides a variable from an outer scope
+    ShadowedVariable,
+    /// A declaration that hides a builtin constant such as `pi`
+    ShadowedBuiltin,
+    /// Statements following a `return` in the same block
+    UnreachableCode,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 5] = [
+        Lint::UnusedVariable,
+        Lint::UnusedParameter,
+        Lint::ShadowedVariable,
+        Lint::ShadowedBuiltin,
+        Lint::UnreachableCode,
+    ];
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => "unused_variable",
+            Lint::UnusedParameter => "unused_parameter",
+            Lint::ShadowedVariable => "shadowed_variable",
+            Lint::ShadowedBuiltin => "shadowed_builtin",
+            Lint::UnreachableCode => "unreachable_code",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Lint::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{0}", self.name())
+    }
+}
+
+/// How a lint is reported, denied lints stop evaluation like an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/// Level of each lint, every lint warns unless configured otherwise
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    levels: HashMap<Lint, Level>,
+    /// Top level names may still be used by later input, as in the REPL, so
+    /// they aren't reported as unused
+    pub incremental: bool,
+}
+
+impl LintConfig {
+    /// Default levels for input evaluated a piece at a time
+    pub fn repl() -> Self {
+        Self {
+            incremental: true,
+            ..Self::default()
+        }
+    }
+    pub fn level(&self, lint: Lint) -> Level {
+        self.levels.get(&lint).copied().unwrap_or(Level::Warn)
+    }
+    pub fn set(&mut self, lint: Lint, level: Level) {
+        self.levels.insert(lint, level);
+    }
+    /// Apply a command line flag such as `--deny=unused_variable`, returns
+    /// false if the flag isn't a lint flag at all
+    pub fn apply_flag(&mut self, flag: &str) -> Result<bool, String> {
+        let (level, name) = if let Some(name) = flag.strip_prefix("--allow=") {
+            (Level::Allow, name)
+        } else if let Some(name) = flag.strip_prefix("--warn=") {
+            (Level::Warn, name)
+        } else if let Some(name) = flag.strip_prefix("--deny=") {
+            (Level::Deny, name)
+        } else {
+            return Ok(false);
+        };
+        let lint = Lint::from_name(name).ok_or_else(|| format!("Unknown lint: {name}"))?;
+        self.set(lint, level);
+        Ok(true)
+    }
+}
+
+/// A lint that fired, with the level it was configured at
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub lint: Lint,
+    pub level: Level,
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{0} [{1}] @ {2}",
+            self.message, self.lint, self.span.start
+        )
+    }
+}
+
+/// Run every lint that isn't allowed over a resolved program, sorted by position
+pub fn lint(resolved: &Resolved, config: &LintConfig) -> Vec<Warning> {
+    let mut linter = Linter {
+        config,
+        scopes: vec![HashSet::new()],
+        globals: HashSet::new(),
+        assigned: HashSet::new(),
+        warnings: Vec::new(),
+    };
+    for decl in &resolved.resolution.declarations {
+        if decl.span.is_none() && matches!(decl.kind, DeclKind::Let | DeclKind::Const) {
+            linter.scopes[0].insert(decl.name.clone());
+        }
+    }
+    linter.stmts(&resolved.stmts);
+    linter.unused(resolved);
+
+    let mut warnings = linter.warnings;
+    warnings.sort_by_key(|warning| warning.span.start);
+    warnings
+}
+
+struct Linter<'a> {
+    config: &'a LintConfig,
+    /// Names declared in each enclosing scope
+    scopes: Vec<HashSet<String>>,
+    /// Spans of names declared at the top level
+    globals: HashSet<Span>,
+    /// Spans of assignment targets, which write a variable without reading it
+    assigned: HashSet<Span>,
+    warnings: Vec<Warning>,
+}
+
+impl Linter<'_> {
+    fn warn(&mut self, lint: Lint, message: String, span: Span) {
+        let level = self.config.level(lint);
+        if level != Level::Allow {
+            self.warnings.push(Warning {
+                lint,
+                level,
+                message,
+                span,
+            });
+        }
+    }
+    /// Whether the name hides a builtin constant
+    fn shadows_builtin(&mut self, id: &Ident) -> bool {
+        let shadows = constant(&id.name).is_some();
+        if shadows {
+            self.warn(
+                Lint::ShadowedBuiltin,
+                format!("Shadowed builtin: {0}", id.name),
+                id.span,
+            );
+        }
+        shadows
+    }
+    /// Check a variable about to be declared in the innermost scope
+    fn declare(&mut self, id: &Ident) {
+        let shadows_outer = self.scopes.len() > 1
+            && self.scopes[..self.scopes.len() - 1]
+                .iter()
+                .any(|scope| scope.contains(&id.name));
+        if !self.shadows_builtin(id) && shadows_outer {
+            self.warn(
+                Lint::ShadowedVariable,
+                format!("Shadowed variable: {0}", id.name),
+                id.span,
+            );
+        }
+        if self.scopes.len() == 1 {
+            self.globals.insert(id.span);
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(id.name.clone());
+        }
+    }
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        if let Some(ret) = stmts
+            .iter()
+            .position(|stmt| matches!(stmt.kind, StmtKind::Return(_)))
+        {
+            if let (Some(first), Some(last)) = (stmts.get(ret + 1), stmts.last()) {
+                self.warn(
+                    Lint::UnreachableCode,
+                    String::from("Unreachable statement"),
+                    first.span.to(last.span),
+                );
+            }
+        }
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Let(id, expr) | StmtKind::Const(id, expr) => {
+                self.expr(expr);
+                self.declare(id);
+            }
+            StmtKind::Assign(id, expr) => {
+                self.expr(expr);
+                self.assigned.insert(id.span);
+            }
+            StmtKind::Fn(decl) => self.function(decl),
+            StmtKind::Expr(expr) | StmtKind::Return(Some(expr)) => self.expr(expr),
+            StmtKind::Return(None) => {}
+        }
+    }
+    /// The body only sees globals, so a parameter can shadow nothing else
+    fn function(&mut self, decl: &FnDecl) {
+        self.shadows_builtin(&decl.name);
+        let outer = self.scopes.split_off(1);
+        self.scopes.push(HashSet::new());
+        for param in &decl.params {
+            self.declare(&param.name);
+        }
+        self.block(&decl.body);
+        self.scopes.pop();
+        self.scopes.extend(outer);
+    }
+    fn block(&mut self, stmts: &[Stmt]) {
+        self.scopes.push(HashSet::new());
+        self.stmts(stmts);
+        self.scopes.pop();
+    }
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Number(_) | ExprKind::Bool(_) | ExprKind::Variable(_) => {}
+            ExprKind::BinaryOp(left, _, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::UnaryOp(operand, _) => self.expr(operand),
+            ExprKind::Builtin(_, args) | ExprKind::FunctionCall(_, args) => {
+                args.iter().for_each(|arg| self.expr(arg))
+            }
+            ExprKind::Block(stmts) => self.block(stmts),
+            ExprKind::If(cond, then_branch, else_branch) => {
+                self.expr(cond);
+                self.expr(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.expr(else_branch);
+                }
+            }
+        }
+    }
+    /// Declarations that are never read, assignments don't count as a read
+    fn unused(&mut self, resolved: &Resolved) {
+        let mut reads: HashMap<DeclId, usize> = HashMap::new();
+        for (span, &id) in &resolved.resolution.uses {
+            if !self.assigned.contains(span) {
+                *reads.entry(id).or_default() += 1;
+            }
+        }
+
+        for (id, decl) in resolved.resolution.declarations.iter().enumerate() {
+            let Some(span) = decl.span else {
+                continue;
+            };
+            if reads.contains_key(&id) {
+                continue;
+            }
+            match decl.kind {
+                DeclKind::Let | DeclKind::Const => {
+                    if !(self.config.incremental && self.globals.contains(&span)) {
+                        self.warn(
+                            Lint::UnusedVariable,
+                            format!("Unused variable: {0}", decl.name),
+                            span,
+                        );
+                    }
+                }
+                DeclKind::Param => self.warn(
+                    Lint::UnusedParameter,
+                    format!("Unused parameter: {0}", decl.name),
+                    span,
+                ),
+                DeclKind::Fn(_) | DeclKind::Constant => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,5 @@
+pub mod error;
+pub mod lint;
+pub mod resolver;
+pub mod suggest;
+pub mod typeck;
@@ -0,0 +1,389 @@
+use crate::ast::ast::constant;
+use crate::ast::ast::Builtin;
+use crate::ast::ast::Expr;
+use crate::ast::ast::ExprKind;
+use crate::ast::ast::FnDecl;
+use crate::ast::ast::Ident;
+use crate::ast::ast::Stmt;
+use crate::ast::ast::StmtKind;
+use crate::ast::ast::CONSTANTS;
+use crate::semantic::error::SemanticError;
+use crate::semantic::error::SemanticErrorKind;
+use crate::semantic::suggest::suggest;
+use crate::tokeniser::token_enum::Span;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/// Index into `Resolution::declarations`
+pub type DeclId = usize;
+
+/// What a name was declared as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeclKind {
+    Let,
+    Const,
+    Param,
+    /// User function and its number of parameters
+    Fn(usize),
+    /// Builtin constant such as `pi`
+    Constant,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declaration {
+    pub name: String,
+    pub kind: DeclKind,
+    /// Where the name was declared, `None` for constants and for names
+    /// carried over from earlier input
+    pub span: Option<Span>,
+}
+
+/// Which declaration every name in a program refers to
+#[derive(Debug, Clone, Default)]
+pub struct Resolution {
+    pub declarations: Vec<Declaration>,
+    /// Keyed by the span of the name at each variable, call or assignment
+    pub uses: HashMap<Span, DeclId>,
+}
+
+impl Resolution {
+    /// Declaration the name written at `span` refers to
+    pub fn lookup(&self, span: Span) -> Option<&Declaration> {
+        self.uses.get(&span).map(|&id| &self.declarations[id])
+    }
+}
+
+/// A program in which every name is known to refer to a declaration
+#[derive(Debug, Clone)]
+pub struct Resolved {
+    pub stmts: Vec<Stmt>,
+    pub resolution: Resolution,
+}
+
+/// Binds every identifier to its declaration before evaluation
+///
+/// Blocks are lexically scoped. A function body sees the globals, every
+/// function, its parameters and its own locals but not the locals around its
+/// definition. Bodies are resolved after the top level, so they may refer to
+/// globals and functions that are defined after them
+pub struct Resolver {
+    resolution: Resolution,
+    scopes: Vec<HashMap<String, DeclId>>,
+    /// Functions defined so far, at runtime these live in one global table
+    functions: HashMap<String, DeclId>,
+    /// Every function declared anywhere in the program, visible from bodies
+    hoisted: HashMap<String, DeclId>,
+    /// Declaration of each `fn`, keyed by the span of its name
+    fn_decls: HashMap<Span, DeclId>,
+    constants: HashMap<String, DeclId>,
+    /// Function bodies waiting for the top level to finish
+    deferred: Vec<FnDecl>,
+    in_function: bool,
+    errors: Vec<SemanticError>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            resolution: Resolution::default(),
+            scopes: vec![HashMap::new()],
+            functions: HashMap::new(),
+            hoisted: HashMap::new(),
+            fn_decls: HashMap::new(),
+            constants: HashMap::new(),
+            deferred: Vec::new(),
+            in_function: false,
+            errors: Vec::new(),
+        }
+    }
+    /// Make a global variable defined by earlier input visible
+    pub fn declare_global(&mut self, name: &str, mutable: bool) {
+        let kind = if mutable {
+            DeclKind::Let
+        } else {
+            DeclKind::Const
+        };
+        let id = self.push_declaration(name, kind, None);
+        self.scopes[0].insert(name.to_string(), id);
+    }
+    /// Make a function defined by earlier input visible
+    pub fn declare_function(&mut self, name: &str, arity: usize) {
+        let id = self.push_declaration(name, DeclKind::Fn(arity), None);
+        self.functions.insert(name.to_string(), id);
+        self.hoisted.insert(name.to_string(), id);
+    }
+    /// Resolve a whole program, reporting every error sorted by position
+    pub fn resolve(mut self, stmts: Vec<Stmt>) -> Result<Resolved, Vec<SemanticError>> {
+        self.hoist(&stmts);
+        for stmt in &stmts {
+            self.stmt(stmt);
+        }
+
+        self.in_function = true;
+        let mut idx = 0;
+        while idx < self.deferred.len() {
+            let decl = self.deferred[idx].clone();
+            self.function_body(&decl);
+            idx += 1;
+        }
+
+        if self.errors.is_empty() {
+            Ok(Resolved {
+                stmts,
+                resolution: self.resolution,
+            })
+        } else {
+            self.errors.sort_by_key(|err| err.span.start);
+            Err(self.errors)
+        }
+    }
+    fn push_declaration(&mut self, name: &str, kind: DeclKind, span: Option<Span>) -> DeclId {
+        self.resolution.declarations.push(Declaration {
+            name: name.to_string(),
+            kind,
+            span,
+        });
+        self.resolution.declarations.len() - 1
+    }
+    fn declare(&mut self, id: &Ident, kind: DeclKind) {
+        let decl = self.push_declaration(&id.name, kind, Some(id.span));
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(id.name.clone(), decl);
+        }
+    }
+    fn error(&mut self, kind: SemanticErrorKind, span: Span) {
+        self.errors.push(SemanticError {
+            kind,
+            span,
+            suggestion: None,
+        });
+    }
+    /// Variables in scope, and when reading also functions and constants
+    fn undeclared_variable(&mut self, name: &str, span: Span, reading: bool) {
+        let mut candidates: Vec<&str> = self
+            .scopes
+            .iter()
+            .flat_map(|scope| scope.keys().map(String::as_str))
+            .collect();
+        if reading {
+            candidates.extend(self.visible_functions().keys().map(String::as_str));
+            candidates.extend(CONSTANTS.iter().map(|(name, _)| *name));
+        }
+        let suggestion = suggest(name, candidates);
+        self.errors.push(SemanticError {
+            kind: SemanticErrorKind::UndeclaredVariable(name.to_string()),
+            span,
+            suggestion,
+        });
+    }
+    /// User functions and builtins
+    fn undefined_function(&mut self, id: &Ident) {
+        let candidates = self
+            .visible_functions()
+            .keys()
+            .map(String::as_str)
+            .chain(Builtin::ALL.iter().map(|builtin| builtin.name()));
+        let suggestion = suggest(&id.name, candidates);
+        self.errors.push(SemanticError {
+            kind: SemanticErrorKind::UndefinedFunction(id.name.clone()),
+            span: id.span,
+            suggestion,
+        });
+    }
+    /// Record every function declaration up front, however deeply nested
+    fn hoist(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            match &stmt.kind {
+                StmtKind::Fn(decl) => {
+                    let id = self.push_declaration(
+                        &decl.name.name,
+                        DeclKind::Fn(decl.params.len()),
+                        Some(decl.name.span),
+                    );
+                    self.fn_decls.insert(decl.name.span, id);
+                    self.hoisted.insert(decl.name.name.clone(), id);
+                    self.hoist(&decl.body);
+                }
+                StmtKind::Let(_, expr)
+                | StmtKind::Const(_, expr)
+                | StmtKind::Assign(_, expr)
+                | StmtKind::Expr(expr)
+                | StmtKind::Return(Some(expr)) => self.hoist_expr(expr),
+                StmtKind::Return(None) => {}
+            }
+        }
+    }
+    fn hoist_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Block(stmts) => self.hoist(stmts),
+            ExprKind::BinaryOp(left, _, right) => {
+                self.hoist_expr(left);
+                self.hoist_expr(right);
+            }
+            ExprKind::UnaryOp(operand, _) => self.hoist_expr(operand),
+            ExprKind::Builtin(_, args) | ExprKind::FunctionCall(_, args) => {
+                args.iter().for_each(|arg| self.hoist_expr(arg))
+            }
+            ExprKind::If(cond, then_branch, else_branch) => {
+                self.hoist_expr(cond);
+                self.hoist_expr(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.hoist_expr(else_branch);
+                }
+            }
+            ExprKind::Number(_) | ExprKind::Bool(_) | ExprKind::Variable(_) => {}
+        }
+    }
+    fn lookup_variable(&self, name: &str) -> Option<DeclId> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+    fn visible_functions(&self) -> &HashMap<String, DeclId> {
+        if self.in_function {
+            &self.hoisted
+        } else {
+            &self.functions
+        }
+    }
+    fn lookup_function(&self, name: &str) -> Option<DeclId> {
+        self.visible_functions().get(name).copied()
+    }
+    fn lookup_constant(&mut self, name: &str) -> Option<DeclId> {
+        constant(name)?;
+        if let Some(&id) = self.constants.get(name) {
+            return Some(id);
+        }
+        let id = self.push_declaration(name, DeclKind::Constant, None);
+        self.constants.insert(name.to_string(), id);
+        Some(id)
+    }
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Let(id, expr) => {
+                self.expr(expr);
+                self.declare(id, DeclKind::Let);
+            }
+            StmtKind::Const(id, expr) => {
+                self.expr(expr);
+                self.declare(id, DeclKind::Const);
+            }
+            StmtKind::Assign(id, expr) => {
+                self.expr(expr);
+                match self.lookup_variable(&id.name) {
+                    Some(decl) => {
+                        if self.resolution.declarations[decl].kind == DeclKind::Const {
+                            self.error(SemanticErrorKind::AssignToConst(id.name.clone()), id.span);
+                        }
+                        self.resolution.uses.insert(id.span, decl);
+                    }
+                    None => self.undeclared_variable(&id.name, id.span, false),
+                }
+            }
+            StmtKind::Fn(decl) => {
+                let id = self.fn_decls[&decl.name.span];
+                self.functions.insert(decl.name.name.clone(), id);
+                self.deferred.push(decl.clone());
+            }
+            StmtKind::Expr(expr) | StmtKind::Return(Some(expr)) => self.expr(expr),
+            StmtKind::Return(None) => {}
+        }
+    }
+    fn block(&mut self, stmts: &[Stmt]) {
+        self.scopes.push(HashMap::new());
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+    /// Resolve a body against the globals and its own parameters
+    fn function_body(&mut self, decl: &FnDecl) {
+        let outer = self.scopes.split_off(1);
+        self.scopes.push(HashMap::new());
+
+        let mut seen = HashSet::new();
+        for param in &decl.params {
+            if !seen.insert(param.name.name.as_str()) {
+                self.error(
+                    SemanticErrorKind::DuplicateParameter {
+                        function: decl.name.name.clone(),
+                        param: param.name.name.clone(),
+                    },
+                    param.name.span,
+                );
+            }
+            self.declare(&param.name, DeclKind::Param);
+        }
+        self.block(&decl.body);
+
+        self.scopes.pop();
+        self.scopes.extend(outer);
+    }
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Number(_) | ExprKind::Bool(_) => {}
+            ExprKind::Variable(name) => {
+                let decl = self
+                    .lookup_variable(name)
+                    .or_else(|| self.lookup_function(name))
+                    .or_else(|| self.lookup_constant(name));
+                match decl {
+                    Some(decl) => {
+                        self.resolution.uses.insert(expr.span, decl);
+                    }
+                    None => self.undeclared_variable(name, expr.span, true),
+                }
+            }
+            ExprKind::BinaryOp(left, _, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::UnaryOp(operand, _) => self.expr(operand),
+            ExprKind::Builtin(_, args) => args.iter().for_each(|arg| self.expr(arg)),
+            ExprKind::Block(stmts) => self.block(stmts),
+            ExprKind::FunctionCall(id, args) => {
+                args.iter().for_each(|arg| self.expr(arg));
+                self.call(id, args.len());
+            }
+            ExprKind::If(cond, then_branch, else_branch) => {
+                self.expr(cond);
+                self.expr(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.expr(else_branch);
+                }
+            }
+        }
+    }
+    /// A variable may hold any function so only calls made directly by a
+    /// function's name have their arity checked
+    fn call(&mut self, id: &Ident, found: usize) {
+        if let Some(decl) = self.lookup_variable(&id.name) {
+            self.resolution.uses.insert(id.span, decl);
+            return;
+        }
+        let Some(decl) = self.lookup_function(&id.name) else {
+            self.undefined_function(id);
+            return;
+        };
+        self.resolution.uses.insert(id.span, decl);
+        if let DeclKind::Fn(expected) = self.resolution.declarations[decl].kind {
+            if expected != found {
+                self.error(
+                    SemanticErrorKind::ArityMismatch {
+                        name: id.name.clone(),
+                        expected,
+                        found,
+                    },
+                    id.span,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,512 @@
+use crate::ast::ast::BinaryOp;
+use crate::ast::ast::Expr;
+use crate::ast::ast::ExprKind;
+use crate::ast::ast::FnDecl;
+use crate::ast::ast::Stmt;
+use crate::ast::ast::StmtKind;
+use crate::ast::ast::TypeAnn;
+use crate::ast::value::Value;
+use crate::semantic::error::SemanticError;
+use crate::semantic::error::SemanticErrorKind;
+use crate::semantic::resolver::DeclId;
+use crate::semantic::resolver::DeclKind;
+use crate::semantic::resolver::Resolved;
+use crate::tokeniser::token_enum::Span;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Static type of a value, `Var` is a type still to be inferred
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Num,
+    Bool,
+    Unit,
+    Fn(Vec<Type>, Box<Type>),
+    Var(usize),
+}
+
+impl Type {
+    pub fn from_annotation(ann: &TypeAnn) -> Type {
+        match ann {
+            TypeAnn::Num => Type::Num,
+            TypeAnn::Bool => Type::Bool,
+            TypeAnn::Unit => Type::Unit,
+            TypeAnn::Fn(params, ret) => Type::Fn(
+                params.iter().map(Type::from_annotation).collect(),
+                Box::new(Type::from_annotation(ret)),
+            ),
+        }
+    }
+    /// Type of a runtime value, a function's parameters are left generic
+    pub fn of_value(value: &Value) -> Type {
+        match value {
+            Value::Number(_) => Type::Num,
+            Value::Bool(_) => Type::Bool,
+            Value::Unit => Type::Unit,
+            Value::Function(func) => Type::Fn(
+                (0..func.params.len()).map(Type::Var).collect(),
+                Box::new(Type::Var(func.params.len())),
+            ),
+        }
+    }
+    fn free_vars(&self, vars: &mut Vec<usize>) {
+        match self {
+            Type::Var(var) => {
+                if !vars.contains(var) {
+                    vars.push(*var);
+                }
+            }
+            Type::Fn(params, ret) => {
+                params.iter().for_each(|param| param.free_vars(vars));
+                ret.free_vars(vars);
+            }
+            Type::Num | Type::Bool | Type::Unit => {}
+        }
+    }
+    /// Write the type naming variables `'a`, `'b`, .. in order of appearance,
+    /// sharing `names` between types printed side by side
+    fn write(&self, names: &mut HashMap<usize, String>, out: &mut String) {
+        match self {
+            Type::Num => out.push_str("num"),
+            Type::Bool => out.push_str("bool"),
+            Type::Unit => out.push_str("unit"),
+            Type::Var(var) => {
+                let next = names.len();
+                let name = names.entry(*var).or_insert_with(|| match next {
+                    0..=25 => format!("'{0}", (b'a' + next as u8) as char),
+                    _ => format!("'t{next}"),
+                });
+                out.push_str(name);
+            }
+            Type::Fn(params, ret) => {
+                out.push_str("fn(");
+                for (idx, param) in params.iter().enumerate() {
+                    if idx > 0 {
+                        out.push_str(", ");
+                    }
+                    param.write(names, out);
+                }
+                out.push_str(") -> ");
+                ret.write(names, out);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        self.write(&mut HashMap::new(), &mut out);
+        write!(f, "{out}")
+    }
+}
+
+/// A type generic over `vars`, each use gets fresh copies of them
+#[derive(Debug, Clone)]
+struct Scheme {
+    vars: Vec<usize>,
+    ty: Type,
+}
+
+impl Scheme {
+    fn mono(ty: Type) -> Self {
+        Self {
+            vars: Vec::new(),
+            ty,
+        }
+    }
+    /// Generic over every variable it mentions
+    fn poly(ty: Type) -> Self {
+        let mut vars = Vec::new();
+        ty.free_vars(&mut vars);
+        Self { vars, ty }
+    }
+}
+
+enum UnifyError {
+    Mismatch,
+    /// A variable would have to contain itself
+    Infinite,
+}
+
+/// Hindley-Milner inference over a resolved program
+///
+/// Every declaration starts out as an unknown and is refined by how it's used,
+/// so a global may be read by a function defined before it. Functions are
+/// generalised after their definition, `fn id(a) { a }` can then be called with
+/// a number and a bool. Annotations are checked like any other use
+pub struct TypeChecker {
+    /// What each type variable has been solved to
+    subst: Vec<Option<Type>>,
+    decls: HashMap<DeclId, Scheme>,
+    /// Declaration introduced by the name at each span
+    declared_at: HashMap<Span, DeclId>,
+    /// Types of globals and functions carried over from earlier input
+    globals: HashMap<String, Type>,
+    functions: HashMap<String, Type>,
+    /// Return type of each function being checked, innermost last
+    returns: Vec<Type>,
+    errors: Vec<SemanticError>,
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        Self {
+            subst: Vec::new(),
+            decls: HashMap::new(),
+            declared_at: HashMap::new(),
+            globals: HashMap::new(),
+            functions: HashMap::new(),
+            returns: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+    /// Type of a global variable defined by earlier input
+    pub fn declare_global(&mut self, name: &str, ty: Type) {
+        self.globals.insert(name.to_string(), ty);
+    }
+    /// Type of a function defined by earlier input
+    pub fn declare_function(&mut self, name: &str, ty: Type) {
+        self.functions.insert(name.to_string(), ty);
+    }
+    /// Infer the type of every declaration in the program, reporting every
+    /// type error sorted by position
+    pub fn check(
+        mut self,
+        resolved: &Resolved,
+    ) -> Result<HashMap<DeclId, Type>, Vec<SemanticError>> {
+        for (id, decl) in resolved.resolution.declarations.iter().enumerate() {
+            let scheme = match (decl.kind, decl.span) {
+                (DeclKind::Constant, _) => Scheme::mono(Type::Num),
+                (DeclKind::Let | DeclKind::Const, None) => match self.globals.get(&decl.name) {
+                    Some(ty) => Scheme::poly(ty.clone()),
+                    None => Scheme::mono(self.fresh()),
+                },
+                (DeclKind::Fn(arity), None) => match self.functions.get(&decl.name) {
+                    Some(ty) => Scheme::poly(ty.clone()),
+                    None => Scheme::poly(Type::Fn(
+                        (0..arity).map(Type::Var).collect(),
+                        Box::new(Type::Var(arity)),
+                    )),
+                },
+                (_, Some(span)) => {
+                    self.declared_at.insert(span, id);
+                    Scheme::mono(self.fresh())
+                }
+                (DeclKind::Param, None) => Scheme::mono(self.fresh()),
+            };
+            self.decls.insert(id, scheme);
+        }
+
+        for stmt in &resolved.stmts {
+            self.stmt(stmt, resolved);
+        }
+
+        if !self.errors.is_empty() {
+            self.errors.sort_by_key(|err| err.span.start);
+            return Err(self.errors);
+        }
+        let ids: Vec<DeclId> = self.declared_at.values().copied().collect();
+        Ok(ids
+            .into_iter()
+            .map(|id| {
+                let ty = self.decls[&id].ty.clone();
+                (id, self.zonk(&ty))
+            })
+            .collect())
+    }
+    fn fresh(&mut self) -> Type {
+        self.subst.push(None);
+        Type::Var(self.subst.len() - 1)
+    }
+    /// Apply every solved variable
+    fn zonk(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(var) => match self.subst.get(*var).and_then(Option::as_ref) {
+                Some(solved) => self.zonk(solved),
+                None => ty.clone(),
+            },
+            Type::Fn(params, ret) => Type::Fn(
+                params.iter().map(|param| self.zonk(param)).collect(),
+                Box::new(self.zonk(ret)),
+            ),
+            Type::Num | Type::Bool | Type::Unit => ty.clone(),
+        }
+    }
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let fresh: HashMap<usize, Type> =
+            scheme.vars.iter().map(|&var| (var, self.fresh())).collect();
+        Self::substitute(&scheme.ty, &fresh)
+    }
+    fn substitute(ty: &Type, vars: &HashMap<usize, Type>) -> Type {
+        match ty {
+            Type::Var(var) => vars.get(var).cloned().unwrap_or_else(|| ty.clone()),
+            Type::Fn(params, ret) => Type::Fn(
+                params
+                    .iter()
+                    .map(|param| Self::substitute(param, vars))
+                    .collect(),
+                Box::new(Self::substitute(ret, vars)),
+            ),
+            Type::Num | Type::Bool | Type::Unit => ty.clone(),
+        }
+    }
+    fn bind(&mut self, var: usize, ty: Type) -> Result<(), UnifyError> {
+        if ty == Type::Var(var) {
+            return Ok(());
+        }
+        let mut vars = Vec::new();
+        self.zonk(&ty).free_vars(&mut vars);
+        if vars.contains(&var) {
+            return Err(UnifyError::Infinite);
+        }
+        self.subst[var] = Some(ty);
+        Ok(())
+    }
+    fn unify_types(&mut self, a: &Type, b: &Type) -> Result<(), UnifyError> {
+        match (self.zonk(a), self.zonk(b)) {
+            (Type::Var(var), other) | (other, Type::Var(var)) => self.bind(var, other),
+            (Type::Fn(a_params, a_ret), Type::Fn(b_params, b_ret)) => {
+                if a_params.len() != b_params.len() {
+                    return Err(UnifyError::Mismatch);
+                }
+                for (a_param, b_param) in a_params.iter().zip(&b_params) {
+                    self.unify_types(a_param, b_param)?;
+                }
+                self.unify_types(&a_ret, &b_ret)
+            }
+            (a, b) if a == b => Ok(()),
+            _ => Err(UnifyError::Mismatch),
+        }
+    }
+    /// Require `found` to be `expected`, reporting an error at `span` if not
+    fn unify(&mut self, expected: &Type, found: &Type, span: Span) {
+        let Err(err) = self.unify_types(expected, found) else {
+            return;
+        };
+        let mut names = HashMap::new();
+        let (mut expected_name, mut found_name) = (String::new(), String::new());
+        self.zonk(expected).write(&mut names, &mut expected_name);
+        self.zonk(found).write(&mut names, &mut found_name);
+        let kind = match err {
+            UnifyError::Mismatch => SemanticErrorKind::TypeMismatch {
+                expected: expected_name,
+                found: found_name,
+            },
+            UnifyError::Infinite => SemanticErrorKind::InfiniteType {
+                var: expected_name,
+                ty: found_name,
+            },
+        };
+        self.errors.push(SemanticError {
+            kind,
+            span,
+            suggestion: None,
+        });
+    }
+    fn decl_type(&mut self, id: DeclId) -> Type {
+        let scheme = self.decls[&id].clone();
+        self.instantiate(&scheme)
+    }
+    /// Type of the declaration a use refers to
+    fn use_type(&mut self, span: Span, resolved: &Resolved) -> Type {
+        match resolved.resolution.uses.get(&span) {
+            Some(&id) => self.decl_type(id),
+            None => self.fresh(),
+        }
+    }
+    fn declared_type(&mut self, span: Span) -> Type {
+        let id = self.declared_at[&span];
+        self.decl_type(id)
+    }
+    /// Type a statement produces as the last one of a block
+    fn stmt(&mut self, stmt: &Stmt, resolved: &Resolved) -> Type {
+        match &stmt.kind {
+            StmtKind::Let(id, expr) | StmtKind::Const(id, expr) => {
+                let found = self.expr(expr, resolved);
+                let expected = self.declared_type(id.span);
+                self.unify(&expected, &found, expr.span);
+                Type::Unit
+            }
+            StmtKind::Assign(id, expr) => {
+                let found = self.expr(expr, resolved);
+                let expected = self.use_type(id.span, resolved);
+                self.unify(&expected, &found, expr.span);
+                Type::Unit
+            }
+            StmtKind::Fn(decl) => {
+                self.function(decl, stmt.span, resolved);
+                Type::Unit
+            }
+            StmtKind::Expr(expr) => self.expr(expr, resolved),
+            StmtKind::Return(expr) => {
+                let found = match expr {
+                    Some(expr) => self.expr(expr, resolved),
+                    None => Type::Unit,
+                };
+                if let Some(expected) = self.returns.last().cloned() {
+                    self.unify(&expected, &found, stmt.span);
+                }
+                // Control never reaches whatever uses this statement's value
+                self.fresh()
+            }
+        }
+    }
+    fn block(&mut self, stmts: &[Stmt], resolved: &Resolved) -> Type {
+        let mut ty = Type::Unit;
+        for stmt in stmts {
+            ty = self.stmt(stmt, resolved);
+        }
+        ty
+    }
+    fn function(&mut self, decl: &FnDecl, span: Span, resolved: &Resolved) {
+        let id = self.declared_at[&decl.name.span];
+
+        let mut params = Vec::new();
+        for param in &decl.params {
+            let ty = self.declared_type(param.name.span);
+            if let Some(ann) = &param.ty {
+                self.unify(&Type::from_annotation(ann), &ty, param.name.span);
+            }
+            params.push(ty);
+        }
+        let ret = self.fresh();
+        if let Some(ann) = &decl.ret {
+            self.unify(&Type::from_annotation(ann), &ret, decl.name.span);
+        }
+        let fn_type = Type::Fn(params, Box::new(ret.clone()));
+        let placeholder = self.decl_type(id);
+        self.unify(&placeholder, &fn_type, decl.name.span);
+
+        self.returns.push(ret.clone());
+        let body = self.block(&decl.body, resolved);
+        let body_span = decl.body.last().map_or(decl.name.span, |stmt| stmt.span);
+        self.unify(&ret, &body, body_span);
+        self.returns.pop();
+
+        // Anything not pinned down by a declaration outside the function is generic
+        let mut env_vars = Vec::new();
+        for (&other, scheme) in &self.decls {
+            let inside = resolved.resolution.declarations[other]
+                .span
+                .is_some_and(|decl_span| {
+                    span.start <= decl_span.start && decl_span.end <= span.end
+                });
+            if other != id && !inside && scheme.vars.is_empty() {
+                self.zonk(&scheme.ty).free_vars(&mut env_vars);
+            }
+        }
+        let ty = self.zonk(&fn_type);
+        let mut vars = Vec::new();
+        ty.free_vars(&mut vars);
+        let env: HashSet<usize> = env_vars.into_iter().collect();
+        vars.retain(|var| !env.contains(var));
+        self.decls.insert(id, Scheme { vars, ty });
+
+        // What earlier input checked against the old definition calls this one
+        if let Some(earlier) = self.functions.get(&decl.name.name).cloned() {
+            self.redefinition(id, &decl.name.name, &earlier, decl.name.span);
+        }
+    }
+    /// Require a function to fit the one it replaces, callers checked against
+    /// the earlier type mustn't be left expecting something it can't give.
+    /// Narrowing a generic function is fine, the runtime checks still apply
+    /// to callers using it at other types
+    fn redefinition(&mut self, id: DeclId, name: &str, earlier: &Type, span: Span) {
+        let scheme = self.decls[&id].clone();
+        let found = self.instantiate(&scheme);
+        let mut vars = Vec::new();
+        earlier.free_vars(&mut vars);
+        let fresh: HashMap<usize, Type> = vars.iter().map(|&var| (var, self.fresh())).collect();
+        let expected = Self::substitute(earlier, &fresh);
+        if self.unify_types(&found, &expected).is_err() {
+            self.errors.push(SemanticError {
+                kind: SemanticErrorKind::Redefinition {
+                    name: name.to_string(),
+                    earlier: earlier.to_string(),
+                    found: self.zonk(&scheme.ty).to_string(),
+                },
+                span,
+                suggestion: None,
+            });
+        }
+    }
+    /// Require `expr` to have type `expected`
+    fn expect(&mut self, expr: &Expr, expected: &Type, resolved: &Resolved) {
+        let found = self.expr(expr, resolved);
+        self.unify(expected, &found, expr.span);
+    }
+    fn expr(&mut self, expr: &Expr, resolved: &Resolved) -> Type {
+        match &expr.kind {
+            ExprKind::Number(_) => Type::Num,
+            ExprKind::Bool(_) => Type::Bool,
+            ExprKind::Variable(_) => self.use_type(expr.span, resolved),
+            ExprKind::BinaryOp(left, op, right) => match op {
+                BinaryOp::Equal | BinaryOp::NotEqual => {
+                    let left = self.expr(left, resolved);
+                    self.expect(right, &left, resolved);
+                    Type::Bool
+                }
+                _ => {
+                    self.expect(left, &Type::Num, resolved);
+                    self.expect(right, &Type::Num, resolved);
+                    if op.is_comparison() {
+                        Type::Bool
+                    } else {
+                        Type::Num
+                    }
+                }
+            },
+            ExprKind::UnaryOp(operand, _) => {
+                self.expect(operand, &Type::Num, resolved);
+                Type::Num
+            }
+            ExprKind::Builtin(_, args) => {
+                for arg in args {
+                    self.expect(arg, &Type::Num, resolved);
+                }
+                Type::Num
+            }
+            ExprKind::Block(stmts) => self.block(stmts, resolved),
+            ExprKind::FunctionCall(id, args) => {
+                let callee = self.use_type(id.span, resolved);
+                // Check arguments one by one when the callee's shape is known so
+                // errors point at the offending argument
+                if let Type::Fn(params, ret) = self.zonk(&callee) {
+                    if params.len() == args.len() {
+                        for (arg, param) in args.iter().zip(&params) {
+                            self.expect(arg, param, resolved);
+                        }
+                        return *ret;
+                    }
+                }
+                let args = args.iter().map(|arg| self.expr(arg, resolved)).collect();
+                let ret = self.fresh();
+                self.unify(&callee, &Type::Fn(args, Box::new(ret.clone())), expr.span);
+                ret
+            }
+            ExprKind::If(cond, then_branch, else_branch) => {
+                self.expect(cond, &Type::Bool, resolved);
+                let then_ty = self.expr(then_branch, resolved);
+                match else_branch {
+                    Some(else_branch) => {
+                        self.expect(else_branch, &then_ty, resolved);
+                        then_ty
+                    }
+                    // Without an else there is no value when the condition fails
+                    None => {
+                        self.unify(&Type::Unit, &then_ty, then_branch.span);
+                        Type::Unit
+                    }
+                }
+            }
+        }
+    }
+}
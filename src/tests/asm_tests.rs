@@ -0,0 +1,199 @@
+#[cfg(test)]
+mod tests {
+    use crate::codegen::asm::generate;
+    use crate::codegen::asm::Target;
+    use crate::repl::session::Emit;
+    use crate::repl::session::Session;
+    use crate::tests::generator::Generator;
+    use crate::tests::support::has_cc;
+    use crate::tests::support::lowered;
+    use crate::tests::support::same_output;
+    use crate::tests::support::script_session;
+    use crate::tests::support::temp_path;
+    use crate::tests::support::walked;
+    use std::process::Command;
+
+    /// Assemble, link and run the program, giving what it printed or the
+    /// error it reported
+    fn linked(asm: &str) -> String {
+        let (source, exe) = (temp_path(".s"), temp_path(""));
+        std::fs::write(&source, asm).unwrap();
+        let built = Command::new("cc")
+            .arg(&source)
+            .arg("-o")
+            .arg(&exe)
+            .arg("-lm")
+            .output()
+            .unwrap();
+        assert!(
+            built.status.success(),
+            "{0}\n{asm}",
+            String::from_utf8_lossy(&built.stderr)
+        );
+        let run = Command::new(&exe).output().unwrap();
+        let _ = std::fs::remove_file(&source);
+        let _ = std::fs::remove_file(&exe);
+        match run.status.success() {
+            true => String::from_utf8_lossy(&run.stdout).trim().to_string(),
+            false => {
+                assert_eq!(run.status.code(), Some(1));
+                String::from_utf8_lossy(&run.stderr).trim().to_string()
+            }
+        }
+    }
+
+    fn assert_runs(input: &str, level: u8) {
+        let module = lowered(input, level).unwrap_or_else(|| panic!("{input} doesn't lower"));
+        let found = linked(&generate(&module, Target::Linked).to_string());
+        let expected = walked(input);
+        assert!(
+            same_output(&found, &expected),
+            "-O{level} {input}\nfound {found}, expected {expected}"
+        );
+    }
+
+    #[test]
+    fn test_golden_assembly() {
+        for (name, input, expected) in [
+            (
+                "square",
+                include_str!("golden/square.ape"),
+                include_str!("golden/square.s"),
+            ),
+            (
+                "branches",
+                include_str!("golden/branches.ape"),
+                include_str!("golden/branches.s"),
+            ),
+            (
+                "globals",
+                include_str!("golden/globals.ape"),
+                include_str!("golden/globals.s"),
+            ),
+        ] {
+            let asm = generate(&lowered(input, 0).unwrap(), Target::Linked).to_string();
+            assert_eq!(asm, expected, "golden/{name}.s");
+        }
+    }
+
+    #[test]
+    fn test_golden_assembly_runs() {
+        if !has_cc() {
+            return;
+        }
+        for input in [
+            include_str!("golden/square.ape"),
+            include_str!("golden/branches.ape"),
+            include_str!("golden/globals.ape"),
+        ] {
+            assert_runs(input, 0);
+        }
+    }
+
+    #[test]
+    fn test_programs_run() {
+        if !has_cc() {
+            return;
+        }
+        for input in [
+            "1 + 2 * 3 - 4 / 5\n2 ^ 3 ^ 2 % 7",
+            "-0 * 1",
+            "~3 + 4! + -(2) + 0.5! + (-1)!",
+            "(1 < 2) == (3 >= 4)",
+            "0.1 + 0.2",
+            "1 / 3",
+            "2 ^ 80",
+            "sqrt(-1)",
+            "sin(pi / 2) + cos(0) + tan(1) + asin(1) + acos(0) + atan(1)",
+            "sinh(1) + cosh(1) + tanh(1) + log(100) + log(8, 2) + exp(1)",
+            "abs(-e) + floor(2.5) + ceil(2.5) + round(2.5) + round(-2.5)",
+            "{}",
+            "let x = 1\n{ let x = x + 1; { let x = x * 10; x } + x } + x",
+            "let x = 1\nlet y = 0\nx / y",
+            "let x = 1\nx / -0",
+            "fn fact(n) { if n < 1 { 1 } else { n * fact(n - 1) } }\nfact(10)",
+            "fn even(n) { if n == 0 { true } else { odd(n - 1) } }\nfn odd(n) { if n == 0 { false } else { even(n - 1) } }\neven(10) == odd(7)",
+            "let c = 5\nfn f() { c = c + 1 }\nf()\nf()\nc",
+            "fn f(x) { if x > 0 { return 1 } else { return -1 } }\nf(2) + f(-2)",
+            "fn f(n) { 1 + { if n > 0 { return n } else { 0 } } }\nf(3) + f(0)",
+            "fn f(b) { if b { 1 } else { 2 } }\nf(true) + f(false)",
+            "fn f(b: bool) { b }\nf(false)",
+            "fn f(x) { let y = x; if x > 1 { y = y + 1 }; if x > 2 { y = y * 3 } else { y = y / 2 }; y }\nf(1) + f(2) + f(3)",
+            "fn fib(n) { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }\nfib(20)",
+            "let t = true\nif t { 1 } else { 2 }",
+            "fn nan() { 0 ^ -1 - 0 ^ -1 }\nnan() == nan()",
+            "fn nan() { 0 ^ -1 - 0 ^ -1 }\nnan() != nan()",
+            "fn nan() { 0 ^ -1 - 0 ^ -1 }\nnan() < 1",
+            "fn nan() { 0 ^ -1 - 0 ^ -1 }\nnan()",
+            "fn f() { {} }\nf() == f()",
+        ] {
+            assert_runs(input, 0);
+            assert_runs(input, 2);
+        }
+    }
+
+    #[test]
+    fn test_arguments_follow_the_abi() {
+        if !has_cc() {
+            return;
+        }
+        // Past eight numbers and six booleans the rest go on the stack
+        assert_runs(
+            "fn f(a, b, c, d, e, g, h, i, j, k, l) { a - b + c - d + e - g + h - i + j * 100 + k * 1000 + l * 10000 }\n\
+             f(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11)",
+            0,
+        );
+        assert_runs(
+            "fn pick(a: bool, b: bool, c: bool, d: bool, e: bool, g: bool, h: bool, x, y) { if a == b { x } else { if g != h { y } else { x - y } } }\n\
+             pick(true, false, true, true, false, true, false, 3, 4) + pick(true, true, false, false, false, false, false, 5, 6)",
+            0,
+        );
+        assert_runs(
+            "fn mix(a, p: bool, b, q: bool, c, r: bool, d, s: bool, e, t: bool, g, u: bool, h, v: bool, i, w: bool, j) { if p == q { a + b + c + d + e + g + h + i + j } else { j } }\n\
+             mix(1, true, 2, true, 3, false, 4, false, 5, true, 6, true, 7, false, 8, true, 9)",
+            0,
+        );
+    }
+
+    #[test]
+    fn test_generated_programs_run() {
+        if !has_cc() {
+            return;
+        }
+        let mut generator = Generator::typed(11);
+        let mut checked = 0;
+        while checked < 40 {
+            let input = generator.program();
+            if lowered(&input, 0).is_some() {
+                assert_runs(&input, (checked % 4) as u8);
+                checked += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn test_emit_asm() {
+        let mut session = Session::new();
+        let asm = session
+            .emit("fn sq(x) { x * x }\nsq(3)", Emit::Asm)
+            .unwrap();
+        assert!(asm.starts_with("\t.text\n\t.globl\tape_sq\n"), "{asm}");
+        assert!(asm.contains("\tmulsd\t-8(%rbp), %xmm0\n"), "{asm}");
+        assert_eq!(
+            session.emit("fn f(g) { g(1) }", Emit::Asm),
+            Err(String::from("Functions can't be used as values @ 3"))
+        );
+    }
+
+    #[test]
+    fn test_emit_asm_keeps_functions() {
+        let asm = script_session(2)
+            .emit(
+                "fn area(r) { pi * r ^ 2 }\nfn sq(x) { x * x }\nsq(2)",
+                Emit::Asm,
+            )
+            .unwrap();
+        assert!(asm.contains("\t.globl\tape_area\n"), "{asm}");
+        assert!(asm.contains("\t.globl\tape_sq\n"), "{asm}");
+    }
+}
@@ -0,0 +1,248 @@
+#[cfg(test)]
+mod tests {
+    use crate::codegen::c::to_c;
+    use crate::repl::session::Session;
+    use crate::tests::generator::Generator;
+    use crate::tests::support::checked;
+    use crate::tests::support::has_cc;
+    use crate::tests::support::same_output;
+    use crate::tests::support::script_session;
+    use crate::tests::support::temp_path;
+    use crate::tests::support::walked;
+    use std::path::Path;
+    use std::process::Command;
+
+    /// The program translated after optimising at `level`, `None` if it
+    /// doesn't check or translate
+    fn translated(input: &str, level: u8) -> Option<String> {
+        let (resolved, types) = checked(input, level)?;
+        to_c(&resolved, &types).ok()
+    }
+
+    /// Compile the C files together, panicking with the compiler's errors
+    /// if they don't
+    fn compile(sources: &[&Path], flags: &[&str], output: &Path) {
+        let out = Command::new("cc")
+            .args(["-std=c99", "-pedantic", "-Wall", "-Werror", "-Wno-unused"])
+            .args(flags)
+            .args(sources)
+            .arg("-o")
+            .arg(output)
+            .arg("-lm")
+            .output()
+            .unwrap();
+        assert!(
+            out.status.success(),
+            "{0}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+    }
+
+    /// Compile and run the C, giving what it printed or the error it
+    /// reported
+    fn executed(c: &str) -> String {
+        let (source, binary) = (temp_path(".c"), temp_path(""));
+        std::fs::write(&source, c).unwrap();
+        compile(&[&source], &[], &binary);
+        let run = Command::new(&binary).output().unwrap();
+        let _ = std::fs::remove_file(&source);
+        let _ = std::fs::remove_file(&binary);
+        match run.status.code() {
+            Some(0) => String::from_utf8_lossy(&run.stdout).trim().to_string(),
+            Some(1) => String::from_utf8_lossy(&run.stderr).trim().to_string(),
+            _ => panic!("{0}\n{c}", String::from_utf8_lossy(&run.stderr)),
+        }
+    }
+
+    fn assert_runs(input: &str, level: u8) {
+        let c = translated(input, level).unwrap_or_else(|| panic!("{input} doesn't translate"));
+        let found = executed(&c);
+        let expected = walked(input);
+        assert!(
+            same_output(&found, &expected),
+            "-O{level} {input}\nfound {found}, expected {expected}\n{c}"
+        );
+    }
+
+    #[test]
+    fn test_golden_c() {
+        for (name, input, expected) in [
+            (
+                "square",
+                include_str!("golden/square.ape"),
+                include_str!("golden/square.c"),
+            ),
+            (
+                "branches",
+                include_str!("golden/branches.ape"),
+                include_str!("golden/branches.c"),
+            ),
+            (
+                "globals",
+                include_str!("golden/globals.ape"),
+                include_str!("golden/globals.c"),
+            ),
+        ] {
+            let c = translated(input, 0).unwrap();
+            assert_eq!(c, expected, "golden/{name}.c");
+        }
+    }
+
+    #[test]
+    fn test_golden_c_runs() {
+        if !has_cc() {
+            return;
+        }
+        for input in [
+            include_str!("golden/square.ape"),
+            include_str!("golden/branches.ape"),
+            include_str!("golden/globals.ape"),
+        ] {
+            assert_runs(input, 0);
+        }
+    }
+
+    #[test]
+    fn test_builtins_map_to_math_h() {
+        let c = translated(
+            "fn f(x) { sqrt(x) + abs(x) + exp(x) + log(x) + log(x, 2) + x % 3 + x ^ 2 }\nf(2)",
+            0,
+        )
+        .unwrap();
+        assert!(
+            c.contains("return ((((((sqrt(x) + fabs(x)) + pow(2.718281828459045, x)) + (log(x) / log(10.0))) + (log(x) / log(2.0))) + fmod(x, 3.0)) + pow(x, 2.0));"),
+            "{c}"
+        );
+        assert!(c.contains("double f(double x)\n{\n"), "{c}");
+    }
+
+    #[test]
+    fn test_names_are_kept_clear_of_c() {
+        let c = translated(
+            "fn int(pow) { pow + 1 }\nfn double(exit) { int(exit) * 2 }\nlet x = 1\n{ let x = x + 1; x }",
+            0,
+        )
+        .unwrap();
+        assert!(c.contains("double int_(double pow_)\n"), "{c}");
+        assert!(c.contains("double double_(double exit_)\n"), "{c}");
+        // The inner `x` reads the global it shadows
+        assert!(c.contains("double x_1 = (x + 1.0);"), "{c}");
+    }
+
+    #[test]
+    fn test_programs_run() {
+        if !has_cc() {
+            return;
+        }
+        for input in [
+            "1 + 2 * 3 - 4 / 5\n2 ^ 3 ^ 2 % 7",
+            "-0 * 1",
+            "~3 + 4! + -(2) + 0.5! + (-1)! + 171!",
+            "(1 < 2) == (3 >= 4)",
+            "0.1 + 0.2",
+            "2 ^ 80",
+            "sqrt(-1)",
+            "sin(pi / 2) + cos(0) + tan(1) + asin(1) + acos(0) + atan(1)",
+            "sinh(1) + cosh(1) + tanh(1) + log(100) + log(8, 2) + exp(1)",
+            "abs(-e) + floor(2.5) + ceil(2.5) + round(2.5) + round(-2.5)",
+            "{}",
+            "let x = 1\n{ let x = x + 1; { let x = x * 10; x } + x } + x",
+            "let x = 1\nlet y = 0\nx / y",
+            "let x = 1\nx / -0",
+            "let u = {}\nfn f() { u }\nf() == {}",
+            "fn fact(n) { if n < 1 { 1 } else { n * fact(n - 1) } }\nfact(10)",
+            "fn even(n) { if n == 0 { true } else { odd(n - 1) } }\nfn odd(n) { if n == 0 { false } else { even(n - 1) } }\neven(10) == odd(7)",
+            "let c = 5\nfn f() { c = c + 1 }\nf()\nf()\nc",
+            "let c = 5\nfn f() { c = c * 2; c }\nc + f() + c",
+            "let c = 1\nc\nc = 2",
+            "fn f(x) { if x > 0 { return 1 } else { return -1 } }\nf(2) + f(-2)",
+            "fn f(n) { 1 + { if n > 0 { return n } else { 0 } } }\nf(3) + f(0)",
+            "fn f(b: bool) { b }\nf(false)",
+            "fn f(x) { let y = x; if x > 1 { y = y + 1 }; if x > 2 { y = y * 3 } else { y = y / 2 }; y }\nf(1) + f(2) + f(3)",
+            "fn fib(n) { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }\nfib(20)",
+            "fn nan() { 0 ^ -1 - 0 ^ -1 }\nnan() == nan()",
+            "fn nan() { 0 ^ -1 - 0 ^ -1 }\nnan() != nan()",
+            "fn nan() { 0 ^ -1 - 0 ^ -1 }\nnan()",
+            "fn f() { {} }\nf() == f()",
+            "fn int(pow) { pow * sqrt(pow) }\nfn double(exit) { int(exit) + exit }\ndouble(2)",
+            "fn f(x) { let x = x + 1; { let x = x * 2; x } + x }\nf(3)",
+            "fn f(x) { if x > 1 { 1 / (x - 2) } else { 0 } }\nf(2)",
+        ] {
+            assert_runs(input, 0);
+            assert_runs(input, 2);
+        }
+    }
+
+    #[test]
+    fn test_generated_programs_run() {
+        if !has_cc() {
+            return;
+        }
+        let mut generator = Generator::typed(19);
+        let mut checked = 0;
+        while checked < 30 {
+            let input = generator.program();
+            if translated(&input, 0).is_some() {
+                assert_runs(&input, (checked % 4) as u8);
+                checked += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn test_functions_are_callable_from_c() {
+        if !has_cc() {
+            return;
+        }
+        let c = translated(
+            "fn hyp(a, b) { sqrt(a * a + b * b) }\nfn pick(b: bool, u: unit, x) { if b { x } else { -x } }\nhyp(1, 1)",
+            0,
+        )
+        .unwrap();
+        let harness = "#include <stdbool.h>\n\
+                       #include <stdio.h>\n\
+                       double hyp(double a, double b);\n\
+                       double pick(bool b, double x);\n\
+                       double ape_main(void);\n\
+                       int main(void)\n\
+                       {\n    \
+                           printf(\"%g %g %g\\n\", hyp(3.0, 4.0), pick(false, 2.0), ape_main());\n    \
+                           return 0;\n\
+                       }\n";
+        let (source, harness_source, binary) = (temp_path(".c"), temp_path(".c"), temp_path(""));
+        std::fs::write(&source, c).unwrap();
+        std::fs::write(&harness_source, harness).unwrap();
+        compile(&[&source, &harness_source], &["-DAPE_NO_MAIN"], &binary);
+        let run = Command::new(&binary).output().unwrap();
+        for path in [&source, &harness_source, &binary] {
+            let _ = std::fs::remove_file(path);
+        }
+        assert_eq!(String::from_utf8_lossy(&run.stdout), "5 -2 1.41421\n");
+    }
+
+    #[test]
+    fn test_unused_functions_are_translated() {
+        // Inlining and dead code elimination leave a script's functions be
+        let c = script_session(2)
+            .compile_c("fn area(r) { pi * r ^ 2 }\nfn sq(x) { x * x }\nsq(2)")
+            .unwrap();
+        assert!(c.contains("\ndouble area(double r)\n{\n"), "{c}");
+        assert!(c.contains("\ndouble sq(double x)\n{\n"), "{c}");
+    }
+
+    #[test]
+    fn test_compile_c() {
+        let mut session = Session::new();
+        let c = session.compile_c("fn sq(x) { x * x }\nsq(3)").unwrap();
+        assert!(c.starts_with("#include <math.h>\n"), "{c}");
+        assert!(c.contains("#ifndef APE_NO_MAIN\n"), "{c}");
+        assert_eq!(
+            session.compile_c("fn main() { 1 }\nmain()"),
+            Err(String::from("main is reserved for the top level @ 3"))
+        );
+        assert_eq!(
+            session.compile_c("fn f(g) { g(1) }"),
+            Err(String::from("Functions can't be used as values @ 3"))
+        );
+    }
+}
@@ -0,0 +1,254 @@
+#[cfg(test)]
+mod tests {
+    use crate::ast::parser::Parser;
+    use crate::repl::session::Emit;
+    use crate::repl::session::Session;
+    use crate::tokeniser::tokeniser::Tokeniser;
+    use crate::vm::bytecode::Op;
+    use crate::vm::bytecode::Program;
+    use crate::vm::cache::decode;
+    use crate::vm::cache::encode;
+    use crate::vm::cache::CacheError;
+    use crate::vm::cache::VERSION;
+    use crate::vm::compiler::compile;
+    use crate::vm::disassemble::disassemble;
+    use crate::vm::vm::Vm;
+
+    const SOURCE: &str = "fn sq(x) { x * x }\nlet r = 3\nsq(r) + log(r, 2)";
+
+    fn program(input: &str) -> Program {
+        let tokens = Tokeniser::new(input.to_string()).to_tokens().unwrap();
+        compile(&Parser::new(tokens).parse_lines().unwrap())
+    }
+
+    fn run(program: &Program) -> Vec<String> {
+        Vm::new()
+            .evaluate(program)
+            .results
+            .into_iter()
+            .map(|res| format!("{0:?}", res.outcome))
+            .collect()
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let expected = [
+            "fn sq(x) #0, 1 local",
+            "; 1: fn sq(x) { x * x }",
+            "0000  get_local 0",
+            "0001  get_local 0",
+            "0002  binary *",
+            "0003  return",
+            "",
+            "statement 1, 0 locals",
+            "; 1: fn sq(x) { x * x }",
+            "0000  define_function sq #0",
+            "0001  end",
+            "",
+            "statement 2, 0 locals",
+            "; 2: let r = 3",
+            "0000  number 3",
+            "0001  define_let r",
+            "0002  end",
+            "",
+            "statement 3, 0 locals",
+            "; 3: sq(r) + log(r, 2)",
+            "0000  callee sq",
+            "0001  callable sq 1",
+            "0002  get_name r",
+            "0003  call 1",
+            "0004  get_name r",
+            "0005  assert_number",
+            "0006  number 2",
+            "0007  builtin log 2",
+            "0008  binary +",
+            "0009  return",
+        ];
+        assert_eq!(disassemble(&program(SOURCE), SOURCE), expected.join("\n"));
+
+        let source = "if true {\n  1\n} else {\n  2\n}";
+        assert_eq!(
+            disassemble(&program(source), source),
+            [
+                "statement 1, 0 locals",
+                "; 1: if true {",
+                "0000  true",
+                "0001  jump_unless 0004",
+                "; 2: 1",
+                "0002  number 1",
+                "; 1: if true {",
+                "0003  jump 0005",
+                "; 4: 2",
+                "0004  number 2",
+                "; 1: if true {",
+                "0005  return",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_emit_bytecode() {
+        let mut session = Session::new();
+        let text = session.emit("fn sq(x) { x * x }\nsq(2)", Emit::Bytecode);
+        // Emitted after -O2, which inlines the call
+        let inlined = [
+            "statement 2, 1 local",
+            "; 2: sq(2)",
+            "0000  number 2",
+            "0001  set_local 0",
+            "; 1: fn sq(x) { x * x }",
+            "0002  get_local 0",
+            "0003  get_local 0",
+            "0004  binary *",
+            "; 2: sq(2)",
+            "0005  return",
+        ];
+        assert!(text.unwrap().ends_with(&inlined.join("\n")));
+        assert_eq!(
+            session.emit("1 +", Emit::Bytecode),
+            Err(String::from("Empty input @ 2"))
+        );
+    }
+
+    #[test]
+    fn test_emit_compiled() {
+        let bytes = encode(&program(SOURCE));
+        let session = Session::new();
+        let text = session.emit_compiled(&bytes, Emit::Bytecode).unwrap();
+        assert!(
+            text.starts_with("fn sq(x) #0, 1 local\n0000  get_local 0\n"),
+            "{text}"
+        );
+        assert!(!text.contains(';'), "{text}");
+        assert_eq!(
+            session.emit_compiled(&bytes, Emit::Ir),
+            Err(String::from(
+                "Compiled bytecode has no ir stage, only bytecode can be emitted"
+            ))
+        );
+        assert_eq!(
+            session.emit_compiled(&bytes[..20], Emit::Bytecode),
+            Err(String::from("Bytecode file is corrupt"))
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for input in [
+            SOURCE,
+            "let x = 1\n{ let x = 2; const y = x * 2.5; x = y } + x\n-0 / 0",
+            "fn f(n) { if n < 1 { return 0 } else { n + f(n - 1) } }\nf(10)\n~2 != 3!",
+            "fn g(x, y) { fn h() { 1 }\nh() + x ^ y % 2 }\nconst c = g(2, 3)\nc = 1",
+        ] {
+            let program = program(input);
+            let decoded = decode(&encode(&program)).unwrap();
+            assert_eq!(decoded, program, "{input}");
+            assert_eq!(run(&decoded), run(&program), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_rejects_damaged_files() {
+        let bytes = encode(&program(SOURCE));
+        assert_eq!(decode(b"let x = 1"), Err(CacheError::NotBytecode));
+        assert_eq!(decode(&bytes[..8]), Err(CacheError::Truncated));
+
+        let mut newer = bytes.clone();
+        newer[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(
+            decode(&newer),
+            Err(CacheError::Version {
+                found: VERSION + 1,
+                expected: VERSION
+            })
+        );
+        assert_eq!(
+            CacheError::Version {
+                found: 2,
+                expected: 1
+            }
+            .to_string(),
+            "Bytecode version 2 isn't supported, expected version 1"
+        );
+
+        let mut flipped = bytes.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert_eq!(decode(&flipped), Err(CacheError::Corrupt));
+        assert_eq!(decode(&bytes[..bytes.len() - 1]), Err(CacheError::Corrupt));
+    }
+
+    #[test]
+    fn test_rejects_invalid_programs() {
+        let invalid = |change: fn(&mut Program)| {
+            let mut program = program(SOURCE);
+            change(&mut program);
+            match decode(&encode(&program)) {
+                Err(CacheError::Invalid(reason)) => reason,
+                other => panic!("expected invalid bytecode, got {other:?}"),
+            }
+        };
+        assert_eq!(
+            invalid(|program| program.statements[1].chunk.code[1] = Op::DefineLet(9)),
+            "unknown name at 0001 in statement 2"
+        );
+        assert_eq!(
+            invalid(|program| program.functions[0].chunk.code[1] = Op::Pop),
+            "stack underflow at 0002 in sq"
+        );
+        assert_eq!(
+            invalid(|program| program.functions[0].chunk.code[0] = Op::GetLocal(1)),
+            "unknown local at 0000 in sq"
+        );
+        assert_eq!(
+            invalid(|program| program.statements[0].chunk.code[0] = Op::DefineFunction(0, 1)),
+            "unknown function at 0000 in statement 1"
+        );
+        assert_eq!(
+            invalid(|program| program.statements[2].chunk.code[9] = Op::Jump(8)),
+            "unbalanced stack at 0008 in statement 3"
+        );
+        assert_eq!(
+            invalid(|program| program.statements[2].chunk.code[9] = Op::End),
+            "misplaced end at 0009 in statement 3"
+        );
+        assert_eq!(
+            invalid(|program| program.statements[2].chunk.code[7] =
+                Op::Builtin(crate::ast::ast::Builtin::Sin, 2)),
+            "wrong number of builtin arguments at 0007 in statement 3"
+        );
+        // Running these would allocate every slot before failing
+        assert_eq!(
+            invalid(|program| program.functions[0].chunk.locals = u32::MAX),
+            "sq has more locals than it uses"
+        );
+        assert_eq!(
+            invalid(|program| program.statements[2].chunk.locals = 1),
+            "statement 3 has more locals than it uses"
+        );
+    }
+
+    #[test]
+    fn test_load_compiled_file() {
+        let path = std::env::temp_dir().join(format!("ape-{0}.apeb", std::process::id()));
+        let mut session = Session::new().with_vm();
+        let program = session.compile_bytecode(SOURCE).unwrap();
+        std::fs::write(&path, encode(&program)).unwrap();
+        let path = path.to_str().unwrap();
+
+        let mut session = Session::new().with_vm();
+        assert_eq!(session.load(path), Ok(Some((9.0 + 3f64.log2()).into())));
+        assert_eq!(
+            Session::new().load(path),
+            Err(String::from(
+                "Bytecode only runs on the virtual machine, pass --vm"
+            ))
+        );
+        std::fs::write(path, &encode(&program)[..20]).unwrap();
+        assert_eq!(
+            session.load(path),
+            Err(String::from("Bytecode file is corrupt"))
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,319 @@
+#[cfg(test)]
+mod tests {
+    use crate::codegen::asm::generate;
+    use crate::codegen::asm::Target;
+    use crate::codegen::elf::executable;
+    use crate::codegen::encode::encode;
+    use crate::codegen::x86::Assembly;
+    use crate::codegen::x86::Inst;
+    use crate::codegen::x86::Opcode;
+    use crate::codegen::x86::Operand;
+    use crate::codegen::x86::Reg;
+    use crate::tests::generator::Generator;
+    use crate::tests::support::can_run;
+    use crate::tests::support::session;
+    use crate::tests::support::temp_path;
+    use crate::tests::support::walked;
+    use std::process::Command;
+
+    /// Build and run the program, giving what it printed or the error it
+    /// reported
+    fn built(input: &str, level: u8) -> String {
+        let bytes = session(level)
+            .compile_executable(input)
+            .unwrap_or_else(|err| panic!("{input}: {err}"));
+        let exe = temp_path("");
+        std::fs::write(&exe, bytes).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&exe, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let run = Command::new(&exe).output().unwrap();
+        let _ = std::fs::remove_file(&exe);
+        match run.status.success() {
+            true => String::from_utf8_lossy(&run.stdout).trim_end().to_string(),
+            false => {
+                assert_eq!(run.status.code(), Some(1), "{input}");
+                String::from_utf8_lossy(&run.stderr).trim_end().to_string()
+            }
+        }
+    }
+
+    fn assert_runs(input: &str, level: u8) {
+        assert_eq!(built(input, level), walked(input), "-O{level} {input}");
+    }
+
+    /// The maths routines aren't libm's, so their results only agree to
+    /// within rounding
+    fn assert_close(input: &str, level: u8) {
+        let (found, expected) = (built(input, level), walked(input));
+        let close = match (found.parse::<f64>(), expected.parse::<f64>()) {
+            (Ok(a), Ok(b)) => {
+                a == b || (a - b).abs() <= 1e-12 * b.abs() || a.is_nan() && b.is_nan()
+            }
+            _ => found == expected,
+        };
+        assert!(
+            close,
+            "-O{level} {input}\nfound {found}, expected {expected}"
+        );
+    }
+
+    #[test]
+    fn test_encoding_matches_as() {
+        let has_as = Command::new("as")
+            .arg("--version")
+            .output()
+            .is_ok_and(|out| out.status.success());
+        if !has_as {
+            return;
+        }
+        // Everything the standalone runtime uses, jumps and references to
+        // symbols aside as `as` shortens or relocates them
+        let input = "let a = 2\n\
+                     fn f(x) { sin(x) + cos(x) + tan(x) + asin(x) + acos(x) + atan(x) + sinh(x) + cosh(x) + tanh(x) }\n\
+                     fn g(x) { log(x) + log(x, 2) + exp(x) + floor(x) + ceil(x) + round(x) + abs(x) + sqrt(x) + x % 3 + x! }\n\
+                     f(0.5) + g(a) / a - ~a";
+        let module = session(0).compile_ir(input).unwrap();
+        let text: Vec<Inst> = generate(&module, Target::Standalone)
+            .text
+            .into_iter()
+            .filter(|inst| match inst {
+                Inst::Op(Opcode::Jmp | Opcode::J(_) | Opcode::Call, _) => false,
+                Inst::Op(_, operands) => !operands.iter().any(|op| matches!(op, Operand::Rip(_))),
+                Inst::Label(_) => false,
+            })
+            .collect();
+        let asm = Assembly {
+            text: text.clone(),
+            ..Assembly::default()
+        };
+        let (source, object, binary) = (temp_path(".s"), temp_path(".o"), temp_path(".bin"));
+        std::fs::write(&source, asm.to_string()).unwrap();
+        let assembled = Command::new("as")
+            .arg(&source)
+            .arg("-o")
+            .arg(&object)
+            .status()
+            .unwrap();
+        assert!(assembled.success());
+        let copied = Command::new("objcopy")
+            .args(["-O", "binary", "--only-section=.text"])
+            .arg(&object)
+            .arg(&binary)
+            .status()
+            .unwrap();
+        assert!(copied.success());
+        let expected = std::fs::read(&binary).unwrap();
+        for path in [source, object, binary] {
+            let _ = std::fs::remove_file(path);
+        }
+        let mut at = 0;
+        for inst in &text {
+            let bytes = encode(std::slice::from_ref(inst)).unwrap().bytes;
+            assert_eq!(
+                bytes,
+                expected[at..(at + bytes.len()).min(expected.len())],
+                "{inst}"
+            );
+            at += bytes.len();
+        }
+        assert_eq!(at, expected.len());
+    }
+
+    #[test]
+    fn test_unencodable_instructions() {
+        assert_eq!(
+            encode(&[Inst::Op(
+                Opcode::Mov,
+                vec![Operand::Imm(1 << 40), Operand::Mem(Reg::Rbp, -8)]
+            )]),
+            Err(String::from(
+                "Can't encode `movq\t$1099511627776, -8(%rbp)`"
+            ))
+        );
+        assert_eq!(
+            encode(&[
+                Inst::Label(String::from("a")),
+                Inst::Label(String::from("a"))
+            ]),
+            Err(String::from("Label a is defined twice"))
+        );
+    }
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([bytes[at], bytes[at + 1]])
+    }
+
+    fn u64_at(bytes: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_executable_layout() {
+        let bytes = session(0).compile_executable("let a = 1\na + 1").unwrap();
+        assert_eq!(bytes[..8], [0x7F, b'E', b'L', b'F', 2, 1, 1, 0]);
+        // An x86-64 executable with segments for code, variables and the
+        // stack
+        assert_eq!(u16_at(&bytes, 16), 2);
+        assert_eq!(u16_at(&bytes, 18), 0x3E);
+        assert_eq!(u16_at(&bytes, 56), 3);
+        let entry = u64_at(&bytes, 24);
+        assert!((0x400000..0x400000 + bytes.len() as u64).contains(&entry));
+        // The first segment maps the whole file, the variables come after
+        // on a page of their own
+        assert_eq!(u64_at(&bytes, 64 + 32), bytes.len() as u64);
+        let data = u64_at(&bytes, 64 + 56 + 16);
+        assert_eq!(data % 0x1000, 0);
+        assert!(data >= 0x400000 + bytes.len() as u64);
+        let bytes = session(0).compile_executable("1").unwrap();
+        assert_eq!(u16_at(&bytes, 56), 2);
+    }
+
+    #[test]
+    fn test_undefined_symbols() {
+        let call = |target: &str| Inst::Op(Opcode::Call, vec![Operand::Label(target.to_string())]);
+        let asm = Assembly {
+            text: vec![Inst::Label(String::from("_start")), call("sin@PLT")],
+            ..Assembly::default()
+        };
+        assert_eq!(
+            executable(&asm),
+            Err(String::from("Undefined symbol sin@PLT"))
+        );
+        let asm = Assembly {
+            text: vec![call("ape_main")],
+            ..Assembly::default()
+        };
+        assert_eq!(
+            executable(&asm),
+            Err(String::from("Undefined symbol _start"))
+        );
+    }
+
+    #[test]
+    fn test_programs_run() {
+        if !can_run() {
+            return;
+        }
+        for input in [
+            "1 + 2 * 3 - 4 / 5\n2 ^ 3 ^ 2 % 7",
+            "-0 * 1",
+            "~3 + 4! + -(2) + 0.5! + (-1)!",
+            "(1 < 2) == (3 >= 4)",
+            "(1 < 2) != (3 >= 4)",
+            "2 ^ 80",
+            "2 ^ -1",
+            "(-2) ^ 3 + (-2) ^ 2 + 4 ^ 0.5",
+            "(-8) ^ (1 / 3)",
+            "-7 % 3 + 7.5 % -2",
+            "sqrt(-1)",
+            "abs(-2.5) + floor(2.5) + ceil(2.5) + round(2.5) + round(-2.5)",
+            "floor(-0.5)",
+            "ceil(-0.5)",
+            "round(0.49999999999999994)",
+            "floor(10 ^ 300)",
+            "{}",
+            "let x = 1\n{ let x = x + 1; { let x = x * 10; x } + x } + x",
+            "let x = 1\nlet y = 0\nx / y",
+            "fn fact(n) { if n < 1 { 1 } else { n * fact(n - 1) } }\nfact(10)",
+            "fn even(n) { if n == 0 { true } else { odd(n - 1) } }\nfn odd(n) { if n == 0 { false } else { even(n - 1) } }\neven(10) == odd(7)",
+            "let c = 5\nfn f() { c = c + 1 }\nf()\nf()\nc",
+            "fn f(b: bool) { b }\nf(false)",
+            "fn fib(n) { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }\nfib(20)",
+            "fn f(a, b, c, d, e, g, h, i, j, k, l) { a - b + c - d + e - g + h - i + j * 100 + k * 1000 + l * 10000 }\n\
+             f(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11)",
+        ] {
+            assert_runs(input, 0);
+            assert_runs(input, 2);
+        }
+    }
+
+    #[test]
+    fn test_numbers_print_like_the_interpreter() {
+        if !can_run() {
+            return;
+        }
+        for input in [
+            "0",
+            "-0",
+            "0.1",
+            "0.1 + 0.2",
+            "1 / 3",
+            "-2 / 3",
+            "100",
+            "123456789012345680000",
+            "10 ^ 21",
+            "10 ^ -7",
+            "2 ^ -1074",
+            "2 ^ -1060 * 3",
+            "10 ^ 300",
+            "1.5 * 10 ^ -300",
+            "2 ^ 1023",
+            "2 ^ 1023 * 1.9999999999999998",
+            "2 ^ 52 + 0.5",
+            "1 / 0.3",
+            "0 ^ -1",
+            "-(0 ^ -1)",
+            "0 ^ -1 - 0 ^ -1",
+            "pi",
+            "e",
+        ] {
+            assert_runs(input, 0);
+        }
+    }
+
+    #[test]
+    fn test_maths_agrees_with_libm() {
+        if !can_run() {
+            return;
+        }
+        for input in [
+            "sin(pi / 2) + cos(0) + tan(1) + asin(1) + acos(0) + atan(1)",
+            "sin(10 ^ 6)",
+            "cos(-3) * tan(-1.5)",
+            "asin(-0.5) + acos(-1) + atan(-10 ^ 10)",
+            "asin(2)",
+            "sinh(1) + cosh(1) + tanh(1)",
+            "sinh(0.001) + sinh(-10 ^ -10) * 10 ^ 10",
+            "tanh(0.1) + tanh(-30) + tanh(5)",
+            "cosh(800)",
+            "sinh(-800)",
+            "log(100) + log(8, 2) + exp(1)",
+            "log(0)",
+            "log(-1)",
+            "exp(-800)",
+            "1.0001 ^ 10000",
+            "(-1) ^ (0 ^ -1)",
+            "10 % 0.3",
+        ] {
+            assert_close(input, 0);
+        }
+    }
+
+    #[test]
+    fn test_runtime_errors_exit_with_status_one() {
+        if !can_run() {
+            return;
+        }
+        assert_runs("let x = 1\nx / -0", 0);
+        assert_runs("fn f(x) { 1 / x }\nf(0)", 2);
+    }
+
+    #[test]
+    fn test_generated_programs_run() {
+        if !can_run() {
+            return;
+        }
+        let mut generator = Generator::typed(13);
+        let mut checked = 0;
+        while checked < 40 {
+            let input = generator.program();
+            if session(0).compile_ir(&input).is_ok() {
+                assert_close(&input, (checked % 4) as u8);
+                checked += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+/// Deterministic source of random programs, shared by the tests comparing
+/// backends
+pub struct Generator {
+    state: u64,
+    /// Leave out `true`, `false` and `{}` so programs typecheck
+    typed: bool,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: seed,
+            typed: false,
+        }
+    }
+    /// Programs using only numbers, for backends that need them typed
+    pub fn typed(seed: u64) -> Self {
+        Self {
+            state: seed,
+            typed: true,
+        }
+    }
+    fn below(&mut self, bound: u64) -> u64 {
+        self.state = self
+            .state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.state >> 33) % bound
+    }
+    fn pick<'a>(&mut self, options: &[&'a str]) -> &'a str {
+        options[self.below(options.len() as u64) as usize]
+    }
+    /// Mostly numeric expressions over `names`, calling `calls`
+    fn expr(&mut self, depth: u32, names: &[&str], calls: &[&str]) -> String {
+        let choice = if depth == 0 {
+            self.below(20)
+        } else {
+            20 + self.below(8)
+        };
+        let depth = depth.saturating_sub(1);
+        match choice {
+            10..=17 if !names.is_empty() => self.pick(names).to_string(),
+            19 if !self.typed => self.pick(&["true", "false", "{}"]).to_string(),
+            0..=19 => self
+                .pick(&["1", "2", "-0", "0.5", "3", "pi", "4"])
+                .to_string(),
+            20..=22 => {
+                let op = self.pick(&["+", "-", "*", "/", "%", "^"]);
+                let left = self.expr(depth, names, calls);
+                let right = self.expr(depth, names, calls);
+                format!("({left} {op} {right})")
+            }
+            23 => match self.below(3) {
+                0 => format!("-({0})", self.expr(depth, names, calls)),
+                1 => format!("sin({0})", self.expr(depth, names, calls)),
+                _ => format!(
+                    "log({0}, {1})",
+                    self.expr(depth, names, calls),
+                    self.expr(depth, names, calls)
+                ),
+            },
+            24 => {
+                let op = self.pick(&["<", ">=", "==", "!="]);
+                format!(
+                    "(if ({0} {op} {1}) {{ {2} }} else {{ {3} }})",
+                    self.expr(depth, names, calls),
+                    self.expr(depth, names, calls),
+                    self.expr(depth, names, calls),
+                    self.expr(depth, names, calls)
+                )
+            }
+            25 => {
+                let init = self.expr(depth, names, calls);
+                let inner: Vec<&str> = names.iter().copied().chain(["y"]).collect();
+                format!("{{ let y = {init}; {0} }}", self.expr(depth, &inner, calls))
+            }
+            _ if calls.is_empty() => self.expr(depth, names, calls),
+            _ => {
+                let callee = self.pick(calls);
+                let args = match callee {
+                    "f" => format!(
+                        "{0}, {1}",
+                        self.expr(depth, names, calls),
+                        self.expr(depth, names, calls)
+                    ),
+                    _ => self.expr(depth, names, calls),
+                };
+                format!("{callee}({args})")
+            }
+        }
+    }
+    /// Two functions, `f` calling `g`, used by globals and reassignments
+    pub fn program(&mut self) -> String {
+        [
+            format!("let a = {0}", self.expr(2, &[], &[])),
+            format!("fn g(x) {{ {0} }}", self.expr(3, &["x", "a"], &[])),
+            format!(
+                "fn f(x, b) {{ {0} }}",
+                self.expr(3, &["x", "b", "a"], &["g"])
+            ),
+            format!("let b = {0}", self.expr(3, &["a"], &["f", "g"])),
+            self.expr(4, &["a", "b"], &["f", "g"]),
+            format!("a = {0}", self.expr(3, &["a", "b"], &["f", "g"])),
+            self.expr(4, &["a", "b"], &["f", "g"]),
+        ]
+        .join(";\n")
+    }
+}
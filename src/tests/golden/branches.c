@@ -0,0 +1,34 @@
+#include <math.h>
+#include <stdbool.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+double sign(double x);
+bool ape_main(void);
+
+double sign(double x)
+{
+    double s = 0.0;
+    if (x < 0.0) {
+        s = (-1.0);
+    } else {
+        if (x > 0.0) {
+            s = 1.0;
+        }
+    }
+    return s;
+}
+
+bool ape_main(void)
+{
+    const double t1 = sign((-2.0));
+    return (t1 == (-1.0));
+}
+
+#ifndef APE_NO_MAIN
+int main(void)
+{
+    puts(ape_main() ? "true" : "false");
+    return 0;
+}
+#endif
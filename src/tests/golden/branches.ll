@@ -0,0 +1,43 @@
+@.true = private unnamed_addr constant [5 x i8] c"true\00"
+@.false = private unnamed_addr constant [6 x i8] c"false\00"
+
+define double @ape_sign(double %v0) {
+b0:
+  %v3 = fcmp olt double %v0, 0.0
+  br i1 %v3, label %b1, label %b2
+b1:
+  %v6 = fneg double 1.0
+  br label %b6
+b2:
+  %v8 = fcmp ogt double %v0, 0.0
+  br i1 %v8, label %b3, label %b4
+b3:
+  br label %b5
+b4:
+  br label %b5
+b5:
+  %v10 = phi double [ 1.0, %b3 ], [ 0.0, %b4 ]
+  br label %b6
+b6:
+  %v11 = phi double [ %v6, %b1 ], [ %v10, %b5 ]
+  ret double %v11
+}
+
+define i1 @ape_main() {
+b0:
+  %v1 = fneg double 2.0
+  %v2 = call double @ape_sign(double %v1)
+  %v4 = fneg double 1.0
+  %v5 = fcmp oeq double %v2, %v4
+  ret i1 %v5
+}
+
+define i32 @main() {
+entry:
+  %result = call i1 @ape_main()
+  %text = select i1 %result, ptr @.true, ptr @.false
+  %written = call i32 @puts(ptr %text)
+  ret i32 0
+}
+
+declare i32 @puts(ptr)
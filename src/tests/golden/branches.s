@@ -0,0 +1,102 @@
+	.text
+	.globl	ape_sign
+	.globl	ape_main
+	.globl	main
+ape_sign:
+	pushq	%rbp
+	movq	%rsp, %rbp
+	subq	$112, %rsp
+	movsd	%xmm0, -8(%rbp)
+	movabsq	$0x0, %rax
+	movq	%rax, -16(%rbp)
+	movabsq	$0x0, %rax
+	movq	%rax, -24(%rbp)
+	movsd	-24(%rbp), %xmm0
+	ucomisd	-8(%rbp), %xmm0
+	seta	%al
+	movzbq	%al, %rax
+	movq	%rax, -32(%rbp)
+	movq	$0, -40(%rbp)
+	cmpq	$0, -32(%rbp)
+	je	.Lsign_b2
+.Lsign_b1:
+	movabsq	$0x3ff0000000000000, %rax
+	movq	%rax, -48(%rbp)
+	movq	-48(%rbp), %rax
+	btcq	$63, %rax
+	movq	%rax, -56(%rbp)
+	pushq	-56(%rbp)
+	popq	-96(%rbp)
+	jmp	.Lsign_b6
+.Lsign_b2:
+	movabsq	$0x0, %rax
+	movq	%rax, -64(%rbp)
+	movsd	-8(%rbp), %xmm0
+	ucomisd	-64(%rbp), %xmm0
+	seta	%al
+	movzbq	%al, %rax
+	movq	%rax, -72(%rbp)
+	cmpq	$0, -72(%rbp)
+	je	.Lsign_b4
+.Lsign_b3:
+	movabsq	$0x3ff0000000000000, %rax
+	movq	%rax, -80(%rbp)
+	pushq	-80(%rbp)
+	popq	-88(%rbp)
+	jmp	.Lsign_b5
+.Lsign_b4:
+	pushq	-16(%rbp)
+	popq	-88(%rbp)
+.Lsign_b5:
+	pushq	-88(%rbp)
+	popq	-96(%rbp)
+.Lsign_b6:
+	movsd	-96(%rbp), %xmm0
+	leave
+	ret
+ape_main:
+	pushq	%rbp
+	movq	%rsp, %rbp
+	subq	$64, %rsp
+	movabsq	$0x4000000000000000, %rax
+	movq	%rax, -8(%rbp)
+	movq	-8(%rbp), %rax
+	btcq	$63, %rax
+	movq	%rax, -16(%rbp)
+	movsd	-16(%rbp), %xmm0
+	call	ape_sign
+	movsd	%xmm0, -24(%rbp)
+	movabsq	$0x3ff0000000000000, %rax
+	movq	%rax, -32(%rbp)
+	movq	-32(%rbp), %rax
+	btcq	$63, %rax
+	movq	%rax, -40(%rbp)
+	movsd	-24(%rbp), %xmm0
+	ucomisd	-40(%rbp), %xmm0
+	sete	%al
+	setnp	%cl
+	andb	%cl, %al
+	movzbq	%al, %rax
+	movq	%rax, -48(%rbp)
+	movq	-48(%rbp), %rax
+	leave
+	ret
+main:
+	pushq	%rbp
+	movq	%rsp, %rbp
+	call	ape_main
+	leaq	.Lrt_true(%rip), %rdi
+	cmpq	$0, %rax
+	jne	.Lrt_puts
+	leaq	.Lrt_false(%rip), %rdi
+.Lrt_puts:
+	call	puts@PLT
+	movq	$0, %rax
+	popq	%rbp
+	ret
+	.section	.rodata
+.Lrt_true:
+	.asciz	"true"
+.Lrt_false:
+	.asciz	"false"
+	.section	.note.GNU-stack,"",@progbits
@@ -0,0 +1,67 @@
+#include <math.h>
+#include <stdbool.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+static double a;
+static bool a_set;
+
+double share(double x);
+double ape_main(void);
+
+static void ape_fail(const char *message)
+{
+    fputs(message, stderr);
+    exit(1);
+}
+
+static double ape_div(double a, double b)
+{
+    if (b == 0.0) {
+        ape_fail("error: Division by zero\n");
+    }
+    return a / b;
+}
+
+double share(double x)
+{
+    if (!a_set) {
+        ape_fail("error: Undeclared Variable: a\n");
+    }
+    return ape_div(a, x);
+}
+
+double ape_main(void)
+{
+    a = 10.0;
+    a_set = true;
+    const double t1 = share(4.0);
+    if (!a_set) {
+        ape_fail("error: Undeclared Variable: a\n");
+    }
+    return (t1 + sqrt(a));
+}
+
+#ifndef APE_NO_MAIN
+static void ape_print(double x)
+{
+    if (isnan(x)) {
+        puts("NaN");
+        return;
+    }
+    char buffer[32];
+    for (int precision = 15; precision <= 17; precision++) {
+        snprintf(buffer, sizeof buffer, "%.*g", precision, x);
+        if (strtod(buffer, NULL) == x) {
+            break;
+        }
+    }
+    puts(buffer);
+}
+
+int main(void)
+{
+    ape_print(ape_main());
+    return 0;
+}
+#endif
@@ -0,0 +1,76 @@
+@.msg_var_a = private unnamed_addr constant [30 x i8] c"error: Undeclared Variable: a\0A"
+@.msg_div = private unnamed_addr constant [24 x i8] c"error: Division by zero\0A"
+@.format = private unnamed_addr constant [5 x i8] c"%.*g\00"
+@.nan = private unnamed_addr constant [4 x i8] c"NaN\00"
+
+@ape_var_a = internal global double 0.0
+@ape_var_a_set = internal global i1 false
+
+define double @ape_share(double %v0) {
+b0:
+  %v1.set = load i1, ptr @ape_var_a_set
+  call void @ape_rt_check(i1 %v1.set, ptr @.msg_var_a, i64 30)
+  %v1 = load double, ptr @ape_var_a
+  %v2.ok = fcmp une double %v0, 0.0
+  call void @ape_rt_check(i1 %v2.ok, ptr @.msg_div, i64 24)
+  %v2 = fdiv double %v1, %v0
+  ret double %v2
+}
+
+define double @ape_main() {
+b0:
+  store double 10.0, ptr @ape_var_a
+  store i1 true, ptr @ape_var_a_set
+  %v2 = call double @ape_share(double 4.0)
+  %v3.set = load i1, ptr @ape_var_a_set
+  call void @ape_rt_check(i1 %v3.set, ptr @.msg_var_a, i64 30)
+  %v3 = load double, ptr @ape_var_a
+  %v4 = call double @llvm.sqrt.f64(double %v3)
+  %v5 = fadd double %v2, %v4
+  ret double %v5
+}
+
+define i32 @main() {
+entry:
+  %result = call double @ape_main()
+  call void @ape_rt_print(double %result)
+  ret i32 0
+}
+
+define internal void @ape_rt_print(double %x) {
+entry:
+  %buffer = alloca [32 x i8]
+  %nan = fcmp uno double %x, 0.0
+  br i1 %nan, label %print_nan, label %try
+try:
+  %precision = phi i32 [ 15, %entry ], [ %next, %try ]
+  %length = call i32 (ptr, i64, ptr, ...) @snprintf(ptr %buffer, i64 32, ptr @.format, i32 %precision, double %x)
+  %back = call double @strtod(ptr %buffer, ptr null)
+  %next = add i32 %precision, 1
+  %same = fcmp oeq double %back, %x
+  br i1 %same, label %print, label %try
+print:
+  %written = call i32 @puts(ptr %buffer)
+  ret void
+print_nan:
+  %written_nan = call i32 @puts(ptr @.nan)
+  ret void
+}
+
+define internal void @ape_rt_check(i1 %ok, ptr %message, i64 %length) {
+entry:
+  br i1 %ok, label %done, label %fail
+fail:
+  %written = call i64 @write(i32 2, ptr %message, i64 %length)
+  call void @exit(i32 1)
+  unreachable
+done:
+  ret void
+}
+
+declare double @llvm.sqrt.f64(double)
+declare double @strtod(ptr, ptr)
+declare i32 @puts(ptr)
+declare i32 @snprintf(ptr, i64, ptr, ...)
+declare i64 @write(i32, ptr, i64)
+declare void @exit(i32)
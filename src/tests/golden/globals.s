@@ -0,0 +1,123 @@
+	.text
+	.globl	ape_share
+	.globl	ape_main
+	.globl	main
+ape_share:
+	pushq	%rbp
+	movq	%rsp, %rbp
+	subq	$32, %rsp
+	movsd	%xmm0, -8(%rbp)
+	cmpq	$0, ape_var_a_set(%rip)
+	jne	.Lshare_ok1
+	leaq	.Lmsg_var_a(%rip), %rdi
+	movq	$30, %rsi
+	call	ape_rt_fail
+.Lshare_ok1:
+	movq	ape_var_a(%rip), %rax
+	movq	%rax, -16(%rbp)
+	movsd	-8(%rbp), %xmm1
+	xorpd	%xmm2, %xmm2
+	ucomisd	%xmm2, %xmm1
+	jp	.Lshare_ok2
+	jne	.Lshare_ok2
+	leaq	.Lmsg_div(%rip), %rdi
+	movq	$24, %rsi
+	call	ape_rt_fail
+.Lshare_ok2:
+	movsd	-16(%rbp), %xmm0
+	divsd	%xmm1, %xmm0
+	movsd	%xmm0, -24(%rbp)
+	movsd	-24(%rbp), %xmm0
+	leave
+	ret
+ape_main:
+	pushq	%rbp
+	movq	%rsp, %rbp
+	subq	$64, %rsp
+	movabsq	$0x4024000000000000, %rax
+	movq	%rax, -8(%rbp)
+	movq	-8(%rbp), %rax
+	movq	%rax, ape_var_a(%rip)
+	movq	$1, ape_var_a_set(%rip)
+	movabsq	$0x4010000000000000, %rax
+	movq	%rax, -16(%rbp)
+	movsd	-16(%rbp), %xmm0
+	call	ape_share
+	movsd	%xmm0, -24(%rbp)
+	cmpq	$0, ape_var_a_set(%rip)
+	jne	.Lmain_ok3
+	leaq	.Lmsg_var_a(%rip), %rdi
+	movq	$30, %rsi
+	call	ape_rt_fail
+.Lmain_ok3:
+	movq	ape_var_a(%rip), %rax
+	movq	%rax, -32(%rbp)
+	sqrtsd	-32(%rbp), %xmm0
+	movsd	%xmm0, -40(%rbp)
+	movsd	-24(%rbp), %xmm0
+	addsd	-40(%rbp), %xmm0
+	movsd	%xmm0, -48(%rbp)
+	movsd	-48(%rbp), %xmm0
+	leave
+	ret
+main:
+	pushq	%rbp
+	movq	%rsp, %rbp
+	call	ape_main
+	call	ape_rt_print
+	movq	$0, %rax
+	popq	%rbp
+	ret
+ape_rt_print:
+	pushq	%rbp
+	movq	%rsp, %rbp
+	subq	$48, %rsp
+	movsd	%xmm0, -40(%rbp)
+	ucomisd	%xmm0, %xmm0
+	jp	.Lrt_print_nan
+	movq	$15, -48(%rbp)
+.Lrt_print_try:
+	leaq	-32(%rbp), %rdi
+	movq	$32, %rsi
+	leaq	.Lrt_format(%rip), %rdx
+	movq	-48(%rbp), %rcx
+	movsd	-40(%rbp), %xmm0
+	movq	$1, %rax
+	call	snprintf@PLT
+	leaq	-32(%rbp), %rdi
+	movq	$0, %rsi
+	call	strtod@PLT
+	addq	$1, -48(%rbp)
+	ucomisd	-40(%rbp), %xmm0
+	jne	.Lrt_print_try
+	leaq	-32(%rbp), %rdi
+	jmp	.Lrt_print_puts
+.Lrt_print_nan:
+	leaq	.Lrt_nan(%rip), %rdi
+.Lrt_print_puts:
+	call	puts@PLT
+	leave
+	ret
+ape_rt_fail:
+	pushq	%rbp
+	movq	%rsi, %rdx
+	movq	%rdi, %rsi
+	movq	$2, %rdi
+	call	write@PLT
+	movq	$1, %rdi
+	call	exit@PLT
+	.data
+ape_var_a:
+	.quad	0
+ape_var_a_set:
+	.quad	0
+	.section	.rodata
+.Lmsg_var_a:
+	.asciz	"error: Undeclared Variable: a\n"
+.Lmsg_div:
+	.asciz	"error: Division by zero\n"
+.Lrt_format:
+	.asciz	"%.*g"
+.Lrt_nan:
+	.asciz	"NaN"
+	.section	.note.GNU-stack,"",@progbits
@@ -0,0 +1,42 @@
+#include <math.h>
+#include <stdbool.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+double sq(double x);
+double ape_main(void);
+
+double sq(double x)
+{
+    return (x * x);
+}
+
+double ape_main(void)
+{
+    const double t1 = sq(3.0);
+    return (t1 + 1.0);
+}
+
+#ifndef APE_NO_MAIN
+static void ape_print(double x)
+{
+    if (isnan(x)) {
+        puts("NaN");
+        return;
+    }
+    char buffer[32];
+    for (int precision = 15; precision <= 17; precision++) {
+        snprintf(buffer, sizeof buffer, "%.*g", precision, x);
+        if (strtod(buffer, NULL) == x) {
+            break;
+        }
+    }
+    puts(buffer);
+}
+
+int main(void)
+{
+    ape_print(ape_main());
+    return 0;
+}
+#endif
@@ -0,0 +1,46 @@
+@.format = private unnamed_addr constant [5 x i8] c"%.*g\00"
+@.nan = private unnamed_addr constant [4 x i8] c"NaN\00"
+
+define double @ape_sq(double %v0) {
+b0:
+  %v1 = fmul double %v0, %v0
+  ret double %v1
+}
+
+define double @ape_main() {
+b0:
+  %v1 = call double @ape_sq(double 3.0)
+  %v3 = fadd double %v1, 1.0
+  ret double %v3
+}
+
+define i32 @main() {
+entry:
+  %result = call double @ape_main()
+  call void @ape_rt_print(double %result)
+  ret i32 0
+}
+
+define internal void @ape_rt_print(double %x) {
+entry:
+  %buffer = alloca [32 x i8]
+  %nan = fcmp uno double %x, 0.0
+  br i1 %nan, label %print_nan, label %try
+try:
+  %precision = phi i32 [ 15, %entry ], [ %next, %try ]
+  %length = call i32 (ptr, i64, ptr, ...) @snprintf(ptr %buffer, i64 32, ptr @.format, i32 %precision, double %x)
+  %back = call double @strtod(ptr %buffer, ptr null)
+  %next = add i32 %precision, 1
+  %same = fcmp oeq double %back, %x
+  br i1 %same, label %print, label %try
+print:
+  %written = call i32 @puts(ptr %buffer)
+  ret void
+print_nan:
+  %written_nan = call i32 @puts(ptr @.nan)
+  ret void
+}
+
+declare double @strtod(ptr, ptr)
+declare i32 @puts(ptr)
+declare i32 @snprintf(ptr, i64, ptr, ...)
@@ -0,0 +1,76 @@
+	.text
+	.globl	ape_sq
+	.globl	ape_main
+	.globl	main
+ape_sq:
+	pushq	%rbp
+	movq	%rsp, %rbp
+	subq	$32, %rsp
+	movsd	%xmm0, -8(%rbp)
+	movsd	-8(%rbp), %xmm0
+	mulsd	-8(%rbp), %xmm0
+	movsd	%xmm0, -16(%rbp)
+	movsd	-16(%rbp), %xmm0
+	leave
+	ret
+ape_main:
+	pushq	%rbp
+	movq	%rsp, %rbp
+	subq	$48, %rsp
+	movabsq	$0x4008000000000000, %rax
+	movq	%rax, -8(%rbp)
+	movsd	-8(%rbp), %xmm0
+	call	ape_sq
+	movsd	%xmm0, -16(%rbp)
+	movabsq	$0x3ff0000000000000, %rax
+	movq	%rax, -24(%rbp)
+	movsd	-16(%rbp), %xmm0
+	addsd	-24(%rbp), %xmm0
+	movsd	%xmm0, -32(%rbp)
+	movsd	-32(%rbp), %xmm0
+	leave
+	ret
+main:
+	pushq	%rbp
+	movq	%rsp, %rbp
+	call	ape_main
+	call	ape_rt_print
+	movq	$0, %rax
+	popq	%rbp
+	ret
+ape_rt_print:
+	pushq	%rbp
+	movq	%rsp, %rbp
+	subq	$48, %rsp
+	movsd	%xmm0, -40(%rbp)
+	ucomisd	%xmm0, %xmm0
+	jp	.Lrt_print_nan
+	movq	$15, -48(%rbp)
+.Lrt_print_try:
+	leaq	-32(%rbp), %rdi
+	movq	$32, %rsi
+	leaq	.Lrt_format(%rip), %rdx
+	movq	-48(%rbp), %rcx
+	movsd	-40(%rbp), %xmm0
+	movq	$1, %rax
+	call	snprintf@PLT
+	leaq	-32(%rbp), %rdi
+	movq	$0, %rsi
+	call	strtod@PLT
+	addq	$1, -48(%rbp)
+	ucomisd	-40(%rbp), %xmm0
+	jne	.Lrt_print_try
+	leaq	-32(%rbp), %rdi
+	jmp	.Lrt_print_puts
+.Lrt_print_nan:
+	leaq	.Lrt_nan(%rip), %rdi
+.Lrt_print_puts:
+	call	puts@PLT
+	leave
+	ret
+	.section	.rodata
+.Lrt_format:
+	.asciz	"%.*g"
+.Lrt_nan:
+	.asciz	"NaN"
+	.section	.note.GNU-stack,"",@progbits
@@ -0,0 +1,112 @@
+use crate::ast::error::RuntimeError;
+use crate::ast::value::Value;
+use crate::ir::ir::BlockId;
+use crate::ir::ir::Constant;
+use crate::ir::ir::Inst;
+use crate::ir::ir::Module;
+use crate::ir::ir::Op;
+use crate::ir::ir::Terminator;
+use crate::ir::ir::ValueId;
+use crate::ir::ir::MAIN;
+use std::collections::HashMap;
+
+/// Interpret a verified module from `main`, giving what the program would
+/// have evaluated to
+pub fn run(module: &Module) -> Result<Value, RuntimeError> {
+    Interpreter {
+        module,
+        globals: HashMap::new(),
+    }
+    .call(MAIN, Vec::new())
+}
+
+struct Interpreter<'a> {
+    module: &'a Module,
+    globals: HashMap<&'a str, Value>,
+}
+
+impl<'a> Interpreter<'a> {
+    fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        let func = self
+            .module
+            .function(name)
+            .ok_or_else(|| RuntimeError::UndefinedFunction(name.to_string()))?;
+        let mut vals: Vec<Value> = args;
+        vals.resize(func.types.len(), Value::Unit);
+
+        let mut from = BlockId(0);
+        let mut block = BlockId(0);
+        loop {
+            let insts = &func.block(block).insts;
+            // Phis read what their predecessors left, all at once
+            let phis: Vec<(usize, Value)> = insts
+                .iter()
+                .map_while(|inst| match inst {
+                    Inst::Define(val, Op::Phi(incoming)) => {
+                        let (src, _) = incoming
+                            .iter()
+                            .find(|&&(_, pred)| pred == from)
+                            .expect("verified phis cover every predecessor");
+                        Some((val.0 as usize, vals[src.0 as usize].clone()))
+                    }
+                    _ => None,
+                })
+                .collect();
+            let skip = phis.len();
+            for (idx, val) in phis {
+                vals[idx] = val;
+            }
+
+            for inst in &insts[skip..] {
+                match inst {
+                    Inst::Store(name, val) => {
+                        self.globals
+                            .insert(name.as_str(), vals[val.0 as usize].clone());
+                    }
+                    Inst::Define(val, op) => {
+                        let get = |val: &ValueId| vals[val.0 as usize].clone();
+                        let result = match op {
+                            Op::Const(Constant::Num(val)) => Value::Number(*val),
+                            Op::Const(Constant::Bool(val)) => Value::Bool(*val),
+                            Op::Const(Constant::Unit) => Value::Unit,
+                            Op::Binary(op, left, right) => {
+                                Value::binary(*op, &get(left), &get(right))?
+                            }
+                            Op::Unary(op, operand) => Value::unary(*op, &get(operand))?,
+                            Op::Builtin(builtin, args) => {
+                                let args = args
+                                    .iter()
+                                    .map(|arg| get(arg).as_number())
+                                    .collect::<Result<Vec<f64>, RuntimeError>>()?;
+                                Value::Number(builtin.call(&args))
+                            }
+                            Op::Call(name, args) => {
+                                let args = args.iter().map(get).collect();
+                                self.call(name, args)?
+                            }
+                            Op::Load(name) => self
+                                .globals
+                                .get(name.as_str())
+                                .cloned()
+                                .ok_or_else(|| RuntimeError::UndeclaredVariable(name.clone()))?,
+                            Op::Phi(_) => unreachable!("verified phis start their block"),
+                        };
+                        vals[val.0 as usize] = result;
+                    }
+                }
+            }
+
+            match func.block(block).term {
+                Terminator::Jump(to) => (from, block) = (block, to),
+                Terminator::Branch(cond, then_block, else_block) => {
+                    let to = match vals[cond.0 as usize] {
+                        Value::Bool(true) => then_block,
+                        _ => else_block,
+                    };
+                    (from, block) = (block, to);
+                }
+                Terminator::Return(val) => return Ok(vals[val.0 as usize].clone()),
+            }
+        }
+    }
+}
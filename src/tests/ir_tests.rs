@@ -0,0 +1,265 @@
+#[cfg(test)]
+mod tests {
+    use crate::ir::ir::Module;
+    use crate::ir::lower::lower;
+    use crate::ir::parse::parse;
+    use crate::ir::verify::verify;
+    use crate::repl::session::Emit;
+    use crate::repl::session::Session;
+    use crate::tests::generator::Generator;
+    use crate::tests::ir_interpreter::run;
+    use crate::tests::support::checked;
+    use crate::tests::support::walked;
+
+    /// The program lowered after optimising at `level`, `None` if it doesn't
+    /// check
+    fn lowered(input: &str, level: u8) -> Option<Result<Module, String>> {
+        let (resolved, types) = checked(input, level)?;
+        Some(lower(&resolved, &types).map_err(|err| err.to_string()))
+    }
+
+    fn module(input: &str) -> Module {
+        let module = lowered(input, 0).unwrap().unwrap();
+        verify(&module).unwrap_or_else(|err| panic!("{err} in\n{module}"));
+        module
+    }
+
+    fn ran(module: &Module) -> String {
+        match run(module) {
+            Ok(val) => val.to_string(),
+            Err(err) => format!("error: {err}"),
+        }
+    }
+
+    /// Lowering at every level verifies, survives printing and parsing, and
+    /// runs to the same result as the tree-walker
+    fn assert_agree(input: &str) {
+        for level in 0..=3 {
+            let module = lowered(input, level)
+                .unwrap_or_else(|| panic!("{input} doesn't check"))
+                .unwrap_or_else(|err| panic!("{err} lowering -O{level} {input}"));
+            verify(&module).unwrap_or_else(|err| panic!("{err} in\n{module}"));
+            let text = module.to_string();
+            assert_eq!(parse(&text), Ok(module.clone()), "{text}");
+            assert_eq!(ran(&module), walked(input), "-O{level} {input}");
+        }
+    }
+
+    #[test]
+    fn test_lower_program() {
+        let input = "let a = 2\nfn f(n) { let t = n; if n > 1 { t = t * a } else { return 0 }; t + 1 }\nf(3)";
+        let expected = "\
+global @a: num
+
+fn @f(%0: num) -> num {
+b0:
+  %1: num = const 1.0
+  %2: bool = gt %0, %1
+  %3: unit = const ()
+  br %2, b1, b2
+b1:
+  %4: num = load @a
+  %5: num = mul %0, %4
+  %6: num = const 1.0
+  %7: num = add %5, %6
+  ret %7
+b2:
+  %8: num = const 0.0
+  ret %8
+}
+
+fn @main() -> num {
+b0:
+  %0: num = const 2.0
+  store @a, %0
+  %1: num = const 3.0
+  %2: num = call @f(%1)
+  ret %2
+}
+";
+        assert_eq!(module(input).to_string(), expected);
+    }
+
+    #[test]
+    fn test_phis_join_branches() {
+        let input = "fn sign(x) { let s = 0; let m = 1; if x < 0 { s = -1 } else if x > 0 { s = 1; m = 2 }; s * m }\nsign(-3) + sign(4) + sign(0)";
+        let sign = module(input).functions[0].to_string();
+        assert!(
+            sign.contains(
+                "b5:\n  %12: num = phi [%11, b3], [%2, b4]\n  %13: num = phi [%10, b3], [%1, b4]\n"
+            ),
+            "{sign}"
+        );
+        assert!(
+            sign.contains(
+                "b6:\n  %14: num = phi [%2, b1], [%12, b5]\n  %15: num = phi [%7, b1], [%13, b5]\n"
+            ),
+            "{sign}"
+        );
+        assert_agree(input);
+    }
+
+    #[test]
+    fn test_programs_agree() {
+        for input in [
+            "1 + 2 * 3 - 4 / 5\n2 ^ 3 ^ 2 % 7",
+            "-0 * 1\n~3 + 4! + -(2)",
+            "(1 < 2) == (3 >= 4)",
+            "sin(pi / 2) + cos(0) + log(100) + log(8, 2) + sqrt(2) + exp(1) + abs(-e)",
+            "{}\n{ 1; 2 }",
+            "let x = 1\n{ let x = x + 1; { let x = x * 10; x } + x } + x",
+            "let x = 1\n{ x = x + 1; { x = x * 10 } }\nx",
+            "let x = 1\nlet y = 0\nx / y",
+            "fn fact(n) { if n < 1 { 1 } else { n * fact(n - 1) } }\nfact(10)",
+            "fn even(n) { if n == 0 { true } else { odd(n - 1) } }\nfn odd(n) { if n == 0 { false } else { even(n - 1) } }\neven(10) == odd(7)",
+            "fn outer(x) { fn inner(y) { y * 2 }\ninner(x) + 1 }\nouter(3)",
+            "let c = 5\nfn f() { c = c + 1 }\nf()\nf()\nc",
+            "fn f(x) { return x * 2; x }\nf(3)",
+            "fn f(x) { if x > 0 { return 1 } else { return -1 } }\nf(2) + f(-2)",
+            "fn f() { { { return 5 } } }\nf()",
+            "fn f() { return }\nf()",
+            "fn f(n) { 1 + { if n > 0 { return n } else { 0 } } }\nf(3) + f(0)",
+            "fn f(b) { if b { 1 } else { 2 } }\nf(true) + f(false)",
+            "fn f(x) { let y = x; if x > 1 { y = y + 1 }; if x > 2 { y = y * 3 } else { y = y / 2 }; y }\nf(1) + f(2) + f(3)",
+            "fn id(a) { a }\nid(2)",
+            "fn fib(n) { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }\nfib(15)",
+            "fn f(x) { x / 0 }\nlet y = 1\nf(y)\ny",
+            "let t = true\nif t { 1 } else { 2 }",
+        ] {
+            assert_agree(input);
+        }
+    }
+
+    #[test]
+    fn test_generated_programs_agree() {
+        let mut generator = Generator::typed(7);
+        let mut checked = 0;
+        for _ in 0..300 {
+            let input = generator.program();
+            if let Some(Ok(_)) = lowered(&input, 0) {
+                assert_agree(&input);
+                checked += 1;
+            }
+        }
+        assert!(checked > 100, "only {checked} programs checked");
+    }
+
+    #[test]
+    fn test_lower_errors() {
+        for (input, expected) in [
+            (
+                "fn sq(x) { x * x }\nlet g = sq",
+                "Functions can't be used as values @ 23",
+            ),
+            (
+                "fn apply(g, x) { g(x) }",
+                "Functions can't be used as values @ 3",
+            ),
+            (
+                "fn f() { 1 }\nfn f() { 2 }",
+                "f is defined more than once @ 16",
+            ),
+            ("fn main() { 1 }", "main is reserved for the top level @ 3"),
+            (
+                "fn id(a) { a }\nid(true)",
+                "Argument 1 of id is bool, the IR only has a version taking num @ 15",
+            ),
+            (
+                "let x = 1\nlet x = true",
+                "x changes type from num to bool @ 14",
+            ),
+        ] {
+            assert_eq!(
+                lowered(input, 0).unwrap(),
+                Err(String::from(expected)),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for (text, expected) in [
+            (
+                "fn @f() -> num {\nb0:\n  %0: num = frob %1\n  ret %0\n}",
+                "Expected operation, found frob on line 3",
+            ),
+            (
+                "fn @f() -> num {\nb1:\n  ret %0\n}",
+                "Expected block b0, found b1 on line 2",
+            ),
+            (
+                "fn @f(%1: num) -> num {\nb0:\n  ret %1\n}",
+                "Expected parameter %0, found 1 on line 1",
+            ),
+            (
+                "fn @f() -> num {\nb0:\n  %0: num = const 1.0\n}",
+                "Expected instruction, found } on line 4",
+            ),
+            ("global @x: float", "Expected type, found float on line 1"),
+            (
+                "fn @f() -> num {\nb0:\n  %0: num = const x\n  ret %0\n}",
+                "Expected constant, found x on line 3",
+            ),
+        ] {
+            assert_eq!(parse(text), Err(String::from(expected)), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_verify_errors() {
+        let header = "global @g: num\nfn @h(%0: num) -> num {\nb0:\n  ret %0\n}\n";
+        for (body, expected) in [
+            (
+                "b0:\n  %0: bool = const true\n  br %0, b1, b2\nb1:\n  %1: num = const 1.0\n  jmp b3\nb2:\n  jmp b3\nb3:\n  ret %1",
+                "%1 is used in b3 where it may not be defined in @f",
+            ),
+            (
+                "b0:\n  %0: bool = const true\n  br %0, b1, b2\nb1:\n  %1: num = const 1.0\n  jmp b3\nb2:\n  jmp b3\nb3:\n  %2: num = phi [%1, b1]\n  ret %2",
+                "Phi %2 doesn't have one value for each predecessor of b3 in @f",
+            ),
+            (
+                "b0:\n  %0: num = const 1.0\n  jmp b1\nb1:\n  %1: num = add %0, %0\n  %2: num = phi [%0, b0]\n  ret %2",
+                "Phi %2 isn't at the start of b1 in @f",
+            ),
+            (
+                "b0:\n  %0: num = const 1.0\n  %1: bool = const true\n  %2: num = add %0, %1\n  ret %2",
+                "Operand of + must be num, %1 is bool in @f",
+            ),
+            (
+                "b0:\n  %0: num = const 1.0\n  %1: bool = add %0, %0\n  ret %0",
+                "%1 is declared bool but add %0, %0 is num in @f",
+            ),
+            ("b0:\n  %0: num = const 1.0\n  br %0, b0, b0", "The entry block b0 is jumped to in @f"),
+            ("b0:\n  %0: num = const 1.0\n  ret %0\nb1:\n  ret %0", "b1 is unreachable in @f"),
+            ("b0:\n  %0: num = const 1.0\n  jmp b4", "b4 doesn't exist, jumped to from b0 in @f"),
+            ("b0:\n  %0: num = const 1.0\n  %0: num = const 2.0\n  ret %0", "%0 is defined twice in @f"),
+            ("b0:\n  %1: num = const 1.0\n  ret %1", "%0 is never defined in @f"),
+            ("b0:\n  %0: bool = const true\n  ret %0", "The returned value must be num, %0 is bool in @f"),
+            ("b0:\n  %0: num = call @k()\n  ret %0", "Function @k doesn't exist in @f"),
+            ("b0:\n  %0: num = call @h()\n  ret %0", "@h takes 1 arguments, found 0 in @f"),
+            ("b0:\n  %0: num = const 1.0\n  %1: num = log %0, %0, %0\n  ret %1", "log takes 1 to 2 arguments, found 3 in @f"),
+            ("b0:\n  %0: bool = const true\n  store @g, %0\n  ret %0", "Storing to @g must be num, %0 is bool in @f"),
+            ("b0:\n  %0: num = load @x\n  ret %0", "Global @x doesn't exist in @f"),
+        ] {
+            let text = format!("{header}fn @f() -> num {{\n{body}\n}}");
+            let module = parse(&text).unwrap_or_else(|err| panic!("{err} in\n{text}"));
+            assert_eq!(verify(&module), Err(String::from(expected)), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_emit_ir() {
+        let mut session = Session::new();
+        assert_eq!(
+            session.emit("let x = 2\nx * 3", Emit::Ir),
+            Ok(String::from(
+                "global @x: num\n\nfn @main() -> num {\nb0:\n  %0: num = const 2.0\n  store @x, %0\n  %1: num = load @x\n  %2: num = const 3.0\n  %3: num = mul %1, %2\n  ret %3\n}"
+            ))
+        );
+        assert_eq!(
+            session.emit("fn f(g) { g(1) }", Emit::Ir),
+            Err(String::from("Functions can't be used as values @ 3"))
+        );
+    }
+}
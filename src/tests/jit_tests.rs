@@ -0,0 +1,214 @@
+#[cfg(test)]
+mod tests {
+    use crate::ast::value::Value;
+    use crate::codegen::encode::encode;
+    use crate::codegen::jit::take_error;
+    use crate::codegen::jit::Jit;
+    use crate::codegen::x86::Inst;
+    use crate::codegen::x86::Opcode;
+    use crate::codegen::x86::Operand;
+    use crate::codegen::x86::Reg;
+    use crate::repl::session::Response;
+    use crate::repl::session::Session;
+    use crate::tests::generator::Generator;
+    use crate::tests::support::can_run;
+    use crate::tests::support::session;
+    use crate::tests::support::walked;
+
+    /// Compile and run the program in process, giving its value or the
+    /// error it stopped with
+    fn native(input: &str, level: u8) -> String {
+        let module = session(level)
+            .compile_ir(input)
+            .unwrap_or_else(|err| panic!("{input}: {err}"));
+        let jit = Jit::new(&module).unwrap_or_else(|err| panic!("{input}: {err}"));
+        match jit.run() {
+            Ok(val) => val.to_string(),
+            Err(err) => format!("error: {err}"),
+        }
+    }
+
+    /// The maths is the interpreter's own, so results agree exactly
+    fn assert_runs(input: &str, level: u8) {
+        assert_eq!(native(input, level), walked(input), "-O{level} {input}");
+    }
+
+    /// Same bits, or both NaN
+    fn assert_same(found: f64, expected: f64, context: &str) {
+        assert!(
+            found.to_bits() == expected.to_bits() || found.is_nan() && expected.is_nan(),
+            "{context}: found {found}, expected {expected}"
+        );
+    }
+
+    #[test]
+    fn test_indirect_jumps_encode() {
+        let text = vec![
+            Inst::Op(Opcode::Push, vec![Operand::Reg(Reg::Rbx)]),
+            Inst::Op(
+                Opcode::Mov,
+                vec![Operand::Reg(Reg::Rsp), Operand::Reg(Reg::Rbx)],
+            ),
+            Inst::Op(Opcode::Call, vec![Operand::Reg(Reg::Rax)]),
+            Inst::Op(Opcode::Jmp, vec![Operand::Reg(Reg::R9)]),
+            Inst::Op(Opcode::Pop, vec![Operand::Reg(Reg::Rbx)]),
+        ];
+        assert_eq!(
+            encode(&text).unwrap().bytes,
+            [0x53, 0x48, 0x89, 0xE3, 0xFF, 0xD0, 0x41, 0xFF, 0xE1, 0x5B]
+        );
+        assert_eq!(text[2].to_string(), "\tcall\t*%rax");
+        assert_eq!(text[3].to_string(), "\tjmp\t*%r9");
+    }
+
+    #[test]
+    fn test_programs_run() {
+        if !can_run() {
+            return;
+        }
+        for input in [
+            "1 + 2 * 3 - 4 / 5\n2 ^ 3 ^ 2 % 7",
+            "~3 + 4! + -(2) + 0.5! + (-1)!",
+            "(1 < 2) == (3 >= 4)",
+            "sin(1) + cos(2) + tan(3) + asin(0.5) + acos(0.5) + atan(9)",
+            "sinh(2) + cosh(2) + tanh(0.5) + log(7) + log(8, 2) + exp(1.5)",
+            "abs(-2.5) + floor(2.5) + ceil(2.5) + round(2.5) + round(-2.5) + sqrt(2)",
+            "(-8) ^ (1 / 3) + -7 % 3 + 7.5 % -2",
+            "{}",
+            "let x = 1\n{ let x = x + 1; { let x = x * 10; x } + x } + x",
+            "let x = 1\nlet y = 0\nx / y",
+            "fn f(x) { if x > 2 { 1 / 0 } else { f(x + 1) } }\nf(0)",
+            "let c = 5\nfn f() { c = c + 1 }\nf()\nf()\nc",
+            "fn f(b: bool) { b }\nf(false)",
+            "fn fib(n) { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }\nfib(20)",
+            "fn f(a, b, c, d, e, g, h, i, j, k, l) { a - b + c - d + e - g + h - i + j * 100 + k * 1000 + l * 10000 }\n\
+             f(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11)",
+        ] {
+            assert_runs(input, 0);
+            assert_runs(input, 2);
+        }
+    }
+
+    #[test]
+    fn test_compiled_functions_agree_with_the_interpreter() {
+        if !can_run() {
+            return;
+        }
+        let definition =
+            "fn f(x, y) { if x > y { sin(x) * y ^ 0.5 } else { log(y - x + 1) + x % 3 } }";
+        let mut session = Session::new().with_jit();
+        session.eval_source(definition).unwrap();
+        // SAFETY: the session outlives every call
+        let f: extern "C" fn(f64, f64) -> f64 = unsafe { session.compile_fn("f") }.unwrap();
+        let mut interpreter = Session::new();
+        interpreter.eval_source(definition).unwrap();
+        for x in [-2.5, -1.0, 0.0, 0.5, 3.0, 10.0, 1e10] {
+            for y in [-4.0, 0.0, 0.25, 2.0, 7.5] {
+                let expected = match interpreter.eval_source(&format!("f({x}, {y})")) {
+                    Ok(Some(Value::Number(val))) => val,
+                    other => panic!("f({x}, {y}) gave {other:?}"),
+                };
+                assert_same(f(x, y), expected, &format!("f({x}, {y})"));
+            }
+        }
+        let g: extern "C" fn() -> f64 = {
+            session.eval_source("fn g() { 4! }").unwrap();
+            // SAFETY: as above
+            unsafe { session.compile_fn("g") }.unwrap()
+        };
+        assert_eq!(g(), 24.0);
+    }
+
+    #[test]
+    fn test_compiled_functions_report_runtime_errors() {
+        if !can_run() {
+            return;
+        }
+        let mut session = Session::new().with_jit();
+        session
+            .eval_source("fn f(a, b) { if b == 0 { 1 / b } else { a / b } }")
+            .unwrap();
+        // SAFETY: the session outlives every call
+        let f: extern "C" fn(f64, f64) -> f64 = unsafe { session.compile_fn("f") }.unwrap();
+        assert!(f(1.0, 0.0).is_nan());
+        assert_eq!(take_error().as_deref(), Some("Division by zero"));
+        assert_eq!(f(1.0, 2.0), 0.5);
+        assert_eq!(take_error(), None);
+    }
+
+    #[test]
+    fn test_compile_fn_errors() {
+        if !can_run() {
+            return;
+        }
+        let mut session = Session::new().with_jit();
+        session
+            .eval_source("fn f(x) { x }\nfn g(b: bool) { b }")
+            .unwrap();
+        // SAFETY: none of these are called
+        let missing = unsafe { session.compile_fn::<extern "C" fn() -> f64>("h") };
+        assert!(missing.unwrap_err().contains("h isn't compiled"));
+        let arity = unsafe { session.compile_fn::<extern "C" fn(f64, f64) -> f64>("f") };
+        assert_eq!(arity.unwrap_err(), "f takes 1 arguments, not 2");
+        let bools = unsafe { session.compile_fn::<extern "C" fn(f64) -> f64>("g") };
+        assert!(bools
+            .unwrap_err()
+            .contains("only functions taking and returning numbers"));
+        assert!(unsafe { session.compile_fn::<extern "C" fn(f64) -> f64>("main") }.is_err());
+
+        // Defined by input the JIT can't run, so the evaluator's version wins
+        session.eval_source("let k = 2").unwrap();
+        session.eval_source("fn f(x) { x * k }").unwrap();
+        assert!(unsafe { session.compile_fn::<extern "C" fn(f64) -> f64>("f") }.is_err());
+        assert_eq!(
+            session.eval_source("f(3)").unwrap().unwrap().to_string(),
+            "6"
+        );
+    }
+
+    #[test]
+    fn test_sessions_fall_back_to_evaluating() {
+        if !can_run() {
+            return;
+        }
+        let lines = [
+            "fn sq(x) { x * x }",
+            "let a = sq(3)",
+            "a + 1",
+            "const t = a > 5",
+            "ans",
+            "t",
+            "let f = sq",
+            "f(4)",
+            "1 / 0",
+            "let b = 1\nb = 2\nlet z = 1 / 0",
+            ":vars",
+            ":funcs",
+            "sq(2) + b",
+            ":reset",
+            "sq(2)",
+        ];
+        let (mut jitted, mut walked) = (Session::new().with_jit(), Session::new());
+        for line in lines {
+            let (found, expected) = (jitted.submit(line), walked.submit(line));
+            assert_eq!(found, expected, "{line}");
+            assert!(!matches!(found, Response::Continue), "{line}");
+        }
+    }
+
+    #[test]
+    fn test_generated_programs_run() {
+        if !can_run() {
+            return;
+        }
+        let mut generator = Generator::typed(29);
+        let mut checked = 0;
+        while checked < 60 {
+            let input = generator.program();
+            if session(0).compile_ir(&input).is_ok() {
+                assert_runs(&input, (checked % 4) as u8);
+                checked += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,126 @@
+#[cfg(test)]
+mod tests {
+    use crate::ast::parser::Parser;
+    use crate::repl::session::Session;
+    use crate::semantic::lint::lint;
+    use crate::semantic::lint::Level;
+    use crate::semantic::lint::Lint;
+    use crate::semantic::lint::LintConfig;
+    use crate::tokeniser::tokeniser::Tokeniser;
+
+    fn lints_with<'a>(input: &'a str, config: &LintConfig) -> Vec<(Lint, &'a str)> {
+        let tokens = Tokeniser::new(input.to_string()).to_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let stmts = parser.parse_lines().unwrap();
+        let resolved = parser.resolver().resolve(stmts).unwrap();
+        lint(&resolved, config)
+            .into_iter()
+            .map(|warning| (warning.lint, warning.span.slice(input)))
+            .collect()
+    }
+
+    fn lints(input: &str) -> Vec<(Lint, &str)> {
+        lints_with(input, &LintConfig::default())
+    }
+
+    #[test]
+    fn test_unused_variables() {
+        assert_eq!(
+            lints("let a = 1\nconst b = 2\nlet c = 3\nc"),
+            [(Lint::UnusedVariable, "a"), (Lint::UnusedVariable, "b")]
+        );
+        // Writing to a variable doesn't count as using it
+        assert_eq!(lints("let a = 1\na = 2"), [(Lint::UnusedVariable, "a")]);
+        assert!(lints("{ let t = 1\nt }").is_empty());
+    }
+
+    #[test]
+    fn test_incremental_input_keeps_globals() {
+        let input = "let a = 1\n{ let b = 2 }";
+        assert_eq!(
+            lints_with(input, &LintConfig::repl()),
+            [(Lint::UnusedVariable, "b")]
+        );
+    }
+
+    #[test]
+    fn test_unused_parameters() {
+        assert_eq!(
+            lints("fn f(a, b) { a }\nf(1, 2)"),
+            [(Lint::UnusedParameter, "b")]
+        );
+        assert!(lints("fn f(n) { if n < 1 { 1 } else { f(n - 1) } }\nf(3)").is_empty());
+    }
+
+    #[test]
+    fn test_shadowing() {
+        assert_eq!(
+            lints("let x = 1\n{ let x = 2\nx }\nx"),
+            [(Lint::ShadowedVariable, "x")]
+        );
+        assert_eq!(
+            lints("fn f(a) { let a = 2\na }\nf(1)"),
+            [(Lint::UnusedParameter, "a"), (Lint::ShadowedVariable, "a")]
+        );
+        assert_eq!(
+            lints("let x = 1\nfn sq(x) { x * x }\nsq(x)"),
+            [(Lint::ShadowedVariable, "x")]
+        );
+        // Locals of an enclosing function aren't visible to begin with
+        assert!(lints("fn f(a) { let b = a\nfn g(b) { b }\ng(b) }\nf(1)").is_empty());
+        assert_eq!(
+            lints("let pi = 3\nfn e(a) { a }\npi + e(1)"),
+            [(Lint::ShadowedBuiltin, "pi"), (Lint::ShadowedBuiltin, "e")]
+        );
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        assert_eq!(
+            lints("fn f(a) { return a\na + 1\na }\nf(1)"),
+            [(Lint::UnreachableCode, "a + 1\na")]
+        );
+        assert!(lints("fn f(a) { if a > 1 { return 1 }\na }\nf(1)").is_empty());
+    }
+
+    #[test]
+    fn test_lint_levels() {
+        let mut config = LintConfig::default();
+        assert_eq!(config.apply_flag("--allow=unused_variable"), Ok(true));
+        assert_eq!(config.apply_flag("--strict"), Ok(false));
+        assert!(config.apply_flag("--deny=nope").is_err());
+        assert_eq!(config.level(Lint::UnusedVariable), Level::Allow);
+        assert!(lints_with("let a = 1", &config).is_empty());
+        assert_eq!(
+            lints_with("fn f(a) { 1 }", &config),
+            [(Lint::UnusedParameter, "a")]
+        );
+    }
+
+    #[test]
+    fn test_session_warnings() {
+        let mut config = LintConfig::repl();
+        config.set(Lint::ShadowedVariable, Level::Deny);
+        let mut session = Session::new().with_lints(config);
+        assert!(session.eval_source("let x = 1\n{ let x = 2\nx }").is_err());
+        assert!(session.eval_source("x").is_err());
+
+        assert!(session.eval_source("{ let y = 2 }").is_ok());
+        let warnings = session.take_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].to_string(),
+            "Unused variable: y [unused_variable] @ 6"
+        );
+        assert!(session.take_warnings().is_empty());
+
+        let mut output = Vec::new();
+        Session::new()
+            .run("{ let y = 2 }\n".as_bytes(), &mut output)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "> warning: Unused variable: y [unused_variable] @ 6\n()\n> "
+        );
+    }
+}
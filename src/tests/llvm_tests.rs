@@ -0,0 +1,201 @@
+#[cfg(test)]
+mod tests {
+    use crate::codegen::llvm::to_llvm;
+    use crate::repl::session::Emit;
+    use crate::repl::session::Session;
+    use crate::tests::generator::Generator;
+    use crate::tests::support::lowered;
+    use crate::tests::support::same_output;
+    use crate::tests::support::script_session;
+    use crate::tests::support::temp_path;
+    use crate::tests::support::walked;
+    use std::process::Command;
+
+    /// Flags `lli` needs to read the IR, `None` if it isn't installed and
+    /// the tests running output skip
+    fn lli_flags() -> Option<Vec<&'static str>> {
+        let out = Command::new("lli").arg("--version").output().ok()?;
+        if !out.status.success() {
+            return None;
+        }
+        // Pointers are only opaque by default from LLVM 15
+        let text = String::from_utf8_lossy(&out.stdout).to_string();
+        let major = text
+            .split("version ")
+            .nth(1)
+            .and_then(|rest| rest.split('.').next())
+            .and_then(|major| major.trim().parse::<u32>().ok())?;
+        Some(match major {
+            ..=14 => vec!["-opaque-pointers"],
+            _ => Vec::new(),
+        })
+    }
+
+    /// Run the IR with `lli`, giving what it printed or the error it
+    /// reported
+    fn interpreted(ir: &str, flags: &[&str]) -> String {
+        let source = temp_path(".ll");
+        std::fs::write(&source, ir).unwrap();
+        let run = Command::new("lli")
+            .args(flags)
+            .arg(&source)
+            .output()
+            .unwrap();
+        let _ = std::fs::remove_file(&source);
+        match run.status.code() {
+            Some(0) => String::from_utf8_lossy(&run.stdout).trim().to_string(),
+            Some(1) => String::from_utf8_lossy(&run.stderr).trim().to_string(),
+            _ => panic!("{0}\n{ir}", String::from_utf8_lossy(&run.stderr)),
+        }
+    }
+
+    fn assert_runs(input: &str, level: u8, flags: &[&str]) {
+        let module = lowered(input, level).unwrap_or_else(|| panic!("{input} doesn't lower"));
+        let found = interpreted(&to_llvm(&module), flags);
+        let expected = walked(input);
+        assert!(
+            same_output(&found, &expected),
+            "-O{level} {input}\nfound {found}, expected {expected}"
+        );
+    }
+
+    #[test]
+    fn test_golden_llvm() {
+        for (name, input, expected) in [
+            (
+                "square",
+                include_str!("golden/square.ape"),
+                include_str!("golden/square.ll"),
+            ),
+            (
+                "branches",
+                include_str!("golden/branches.ape"),
+                include_str!("golden/branches.ll"),
+            ),
+            (
+                "globals",
+                include_str!("golden/globals.ape"),
+                include_str!("golden/globals.ll"),
+            ),
+        ] {
+            let ir = to_llvm(&lowered(input, 0).unwrap());
+            assert_eq!(ir, expected, "golden/{name}.ll");
+        }
+    }
+
+    #[test]
+    fn test_builtins_map_to_intrinsics() {
+        let ir =
+            to_llvm(&lowered("fn f(x) { sqrt(x) + sin(x) + tan(x) + exp(x) }\nf(2)", 0).unwrap());
+        assert!(
+            ir.contains("call double @llvm.sqrt.f64(double %v0)"),
+            "{ir}"
+        );
+        assert!(ir.contains("call double @llvm.sin.f64(double %v0)"), "{ir}");
+        // libm has what LLVM has no intrinsic for
+        assert!(ir.contains("call double @tan(double %v0)"), "{ir}");
+        assert!(
+            ir.contains("call double @llvm.pow.f64(double 0x4005BF0A8B145769, double %v0)"),
+            "{ir}"
+        );
+        assert!(
+            ir.contains("declare double @llvm.sqrt.f64(double)\n"),
+            "{ir}"
+        );
+        assert!(ir.contains("declare double @tan(double)\n"), "{ir}");
+    }
+
+    #[test]
+    fn test_golden_llvm_runs() {
+        let Some(flags) = lli_flags() else {
+            return;
+        };
+        for input in [
+            include_str!("golden/square.ape"),
+            include_str!("golden/branches.ape"),
+            include_str!("golden/globals.ape"),
+        ] {
+            assert_runs(input, 0, &flags);
+        }
+    }
+
+    #[test]
+    fn test_programs_run() {
+        let Some(flags) = lli_flags() else {
+            return;
+        };
+        for input in [
+            "1 + 2 * 3 - 4 / 5\n2 ^ 3 ^ 2 % 7",
+            "-0 * 1",
+            "~3 + 4! + -(2) + 0.5! + (-1)!",
+            "(1 < 2) == (3 >= 4)",
+            "0.1 + 0.2",
+            "2 ^ 80",
+            "sqrt(-1)",
+            "sin(pi / 2) + cos(0) + tan(1) + asin(1) + acos(0) + atan(1)",
+            "sinh(1) + cosh(1) + tanh(1) + log(100) + log(8, 2) + exp(1)",
+            "abs(-e) + floor(2.5) + ceil(2.5) + round(2.5) + round(-2.5)",
+            "{}",
+            "let x = 1\n{ let x = x + 1; { let x = x * 10; x } + x } + x",
+            "let x = 1\nlet y = 0\nx / y",
+            "let x = 1\nx / -0",
+            "fn fact(n) { if n < 1 { 1 } else { n * fact(n - 1) } }\nfact(10)",
+            "fn even(n) { if n == 0 { true } else { odd(n - 1) } }\nfn odd(n) { if n == 0 { false } else { even(n - 1) } }\neven(10) == odd(7)",
+            "let c = 5\nfn f() { c = c + 1 }\nf()\nf()\nc",
+            "fn f(n) { 1 + { if n > 0 { return n } else { 0 } } }\nf(3) + f(0)",
+            "fn f(b: bool) { b }\nf(false)",
+            "fn f(x) { let y = x; if x > 1 { y = y + 1 }; if x > 2 { y = y * 3 } else { y = y / 2 }; y }\nf(1) + f(2) + f(3)",
+            "fn fib(n) { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }\nfib(20)",
+            "fn nan() { 0 ^ -1 - 0 ^ -1 }\nnan() == nan()",
+            "fn nan() { 0 ^ -1 - 0 ^ -1 }\nnan() != nan()",
+            "fn nan() { 0 ^ -1 - 0 ^ -1 }\nnan()",
+            "fn f() { {} }\nf() == f()",
+        ] {
+            assert_runs(input, 0, &flags);
+            assert_runs(input, 2, &flags);
+        }
+    }
+
+    #[test]
+    fn test_generated_programs_run() {
+        let Some(flags) = lli_flags() else {
+            return;
+        };
+        let mut generator = Generator::typed(13);
+        let mut checked = 0;
+        while checked < 40 {
+            let input = generator.program();
+            if lowered(&input, 0).is_some() {
+                assert_runs(&input, (checked % 4) as u8, &flags);
+                checked += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn test_emit_llvm() {
+        let mut session = Session::new();
+        let ir = session
+            .emit("fn sq(x) { x * x }\nsq(3)", Emit::Llvm)
+            .unwrap();
+        assert!(
+            ir.contains("define double @ape_sq(double %v0) {\nb0:\n  %v1 = fmul double %v0, %v0\n"),
+            "{ir}"
+        );
+        assert!(ir.contains("define i32 @main() {\n"), "{ir}");
+        assert_eq!(
+            session.emit("fn f(g) { g(1) }", Emit::Llvm),
+            Err(String::from("Functions can't be used as values @ 3"))
+        );
+    }
+
+    #[test]
+    fn test_emit_llvm_keeps_functions() {
+        // Unused or inlined away, a script's functions are still defined for
+        // other code to call
+        let input = "fn area(r) { pi * r ^ 2 }\nfn sq(x) { x * x }\nsq(2)";
+        let ir = script_session(2).emit(input, Emit::Llvm).unwrap();
+        assert!(ir.contains("define double @ape_area(double %v0) {"), "{ir}");
+        assert!(ir.contains("define double @ape_sq(double %v0) {"), "{ir}");
+    }
+}
@@ -1 +1,22 @@
+pub mod asm_tests;
+pub mod c_tests;
+pub mod cache_tests;
+pub mod elf_tests;
+pub mod generator;
+pub mod ir_interpreter;
+pub mod ir_tests;
+pub mod jit_tests;
+pub mod lint_tests;
+pub mod llvm_tests;
+pub mod optimiser_tests;
 pub mod parser_tests;
+pub mod precedence_tests;
+pub mod repl_tests;
+pub mod resolver_tests;
+pub mod suggest_tests;
+pub mod support;
+pub mod tokeniser_tests;
+pub mod typeck_tests;
+pub mod vm_tests;
+pub mod wasm_interpreter;
+pub mod wasm_tests;
@@ -0,0 +1,454 @@
+#[cfg(test)]
+mod tests {
+    use crate::ast::parser::Parser;
+    use crate::optimiser::cse::eliminate_common_subexpressions;
+    use crate::optimiser::dce::eliminate_dead_code;
+    use crate::optimiser::fold::fold;
+    use crate::optimiser::inline::inline_functions;
+    use crate::optimiser::pipeline::Pass;
+    use crate::optimiser::pipeline::Pipeline;
+    use crate::semantic::resolver::Resolved;
+    use crate::tokeniser::tokeniser::Tokeniser;
+
+    fn resolve(input: &str) -> Resolved {
+        let tokens = Tokeniser::new(input.to_string()).to_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let stmts = parser.parse_lines().unwrap();
+        parser.resolver().resolve(stmts).unwrap()
+    }
+
+    /// One statement per line
+    fn show(resolved: Resolved) -> String {
+        resolved
+            .stmts
+            .iter()
+            .map(|stmt| stmt.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn folded(input: &str) -> String {
+        show(fold(resolve(input)))
+    }
+
+    fn shared(input: &str) -> String {
+        show(eliminate_common_subexpressions(resolve(input)))
+    }
+
+    fn inlined(input: &str, threshold: usize) -> String {
+        show(inline_functions(resolve(input), threshold))
+    }
+
+    fn without_dead_code(input: &str, keep_globals: bool) -> String {
+        show(eliminate_dead_code(resolve(input), keep_globals))
+    }
+
+    /// What evaluating the program prints, or the error that stopped it
+    fn run(resolved: &Resolved) -> String {
+        match Parser::new(Vec::new())
+            .evaluate(&resolved.stmts)
+            .into_result()
+        {
+            Ok(Some(val)) => val.to_string(),
+            Ok(None) => String::from("nothing"),
+            Err(err) => format!("error: {err}"),
+        }
+    }
+
+    /// Every pass, alone and together, gives the same result
+    fn assert_unchanged(input: &str) {
+        let resolved = resolve(input);
+        let expected = run(&resolved);
+        assert_eq!(run(&fold(resolved.clone())), expected, "{input}");
+        assert_eq!(
+            run(&eliminate_common_subexpressions(resolved.clone())),
+            expected,
+            "{input}"
+        );
+        assert_eq!(
+            run(&eliminate_dead_code(resolved.clone(), false)),
+            expected,
+            "{input}"
+        );
+        let optimised = eliminate_common_subexpressions(eliminate_dead_code(fold(resolved), false));
+        assert_eq!(run(&optimised), expected, "{input}");
+    }
+
+    #[test]
+    fn test_folds_constants() {
+        assert_eq!(folded("2*pi/360"), "0.017453292519943295");
+        assert_eq!(folded("1 + 2 * 3 - 4!"), "-17");
+        assert_eq!(folded("sin(0) + log(8, 2) + sqrt 16"), "7");
+        assert_eq!(folded("(1 < 2) == true"), "true");
+        assert_eq!(folded("fn f(x) { x * (2 + 3) }"), "fn f(x) { (x * 5) }");
+    }
+
+    #[test]
+    fn test_keeps_what_fails_at_runtime() {
+        assert_eq!(folded("1 / 0"), "(1 / 0)");
+        assert_eq!(folded("let a = 2\na / (1 - 1)"), "let a = 2\n(a / 0)");
+    }
+
+    #[test]
+    fn test_shadowed_constants_are_not_folded() {
+        assert_eq!(folded("let pi = 3\npi * 2"), "let pi = 3\n(pi * 2)");
+        assert_eq!(folded("fn f(e) { e + 1 }"), "fn f(e) { (e + 1) }");
+    }
+
+    #[test]
+    fn test_algebraic_identities() {
+        let x = "let x = 3\n";
+        assert_eq!(folded(&format!("{x}x * 1 + 1 * x")), "let x = 3\n(x + x)");
+        assert_eq!(folded(&format!("{x}x / 1 - x ^ 1")), "let x = 3\n(x - x)");
+        assert_eq!(folded(&format!("{x}x - 0")), "let x = 3\nx");
+        assert_eq!(folded(&format!("{x}--x")), "let x = 3\nx");
+        assert_eq!(folded(&format!("{x}x * -1")), "let x = 3\n(-x)");
+        assert_eq!(folded(&format!("{x}-(x / -1)")), "let x = 3\nx");
+        // x + 0 turns -0 into 0 so it only goes when x can't be -0
+        assert_eq!(folded(&format!("{x}x + 0")), "let x = 3\n(x + 0)");
+        assert_eq!(folded(&format!("{x}abs(x) + 0")), "let x = 3\nabs(x)");
+        assert_eq!(folded(&format!("{x}0 + (x + 1)")), "let x = 3\n(x + 1)");
+        // Neither of these hold for every float
+        assert_eq!(folded(&format!("{x}x * 0")), "let x = 3\n(x * 0)");
+        assert_eq!(
+            folded(&format!("{x}(x + 1) + 2")),
+            "let x = 3\n((x + 1) + 2)"
+        );
+    }
+
+    #[test]
+    fn test_constant_conditions() {
+        assert_eq!(folded("if 1 < 2 { 1 } else { 2 }"), "{ 1 }");
+        assert_eq!(folded("if pi < 3 { 1 } else if true { 2 }"), "{ 2 }");
+        assert_eq!(folded("if false { 1 }"), "{}");
+        // The branch that can't run isn't folded, however long it'd take
+        assert_eq!(folded("if false { 100000000000! } else { 1 }"), "{ 1 }");
+        assert_eq!(folded("if true { 1 } else { 100000000000! }"), "{ 1 }");
+    }
+
+    #[test]
+    fn test_results_are_unchanged() {
+        for input in [
+            "2*pi/360",
+            "let x = 7\nx * 1 + 0 * x - x ^ 1",
+            "let z = -0\nz + 0",
+            "let z = -0\nz - 0",
+            "let z = -0\n-0 + z",
+            "let z = -0\nz * 1",
+            "let z = -0\nz ^ 1",
+            "let n = sqrt(-1)\nn * 1 == n",
+            "let i = exp(1000)\ni * 0",
+            "let i = exp(1000)\n(i - 1) + 1",
+            "let x = 0.1\n(x + 0.2) + 0.3",
+            "let x = 2\n--x + ~~x",
+            "let a = 5\na / (2 - 2)",
+            "fn f(x) { if 2 > 1 { return x * 1 } \n x + 0 }\nf(-0)",
+            "fn g(x) { x * (1 + 1) }\ng(sin(pi))",
+            "log(0) + 1",
+            "(-1)! * 1",
+            "170! + 171! + 100000000000!",
+        ] {
+            assert_unchanged(input);
+        }
+    }
+
+    #[test]
+    fn test_shares_common_subexpressions() {
+        assert_eq!(
+            shared("let x = 2\nsin(x)*sin(x) + cos(x)*cos(x)"),
+            "let x = 2\n{ let cse0 = sin(x); let cse1 = cos(x); ((cse0 * cse0) + (cse1 * cse1)) }"
+        );
+        // a + b is only needed by the shared product
+        assert_eq!(
+            shared("let a = 1\n(a + 2) * a + (a + 2) * a"),
+            "let a = 1\n{ let cse0 = ((a + 2) * a); (cse0 + cse0) }"
+        );
+        assert_eq!(
+            shared("fn f(x) { return x^2 - x^2 }"),
+            "fn f(x) { return { let cse0 = (x ^ 2); (cse0 - cse0) } }"
+        );
+        assert_eq!(
+            shared("let z = 0\n(z + 0) * (z + -0)"),
+            "let z = 0\n((z + 0) * (z + (-0)))"
+        );
+    }
+
+    #[test]
+    fn test_branches_are_shared_separately() {
+        assert_eq!(
+            shared("let x = 2\nif x > 1 { sqrt(x) } else { sqrt(x) }"),
+            "let x = 2\nif (x > 1) { sqrt(x) } else { sqrt(x) }"
+        );
+        assert_eq!(
+            shared("let x = 2\nif x * x > 1 { x * x }"),
+            "let x = 2\nif ((x * x) > 1) { (x * x) }"
+        );
+        assert_eq!(
+            shared("let x = 2\nif true { -x * -x }"),
+            "let x = 2\nif true { { let cse0 = (-x); (cse0 * cse0) } }"
+        );
+    }
+
+    #[test]
+    fn test_calls_limit_sharing() {
+        let setup = "let y = 2\nfn f(a) { a }\n";
+        // Only subexpressions that can't fail move ahead of a call
+        assert_eq!(
+            shared(&format!("{setup}1/y + f(1) + 1/y")),
+            format!("{setup}(((1 / y) + f(1)) + (1 / y))")
+        );
+        assert_eq!(
+            shared(&format!("{setup}sin(y) + f(1) + sin(y)")),
+            format!("{setup}{{ let cse0 = sin(y); ((cse0 + f(1)) + cse0) }}")
+        );
+        // A call may change a variable that is assigned somewhere
+        let setup = "let y = 2\nfn bump() { y = y + 1\ny }\n";
+        assert_eq!(
+            shared(&format!("{setup}sin(y) + bump() + sin(y)")),
+            "let y = 2\nfn bump() { y = (y + 1); y }\n((sin(y) + bump()) + sin(y))"
+        );
+    }
+
+    #[test]
+    fn test_removes_dead_definitions() {
+        assert_eq!(
+            without_dead_code(
+                "let a = 1\nlet b = a + 1\nfn unused(x) { x }\nfn rec(n) { rec(n - 1) }\n3",
+                false
+            ),
+            "3"
+        );
+        assert_eq!(
+            without_dead_code("let a = { let t = 5; let u = t; 1 }\na", false),
+            "let a = { 1 }\na"
+        );
+        // The last statement decides what a block evaluates to
+        assert_eq!(
+            without_dead_code("fn f() { 1; let t = 2 }\nf()", false),
+            "fn f() { 1; let t = 2 }\nf()"
+        );
+    }
+
+    #[test]
+    fn test_keeps_definitions_with_effects() {
+        assert_eq!(
+            without_dead_code("let a = 1 / 0\n2", false),
+            "let a = (1 / 0)\n2"
+        );
+        assert_eq!(
+            without_dead_code("fn f() { 1 }\nlet a = f()\n2", false),
+            "fn f() { 1 }\nlet a = f()\n2"
+        );
+        // The global may not exist yet when the function runs
+        assert_eq!(
+            without_dead_code("fn f() { let a = later; 1 }\nf()\nlet later = 2", false),
+            "fn f() { let a = later; 1 }\nf()\nlet later = 2"
+        );
+    }
+
+    #[test]
+    fn test_keeps_globals_for_later_input() {
+        assert_eq!(
+            without_dead_code(
+                "let a = 1\nfn f() { fn g() { 1 }; 2 }\nlet b = { let t = 1; 2 }",
+                true
+            ),
+            "let a = 1\nfn f() { fn g() { 1 }; 2 }\nlet b = { 2 }"
+        );
+    }
+
+    #[test]
+    fn test_shared_and_removed_results_are_unchanged() {
+        for input in [
+            "let x = 2\nsin(x)*sin(x) + cos(x)*cos(x)",
+            "let x = 2\n(x + 1) * (x + 1) / ((x + 1) * (x + 1))",
+            "let y = 2\nfn bump() { y = y + 1\ny }\nsin(y) + bump() + sin(y)",
+            "let y = 0\nfn f(a) { y = 1\na }\n1/y + f(1) + 1/y",
+            "let y = 0\nfn f(a) { y = 1\na }\nf(1) + 1/y + 1/y",
+            "fn f(x) { if x > 0 { return x * x + x * x }\n-x * -x }\nf(3) + f(-3)",
+            "fn fact(n) { if n < 1 { 1 } else { n * fact(n - 1) } }\nfact(5) + fact(5)",
+            "let z = -0\n(z + 0) + (z + 0) * (z + -0)",
+            "let a = 1\nlet b = a + 1\nfn unused(x) { x }\n3",
+            "fn f() { let a = later; 1 }\nf()\nlet later = 2",
+            "let r = 1\nlet r = r + 1\nr * r + r * r",
+        ] {
+            assert_unchanged(input);
+        }
+    }
+
+    #[test]
+    fn test_inlines_small_functions() {
+        assert_eq!(
+            inlined("fn sq(x) { x * x }\nsq(3)", 16),
+            "fn sq(x) { (x * x) }\n{ let x_0 = 3; (x_0 * x_0) }"
+        );
+        assert_eq!(
+            inlined("fn sq(x) { x * x }\nfn quad(x) { sq(sq(x)) }\nquad(2)", 16),
+            "fn sq(x) { (x * x) }\n\
+             fn quad(x) { { let x_0 = { let x_1 = x; (x_1 * x_1) }; (x_0 * x_0) } }\n\
+             { let x_2 = 2; { let x_3 = { let x_4 = x_2; (x_4 * x_4) }; (x_3 * x_3) } }"
+        );
+        assert_eq!(
+            inlined("fn inc(x) { return x + 1 }\ninc(1)", 16),
+            "fn inc(x) { return (x + 1) }\n{ let x_0 = 1; (x_0 + 1) }"
+        );
+    }
+
+    #[test]
+    fn test_inlining_is_hygienic() {
+        // Arguments naming the parameters aren't captured
+        assert_eq!(
+            inlined("fn sub(a, b) { a - b }\nlet a = 5\nlet b = 2\nsub(b, a)", 16),
+            "fn sub(a, b) { (a - b) }\nlet a = 5\nlet b = 2\n{ let a_0 = b; let b_1 = a; (a_0 - b_1) }"
+        );
+        // A local hiding a global the body reads keeps the call
+        let input = "let k = 1\nfn f(x) { x + k }\nfn g(k) { f(k) }\nf(2)";
+        assert_eq!(
+            inlined(input, 16),
+            "let k = 1\nfn f(x) { (x + k) }\nfn g(k) { f(k) }\n{ let x_0 = 2; (x_0 + k) }"
+        );
+    }
+
+    #[test]
+    fn test_calls_that_stay() {
+        for input in [
+            // Recursive, directly or through another function
+            "fn fact(n) { if n < 1 { 1 } else { n * fact(n - 1) } }\nfact(3)",
+            "fn even(n) { if n < 1 { true } else { odd(n - 1) } }\n\
+             fn odd(n) { if n < 1 { false } else { even(n - 1) } }\neven(4)",
+            // Returns early
+            "fn f(x) { if x > 1 { return 1 }\nx }\nf(2)",
+            // Defined twice
+            "fn f() { 1 }\nf()\nfn f() { 2 }\nf()",
+            // Defines a function
+            "fn f() { fn g() { 1 }\ng() }\nf()",
+            // Only defined when the branch runs
+            "if true { fn f() { 1 } }\nf()",
+        ] {
+            let unchanged = show(resolve(input));
+            assert_eq!(inlined(input, 16), unchanged, "{input}");
+        }
+        // f may not be defined yet when g runs, g itself is inlined
+        assert_eq!(
+            inlined("fn g() { f(1) }\nfn f(x) { x }\ng()", 16),
+            "fn g() { f(1) }\nfn f(x) { x }\n{ f(1) }"
+        );
+    }
+
+    #[test]
+    fn test_inline_threshold() {
+        let input = "fn f(x) { x * x + x }\nf(1)";
+        assert_eq!(inlined(input, 0), "fn f(x) { ((x * x) + x) }\nf(1)");
+        assert_eq!(inlined(input, 5), "fn f(x) { ((x * x) + x) }\nf(1)");
+        assert_eq!(
+            inlined(input, 6),
+            "fn f(x) { ((x * x) + x) }\n{ let x_0 = 1; ((x_0 * x_0) + x_0) }"
+        );
+    }
+
+    #[test]
+    fn test_inlined_results_are_unchanged() {
+        for input in [
+            "fn sub(a, b) { a - b }\nlet a = 5\nlet b = 2\nsub(b, a) + sub(a, b)",
+            "fn sq(x) { x * x }\nfn quad(x) { sq(sq(x)) }\nquad(2) + quad(-0)",
+            "let k = 1\nfn f(x) { x + k }\nfn g(k) { f(k) }\ng(5) + f(2)",
+            "let n = 0\nfn bump(by) { n = n + by\nn }\nbump(1) + bump(2) + n",
+            "fn f(x) { x = x * 2\nx + 1 }\nlet x = 3\nf(x) + x",
+            "fn f(x) { let y = x\n{ let x = 1; y + x } }\nlet y = 10\nf(2) + y",
+            "fn inc(x) { return x + 1 }\ninc(inc(1))",
+            "fn apply(g, x) { g(x) }\nfn sq(x) { x * x }\napply(sq, 3)",
+            "fn f() { 1; let t = 2 }\nf()",
+            "fn f() { return }\nf()\n1",
+            "fn g() { f(1) }\ng()\nfn f(x) { x }",
+        ] {
+            assert_unchanged(input);
+        }
+    }
+
+    #[test]
+    fn test_pipeline_levels() {
+        assert_eq!(Pipeline::level(0).unwrap().passes(), []);
+        assert_eq!(Pipeline::level(1).unwrap().passes(), [Pass::Fold]);
+        assert_eq!(
+            Pipeline::default().passes(),
+            [
+                Pass::Fold,
+                Pass::Inline,
+                Pass::DeadCode,
+                Pass::CommonSubexpressions
+            ]
+        );
+        assert_eq!(Pipeline::default().inline_threshold(), 16);
+        assert_eq!(Pipeline::level(3).unwrap().inline_threshold(), 64);
+        assert_eq!(
+            Pipeline::level(4).unwrap_err(),
+            "Unknown optimisation level: 4"
+        );
+    }
+
+    #[test]
+    fn test_pipeline_flags() {
+        let mut pipeline = Pipeline::default();
+        assert_eq!(pipeline.apply_flag("--inline-threshold=3"), Ok(true));
+        assert_eq!(pipeline.apply_flag("-O3"), Ok(true));
+        assert_eq!(pipeline.inline_threshold(), 3);
+        assert_eq!(pipeline.apply_flag("--passes=cse,fold"), Ok(true));
+        assert_eq!(pipeline.passes(), [Pass::CommonSubexpressions, Pass::Fold]);
+        assert_eq!(pipeline.apply_flag("--passes="), Ok(true));
+        assert_eq!(pipeline.passes(), []);
+        assert_eq!(pipeline.apply_flag("--strict"), Ok(false));
+        assert_eq!(
+            pipeline.apply_flag("-Ofast"),
+            Err(String::from("Unknown optimisation level: fast"))
+        );
+        assert_eq!(
+            pipeline.apply_flag("--passes=fold,licm"),
+            Err(String::from("Unknown pass: licm"))
+        );
+    }
+
+    #[test]
+    fn test_pipeline_runs_passes_in_order() {
+        let input = "fn sq(x) { x * x }\nlet a = 2 * 3\nsq(a) + sq(a)";
+        let optimised = |pipeline: Pipeline| show(pipeline.run(resolve(input)));
+        assert_eq!(optimised(Pipeline::level(0).unwrap()), show(resolve(input)));
+        assert_eq!(
+            optimised(Pipeline::level(1).unwrap()),
+            "fn sq(x) { (x * x) }\nlet a = 6\n(sq(a) + sq(a))"
+        );
+        // Calls may have effects so aren't shared, their inlined bodies are
+        assert_eq!(
+            optimised(Pipeline::new(vec![Pass::CommonSubexpressions])),
+            "fn sq(x) { (x * x) }\nlet a = (2 * 3)\n(sq(a) + sq(a))"
+        );
+        let mut pipeline = Pipeline::default();
+        pipeline.keep_globals = false;
+        assert_eq!(
+            optimised(pipeline),
+            "let a = 6\n({ let x_0 = a; (x_0 * x_0) } + { let x_1 = a; (x_1 * x_1) })"
+        );
+    }
+
+    #[test]
+    fn test_levels_give_the_same_results() {
+        for input in [
+            "fn sq(x) { x * x }\nlet a = 2 * 3\nsq(a) + sin(a) * sin(a)",
+            "fn f(n) { if n < 1 { 1 } else { n * f(n - 1) } }\nf(5) + f(5)",
+            "let n = 0\nfn bump(by) { n = n + by\nn }\nbump(1) + bump(2) + n",
+            "fn div(a, b) { a / b }\nlet z = 0\ndiv(1, z) + 1",
+            "-0 + 0 * -1",
+        ] {
+            let resolved = resolve(input);
+            let expected = run(&resolved);
+            for level in 0..=3 {
+                let mut pipeline = Pipeline::level(level).unwrap();
+                pipeline.keep_globals = false;
+                assert_eq!(
+                    run(&pipeline.run(resolved.clone())),
+                    expected,
+                    "-O{level} {input}"
+                );
+            }
+        }
+    }
+}
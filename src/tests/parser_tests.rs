@@ -1,6 +1,14 @@
 #[cfg(test)]
 mod tests {
+    use crate::ast::ast::ExprKind;
+    use crate::ast::ast::Stmt;
+    use crate::ast::ast::StmtKind;
+    use crate::ast::error::RuntimeError;
+    use crate::ast::evaluation::Evaluation;
+    use crate::ast::evaluation::Outcome;
     use crate::ast::parser::Parser;
+    use crate::ast::value::Value;
+    use crate::tokeniser::token_enum::Span;
     use crate::tokeniser::tokeniser::Tokeniser;
 
     fn parse_and_eval(input: &str) -> f64 {
@@ -8,6 +16,24 @@ mod tests {
         let tokens = tokeniser.to_tokens().unwrap();
         let mut parser = Parser::new(tokens);
         let ast = parser.parse_lines().unwrap();
+        parser
+            .evaluate(&ast)
+            .into_result()
+            .unwrap_or_else(|err| panic!("{err}"))
+            .expect("program has no expression statement")
+            .as_number()
+            .unwrap()
+    }
+
+    fn parse(input: &str) -> Result<Vec<Stmt>, String> {
+        let tokens = Tokeniser::new(input.to_string()).to_tokens().unwrap();
+        Parser::new(tokens).parse_lines()
+    }
+
+    fn evaluate(input: &str) -> Evaluation {
+        let tokens = Tokeniser::new(input.to_string()).to_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_lines().unwrap();
         parser.evaluate(&ast)
     }
 
@@ -103,4 +129,254 @@ mod tests {
     fn test_mismatched_scope_braces() {
         parse_and_eval("{ let x = 5 } }");
     }
+
+    #[test]
+    fn test_zero_result_is_kept() {
+        assert_eq!(parse_and_eval("5\n1 - 1"), 0.0);
+        assert_eq!(parse_and_eval("let x = 3\nx - 3\nlet y = 4"), 0.0);
+    }
+
+    #[test]
+    fn test_statement_outcomes() {
+        let input = "let x = 2\nfn f(a) { a }\nx * 3\nx = 1";
+        let evaluation = evaluate(input);
+        let outcomes: Vec<Outcome> = evaluation
+            .results
+            .iter()
+            .map(|res| res.outcome.clone())
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                Outcome::Unit,
+                Outcome::Unit,
+                Outcome::Value(Value::Number(6.0)),
+                Outcome::Unit
+            ]
+        );
+        let sources: Vec<&str> = evaluation
+            .results
+            .iter()
+            .map(|res| res.span.slice(input))
+            .collect();
+        assert_eq!(sources, ["let x = 2", "fn f(a) { a }", "x * 3", "x = 1"]);
+        assert_eq!(evaluation.value(), Some(Value::Number(6.0)));
+    }
+
+    #[test]
+    fn test_definitions_only_have_no_value() {
+        assert_eq!(evaluate("let x = 1\nfn f() { 2 }").value(), None);
+    }
+
+    #[test]
+    fn test_evaluation_stops_at_error() {
+        let input = "1 + 1\n2 / 0\n3";
+        let evaluation = evaluate(input);
+        assert_eq!(evaluation.results.len(), 2);
+        assert_eq!(
+            evaluation.error(),
+            Some((&RuntimeError::DivisionByZero, Span::new(6, 11)))
+        );
+        assert_eq!(evaluation.into_result(), Err(RuntimeError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_bools() {
+        assert_eq!(evaluate("true").value(), Some(Value::Bool(true)));
+        assert_eq!(
+            evaluate("let b = false\nb").value(),
+            Some(Value::Bool(false))
+        );
+        assert_eq!(evaluate("{}").value(), Some(Value::Unit));
+    }
+
+    #[test]
+    fn test_comparisons() {
+        assert_eq!(evaluate("1 + 1 == 2").value(), Some(Value::Bool(true)));
+        assert_eq!(evaluate("3 < 2").value(), Some(Value::Bool(false)));
+        assert_eq!(evaluate("2 >= 2").value(), Some(Value::Bool(true)));
+        assert_eq!(evaluate("true != false").value(), Some(Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_if_expression() {
+        assert_eq!(parse_and_eval("if 1 < 2 { 10 } else { 20 }"), 10.0);
+        assert_eq!(
+            parse_and_eval("let x = 5\nif x > 9 { 1 } else if x > 4 { 2 } else { 3 }"),
+            2.0
+        );
+        let input = r#"
+        fn fact(n) {
+            if n <= 1 { 1 } else { n * fact(n - 1) }
+        }
+        fact(5)
+        "#;
+        assert_eq!(parse_and_eval(input), 120.0);
+        assert_eq!(evaluate("if false { 1 }").value(), Some(Value::Unit));
+    }
+
+    #[test]
+    fn test_function_values() {
+        let evaluation = evaluate("fn f(a) { a + 1 }\nlet g = f\ng(2)");
+        assert_eq!(evaluation.value(), Some(Value::Number(3.0)));
+        assert_eq!(
+            evaluate("fn f(a, b) { a }\nf").value().unwrap().to_string(),
+            "<fn f(a, b)>"
+        );
+    }
+
+    #[test]
+    fn test_runtime_type_errors() {
+        let mismatch = |expected, found| RuntimeError::TypeMismatch { expected, found };
+        assert_eq!(
+            evaluate("true + 1").into_result(),
+            Err(mismatch("number", "bool"))
+        );
+        assert_eq!(
+            evaluate("-false").into_result(),
+            Err(mismatch("number", "bool"))
+        );
+        assert_eq!(
+            evaluate("if 1 { 2 }").into_result(),
+            Err(mismatch("bool", "number"))
+        );
+        assert_eq!(
+            evaluate("let x = 1\nx(2)").into_result(),
+            Err(mismatch("function", "number"))
+        );
+        assert_eq!(
+            evaluate("1 == true").into_result(),
+            Err(mismatch("number", "bool"))
+        );
+        assert_eq!(
+            evaluate("fn f(a) { a }\nf(1, 2)").into_result(),
+            Err(RuntimeError::ArityMismatch {
+                name: String::from("f"),
+                expected: 1,
+                found: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_statement_kinds() {
+        let stmts = parse("let a = 1\nconst b = 2\na = 3\nfn f(x) { return x }\nf(a)").unwrap();
+        assert!(matches!(stmts[0].kind, StmtKind::Let(ref id, _) if id.name == "a"));
+        assert!(matches!(stmts[1].kind, StmtKind::Const(ref id, _) if id.name == "b"));
+        assert!(matches!(stmts[2].kind, StmtKind::Assign(ref id, _) if id.name == "a"));
+        let StmtKind::Fn(ref decl) = stmts[3].kind else {
+            panic!("expected a function definition")
+        };
+        assert_eq!(decl.params[0].name.name, "x");
+        assert!(matches!(decl.body[0].kind, StmtKind::Return(Some(_))));
+        let StmtKind::Expr(ref call) = stmts[4].kind else {
+            panic!("expected an expression statement")
+        };
+        assert!(matches!(call.kind, ExprKind::FunctionCall(ref id, _) if id.name == "f"));
+    }
+
+    #[test]
+    fn test_definitions_only_at_statement_level() {
+        assert!(parse("1 + fn f() { 2 }").is_err());
+        assert!(parse("2 * let x = 1").is_err());
+        assert!(parse("sin(const c = 1)").is_err());
+        assert!(parse("{ fn f() { 2 }\nf() }").is_ok());
+    }
+
+    #[test]
+    fn test_return() {
+        let input = r#"
+        fn clamp(x) {
+            if x > 10 { return 10 }
+            x
+        }
+        clamp(50) + clamp(3)
+        "#;
+        assert_eq!(parse_and_eval(input), 13.0);
+        assert_eq!(
+            evaluate("fn f() { return }\nf()").value(),
+            Some(Value::Unit)
+        );
+        assert!(parse("return 1").is_err());
+    }
+
+    #[test]
+    fn test_const_and_reassignment() {
+        assert_eq!(parse_and_eval("let x = 1\n{ x = 5 }\nx"), 5.0);
+        assert_eq!(
+            evaluate("const x = 1\nx = 2").into_result(),
+            Err(RuntimeError::AssignToConst(String::from("x")))
+        );
+        assert_eq!(
+            evaluate("y = 2").into_result(),
+            Err(RuntimeError::UndeclaredVariable(String::from("y")))
+        );
+    }
+
+    #[test]
+    fn test_builtins_and_constants() {
+        assert_eq!(parse_and_eval("log(8, 2) + log(1000)"), 6.0);
+        assert_eq!(parse_and_eval("sqrt 16 + 1"), 5.0);
+        assert_eq!(parse_and_eval("cos(pi)"), -1.0);
+        assert_eq!(parse_and_eval("let e = 2\ne * 3"), 6.0);
+        assert!(parse("log(1, 2, 3)").is_err());
+        assert!(parse("sqrt()").is_err());
+        assert!(parse("let sin = 1").is_err());
+        assert!(parse("fn f(cos) { cos }").is_err());
+    }
+
+    #[test]
+    fn test_functions_do_not_see_caller_locals() {
+        assert_eq!(
+            evaluate("fn f() { y }\n{ let y = 1\nf() }").into_result(),
+            Err(RuntimeError::UndeclaredVariable("y".to_string()))
+        );
+        assert_eq!(
+            parse_and_eval("let y = 2\nfn f() { y }\n{ let y = 1\nf() }"),
+            2.0
+        );
+    }
+
+    #[test]
+    fn test_statements_end_at_line_breaks() {
+        let stmts = parse("let x = 1\n-2").unwrap();
+        assert_eq!(stmts.len(), 2);
+        assert_eq!(stmts[0].to_string(), "let x = 1");
+        assert_eq!(stmts[1].to_string(), "(-2)");
+        assert!(parse("x\n!y").is_err());
+        // A bracket on the next line isn't a call
+        let stmts = parse("let a = 3\na\n(a)").unwrap();
+        assert_eq!(stmts.len(), 3);
+        assert_eq!(stmts[1].to_string(), "a");
+        assert_eq!(stmts[2].to_string(), "a");
+        let stmts = parse("f\n(2)").unwrap();
+        assert_eq!(stmts.len(), 2);
+        assert_eq!(stmts[0].to_string(), "f");
+        assert_eq!(stmts[1].to_string(), "2");
+        assert_eq!(parse_and_eval("let a = 3\na\n(a)"), 3.0);
+        assert_eq!(
+            parse("fn f() { return\n1 }").unwrap()[0].to_string(),
+            "fn f() { return; 1 }"
+        );
+    }
+
+    #[test]
+    fn test_semicolons_separate_statements() {
+        assert_eq!(parse_and_eval("let x = 2; let y = 3; x * y"), 6.0);
+        assert_eq!(parse_and_eval(";; 1;; 2 ;"), 2.0);
+        assert_eq!(parse_and_eval("{ let a = 1; a + 1 }"), 2.0);
+        assert_eq!(parse("{ 1; 2 }").unwrap()[0].to_string(), "{ 1; 2 }");
+        assert!(parse("let x = 1 let y = 2").is_err());
+        assert!(parse("x = 1 y = 2").is_err());
+    }
+
+    #[test]
+    fn test_line_continuation() {
+        assert_eq!(parse_and_eval("let x = 1 +\n2\nx"), 3.0);
+        assert_eq!(parse_and_eval("let x =\n  4\nx"), 4.0);
+        assert_eq!(parse_and_eval("fn f(a, b) { a - b }\nf(5,\n  2)"), 3.0);
+        assert_eq!(parse_and_eval("(1\n- 2)"), -1.0);
+        assert_eq!(parse_and_eval("if 1 < 2\n{ 3 }\nelse { 4 }"), 3.0);
+        assert_eq!(parse_and_eval("let x = 0\n{\n  x = 5\n  x * 2\n}"), 10.0);
+    }
 }
@@ -0,0 +1,196 @@
+#[cfg(test)]
+mod tests {
+    use crate::ast::parser::Dialect;
+    use crate::ast::parser::Parser;
+    use crate::tokeniser::tokeniser::Tokeniser;
+
+    /// Parse a single expression and print it fully parenthesised
+    fn shape(input: &str) -> String {
+        let tokens = Tokeniser::new(input.to_string()).to_tokens().unwrap();
+        let stmts = Parser::new(tokens).parse_lines().unwrap();
+        assert_eq!(stmts.len(), 1, "{input} parsed as several statements");
+        stmts[0].to_string()
+    }
+
+    fn parse_err(input: &str) -> bool {
+        let tokens = Tokeniser::new(input.to_string()).to_tokens().unwrap();
+        Parser::new(tokens).parse_lines().is_err()
+    }
+
+    #[test]
+    fn test_power_is_right_associative() {
+        assert_eq!(shape("2^3^2"), "(2 ^ (3 ^ 2))");
+        assert_eq!(shape("a^b^c^d"), "(a ^ (b ^ (c ^ d)))");
+    }
+
+    #[test]
+    fn test_power_binds_tighter_than_prefix() {
+        assert_eq!(shape("-2^2"), "(-(2 ^ 2))");
+        assert_eq!(shape("~2^2"), "(~(2 ^ 2))");
+        assert_eq!(shape("2^-1"), "(2 ^ (-1))");
+        assert_eq!(shape("2^-3^2"), "(2 ^ (-(3 ^ 2)))");
+        assert_eq!(shape("--2"), "(-(-2))");
+    }
+
+    #[test]
+    fn test_prefix_binds_tighter_than_multiplicative() {
+        assert_eq!(shape("-2*3"), "((-2) * 3)");
+        assert_eq!(shape("2*-3"), "(2 * (-3))");
+        assert_eq!(shape("-a+b"), "((-a) + b)");
+    }
+
+    #[test]
+    fn test_postfix_binds_tightest() {
+        assert_eq!(shape("3!"), "(3!)");
+        assert_eq!(shape("-3!"), "(-(3!))");
+        assert_eq!(shape("2^3!"), "(2 ^ (3!))");
+        assert_eq!(shape("3!^2"), "((3!) ^ 2)");
+        assert_eq!(shape("3!!"), "((3!)!)");
+        assert_eq!(shape("2*3!"), "(2 * (3!))");
+    }
+
+    #[test]
+    fn test_multiplicative_operators() {
+        assert_eq!(shape("a*b/c%d"), "(((a * b) / c) % d)");
+        assert_eq!(shape("a%b*c"), "((a % b) * c)");
+        assert_eq!(shape("a+b%c"), "(a + (b % c))");
+        assert_eq!(shape("a%b^c"), "(a % (b ^ c))");
+        assert_eq!(shape("8/4/2"), "((8 / 4) / 2)");
+    }
+
+    #[test]
+    fn test_additive_operators() {
+        assert_eq!(shape("a-b-c"), "((a - b) - c)");
+        assert_eq!(shape("a-b+c"), "((a - b) + c)");
+        assert_eq!(shape("a+b*c"), "(a + (b * c))");
+        assert_eq!(shape("a*b+c"), "((a * b) + c)");
+    }
+
+    #[test]
+    fn test_comparisons_bind_loosest() {
+        assert_eq!(shape("a+1<b*2"), "((a + 1) < (b * 2))");
+        assert_eq!(shape("a<=b"), "(a <= b)");
+        assert_eq!(shape("a>=b"), "(a >= b)");
+        assert_eq!(shape("a>b"), "(a > b)");
+        assert_eq!(shape("a==-b"), "(a == (-b))");
+        assert_eq!(shape("a!=b^2"), "(a != (b ^ 2))");
+        assert!(parse_err("a < b < c"));
+        assert!(parse_err("a == b != c"));
+        assert_eq!(shape("(a < b) == c"), "((a < b) == c)");
+    }
+
+    #[test]
+    fn test_bracketless_builtins() {
+        assert_eq!(shape("sin x + 1"), "(sin(x) + 1)");
+        assert_eq!(shape("sin x * 2"), "(sin(x) * 2)");
+        assert_eq!(shape("sin x^2"), "sin((x ^ 2))");
+        assert_eq!(shape("sin -x"), "sin((-x))");
+        assert_eq!(shape("sqrt x!"), "sqrt((x!))");
+        assert_eq!(shape("-cos x"), "(-cos(x))");
+        assert_eq!(shape("sin cos x"), "sin(cos(x))");
+    }
+
+    #[test]
+    fn test_bracketed_builtins_are_primaries() {
+        assert_eq!(shape("sin(x)^2"), "(sin(x) ^ 2)");
+        assert_eq!(shape("sin(x)!"), "(sin(x)!)");
+        assert_eq!(shape("log(8, 2) * 3"), "(log(8, 2) * 3)");
+        assert_eq!(shape("f(x)^2"), "(f(x) ^ 2)");
+    }
+
+    #[test]
+    fn test_parentheses_override() {
+        assert_eq!(shape("(2^3)^2"), "((2 ^ 3) ^ 2)");
+        assert_eq!(shape("(-2)^2"), "((-2) ^ 2)");
+        assert_eq!(shape("a-(b-c)"), "(a - (b - c))");
+    }
+
+    #[test]
+    fn test_implicit_multiplication() {
+        assert_eq!(shape("2x"), "(2 * x)");
+        assert_eq!(shape("3(x+1)"), "(3 * (x + 1))");
+        assert_eq!(shape("(a)(b)"), "(a * b)");
+        assert_eq!(shape("2pi"), "(2 * pi)");
+        assert_eq!(shape("2x y"), "((2 * x) * y)");
+        assert_eq!(shape("2 sin x"), "(2 * sin(x))");
+        assert_eq!(shape("f(x)"), "f(x)");
+    }
+
+    #[test]
+    fn test_implicit_multiplication_precedence() {
+        assert_eq!(shape("4x^2"), "(4 * (x ^ 2))");
+        assert_eq!(shape("2^3x"), "((2 ^ 3) * x)");
+        assert_eq!(shape("-2x"), "((-2) * x)");
+        assert_eq!(shape("1/2x"), "(1 / (2 * x))");
+        assert_eq!(shape("2x + 1"), "((2 * x) + 1)");
+        assert_eq!(shape("3x!"), "(3 * (x!))");
+        assert_eq!(shape("sin 2x"), "sin((2 * x))");
+        assert_eq!(shape("sin 2x + 1"), "(sin((2 * x)) + 1)");
+    }
+
+    #[test]
+    fn test_numbers_are_not_multiplied_implicitly() {
+        let err = |input: &str| {
+            let tokens = Tokeniser::new(input.to_string()).to_tokens().unwrap();
+            Parser::new(tokens).parse_lines().unwrap_err()
+        };
+        assert_eq!(err("2 3"), "A number can't follow another number @ 1");
+        assert_eq!(err("2^2 3"), "A number can't follow another number @ 3");
+        assert_eq!(
+            err("1e3"),
+            "Scientific notation isn't supported, write the power of ten with ^ @ 1"
+        );
+        assert_eq!(
+            err("let x = 2.5e10"),
+            "Scientific notation isn't supported, write the power of ten with ^ @ 4"
+        );
+        // A constant apart from the number still multiplies
+        assert_eq!(shape("2e"), "(2 * e)");
+        assert_eq!(shape("2 e 3"), "((2 * e) * 3)");
+        assert_eq!(shape("(2)3"), "(2 * 3)");
+    }
+
+    #[test]
+    fn test_implicit_multiplication_stops_at_line_breaks() {
+        let tokens = Tokeniser::new("let x = 5\nx + 3".to_string())
+            .to_tokens()
+            .unwrap();
+        assert_eq!(Parser::new(tokens).parse_lines().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_strict_dialect() {
+        let parse = |input: &str| {
+            let tokens = Tokeniser::new(input.to_string()).to_tokens().unwrap();
+            Parser::new(tokens)
+                .with_dialect(Dialect::strict())
+                .parse_lines()
+        };
+        assert!(parse("2x").unwrap_err().contains("strict"));
+        assert!(parse("3(x+1)").is_err());
+        assert_eq!(parse("2*x").unwrap()[0].to_string(), "(2 * x)");
+    }
+
+    #[test]
+    fn test_evaluated_precedence() {
+        let eval = |input: &str| {
+            let tokens = Tokeniser::new(input.to_string()).to_tokens().unwrap();
+            let mut parser = Parser::new(tokens);
+            let stmts = parser.parse_lines().unwrap();
+            parser
+                .evaluate(&stmts)
+                .into_result()
+                .unwrap()
+                .unwrap()
+                .as_number()
+                .unwrap()
+        };
+        assert_eq!(eval("2^3^2"), 512.0);
+        assert_eq!(eval("-2^2"), -4.0);
+        assert_eq!(eval("2 + 7 % 4 * 2"), 8.0);
+        assert_eq!(eval("sqrt 16 + 1"), 5.0);
+        assert_eq!(eval("-3! + 1"), -5.0);
+        assert_eq!(eval("let x = 3\n4x^2 - 2x"), 30.0);
+        assert_eq!(eval("1/2pi"), 1.0 / (2.0 * std::f64::consts::PI));
+    }
+}
@@ -0,0 +1,167 @@
+#[cfg(test)]
+mod tests {
+    use crate::ast::value::Value;
+    use crate::optimiser::pipeline::Pipeline;
+    use crate::repl::input::is_incomplete;
+    use crate::repl::input::Command;
+    use crate::repl::session::Emit;
+    use crate::repl::session::Response;
+    use crate::repl::session::Session;
+
+    #[test]
+    fn test_state_persists_between_lines() {
+        let mut session = Session::new();
+        assert_eq!(session.submit("let x = 5"), Response::Nothing);
+        assert_eq!(session.submit("fn double(a) { a * 2 }"), Response::Nothing);
+        assert_eq!(
+            session.submit("double(x) + 1"),
+            Response::Value(Value::Number(11.0))
+        );
+    }
+
+    #[test]
+    fn test_ans_holds_previous_result() {
+        let mut session = Session::new();
+        assert_eq!(session.submit("2 + 3"), Response::Value(Value::Number(5.0)));
+        assert_eq!(
+            session.submit("ans * 2"),
+            Response::Value(Value::Number(10.0))
+        );
+        assert_eq!(session.submit("1 - 1"), Response::Value(Value::Number(0.0)));
+        assert_eq!(
+            session.submit("ans + 4"),
+            Response::Value(Value::Number(4.0))
+        );
+    }
+
+    #[test]
+    fn test_multi_line_input() {
+        let mut session = Session::new();
+        assert_eq!(session.submit("fn add(a, b) {"), Response::Continue);
+        assert_eq!(session.submit("a + b"), Response::Continue);
+        assert_eq!(session.submit("}"), Response::Nothing);
+        assert_eq!(session.submit("add(1,"), Response::Continue);
+        assert_eq!(session.submit("2)"), Response::Value(Value::Number(3.0)));
+        assert_eq!(
+            session.history(),
+            ["fn add(a, b) {\na + b\n}", "add(1,\n2)"]
+        );
+    }
+
+    #[test]
+    fn test_errors_keep_session_alive() {
+        let mut session = Session::new();
+        assert!(matches!(session.submit("y + 1"), Response::Error(_)));
+        assert!(matches!(session.submit("1 / 0"), Response::Error(_)));
+        assert_eq!(session.submit("let y = 1"), Response::Nothing);
+        assert_eq!(session.submit("y + 1"), Response::Value(Value::Number(2.0)));
+    }
+
+    #[test]
+    fn test_names_are_checked_before_evaluation() {
+        let mut session = Session::new();
+        assert_eq!(
+            session.submit("let z = 1; z + w"),
+            Response::Error(String::from("Undeclared Variable: w @ 15"))
+        );
+        assert!(matches!(session.submit("z"), Response::Error(_)));
+    }
+
+    #[test]
+    fn test_meta_commands() {
+        let mut session = Session::new();
+        session.submit("let b = 2");
+        session.submit("let a = 1");
+        session.submit("fn f(x, y) { x }");
+        assert_eq!(
+            session.submit(":vars"),
+            Response::Output(String::from("a = 1\nb = 2"))
+        );
+        assert_eq!(
+            session.submit(":funcs"),
+            Response::Output(String::from("f(x, y)"))
+        );
+        assert_eq!(session.submit(":reset"), Response::Nothing);
+        assert_eq!(session.submit(":vars"), Response::Output(String::new()));
+        assert!(matches!(session.submit(":nope"), Response::Error(_)));
+        assert_eq!(session.submit(":quit"), Response::Quit);
+    }
+
+    #[test]
+    fn test_load_file() {
+        let path = std::env::temp_dir().join("ape_repl_load_test.ape");
+        std::fs::write(&path, "let r = 3\nfn sq(v) { v * v }\nsq(r)\n").unwrap();
+        let mut session = Session::new();
+        let response = session.submit(&format!(":load {0}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(response, Response::Value(Value::Number(9.0)));
+        assert_eq!(
+            session.submit("sq(ans)"),
+            Response::Value(Value::Number(81.0))
+        );
+    }
+
+    #[test]
+    fn test_run_loop() {
+        let mut session = Session::new();
+        let mut output = Vec::new();
+        session
+            .run("let x = 4\n{\nx * 2\n}\n:quit\n1\n".as_bytes(), &mut output)
+            .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "> > . . 8\n> ");
+    }
+
+    #[test]
+    fn test_incomplete_input() {
+        assert!(is_incomplete("fn f(x) {"));
+        assert!(is_incomplete("sin(1 + (2"));
+        assert!(!is_incomplete("{ 1 }"));
+        assert!(!is_incomplete("1 + 2)"));
+        assert!(is_incomplete("let x = 1 +"));
+        assert!(!is_incomplete("3!"));
+        assert_eq!(
+            Command::parse(":load  a b.ape "),
+            Ok(Command::Load(String::from("a b.ape")))
+        );
+        assert!(Command::parse(":load").is_err());
+    }
+
+    #[test]
+    fn test_emit_stages() {
+        let source = "fn sq(x) { x * x }\nsq(2 * 3)";
+        let mut session = Session::new();
+        let tokens = session.emit("sq(1)", Emit::Tokens).unwrap();
+        assert_eq!(
+            tokens.lines().collect::<Vec<_>>(),
+            [
+                "0 0..2 Identifier(\"sq\")",
+                "0 2..3 LParen",
+                "0 3..4 Number(1.0)",
+                "0 4..5 RParen",
+            ]
+        );
+        assert_eq!(
+            session.emit(source, Emit::Ast).unwrap(),
+            "fn sq(x) { (x * x) }\nsq((2 * 3))"
+        );
+        assert_eq!(
+            session.emit(source, Emit::Resolved).unwrap(),
+            "fn sq(x) { (x * x) }\n{ let x_0 = 6; (x_0 * x_0) }"
+        );
+        let mut session = Session::new().with_pipeline(Pipeline::level(0).unwrap());
+        assert_eq!(
+            session.emit(source, Emit::Resolved).unwrap(),
+            "fn sq(x) { (x * x) }\nsq((2 * 3))"
+        );
+        assert_eq!(
+            session
+                .emit("fn sq(x) { x * x }\nsq(true)", Emit::Resolved)
+                .unwrap_err(),
+            "Type error: expected num, found bool @ 22"
+        );
+        assert_eq!(Emit::from_name("asm"), Some(Emit::Asm));
+        assert_eq!(Emit::from_name("llvm"), Some(Emit::Llvm));
+        assert_eq!(Emit::from_name("wasm"), None);
+    }
+}
@@ -0,0 +1,125 @@
+#[cfg(test)]
+mod tests {
+    use crate::ast::parser::Parser;
+    use crate::semantic::error::SemanticError;
+    use crate::semantic::error::SemanticErrorKind;
+    use crate::semantic::resolver::DeclKind;
+    use crate::semantic::resolver::Resolved;
+    use crate::tokeniser::token_enum::Span;
+    use crate::tokeniser::tokeniser::Tokeniser;
+
+    fn resolve(input: &str) -> Result<Resolved, Vec<SemanticError>> {
+        let tokens = Tokeniser::new(input.to_string()).to_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let stmts = parser.parse_lines().unwrap();
+        parser.resolver().resolve(stmts)
+    }
+
+    /// Every error as its message and the source it points at
+    fn errors(input: &str) -> Vec<(String, &str)> {
+        resolve(input)
+            .unwrap_err()
+            .into_iter()
+            .map(|err| (err.kind.to_string(), err.span.slice(input)))
+            .collect()
+    }
+
+    #[test]
+    fn test_undeclared_names() {
+        assert_eq!(
+            errors("let a = 1\na + b"),
+            [("Undeclared Variable: b".to_string(), "b")]
+        );
+        assert_eq!(errors("g(1)"), [("Undefined Function: g".to_string(), "g")]);
+        assert_eq!(
+            errors("y = 2"),
+            [("Undeclared Variable: y".to_string(), "y")]
+        );
+    }
+
+    #[test]
+    fn test_every_error_is_reported_in_order() {
+        let found: Vec<&str> = errors("fn f(a) { a + q }\nx + f(1, 2)")
+            .into_iter()
+            .map(|(_, src)| src)
+            .collect();
+        assert_eq!(found, ["q", "x", "f"]);
+    }
+
+    #[test]
+    fn test_arity_and_parameters() {
+        assert_eq!(
+            errors("fn f(a, b) { a }\nf(1)")[0].0,
+            "Function f called with wrong # of params, expected 2 got 1"
+        );
+        let input = "fn f(a, b, a) { a }";
+        let err = &resolve(input).unwrap_err()[0];
+        assert_eq!(
+            err.kind,
+            SemanticErrorKind::DuplicateParameter {
+                function: "f".to_string(),
+                param: "a".to_string()
+            }
+        );
+        assert_eq!(err.span, Span::new(11, 12));
+        // A variable may hold any function, its arity is only known at runtime
+        assert!(resolve("fn f(a) { a }\nlet g = f\ng(1, 2)").is_ok());
+    }
+
+    #[test]
+    fn test_assign_to_const() {
+        assert_eq!(
+            errors("const c = 1\n{ c = 2 }"),
+            [("Cannot assign to const: c".to_string(), "c")]
+        );
+    }
+
+    #[test]
+    fn test_names_bind_to_their_declaration() {
+        let input = "let x = 1\n{ let x = 2\nx }\nx + pi";
+        let resolved = resolve(input).unwrap();
+        let decl_at = |offset: usize, len: usize| {
+            let decl = resolved
+                .resolution
+                .lookup(Span::new(offset, offset + len))
+                .unwrap();
+            (decl.kind, decl.span.map(|span| span.start))
+        };
+        assert_eq!(decl_at(22, 1), (DeclKind::Let, Some(16)));
+        assert_eq!(decl_at(26, 1), (DeclKind::Let, Some(4)));
+        assert_eq!(decl_at(30, 2), (DeclKind::Constant, None));
+    }
+
+    #[test]
+    fn test_function_scoping() {
+        // Bodies see functions and globals defined after them
+        assert!(resolve("fn even(n) { if n == 0 { true } else { odd(n - 1) } }\nfn odd(n) { if n == 0 { false } else { even(n - 1) } }\nlet limit = 3\neven(4)").is_ok());
+        assert!(resolve("fn f() { limit }\nlet limit = 1\nf()").is_ok());
+        // but not the locals around their definition
+        assert_eq!(errors("{ let a = 1\nfn f() { a } }")[0].1, "a");
+        // and top level code can't call a function before defining it
+        assert_eq!(errors("f()\nfn f() { 1 }")[0].1, "f");
+    }
+
+    #[test]
+    fn test_session_names_are_known() {
+        let tokens = Tokeniser::new("let x = 1\nconst y = 2\nfn f(a) { a }".to_string())
+            .to_tokens()
+            .unwrap();
+        let mut parser = Parser::new(tokens);
+        let stmts = parser.parse_lines().unwrap();
+        parser.evaluate(&stmts);
+
+        let tokens = Tokeniser::new("x = f(y)\ny = 1".to_string())
+            .to_tokens()
+            .unwrap();
+        parser.feed(tokens);
+        let stmts = parser.parse_lines().unwrap();
+        let errs = parser.resolver().resolve(stmts).unwrap_err();
+        assert_eq!(errs.len(), 1);
+        assert_eq!(
+            errs[0].kind,
+            SemanticErrorKind::AssignToConst("y".to_string())
+        );
+    }
+}
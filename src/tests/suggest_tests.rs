@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod tests {
+    use crate::ast::parser::Parser;
+    use crate::repl::session::Response;
+    use crate::repl::session::Session;
+    use crate::semantic::suggest::edit_distance;
+    use crate::semantic::suggest::suggest;
+    use crate::tokeniser::tokeniser::Tokeniser;
+
+    /// Suggestion attached to the first resolver error
+    fn suggestion(input: &str) -> Option<String> {
+        let tokens = Tokeniser::new(input.to_string()).to_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let stmts = parser.parse_lines().unwrap();
+        parser.resolver().resolve(stmts).unwrap_err()[0]
+            .suggestion
+            .clone()
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("sqr", "sqrt"), 1);
+        assert_eq!(edit_distance("tset", "test"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
+
+    #[test]
+    fn test_suggest_picks_the_closest() {
+        assert_eq!(
+            suggest("sinn", ["sin", "sinh", "tan"]),
+            Some("sin".to_string())
+        );
+        assert_eq!(
+            suggest("lenght", ["length", "height"]),
+            Some("length".to_string())
+        );
+        assert_eq!(suggest("x", ["y", "e"]), None);
+        assert_eq!(suggest("total", ["sum", "count"]), None);
+    }
+
+    #[test]
+    fn test_suggestions_for_functions() {
+        assert_eq!(suggestion("sqr(4)"), Some("sqrt".to_string()));
+        assert_eq!(
+            suggestion("fn test(a, b) { a - b }\ntset(5, 2)"),
+            Some("test".to_string())
+        );
+        assert_eq!(
+            suggestion("fn f() { helpr() }\nfn helper() { 1 }"),
+            Some("helper".to_string())
+        );
+        assert_eq!(suggestion("nothinglike(1)"), None);
+    }
+
+    #[test]
+    fn test_suggestions_for_variables() {
+        assert_eq!(
+            suggestion("let radius = 2\nradus * 2"),
+            Some("radius".to_string())
+        );
+        assert_eq!(
+            suggestion("let total = 1\n{ totl = 2 }"),
+            Some("total".to_string())
+        );
+        assert_eq!(
+            suggestion("fn square(v) { v * v }\nsquar"),
+            Some("square".to_string())
+        );
+        assert_eq!(suggestion("pii * 2"), Some("pi".to_string()));
+    }
+
+    #[test]
+    fn test_session_suggestions() {
+        let mut session = Session::new();
+        session.submit("fn test(a, b) { a - b }");
+        assert_eq!(
+            session.submit("tset(5, 2)"),
+            Response::Error(String::from(
+                "Undefined Function: tset (did you mean test?) @ 0"
+            ))
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use crate::ast::parser::Parser;
+use crate::ir::ir::Module;
+use crate::ir::lower::lower;
+use crate::ir::verify::verify;
+use crate::optimiser::pipeline::Pipeline;
+use crate::repl::session::Session;
+use crate::semantic::resolver::DeclId;
+use crate::semantic::resolver::Resolved;
+use crate::semantic::typeck::Type;
+use crate::semantic::typeck::TypeChecker;
+use crate::tokeniser::tokeniser::Tokeniser;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+/// What the tree-walker makes of the program, its value or the error it
+/// stopped with as compiled programs report them
+pub fn walked(input: &str) -> String {
+    let tokens = Tokeniser::new(input.to_string()).to_tokens().unwrap();
+    let stmts = Parser::new(tokens).parse_lines().unwrap();
+    match Parser::new(Vec::new()).evaluate(&stmts).into_result() {
+        Ok(val) => val.map_or(String::from("()"), |val| val.to_string()),
+        Err(err) => format!("error: {err}"),
+    }
+}
+
+/// The program checked and optimised at `level` as a script, `None` if it
+/// doesn't check
+pub fn checked(input: &str, level: u8) -> Option<(Resolved, HashMap<DeclId, Type>)> {
+    let tokens = Tokeniser::new(input.to_string()).to_tokens().unwrap();
+    let stmts = Parser::new(tokens).parse_lines().unwrap();
+    let resolved = Parser::new(Vec::new()).resolver().resolve(stmts).ok()?;
+    let types = TypeChecker::new().check(&resolved).ok()?;
+    let mut pipeline = Pipeline::level(level).unwrap();
+    pipeline.keep_globals = false;
+    Some((pipeline.run(resolved), types))
+}
+
+/// The program lowered after optimising at `level`, `None` if it doesn't
+/// check or lower
+pub fn lowered(input: &str, level: u8) -> Option<Module> {
+    let (resolved, types) = checked(input, level)?;
+    let module = lower(&resolved, &types).ok()?;
+    verify(&module).unwrap();
+    Some(module)
+}
+
+/// Numbers agree when they read back the same, `%g` writes large ones with
+/// an exponent where the interpreter doesn't
+pub fn same_output(found: &str, expected: &str) -> bool {
+    match (found.parse::<f64>(), expected.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan()),
+        _ => found == expected,
+    }
+}
+
+pub fn session(level: u8) -> Session {
+    Session::new().with_pipeline(Pipeline::level(level).unwrap())
+}
+
+/// A session optimising at `level` as it does for a script, removing
+/// whatever the script doesn't use
+pub fn script_session(level: u8) -> Session {
+    let mut pipeline = Pipeline::level(level).unwrap();
+    pipeline.keep_globals = false;
+    Session::new().with_pipeline(pipeline)
+}
+
+/// Whether native code runs here, it's only generated for x86-64 Linux
+pub fn can_run() -> bool {
+    cfg!(all(target_os = "linux", target_arch = "x86_64"))
+}
+
+/// Whether there's a C compiler to assemble and link with, the tests
+/// needing one skip without it
+pub fn has_cc() -> bool {
+    Command::new("cc")
+        .arg("--version")
+        .output()
+        .is_ok_and(|out| out.status.success())
+}
+
+/// A path in the temporary directory no other test uses, ending in `suffix`
+pub fn temp_path(suffix: &str) -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "ape-{0}-{1}{suffix}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ))
+}
@@ -0,0 +1,75 @@
+#[cfg(test)]
+mod tests {
+    use crate::tokeniser::token_enum::Span;
+    use crate::tokeniser::token_enum::TokenKind;
+    use crate::tokeniser::tokeniser::Tokeniser;
+
+    fn kinds(input: &str) -> Vec<TokenKind> {
+        Tokeniser::new(input.to_string())
+            .to_tokens()
+            .unwrap()
+            .into_iter()
+            .map(|tok| tok.kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_token_kinds() {
+        assert_eq!(
+            kinds("let x = sin(2.5) != 3!"),
+            [
+                TokenKind::Let,
+                TokenKind::Identifier(String::from("x")),
+                TokenKind::Assign,
+                TokenKind::Identifier(String::from("sin")),
+                TokenKind::LParen,
+                TokenKind::Number(2.5),
+                TokenKind::RParen,
+                TokenKind::BangEqual,
+                TokenKind::Number(3.0),
+                TokenKind::Bang,
+            ]
+        );
+        assert_eq!(
+            kinds("a<=b>=c==d<e>f"),
+            [
+                TokenKind::Identifier(String::from("a")),
+                TokenKind::LessEqual,
+                TokenKind::Identifier(String::from("b")),
+                TokenKind::GreaterEqual,
+                TokenKind::Identifier(String::from("c")),
+                TokenKind::EqualEqual,
+                TokenKind::Identifier(String::from("d")),
+                TokenKind::Less,
+                TokenKind::Identifier(String::from("e")),
+                TokenKind::Greater,
+                TokenKind::Identifier(String::from("f")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spans_and_lines() {
+        let tokens = Tokeniser::new(String::from("fn f() {\n  1.25 + pi\n}"))
+            .to_tokens()
+            .unwrap();
+        let number = &tokens[5];
+        assert_eq!(number.kind, TokenKind::Number(1.25));
+        assert_eq!(number.span, Span::new(11, 15));
+        assert_eq!(number.line_number, 1);
+        assert_eq!(tokens.last().unwrap().line_number, 2);
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(
+            kinds("0.1+0.2"),
+            [
+                TokenKind::Number(0.1),
+                TokenKind::Plus,
+                TokenKind::Number(0.2)
+            ]
+        );
+        assert!(Tokeniser::new(String::from("1.2.3")).to_tokens().is_err());
+    }
+}
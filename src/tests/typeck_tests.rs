@@ -0,0 +1,199 @@
+#[cfg(test)]
+mod tests {
+    use crate::ast::parser::Parser;
+    use crate::ast::value::Value;
+    use crate::repl::session::Response;
+    use crate::repl::session::Session;
+    use crate::semantic::typeck::TypeChecker;
+    use crate::tokeniser::tokeniser::Tokeniser;
+
+    /// Each name paired with its type, or each error with the source it points at
+    type Inferred<'a> = Result<Vec<(String, String)>, Vec<(String, &'a str)>>;
+
+    /// Inferred type of every declaration in source order
+    fn infer(input: &str) -> Inferred<'_> {
+        let tokens = Tokeniser::new(input.to_string()).to_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let stmts = parser.parse_lines().unwrap();
+        let resolved = parser.resolver().resolve(stmts).unwrap();
+        match TypeChecker::new().check(&resolved) {
+            Ok(types) => {
+                let mut decls: Vec<_> = types
+                    .into_iter()
+                    .map(|(id, ty)| (&resolved.resolution.declarations[id], ty))
+                    .collect();
+                decls.sort_by_key(|(decl, _)| decl.span.unwrap().start);
+                Ok(decls
+                    .into_iter()
+                    .map(|(decl, ty)| (decl.name.clone(), ty.to_string()))
+                    .collect())
+            }
+            Err(errs) => Err(errs
+                .into_iter()
+                .map(|err| (err.kind.to_string(), err.span.slice(input)))
+                .collect()),
+        }
+    }
+
+    fn type_of(input: &str, name: &str) -> String {
+        infer(input)
+            .unwrap()
+            .into_iter()
+            .find(|(decl, _)| decl == name)
+            .unwrap()
+            .1
+    }
+
+    fn first_error(input: &str) -> (String, &str) {
+        infer(input).unwrap_err().remove(0)
+    }
+
+    #[test]
+    fn test_infers_let_bindings() {
+        assert_eq!(type_of("let x = 1", "x"), "num");
+        assert_eq!(type_of("let b = 1 < 2", "b"), "bool");
+        assert_eq!(type_of("let u = {}", "u"), "unit");
+        assert_eq!(type_of("let c = if true { 1 } else { 2 }", "c"), "num");
+    }
+
+    #[test]
+    fn test_infers_functions() {
+        assert_eq!(type_of("fn sq(x) { x * x }", "sq"), "fn(num) -> num");
+        assert_eq!(
+            type_of(
+                "fn fact(n) { if n < 1 { 1 } else { n * fact(n - 1) } }",
+                "fact"
+            ),
+            "fn(num) -> num"
+        );
+        assert_eq!(
+            type_of("fn apply(f, x) { f(x) }", "apply"),
+            "fn(fn('a) -> 'b, 'a) -> 'b"
+        );
+        assert_eq!(
+            type_of("fn pos(a) { if a > 0 { return true }\nfalse }", "pos"),
+            "fn(num) -> bool"
+        );
+    }
+
+    #[test]
+    fn test_functions_are_generic() {
+        let input = "fn id(a) { a }\nlet n = id(1)\nlet b = id(true)";
+        assert_eq!(type_of(input, "id"), "fn('a) -> 'a");
+        assert_eq!(type_of(input, "n"), "num");
+        assert_eq!(type_of(input, "b"), "bool");
+    }
+
+    #[test]
+    fn test_annotations() {
+        assert_eq!(type_of("fn f(x: num) -> num { x }", "f"), "fn(num) -> num");
+        assert_eq!(type_of("fn f(x: bool) { x }", "f"), "fn(bool) -> bool");
+        assert_eq!(
+            type_of("fn f(g: fn(num) -> bool) { g(1) }", "f"),
+            "fn(fn(num) -> bool) -> bool"
+        );
+        assert_eq!(
+            first_error("fn f(x: bool) -> num { x }"),
+            ("Type error: expected num, found bool".to_string(), "x")
+        );
+    }
+
+    #[test]
+    fn test_type_errors_have_spans() {
+        assert_eq!(
+            first_error("1 + true"),
+            ("Type error: expected num, found bool".to_string(), "true")
+        );
+        assert_eq!(
+            first_error("if 1 { 2 } else { 3 }"),
+            ("Type error: expected bool, found num".to_string(), "1")
+        );
+        assert_eq!(
+            first_error("if true { 1 } else { false }"),
+            (
+                "Type error: expected num, found bool".to_string(),
+                "{ false }"
+            )
+        );
+        assert_eq!(
+            first_error("if true { 1 }"),
+            ("Type error: expected unit, found num".to_string(), "{ 1 }")
+        );
+        assert_eq!(
+            first_error("fn sq(x) { x * x }\nsq(1 < 2)"),
+            ("Type error: expected num, found bool".to_string(), "1 < 2")
+        );
+        assert_eq!(
+            first_error("let g = 1\ng(2)"),
+            (
+                "Type error: expected num, found fn(num) -> 'a".to_string(),
+                "g(2)"
+            )
+        );
+        assert_eq!(
+            first_error("fn f(g) { g(g) }").0,
+            "Type error: infinite type 'a = fn('a) -> 'b"
+        );
+    }
+
+    #[test]
+    fn test_every_error_is_reported() {
+        let errs = infer("let a = 1 + true\nlet b = -false").unwrap_err();
+        assert_eq!(errs.len(), 2);
+        // Globals read by a function are checked against their later definition
+        assert_eq!(
+            first_error("fn f() { limit + 1 }\nlet limit = true").1,
+            "true"
+        );
+    }
+
+    #[test]
+    fn test_session_remembers_types() {
+        let mut session = Session::new();
+        assert_eq!(session.submit("fn sq(x) { x * x }"), Response::Nothing);
+        assert_eq!(
+            session.submit("sq(true)"),
+            Response::Error(String::from("Type error: expected num, found bool @ 3"))
+        );
+        session.submit("let flag = true");
+        assert!(matches!(session.submit("flag + 1"), Response::Error(_)));
+        assert!(matches!(session.submit("sq(2)"), Response::Value(_)));
+    }
+
+    #[test]
+    fn test_redefinitions_keep_callers_checked() {
+        let mut session = Session::new();
+        session.submit("fn f(a) { a + 1 }");
+        session.submit("fn g() { f(1) }");
+        assert_eq!(
+            session.submit("fn f(a) { a == 1 }"),
+            Response::Error(String::from(
+                "Type error: f was defined as fn(num) -> num, it can't be redefined as fn(num) -> bool @ 3"
+            ))
+        );
+        assert!(matches!(session.submit("g() + 1"), Response::Value(_)));
+        assert_eq!(session.submit("fn f(b) { b * 2 }"), Response::Nothing);
+
+        // A generic function can be narrowed, callers using it at other
+        // types meet the runtime checks
+        session.submit("fn id(a) { a }");
+        session.submit("fn yes() { id(true) }");
+        assert_eq!(session.submit("fn id(a) { a + 0 }"), Response::Nothing);
+        assert!(matches!(session.submit("yes()"), Response::Error(_)));
+        session.submit("fn k(a, b) { a + b }");
+        assert_eq!(session.submit("fn k(a, b) { b }"), Response::Nothing);
+        assert!(matches!(
+            session.submit("fn k(a) { a }"),
+            Response::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_functions_never_defined_keep_no_type() {
+        let mut session = Session::new();
+        assert!(session.eval_source("1 / 0\nfn f(a) { a > 1 }").is_err());
+        assert_eq!(session.submit(":funcs"), Response::Output(String::new()));
+        assert_eq!(session.submit("fn f(a) { a + 1 }"), Response::Nothing);
+        assert_eq!(session.submit("f(2)"), Response::Value(Value::Number(3.0)));
+    }
+}
@@ -0,0 +1,201 @@
+#[cfg(test)]
+mod tests {
+    use crate::ast::ast::Stmt;
+    use crate::ast::evaluation::Evaluation;
+    use crate::ast::evaluation::Outcome;
+    use crate::ast::parser::Parser;
+    use crate::optimiser::pipeline::Pipeline;
+    use crate::repl::session::Response;
+    use crate::repl::session::Session;
+    use crate::tests::generator::Generator;
+    use crate::tokeniser::tokeniser::Tokeniser;
+    use crate::vm::compiler::compile;
+    use crate::vm::vm::Vm;
+
+    fn parse(input: &str) -> Vec<Stmt> {
+        let tokens = Tokeniser::new(input.to_string()).to_tokens().unwrap();
+        Parser::new(tokens)
+            .parse_lines()
+            .unwrap_or_else(|err| panic!("{err} in {input}"))
+    }
+
+    /// What every statement produced, function values compare by how they
+    /// print since each backend makes its own
+    fn describe(evaluation: Evaluation) -> Vec<String> {
+        evaluation
+            .results
+            .into_iter()
+            .map(|res| {
+                let outcome = match res.outcome {
+                    Outcome::Value(val) => val.to_string(),
+                    Outcome::Unit => String::from("nothing"),
+                    Outcome::Error(err) => format!("error: {err}"),
+                };
+                format!("{0}..{1} {outcome}", res.span.start, res.span.end)
+            })
+            .collect()
+    }
+
+    fn walked(stmts: &[Stmt]) -> Vec<String> {
+        describe(Parser::new(Vec::new()).evaluate(stmts))
+    }
+
+    fn compiled(stmts: &[Stmt]) -> Vec<String> {
+        describe(Vm::new().evaluate(&compile(stmts)))
+    }
+
+    /// The tree-walker and the virtual machine agree on the program as
+    /// written and, when it resolves, after every optimisation level
+    fn assert_agree(input: &str) {
+        let stmts = parse(input);
+        assert_eq!(compiled(&stmts), walked(&stmts), "{input}");
+
+        let Ok(resolved) = Parser::new(Vec::new()).resolver().resolve(stmts) else {
+            return;
+        };
+        for level in 0..=3 {
+            let mut pipeline = Pipeline::level(level).unwrap();
+            pipeline.keep_globals = false;
+            let stmts = pipeline.run(resolved.clone()).stmts;
+            assert_eq!(compiled(&stmts), walked(&stmts), "-O{level} {input}");
+        }
+    }
+
+    #[test]
+    fn test_vm_runs_programs() {
+        let stmts = parse("fn fib(n) { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }\nfib(20)");
+        assert_eq!(compiled(&stmts), ["0..61 nothing", "62..69 6765"]);
+        let stmts = parse("let x = 1\n{ let x = 2; x } + x\nx = 3\nx / 0\nx");
+        assert_eq!(
+            compiled(&stmts),
+            [
+                "0..9 nothing",
+                "10..30 3",
+                "31..36 nothing",
+                "37..42 error: Division by zero"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expressions_agree() {
+        for input in [
+            "1 + 2 * 3 - 4 / 5",
+            "2 ^ 3 ^ 2 % 7",
+            "-0 + -0\n-0 * 1",
+            "~3 + 4! + -(2)",
+            "(1 < 2) == (3 >= 4)\n1 != 1\ntrue == true",
+            "sin(pi / 2) + cos(0) + log(100) + log(8, 2) + sqrt(2) + exp(1)",
+            "floor(2.5) + ceil(2.5) + round(2.5) + abs(-3) + atan(1) + tanh(1)",
+            "0 / 0",
+            "{}\n{ 1; 2 }\n{ let x = 1 }",
+            "if 1 < 2 { 1 } else { 2 }\nif false { 1 }\nif false { 1 } else if true { 2 } else { 3 }",
+        ] {
+            assert_agree(input);
+        }
+    }
+
+    #[test]
+    fn test_scopes_agree() {
+        for input in [
+            "let x = 1\n{ let x = x + 1; { let x = x * 10; x } + x } + x",
+            "let x = 1\n{ x = x + 1; { x = x * 10 } }\nx",
+            "let x = 1\nconst y = 2\nlet x = x + y\nx",
+            "let a = 1\n{ let b = 2; { let c = 3; a + b + c } }",
+            "{ let x = 1; let x = x + 1; x }",
+            "let x = 5\nfn f() { x }\n{ let x = 1; f() }",
+            "let x = 5\nfn f() { x = x + 1 }\nf()\nf()\nx",
+            "let ans = 3\nans * 2",
+        ] {
+            assert_agree(input);
+        }
+    }
+
+    #[test]
+    fn test_functions_agree() {
+        for input in [
+            "fn fact(n) { if n < 1 { 1 } else { n * fact(n - 1) } }\nfact(10)",
+            "fn even(n) { if n == 0 { true } else { odd(n - 1) } }\nfn odd(n) { if n == 0 { false } else { even(n - 1) } }\neven(10) == odd(7)",
+            "fn outer(x) { fn inner(y) { y * 2 }\ninner(x) + 1 }\nouter(3)\ninner(4)",
+            "fn apply(g, x) { g(x) }\nfn sq(x) { x * x }\napply(sq, 3)",
+            "fn sq(x) { x * x }\nlet g = sq\ng(4)\ng == sq\nsq\ng",
+            "fn f() { 1 }\nlet g = f\nfn f() { 2 }\ng() + f()\ng == f",
+            "fn f(x, x) { x }\nf(1, 2)",
+            "fn f(x) { x = x + 1; x }\nlet y = 1\nf(y) + y",
+            "fn f(x) { return x * 2; x }\nf(3)",
+            "fn f(x) { if x > 0 { return 1 } else { return -1 } }\nf(2) + f(-2)",
+            "fn f() { { { return 5 } } }\nf()",
+            "fn f() { return }\nf()",
+            "fn f() { let t = 2 }\nf()",
+            "fn f(n) { 1 + { if n > 0 { return n } else { 0 } } }\nf(3) + f(0)",
+            "fn pi() { 3 }\npi()\npi",
+        ] {
+            assert_agree(input);
+        }
+    }
+
+    #[test]
+    fn test_runtime_errors_agree() {
+        for input in [
+            "1 / 0\n2",
+            "let x = 1\nx = 2 / 0\nx",
+            "fn g() { f(1) }\ng()\nfn f(x) { x }",
+            "fn g() { y }\ng()\nlet y = 1\ng()",
+            "fn g() { sq }\nfn h() { fn sq(x) { x * x } }\ng()\nh()\ng()",
+            "const x = 1\nfn f() { x = 2 }\nf()\nlet x = 5",
+            "{ const c = 1; c = 2 }",
+            "x = 1",
+            "1 + true",
+            "-true",
+            "if 1 { 2 }",
+            "let n = 1\nn(2)",
+            "fn f(x) { x }\nlet g = f\ng(1, 2)",
+            "fn f(x) { x }\nf()",
+            "log(true, 1 / 0)",
+            "log(1 / 0, true)",
+            "sin(true)",
+            "fn f() { 1 }\nf + 1",
+            "fn f() { 1 }\nf == 1",
+            "{} == 1",
+            "missing(1 / 0)",
+            "let g = 1\n{ let g = 2; g(1 / 0) }",
+            "undefined + 1",
+        ] {
+            assert_agree(input);
+        }
+    }
+
+    #[test]
+    fn test_generated_programs_agree() {
+        let mut generator = Generator::new(42);
+        for _ in 0..300 {
+            assert_agree(&generator.program());
+        }
+    }
+
+    #[test]
+    fn test_session_on_vm() {
+        let mut session = Session::new().with_vm();
+        assert_eq!(session.submit("let r = 3"), Response::Nothing);
+        assert_eq!(session.submit("fn sq(v) { v * v }"), Response::Nothing);
+        assert_eq!(session.submit("sq(r)"), Response::Value(9.0.into()));
+        assert_eq!(session.submit("ans + 1"), Response::Value(10.0.into()));
+        assert_eq!(
+            session.submit("sq(true)"),
+            Response::Error(String::from("Type error: expected num, found bool @ 3"))
+        );
+        assert_eq!(
+            session.submit(":vars"),
+            Response::Output(String::from("ans = 10\nr = 3"))
+        );
+        assert_eq!(
+            session.submit(":funcs"),
+            Response::Output(String::from("sq(v)"))
+        );
+        assert_eq!(session.submit(":reset"), Response::Nothing);
+        assert_eq!(
+            session.submit("r"),
+            Response::Error(String::from("Undeclared Variable: r @ 0"))
+        );
+    }
+}
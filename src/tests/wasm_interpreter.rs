@@ -0,0 +1,921 @@
+use std::collections::HashMap;
+
+/// Calls nested deeper than this trap rather than overflow the test's stack
+const MAX_DEPTH: usize = 256;
+const PAGE: usize = 0x10000;
+
+/// A WebAssembly value, only the types the wasm backend uses
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Val {
+    I32(i32),
+    F64(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValType {
+    I32,
+    F64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct FuncType {
+    params: Vec<ValType>,
+    results: Vec<ValType>,
+}
+
+/// A decoded instruction, blocks know where they end so branches needn't
+/// search for it
+#[derive(Debug, Clone, PartialEq)]
+enum Instr {
+    Unreachable,
+    Nop,
+    Block(Option<ValType>, usize),
+    Loop(Option<ValType>),
+    /// Result type, the `else` if there is one and the `end`
+    If(Option<ValType>, Option<usize>, usize),
+    Else(usize),
+    End,
+    Br(u32),
+    BrIf(u32),
+    BrTable(Vec<u32>, u32),
+    Return,
+    Call(u32),
+    Drop,
+    Select,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    I32Const(i32),
+    F64Const(f64),
+    /// Any of the numeric instructions, by opcode
+    Numeric(u8),
+}
+
+struct Body {
+    locals: Vec<ValType>,
+    code: Vec<Instr>,
+}
+
+struct Global {
+    ty: ValType,
+    mutable: bool,
+    init: Val,
+}
+
+/// A decoded module that passed validation
+pub struct WasmModule {
+    types: Vec<FuncType>,
+    /// Module, name and type of each imported function
+    imports: Vec<(String, String, u32)>,
+    /// Type of each function the module defines
+    funcs: Vec<u32>,
+    bodies: Vec<Body>,
+    /// Initial pages of the memory, if there is one
+    memory: Option<u32>,
+    globals: Vec<Global>,
+    /// Kind, 0 for functions and 2 for memory, and index by name
+    exports: HashMap<String, (u8, u32)>,
+    data: Vec<(u32, Vec<u8>)>,
+}
+
+impl WasmModule {
+    /// Module and name of every imported function
+    pub fn imports(&self) -> Vec<(&str, &str)> {
+        self.imports
+            .iter()
+            .map(|(module, name, _)| (module.as_str(), name.as_str()))
+            .collect()
+    }
+    /// Names of the exported functions
+    pub fn exported_functions(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .exports
+            .iter()
+            .filter(|(_, &(kind, _))| kind == 0)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        names.sort();
+        names
+    }
+    fn func_type(&self, func: u32) -> Option<&FuncType> {
+        let func = func as usize;
+        let ty = match self.imports.get(func) {
+            Some(&(_, _, ty)) => ty,
+            None => *self.funcs.get(func - self.imports.len())?,
+        };
+        self.types.get(ty as usize)
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn done(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self
+            .bytes
+            .get(self.pos)
+            .ok_or_else(|| String::from("Unexpected end of module"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len());
+        let end = end.ok_or_else(|| String::from("Unexpected end of module"))?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+    fn u32(&mut self) -> Result<u32, String> {
+        let mut val: u64 = 0;
+        for shift in 0..5 {
+            let byte = self.byte()?;
+            val |= ((byte & 0x7F) as u64) << (7 * shift);
+            if byte & 0x80 == 0 {
+                return u32::try_from(val).map_err(|_| String::from("Integer too large"));
+            }
+        }
+        Err(String::from("Integer representation too long"))
+    }
+    fn i32(&mut self) -> Result<i32, String> {
+        let mut val: i64 = 0;
+        for shift in 0..5 {
+            let byte = self.byte()?;
+            val |= ((byte & 0x7F) as i64) << (7 * shift);
+            if byte & 0x80 == 0 {
+                if byte & 0x40 != 0 {
+                    val |= -1 << (7 * (shift + 1));
+                }
+                return i32::try_from(val).map_err(|_| String::from("Integer too large"));
+            }
+        }
+        Err(String::from("Integer representation too long"))
+    }
+    fn f64(&mut self) -> Result<f64, String> {
+        let bytes = self.take(8)?;
+        Ok(f64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+    fn name(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| String::from("Malformed UTF-8 name"))
+    }
+    fn vec<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> Result<T, String>,
+    ) -> Result<Vec<T>, String> {
+        let len = self.u32()?;
+        (0..len).map(|_| item(self)).collect()
+    }
+    fn valtype(&mut self) -> Result<ValType, String> {
+        match self.byte()? {
+            0x7F => Ok(ValType::I32),
+            0x7C => Ok(ValType::F64),
+            byte => Err(format!("Unsupported value type 0x{byte:02X}")),
+        }
+    }
+    fn blocktype(&mut self) -> Result<Option<ValType>, String> {
+        match self.bytes.get(self.pos) {
+            Some(0x40) => {
+                self.pos += 1;
+                Ok(None)
+            }
+            _ => self.valtype().map(Some),
+        }
+    }
+    /// A constant expression, one `const` then `end`
+    fn constant(&mut self) -> Result<Val, String> {
+        let val = match self.byte()? {
+            0x41 => Val::I32(self.i32()?),
+            0x44 => Val::F64(self.f64()?),
+            op => return Err(format!("Unsupported constant expression opcode 0x{op:02X}")),
+        };
+        match self.byte()? {
+            0x0B => Ok(val),
+            _ => Err(String::from("Constant expression isn't a single constant")),
+        }
+    }
+    /// A function body's code up to and including its final `end`
+    fn code(&mut self) -> Result<Vec<Instr>, String> {
+        let mut code = Vec::new();
+        // Blocks, loops and ifs not yet closed
+        let mut open: Vec<usize> = Vec::new();
+        loop {
+            let at = code.len();
+            let instr = match self.byte()? {
+                0x00 => Instr::Unreachable,
+                0x01 => Instr::Nop,
+                0x02 => {
+                    open.push(at);
+                    Instr::Block(self.blocktype()?, 0)
+                }
+                0x03 => {
+                    open.push(at);
+                    Instr::Loop(self.blocktype()?)
+                }
+                0x04 => {
+                    open.push(at);
+                    Instr::If(self.blocktype()?, None, 0)
+                }
+                0x05 => {
+                    let Some(Instr::If(_, other, _)) = open.last().map(|&idx| &mut code[idx])
+                    else {
+                        return Err(String::from("else outside an if"));
+                    };
+                    if other.is_some() {
+                        return Err(String::from("if has two elses"));
+                    }
+                    *other = Some(at);
+                    Instr::Else(0)
+                }
+                0x0B => {
+                    match open.pop() {
+                        Some(start) => match &mut code[start] {
+                            Instr::Block(_, end) => *end = at,
+                            Instr::If(_, other, end) => {
+                                *end = at;
+                                if let Some(other) = *other {
+                                    code[other] = Instr::Else(at);
+                                }
+                            }
+                            _ => {}
+                        },
+                        None => {
+                            code.push(Instr::End);
+                            return Ok(code);
+                        }
+                    }
+                    Instr::End
+                }
+                0x0C => Instr::Br(self.u32()?),
+                0x0D => Instr::BrIf(self.u32()?),
+                0x0E => {
+                    let labels = self.vec(Reader::u32)?;
+                    Instr::BrTable(labels, self.u32()?)
+                }
+                0x0F => Instr::Return,
+                0x10 => Instr::Call(self.u32()?),
+                0x1A => Instr::Drop,
+                0x1B => Instr::Select,
+                0x20 => Instr::LocalGet(self.u32()?),
+                0x21 => Instr::LocalSet(self.u32()?),
+                0x22 => Instr::LocalTee(self.u32()?),
+                0x23 => Instr::GlobalGet(self.u32()?),
+                0x24 => Instr::GlobalSet(self.u32()?),
+                0x41 => Instr::I32Const(self.i32()?),
+                0x44 => Instr::F64Const(self.f64()?),
+                op if numeric(op).is_some() => Instr::Numeric(op),
+                op => return Err(format!("Unsupported opcode 0x{op:02X}")),
+            };
+            code.push(instr);
+        }
+    }
+}
+
+/// Operand types and result of a numeric instruction
+fn numeric(op: u8) -> Option<(Vec<ValType>, ValType)> {
+    use ValType::F64;
+    use ValType::I32;
+    Some(match op {
+        // eqz
+        0x45 => (vec![I32], I32),
+        // eq, ne
+        0x46 | 0x47 => (vec![I32, I32], I32),
+        // eq, ne, lt, gt, le, ge
+        0x61..=0x66 => (vec![F64, F64], I32),
+        // abs, neg, ceil, floor, trunc, nearest, sqrt
+        0x99..=0x9F => (vec![F64], F64),
+        // add, sub, mul, div
+        0xA0..=0xA3 => (vec![F64, F64], F64),
+        _ => return None,
+    })
+}
+
+/// Decode a binary module and check it's valid as a WebAssembly engine
+/// would before running it
+pub fn validate(bytes: &[u8]) -> Result<WasmModule, String> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.take(4)? != b"\0asm" {
+        return Err(String::from("Missing magic number"));
+    }
+    if reader.take(4)? != [1, 0, 0, 0] {
+        return Err(String::from("Unknown binary version"));
+    }
+    let mut module = WasmModule {
+        types: Vec::new(),
+        imports: Vec::new(),
+        funcs: Vec::new(),
+        bodies: Vec::new(),
+        memory: None,
+        globals: Vec::new(),
+        exports: HashMap::new(),
+        data: Vec::new(),
+    };
+    let mut last = 0;
+    while !reader.done() {
+        let id = reader.byte()?;
+        let len = reader.u32()? as usize;
+        let mut section = Reader {
+            bytes: reader.take(len)?,
+            pos: 0,
+        };
+        if id != 0 {
+            if id <= last {
+                return Err(format!("Section {id} is out of order"));
+            }
+            last = id;
+        }
+        match id {
+            0 => continue,
+            1 => {
+                module.types = section.vec(|reader| {
+                    if reader.byte()? != 0x60 {
+                        return Err(String::from("Malformed function type"));
+                    }
+                    Ok(FuncType {
+                        params: reader.vec(Reader::valtype)?,
+                        results: reader.vec(Reader::valtype)?,
+                    })
+                })?
+            }
+            2 => {
+                module.imports = section.vec(|reader| {
+                    let (module, name) = (reader.name()?, reader.name()?);
+                    match reader.byte()? {
+                        0x00 => Ok((module, name, reader.u32()?)),
+                        kind => Err(format!("Unsupported import kind {kind}")),
+                    }
+                })?
+            }
+            3 => module.funcs = section.vec(Reader::u32)?,
+            5 => {
+                let memories = section.vec(|reader| match reader.byte()? {
+                    0x00 => reader.u32(),
+                    0x01 => {
+                        let min = reader.u32()?;
+                        match reader.u32()? >= min {
+                            true => Ok(min),
+                            false => Err(String::from("Memory maximum below its minimum")),
+                        }
+                    }
+                    _ => Err(String::from("Malformed limits")),
+                })?;
+                if memories.len() > 1 {
+                    return Err(String::from("Multiple memories"));
+                }
+                module.memory = memories.first().copied();
+            }
+            6 => {
+                module.globals = section.vec(|reader| {
+                    let ty = reader.valtype()?;
+                    let mutable = match reader.byte()? {
+                        0x00 => false,
+                        0x01 => true,
+                        _ => return Err(String::from("Malformed mutability")),
+                    };
+                    let init = reader.constant()?;
+                    Ok(Global { ty, mutable, init })
+                })?
+            }
+            7 => {
+                for (name, kind, index) in
+                    section.vec(|reader| Ok((reader.name()?, reader.byte()?, reader.u32()?)))?
+                {
+                    if module.exports.insert(name.clone(), (kind, index)).is_some() {
+                        return Err(format!("Duplicate export {name}"));
+                    }
+                }
+            }
+            10 => {
+                module.bodies = section.vec(|reader| {
+                    let len = reader.u32()? as usize;
+                    let mut body = Reader {
+                        bytes: reader.take(len)?,
+                        pos: 0,
+                    };
+                    let mut locals = Vec::new();
+                    for (count, ty) in body.vec(|reader| Ok((reader.u32()?, reader.valtype()?)))? {
+                        locals.extend(std::iter::repeat_n(ty, count as usize));
+                    }
+                    let code = body.code()?;
+                    match body.done() {
+                        true => Ok(Body { locals, code }),
+                        false => Err(String::from("Code after a function's end")),
+                    }
+                })?
+            }
+            11 => {
+                module.data = section.vec(|reader| {
+                    if reader.u32()? != 0 {
+                        return Err(String::from("Unsupported data segment"));
+                    }
+                    let Val::I32(offset) = reader.constant()? else {
+                        return Err(String::from("Data offset isn't an i32"));
+                    };
+                    let len = reader.u32()? as usize;
+                    Ok((offset as u32, reader.take(len)?.to_vec()))
+                })?
+            }
+            _ => return Err(format!("Unsupported section {id}")),
+        }
+        if !section.done() {
+            return Err(format!("Section {id} is longer than its contents"));
+        }
+    }
+
+    if module.funcs.len() != module.bodies.len() {
+        return Err(String::from("Function and code section lengths differ"));
+    }
+    for &(_, _, ty) in &module.imports {
+        if ty as usize >= module.types.len() {
+            return Err(format!("Unknown type {ty}"));
+        }
+    }
+    for &ty in &module.funcs {
+        if ty as usize >= module.types.len() {
+            return Err(format!("Unknown type {ty}"));
+        }
+    }
+    for global in &module.globals {
+        let matches = matches!(
+            (global.ty, global.init),
+            (ValType::I32, Val::I32(_)) | (ValType::F64, Val::F64(_))
+        );
+        if !matches {
+            return Err(String::from("Global initialised with the wrong type"));
+        }
+    }
+    let count = (module.imports.len() + module.funcs.len()) as u32;
+    for (name, &(kind, index)) in &module.exports {
+        let known = match kind {
+            0x00 => index < count,
+            0x02 => index == 0 && module.memory.is_some(),
+            _ => false,
+        };
+        if !known {
+            return Err(format!("Export {name} refers to nothing"));
+        }
+    }
+    if !module.data.is_empty() && module.memory.is_none() {
+        return Err(String::from("Data without a memory"));
+    }
+    for idx in 0..module.bodies.len() {
+        let func = module.imports.len() as u32 + idx as u32;
+        check_body(&module, func, &module.bodies[idx])
+            .map_err(|err| format!("Function {func}: {err}"))?;
+    }
+    Ok(module)
+}
+
+/// A block, loop or if being checked
+struct Ctrl {
+    is_loop: bool,
+    is_if: bool,
+    results: Vec<ValType>,
+    height: usize,
+    unreachable: bool,
+}
+
+/// The operand stack of a body being checked, `None` is a value of any
+/// type left by unreachable code
+struct Checker {
+    vals: Vec<Option<ValType>>,
+    ctrls: Vec<Ctrl>,
+}
+
+impl Checker {
+    fn pop(&mut self) -> Result<Option<ValType>, String> {
+        let ctrl = self.ctrls.last().unwrap();
+        if self.vals.len() == ctrl.height {
+            return match ctrl.unreachable {
+                true => Ok(None),
+                false => Err(String::from("Operand stack underflow")),
+            };
+        }
+        Ok(self.vals.pop().unwrap())
+    }
+    fn expect(&mut self, ty: ValType) -> Result<(), String> {
+        match self.pop()? {
+            Some(found) if found != ty => Err(format!("Expected {ty:?}, found {found:?}")),
+            _ => Ok(()),
+        }
+    }
+    fn expect_all(&mut self, types: &[ValType]) -> Result<(), String> {
+        types.iter().rev().try_for_each(|&ty| self.expect(ty))
+    }
+    fn push_all(&mut self, types: &[ValType]) {
+        self.vals.extend(types.iter().map(|&ty| Some(ty)));
+    }
+    fn unreachable(&mut self) {
+        let ctrl = self.ctrls.last_mut().unwrap();
+        self.vals.truncate(ctrl.height);
+        ctrl.unreachable = true;
+    }
+    /// What a branch to the label must carry, nothing for loops
+    fn label(&self, depth: u32) -> Result<Vec<ValType>, String> {
+        let ctrl = self
+            .ctrls
+            .len()
+            .checked_sub(depth as usize + 1)
+            .map(|idx| &self.ctrls[idx])
+            .ok_or_else(|| format!("Unknown label {depth}"))?;
+        Ok(match ctrl.is_loop {
+            true => Vec::new(),
+            false => ctrl.results.clone(),
+        })
+    }
+    fn open(&mut self, is_loop: bool, is_if: bool, result: Option<ValType>) {
+        self.ctrls.push(Ctrl {
+            is_loop,
+            is_if,
+            results: result.into_iter().collect(),
+            height: self.vals.len(),
+            unreachable: false,
+        });
+    }
+    fn close(&mut self) -> Result<Ctrl, String> {
+        let results = self.ctrls.last().unwrap().results.clone();
+        self.expect_all(&results)?;
+        let ctrl = self.ctrls.pop().unwrap();
+        if self.vals.len() != ctrl.height {
+            return Err(String::from(
+                "Values left on the stack at the end of a block",
+            ));
+        }
+        Ok(ctrl)
+    }
+}
+
+fn check_body(module: &WasmModule, func: u32, body: &Body) -> Result<(), String> {
+    let ty = module.func_type(func).unwrap();
+    let locals: Vec<ValType> = ty.params.iter().chain(&body.locals).copied().collect();
+    let local = |idx: u32| {
+        locals
+            .get(idx as usize)
+            .copied()
+            .ok_or_else(|| format!("Unknown local {idx}"))
+    };
+    let mut checker = Checker {
+        vals: Vec::new(),
+        ctrls: vec![Ctrl {
+            is_loop: false,
+            is_if: false,
+            results: ty.results.clone(),
+            height: 0,
+            unreachable: false,
+        }],
+    };
+    for (idx, instr) in body.code.iter().enumerate() {
+        match instr {
+            Instr::Unreachable => checker.unreachable(),
+            Instr::Nop => {}
+            Instr::Block(result, _) => checker.open(false, false, *result),
+            Instr::Loop(result) => checker.open(true, false, *result),
+            Instr::If(result, _, _) => {
+                checker.expect(ValType::I32)?;
+                checker.open(false, true, *result);
+            }
+            Instr::Else(_) => {
+                let ctrl = checker.close()?;
+                checker.ctrls.push(Ctrl {
+                    is_if: false,
+                    unreachable: false,
+                    ..ctrl
+                });
+            }
+            Instr::End => {
+                let ctrl = checker.close()?;
+                if ctrl.is_if && !ctrl.results.is_empty() {
+                    return Err(String::from("if with a result has no else"));
+                }
+                checker.push_all(&ctrl.results);
+                if checker.ctrls.is_empty() && idx + 1 != body.code.len() {
+                    return Err(String::from("Code after a function's end"));
+                }
+            }
+            Instr::Br(depth) => {
+                checker.expect_all(&checker.label(*depth)?)?;
+                checker.unreachable();
+            }
+            Instr::BrIf(depth) => {
+                checker.expect(ValType::I32)?;
+                let label = checker.label(*depth)?;
+                checker.expect_all(&label)?;
+                checker.push_all(&label);
+            }
+            Instr::BrTable(labels, default) => {
+                checker.expect(ValType::I32)?;
+                let arity = checker.label(*default)?;
+                for &depth in labels {
+                    if checker.label(depth)? != arity {
+                        return Err(String::from("br_table targets disagree on types"));
+                    }
+                }
+                checker.expect_all(&arity)?;
+                checker.unreachable();
+            }
+            Instr::Return => {
+                checker.expect_all(&ty.results)?;
+                checker.unreachable();
+            }
+            Instr::Call(callee) => {
+                let callee = module
+                    .func_type(*callee)
+                    .ok_or_else(|| format!("Unknown function {callee}"))?;
+                checker.expect_all(&callee.params)?;
+                checker.push_all(&callee.results);
+            }
+            Instr::Drop => {
+                checker.pop()?;
+            }
+            Instr::Select => {
+                checker.expect(ValType::I32)?;
+                let first = checker.pop()?;
+                let second = checker.pop()?;
+                if let (Some(first), Some(second)) = (first, second) {
+                    if first != second {
+                        return Err(String::from("select operands differ in type"));
+                    }
+                }
+                checker.vals.push(first.or(second));
+            }
+            Instr::LocalGet(idx) => checker.vals.push(Some(local(*idx)?)),
+            Instr::LocalSet(idx) => checker.expect(local(*idx)?)?,
+            Instr::LocalTee(idx) => {
+                checker.expect(local(*idx)?)?;
+                checker.vals.push(Some(local(*idx)?));
+            }
+            Instr::GlobalGet(idx) | Instr::GlobalSet(idx) => {
+                let global = module
+                    .globals
+                    .get(*idx as usize)
+                    .ok_or_else(|| format!("Unknown global {idx}"))?;
+                match instr {
+                    Instr::GlobalGet(_) => checker.vals.push(Some(global.ty)),
+                    _ if !global.mutable => return Err(format!("Global {idx} is immutable")),
+                    _ => checker.expect(global.ty)?,
+                }
+            }
+            Instr::I32Const(_) => checker.vals.push(Some(ValType::I32)),
+            Instr::F64Const(_) => checker.vals.push(Some(ValType::F64)),
+            Instr::Numeric(op) => {
+                let (params, result) = numeric(*op).unwrap();
+                checker.expect_all(&params)?;
+                checker.vals.push(Some(result));
+            }
+        }
+    }
+    match checker.ctrls.is_empty() {
+        true => Ok(()),
+        false => Err(String::from("Function doesn't end")),
+    }
+}
+
+/// Where a branch to a label goes
+struct Label {
+    is_loop: bool,
+    target: usize,
+    height: usize,
+    arity: usize,
+}
+
+/// A module ready to run, calling `host` for its imports with their module,
+/// name, arguments and the memory
+pub struct Instance<H> {
+    module: WasmModule,
+    globals: Vec<Val>,
+    memory: Vec<u8>,
+    host: H,
+    depth: usize,
+}
+
+impl<H> Instance<H>
+where
+    H: FnMut(&str, &str, &[Val], &[u8]) -> Result<Vec<Val>, String>,
+{
+    pub fn new(module: WasmModule, host: H) -> Result<Self, String> {
+        let mut memory = vec![0; module.memory.unwrap_or(0) as usize * PAGE];
+        for (offset, bytes) in &module.data {
+            let start = *offset as usize;
+            memory
+                .get_mut(start..start + bytes.len())
+                .ok_or_else(|| String::from("Data segment out of bounds"))?
+                .copy_from_slice(bytes);
+        }
+        Ok(Instance {
+            globals: module.globals.iter().map(|global| global.init).collect(),
+            module,
+            memory,
+            host,
+            depth: 0,
+        })
+    }
+
+    /// Call an exported function, a trap or error from the host is the
+    /// error
+    pub fn invoke(&mut self, name: &str, args: &[Val]) -> Result<Vec<Val>, String> {
+        let Some(&(0x00, func)) = self.module.exports.get(name) else {
+            return Err(format!("No exported function {name}"));
+        };
+        let ty = self.module.func_type(func).unwrap();
+        let types_match = ty.params.len() == args.len()
+            && ty.params.iter().zip(args).all(|(ty, arg)| {
+                matches!(
+                    (ty, arg),
+                    (ValType::I32, Val::I32(_)) | (ValType::F64, Val::F64(_))
+                )
+            });
+        if !types_match {
+            return Err(format!("Wrong arguments for {name}"));
+        }
+        self.call(func, args.to_vec())
+    }
+
+    fn call(&mut self, func: u32, args: Vec<Val>) -> Result<Vec<Val>, String> {
+        let imports = self.module.imports.len();
+        if (func as usize) < imports {
+            let (module, name, _) = &self.module.imports[func as usize];
+            return (self.host)(module, name, &args, &self.memory);
+        }
+        if self.depth == MAX_DEPTH {
+            return Err(String::from("call stack exhausted"));
+        }
+        self.depth += 1;
+        let result = self.execute(func as usize - imports, args);
+        self.depth -= 1;
+        result
+    }
+
+    fn execute(&mut self, idx: usize, args: Vec<Val>) -> Result<Vec<Val>, String> {
+        let results = self.module.types[self.module.funcs[idx] as usize]
+            .results
+            .len();
+        let mut locals = args;
+        locals.extend(self.module.bodies[idx].locals.iter().map(|ty| match ty {
+            ValType::I32 => Val::I32(0),
+            ValType::F64 => Val::F64(0.0),
+        }));
+        let mut stack: Vec<Val> = Vec::new();
+        let mut labels: Vec<Label> = Vec::new();
+        let mut pc = 0;
+        loop {
+            let instr = self.module.bodies[idx].code[pc].clone();
+            pc += 1;
+            match instr {
+                Instr::Unreachable => return Err(String::from("unreachable executed")),
+                Instr::Nop => {}
+                Instr::Block(result, end) => labels.push(Label {
+                    is_loop: false,
+                    target: end + 1,
+                    height: stack.len(),
+                    arity: result.is_some() as usize,
+                }),
+                Instr::Loop(_) => labels.push(Label {
+                    is_loop: true,
+                    target: pc,
+                    height: stack.len(),
+                    arity: 0,
+                }),
+                Instr::If(result, other, end) => {
+                    let Val::I32(cond) = stack.pop().unwrap() else {
+                        unreachable!("validated");
+                    };
+                    let label = Label {
+                        is_loop: false,
+                        target: end + 1,
+                        height: stack.len(),
+                        arity: result.is_some() as usize,
+                    };
+                    match (cond != 0, other) {
+                        (true, _) => labels.push(label),
+                        (false, Some(other)) => {
+                            labels.push(label);
+                            pc = other + 1;
+                        }
+                        (false, None) => pc = end + 1,
+                    }
+                }
+                // The end of the then arm
+                Instr::Else(end) => {
+                    labels.pop();
+                    pc = end + 1;
+                }
+                Instr::End => {
+                    if labels.pop().is_none() {
+                        return Ok(stack.split_off(stack.len() - results));
+                    }
+                }
+                Instr::Br(depth) => {
+                    if branch(&mut labels, depth, &mut stack, &mut pc) {
+                        return Ok(stack.split_off(stack.len() - results));
+                    }
+                }
+                Instr::BrIf(depth) => {
+                    let Val::I32(cond) = stack.pop().unwrap() else {
+                        unreachable!("validated");
+                    };
+                    if cond != 0 && branch(&mut labels, depth, &mut stack, &mut pc) {
+                        return Ok(stack.split_off(stack.len() - results));
+                    }
+                }
+                Instr::BrTable(targets, default) => {
+                    let Val::I32(at) = stack.pop().unwrap() else {
+                        unreachable!("validated");
+                    };
+                    let depth = targets.get(at as u32 as usize).copied().unwrap_or(default);
+                    if branch(&mut labels, depth, &mut stack, &mut pc) {
+                        return Ok(stack.split_off(stack.len() - results));
+                    }
+                }
+                Instr::Return => return Ok(stack.split_off(stack.len() - results)),
+                Instr::Call(func) => {
+                    let params = self.module.func_type(func).unwrap().params.len();
+                    let args = stack.split_off(stack.len() - params);
+                    stack.extend(self.call(func, args)?);
+                }
+                Instr::Drop => {
+                    stack.pop();
+                }
+                Instr::Select => {
+                    let cond = stack.pop().unwrap();
+                    let second = stack.pop().unwrap();
+                    let first = stack.pop().unwrap();
+                    stack.push(match cond {
+                        Val::I32(0) => second,
+                        _ => first,
+                    });
+                }
+                Instr::LocalGet(local) => stack.push(locals[local as usize]),
+                Instr::LocalSet(local) => locals[local as usize] = stack.pop().unwrap(),
+                Instr::LocalTee(local) => locals[local as usize] = *stack.last().unwrap(),
+                Instr::GlobalGet(global) => stack.push(self.globals[global as usize]),
+                Instr::GlobalSet(global) => self.globals[global as usize] = stack.pop().unwrap(),
+                Instr::I32Const(val) => stack.push(Val::I32(val)),
+                Instr::F64Const(val) => stack.push(Val::F64(val)),
+                Instr::Numeric(op) => {
+                    let arity = numeric(op).unwrap().0.len();
+                    let operands = stack.split_off(stack.len() - arity);
+                    stack.push(apply(op, &operands));
+                }
+            }
+        }
+    }
+}
+
+/// Branch `depth` labels out, past the outermost is a return
+fn branch(labels: &mut Vec<Label>, depth: u32, stack: &mut Vec<Val>, pc: &mut usize) -> bool {
+    let Some(at) = labels.len().checked_sub(depth as usize + 1) else {
+        return true;
+    };
+    let label = &labels[at];
+    let kept = stack.split_off(stack.len() - label.arity);
+    stack.truncate(label.height);
+    stack.extend(kept);
+    *pc = label.target;
+    labels.truncate(at + label.is_loop as usize);
+    false
+}
+
+fn apply(op: u8, operands: &[Val]) -> Val {
+    let num = |idx: usize| match operands[idx] {
+        Val::F64(val) => val,
+        Val::I32(_) => unreachable!("validated"),
+    };
+    let int = |idx: usize| match operands[idx] {
+        Val::I32(val) => val,
+        Val::F64(_) => unreachable!("validated"),
+    };
+    match op {
+        0x45 => Val::I32((int(0) == 0) as i32),
+        0x46 => Val::I32((int(0) == int(1)) as i32),
+        0x47 => Val::I32((int(0) != int(1)) as i32),
+        0x61 => Val::I32((num(0) == num(1)) as i32),
+        0x62 => Val::I32((num(0) != num(1)) as i32),
+        0x63 => Val::I32((num(0) < num(1)) as i32),
+        0x64 => Val::I32((num(0) > num(1)) as i32),
+        0x65 => Val::I32((num(0) <= num(1)) as i32),
+        0x66 => Val::I32((num(0) >= num(1)) as i32),
+        0x99 => Val::F64(num(0).abs()),
+        0x9A => Val::F64(-num(0)),
+        0x9B => Val::F64(num(0).ceil()),
+        0x9C => Val::F64(num(0).floor()),
+        0x9D => Val::F64(num(0).trunc()),
+        0x9E => Val::F64(num(0).round_ties_even()),
+        0x9F => Val::F64(num(0).sqrt()),
+        0xA0 => Val::F64(num(0) + num(1)),
+        0xA1 => Val::F64(num(0) - num(1)),
+        0xA2 => Val::F64(num(0) * num(1)),
+        0xA3 => Val::F64(num(0) / num(1)),
+        _ => unreachable!("validated"),
+    }
+}
@@ -0,0 +1,212 @@
+#[cfg(test)]
+mod tests {
+    use crate::ast::value::Value;
+    use crate::codegen::wasm::to_wasm;
+    use crate::ir::ir::Module;
+    use crate::ir::ir::Ty;
+    use crate::ir::ir::MAIN;
+    use crate::repl::session::Session;
+    use crate::tests::generator::Generator;
+    use crate::tests::support::lowered;
+    use crate::tests::support::script_session;
+    use crate::tests::support::walked;
+    use crate::tests::wasm_interpreter::validate;
+    use crate::tests::wasm_interpreter::Instance;
+    use crate::tests::wasm_interpreter::Val;
+
+    /// The imports a browser would pass, maths done as the interpreter does
+    /// it and errors read out of memory
+    fn host(module: &str, name: &str, args: &[Val], memory: &[u8]) -> Result<Vec<Val>, String> {
+        let num = |idx: usize| match args[idx] {
+            Val::F64(val) => val,
+            Val::I32(_) => panic!("{name} takes numbers"),
+        };
+        let int = |idx: usize| match args[idx] {
+            Val::I32(val) => val as usize,
+            Val::F64(_) => panic!("{name} takes offsets"),
+        };
+        let result = match (module, name) {
+            ("env", "error") => {
+                let text = &memory[int(0)..int(0) + int(1)];
+                return Err(format!("error: {0}", String::from_utf8_lossy(text)));
+            }
+            ("math", "sin") => num(0).sin(),
+            ("math", "cos") => num(0).cos(),
+            ("math", "tan") => num(0).tan(),
+            ("math", "asin") => num(0).asin(),
+            ("math", "acos") => num(0).acos(),
+            ("math", "atan") => num(0).atan(),
+            ("math", "sinh") => num(0).sinh(),
+            ("math", "cosh") => num(0).cosh(),
+            ("math", "tanh") => num(0).tanh(),
+            ("math", "round") => num(0).round(),
+            ("math", "log") => num(0).ln(),
+            ("math", "pow") => num(0).powf(num(1)),
+            ("math", "fmod") => num(0) % num(1),
+            _ => panic!("unknown import {module}.{name}"),
+        };
+        Ok(vec![Val::F64(result)])
+    }
+
+    /// Validate the module and run `main`, giving what the program
+    /// evaluates to or the error it reported
+    fn executed(module: &Module) -> String {
+        let bytes = to_wasm(module).unwrap();
+        let wasm = validate(&bytes).unwrap();
+        let mut instance = Instance::new(wasm, host).unwrap();
+        let ret = module.function(MAIN).unwrap().ret;
+        match instance.invoke(MAIN, &[]) {
+            Ok(vals) => match (ret, vals.as_slice()) {
+                (Ty::Num, &[Val::F64(val)]) => Value::Number(val).to_string(),
+                (Ty::Bool, &[Val::I32(val)]) => (val != 0).to_string(),
+                (Ty::Unit, []) => String::from("()"),
+                (ret, vals) => panic!("main returning {ret:?} gave {vals:?}"),
+            },
+            Err(err) => err,
+        }
+    }
+
+    fn assert_runs(input: &str, level: u8) {
+        let module = lowered(input, level).unwrap_or_else(|| panic!("{input} doesn't lower"));
+        assert_eq!(executed(&module), walked(input), "-O{level} {input}");
+    }
+
+    #[test]
+    fn test_exports_and_imports() {
+        let module = lowered(
+            "fn sq(x) { x * x }\nfn wave(x) { sin(x) + sqrt(x) + x % 2 }\nsq(3) + wave(1)",
+            0,
+        )
+        .unwrap();
+        let wasm = validate(&to_wasm(&module).unwrap()).unwrap();
+        assert_eq!(wasm.exported_functions(), vec!["main", "sq", "wave"]);
+        // `sqrt` is an instruction
+        assert_eq!(
+            wasm.imports(),
+            vec![("math", "fmod"), ("math", "sin"), ("env", "error")]
+        );
+    }
+
+    #[test]
+    fn test_functions_are_callable() {
+        let module = lowered(
+            "fn hyp(a, b) { sqrt(a * a + b * b) }\nfn pick(b: bool, x) { if b { x } else { -x } }\nfn unit(u: unit, x) { x }\n{}",
+            0,
+        )
+        .unwrap();
+        let wasm = validate(&to_wasm(&module).unwrap()).unwrap();
+        let mut instance = Instance::new(wasm, host).unwrap();
+        assert_eq!(
+            instance.invoke("hyp", &[Val::F64(3.0), Val::F64(4.0)]),
+            Ok(vec![Val::F64(5.0)])
+        );
+        assert_eq!(
+            instance.invoke("pick", &[Val::I32(0), Val::F64(2.0)]),
+            Ok(vec![Val::F64(-2.0)])
+        );
+        // Units are left out of signatures
+        assert_eq!(
+            instance.invoke("unit", &[Val::F64(7.0)]),
+            Ok(vec![Val::F64(7.0)])
+        );
+        assert_eq!(instance.invoke("main", &[]), Ok(Vec::new()));
+        assert!(instance.invoke("hyp", &[Val::F64(3.0)]).is_err());
+    }
+
+    #[test]
+    fn test_unused_functions_are_exported() {
+        let bytes = script_session(2)
+            .compile_wasm("fn area(r) { pi * r ^ 2 }\nfn sq(x) { x * x }\nsq(2)")
+            .unwrap();
+        let wasm = validate(&bytes).unwrap();
+        assert_eq!(wasm.exported_functions(), vec!["area", "main", "sq"]);
+        let mut instance = Instance::new(wasm, host).unwrap();
+        assert_eq!(
+            instance.invoke("area", &[Val::F64(1.0)]),
+            Ok(vec![Val::F64(std::f64::consts::PI)])
+        );
+    }
+
+    #[test]
+    fn test_programs_run() {
+        for input in [
+            "1 + 2 * 3 - 4 / 5\n2 ^ 3 ^ 2 % 7",
+            "-0 * 1",
+            "~3 + 4! + -(2) + 0.5! + (-1)! + 171!",
+            "(1 < 2) == (3 >= 4)",
+            "0.1 + 0.2",
+            "2 ^ 80",
+            "sqrt(-1)",
+            "sin(pi / 2) + cos(0) + tan(1) + asin(1) + acos(0) + atan(1)",
+            "sinh(1) + cosh(1) + tanh(1) + log(100) + log(8, 2) + exp(1)",
+            "abs(-e) + floor(2.5) + ceil(2.5) + round(2.5) + round(-2.5)",
+            "{}",
+            "let x = 1\n{ let x = x + 1; { let x = x * 10; x } + x } + x",
+            "let x = 1\nlet y = 0\nx / y",
+            "let x = 1\nx / -0",
+            "let u = {}\nfn f() { u }\nf() == {}",
+            "fn fact(n) { if n < 1 { 1 } else { n * fact(n - 1) } }\nfact(10)",
+            "fn even(n) { if n == 0 { true } else { odd(n - 1) } }\nfn odd(n) { if n == 0 { false } else { even(n - 1) } }\neven(10) == odd(7)",
+            "let c = 5\nfn f() { c = c + 1 }\nf()\nf()\nc",
+            "fn f(x) { if x > 0 { return 1 } else { return -1 } }\nf(2) + f(-2)",
+            "fn f(n) { 1 + { if n > 0 { return n } else { 0 } } }\nf(3) + f(0)",
+            "fn f(b: bool) { b }\nf(false)",
+            "fn f(x) { let y = x; if x > 1 { y = y + 1 }; if x > 2 { y = y * 3 } else { y = y / 2 }; y }\nf(1) + f(2) + f(3)",
+            "fn fib(n) { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }\nfib(15)",
+            "fn nan() { 0 ^ -1 - 0 ^ -1 }\nnan() == nan()",
+            "fn nan() { 0 ^ -1 - 0 ^ -1 }\nnan() != nan()",
+            "fn nan() { 0 ^ -1 - 0 ^ -1 }\nnan()",
+            "fn f() { {} }\nf() == f()",
+        ] {
+            assert_runs(input, 0);
+            assert_runs(input, 2);
+        }
+    }
+
+    #[test]
+    fn test_generated_programs_run() {
+        let mut generator = Generator::typed(17);
+        let mut checked = 0;
+        while checked < 60 {
+            let input = generator.program();
+            if lowered(&input, 0).is_some() {
+                assert_runs(&input, (checked % 4) as u8);
+                checked += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_modules_are_rejected() {
+        let module = lowered("fn sq(x) { x * x }\nsq(3)", 0).unwrap();
+        let bytes = to_wasm(&module).unwrap();
+        assert!(validate(&bytes[..bytes.len() - 1]).is_err());
+        assert!(validate(&[&b"\0wsm"[..], &bytes[4..]].concat()).is_err());
+        // `sq`'s body `local.get 0; local.get 0; f64.mul` made an `i32.eq`
+        let mul = bytes
+            .windows(5)
+            .position(|window| window == [0x20, 0x00, 0x20, 0x00, 0xA2])
+            .unwrap();
+        let mut mismatched = bytes.clone();
+        mismatched[mul + 4] = 0x46;
+        assert_eq!(
+            validate(&mismatched).err(),
+            Some(String::from("Function 1: Expected I32, found F64"))
+        );
+    }
+
+    #[test]
+    fn test_compile_wasm() {
+        let mut session = Session::new();
+        let bytes = session.compile_wasm("fn sq(x) { x * x }\nsq(3)").unwrap();
+        assert!(bytes.starts_with(b"\0asm\x01\0\0\0"));
+        assert_eq!(
+            session.compile_wasm("fn memory() { 1 }\nmemory()"),
+            Err(String::from("memory is reserved for the module's memory"))
+        );
+        assert_eq!(
+            session.compile_wasm("fn f(g) { g(1) }"),
+            Err(String::from("Functions can't be used as values @ 3"))
+        );
+    }
+}
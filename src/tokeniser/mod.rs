@@ -1,2 +1,3 @@
-pub mod tokeniser;
 pub mod token_enum;
+#[allow(clippy::module_inception)]
+pub mod tokeniser;
@@ -1,87 +1,118 @@
-/// Basic enum to hold all operations for our calculator
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum Operations {
-    NOT,
-    /// A + B
-    ADD,
-    /// A - B
-    MINUS,
-    /// A ^ B
-    POWER,
-    /// A / B
-    DIVIDE,
-    /// A * B
-    MULTIPLY,
+/// Every kind of token the tokeniser produces
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Number(f64),
+    Bool(bool),
+    /// Variable, function, builtin or constant name
+    Identifier(String),
+    /// +
+    Plus,
+    /// -
+    Minus,
+    /// *
+    Star,
+    /// /
+    Slash,
+    /// ^
+    Caret,
+    /// %
+    Percent,
+    /// !
+    Bang,
+    /// ~
+    Tilde,
     /// (
-    LPAREN,
+    LParen,
     /// )
-    RPAREN,
-    /// cos
-    FNCOS,
-    /// sin
-    FNSIN,
-    // tan
-    FNTAN,
-    // !
-    FNFACT,
-    // log(a(,b)?)
-    FNLOG,
-    //,
-    COMMA,
-    // abs(a)
-    FNABS,
-    // sqrt(a)
-    FNSQRT,
-    // exp(a)
-    FNEXP,
-    // a % b
-    FNMOD,
-    // asin(a)
-    FNASIN,
-    //acos(a)
-    FNACOS,
-    //atan(a)
-    FNATAN,
-    //sinh(a)
-    FNSINH,
-    //cosh(a)
-    FNCOSH,
-    //tanh(a)
-    FNTANH,
-    //floor(a)
-    FNFLOOR,
-    //ceil(a)
-    FNCEIL,
-    //round(a)
-    FNROUND,
-    // let
-    VARLET,
-    // =
-    VARASSIGN,
-    // {
-    RBRACE,
-    // }
-    LBRACE,
-    // fn
-    FNDEFINE,
+    RParen,
+    /// {
+    LBrace,
+    /// }
+    RBrace,
+    /// ,
+    Comma,
+    /// ;, ends a statement like a line break does
+    Semicolon,
+    /// :, introduces a type annotation
+    Colon,
+    /// ->
+    Arrow,
+    /// =
+    Assign,
+    /// <
+    Less,
+    /// <=
+    LessEqual,
+    /// >
+    Greater,
+    /// >=
+    GreaterEqual,
+    /// ==
+    EqualEqual,
+    /// !=
+    BangEqual,
+    /// let
+    Let,
+    /// const
+    Const,
+    /// fn
+    Fn,
+    /// return
+    Return,
+    /// if
+    If,
+    /// else
+    Else,
 }
-#[derive(Debug, Clone)]
-pub enum ValueType {
-    Number(f64),
-    Identifier(String),
+
+impl TokenKind {
+    /// Keyword for an identifier-like word, if it is one
+    pub fn keyword(word: &str) -> Option<TokenKind> {
+        match word {
+            "let" => Some(TokenKind::Let),
+            "const" => Some(TokenKind::Const),
+            "fn" => Some(TokenKind::Fn),
+            "return" => Some(TokenKind::Return),
+            "if" => Some(TokenKind::If),
+            "else" => Some(TokenKind::Else),
+            "true" => Some(TokenKind::Bool(true)),
+            "false" => Some(TokenKind::Bool(false)),
+            _ => None,
+        }
+    }
+}
+
+/// Byte range of a token or node within the source text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+    /// Smallest span covering both self and other
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+    /// Slice of the source text this span covers
+    pub fn slice(self, source: &str) -> &str {
+        &source[self.start..self.end]
+    }
 }
 
-/// Tokens are represented via two properties, operation & value
-/// Tokens that represent strict values contain None
-/// some_token = Token { operation: None, value: Some(5.0) }
-#[derive(Debug, Clone)]
+/// A token and where it came from
+/// some_token = Token { kind: TokenKind::Number(5.0), line_number: 0, span }
+#[derive(Debug, Clone, PartialEq)]
 pub struct Token {
-    /// Field for token operation declared at line 2
-    pub operation: Option<Operations>,
-    // Field for value instead of operation (See line 14)
-    //pub value: Option<f64>,
-    pub value: Option<ValueType>,
+    pub kind: TokenKind,
     pub line_number: i16,
+    pub span: Span,
 }
 
 /// Custom data type in order to represent a stream of tokens
@@ -1,9 +1,8 @@
-use crate::tokeniser::token_enum::Operations;
+use crate::tokeniser::token_enum::Span;
 use crate::tokeniser::token_enum::Token;
+use crate::tokeniser::token_enum::TokenKind;
 use crate::tokeniser::token_enum::TokenStream;
-use crate::tokeniser::token_enum::ValueType;
 use core::iter::Peekable;
-use std::collections::HashMap;
 
 pub struct Tokeniser {
     tape: String,
@@ -17,86 +16,32 @@ impl Tokeniser {
             line_number: 0i16,
         }
     }
-    fn parse_alphanumeric<I>(&self, chars: &mut Peekable<I>) -> Option<Token>
+    fn parse_alphanumeric<I>(&self, chars: &mut Peekable<I>, start: usize) -> Option<Token>
     where
         I: Iterator<Item = char> + Clone,
     {
         let mut parsed_string = String::new();
-        let mut iter = chars.clone();
 
-        let fn_map: HashMap<String, Operations> = [
-            (String::from("sin"), Operations::FNSIN),
-            (String::from("cos"), Operations::FNCOS),
-            (String::from("tan"), Operations::FNTAN),
-            (String::from("log"), Operations::FNLOG),
-            (String::from("abs"), Operations::FNABS),
-            (String::from("sqrt"), Operations::FNSQRT),
-            (String::from("exp"), Operations::FNEXP),
-            (String::from("asin"), Operations::FNASIN),
-            (String::from("acos"), Operations::FNACOS),
-            (String::from("atan"), Operations::FNATAN),
-            (String::from("sinh"), Operations::FNSINH),
-            (String::from("cosh"), Operations::FNCOSH),
-            (String::from("tanh"), Operations::FNTANH),
-            (String::from("floor"), Operations::FNFLOOR),
-            (String::from("ceil"), Operations::FNCEIL),
-            (String::from("round"), Operations::FNROUND),
-            (String::from("let"), Operations::VARLET),
-            (String::from("fn"), Operations::FNDEFINE),
-        ]
-        .iter()
-        .cloned()
-        .collect();
-
-        let constant_map: HashMap<String, f64> = [
-            (String::from("e"), std::f64::consts::E),
-            (String::from("pi"), std::f64::consts::PI),
-        ]
-        .iter()
-        .cloned()
-        .collect();
-
-        while let Some(ch) = iter.next() {
+        while let Some(&ch) = chars.peek() {
             if ch.is_alphabetic() {
                 parsed_string.push(ch);
+                chars.next();
             } else {
                 break;
             }
         }
-
-        if let Some(&op) = fn_map.get(&parsed_string) {
-            for _ in 0..parsed_string.len() {
-                chars.next();
-            }
-
-            return Some(Token {
-                operation: Some(op),
-                value: None,
-                line_number: self.line_number,
-            });
+        if parsed_string.is_empty() {
+            return None;
         }
 
-        if let Some(&val) = constant_map.get(&parsed_string) {
-            for _ in 0..parsed_string.len() {
-                chars.next();
-            }
-
-            return Some(Token {
-                operation: None,
-                value: Some(ValueType::Number(val)),
-                line_number: self.line_number,
-            });
-        }
-        for _ in 0..parsed_string.len() {
-            chars.next();
-        }
+        let span = Span::new(start, start + parsed_string.len());
+        let kind =
+            TokenKind::keyword(&parsed_string).unwrap_or(TokenKind::Identifier(parsed_string));
         Some(Token {
-            operation: None,
-            value: Some(ValueType::Identifier(parsed_string)),
+            kind,
             line_number: self.line_number,
+            span,
         })
-
-        //None
     }
     fn parse_number<I>(chars: &mut Peekable<I>) -> Result<(f64, i8), String>
     where
@@ -107,7 +52,7 @@ impl Tokeniser {
 
         if chars
             .clone()
-            .take_while(|&c| c != ' ')
+            .take_while(|&c| c.is_ascii_digit() || c == '.')
             .filter(|&c| c == '.')
             .count()
             > 1
@@ -135,153 +80,110 @@ impl Tokeniser {
             .map(|val| (val, parsed_index))
             .map_err(|_| String::from("Failed to parse value"))
     }
+    /// Byte offset of the next unconsumed character
+    fn offset<I>(&self, chars: &mut Peekable<I>) -> usize
+    where
+        I: Iterator<Item = (usize, char)>,
+    {
+        chars.peek().map_or(self.tape.len(), |&(idx, _)| idx)
+    }
+    /// Whether the character after the current one is `next`
+    fn followed_by<I>(chars: &Peekable<I>, next: char) -> bool
+    where
+        I: Iterator<Item = (usize, char)> + Clone,
+    {
+        chars.clone().nth(1).is_some_and(|(_, ch)| ch == next)
+    }
     pub fn to_tokens(&mut self) -> Result<TokenStream, String> {
         // Setup return vector
         let mut return_stream = TokenStream::new();
-        let mut inp_chars = self.tape.chars().peekable();
+        let tape = self.tape.clone();
+        let mut inp_chars = tape.char_indices().peekable();
 
-        while let Some(&ch) = inp_chars.peek() {
-            match ch {
+        while let Some(&(start, ch)) = inp_chars.peek() {
+            let kind = match ch {
                 'a'..='z' => {
-                    if let Some(tok) = self.parse_alphanumeric(&mut inp_chars) {
-                        return_stream.push(tok);
-                    } else {
+                    let Some(tok) = self.parse_alphanumeric(
+                        &mut inp_chars.clone().map(|(_, c)| c).peekable(),
+                        start,
+                    ) else {
                         return Err(String::from("Unrecognised alphanumeric value"));
+                    };
+                    while self.offset(&mut inp_chars) < tok.span.end {
+                        inp_chars.next();
                     }
+                    return_stream.push(tok);
+                    continue;
                 }
                 '0'..='9' => {
                     //Parse number
-                    let (parsed_val, parsed_idx) = Self::parse_number(&mut inp_chars.clone())?;
-                    return_stream.push(Token {
-                        operation: None,
-                        value: Some(ValueType::Number(parsed_val)),
-                        line_number: self.line_number,
-                    });
+                    let (parsed_val, parsed_idx) =
+                        Self::parse_number(&mut inp_chars.clone().map(|(_, c)| c).peekable())?;
                     for _ in 0..parsed_idx {
                         inp_chars.next();
                     }
-                }
-                '{' => {
                     return_stream.push(Token {
-                        operation: Some(Operations::LBRACE),
-                        value: None,
+                        kind: TokenKind::Number(parsed_val),
                         line_number: self.line_number,
+                        span: Span::new(start, self.offset(&mut inp_chars)),
                     });
-                    inp_chars.next();
+                    continue;
                 }
-                '}' => {
-                    return_stream.push(Token {
-                        operation: Some(Operations::RBRACE),
-                        value: None,
-                        line_number: self.line_number,
-                    });
+                ';' => TokenKind::Semicolon,
+                ':' => TokenKind::Colon,
+                '{' => TokenKind::LBrace,
+                '}' => TokenKind::RBrace,
+                '=' if Self::followed_by(&inp_chars, '=') => {
                     inp_chars.next();
+                    TokenKind::EqualEqual
                 }
-                '=' => {
-                    return_stream.push(Token {
-                        operation: Some(Operations::VARASSIGN),
-                        value: None,
-                        line_number: self.line_number,
-                    });
+                '=' => TokenKind::Assign,
+                '!' if Self::followed_by(&inp_chars, '=') => {
                     inp_chars.next();
+                    TokenKind::BangEqual
                 }
-                '~' => {
-                    return_stream.push(Token {
-                        operation: Some(Operations::NOT),
-                        value: None,
-                        line_number: self.line_number,
-                    });
+                '<' if Self::followed_by(&inp_chars, '=') => {
                     inp_chars.next();
+                    TokenKind::LessEqual
                 }
-                '+' => {
-                    return_stream.push(Token {
-                        operation: Some(Operations::ADD),
-                        value: None,
-                        line_number: self.line_number,
-                    });
+                '<' => TokenKind::Less,
+                '>' if Self::followed_by(&inp_chars, '=') => {
                     inp_chars.next();
+                    TokenKind::GreaterEqual
                 }
-                '-' => {
-                    return_stream.push(Token {
-                        operation: Some(Operations::MINUS),
-                        value: None,
-                        line_number: self.line_number,
-                    });
-                    inp_chars.next();
-                }
-                '^' => {
-                    return_stream.push(Token {
-                        operation: Some(Operations::POWER),
-                        value: None,
-                        line_number: self.line_number,
-                    });
-                    inp_chars.next();
-                }
-                '*' => {
-                    return_stream.push(Token {
-                        operation: Some(Operations::MULTIPLY),
-                        value: None,
-                        line_number: self.line_number,
-                    });
-                    inp_chars.next();
-                }
-                '/' => {
-                    return_stream.push(Token {
-                        operation: Some(Operations::DIVIDE),
-                        value: None,
-                        line_number: self.line_number,
-                    });
-                    inp_chars.next();
-                }
-                '!' => {
-                    return_stream.push(Token {
-                        operation: Some(Operations::FNFACT),
-                        value: None,
-                        line_number: self.line_number,
-                    });
-                    inp_chars.next();
-                }
-                '(' => {
-                    return_stream.push(Token {
-                        operation: Some(Operations::LPAREN),
-                        value: None,
-                        line_number: self.line_number,
-                    });
-                    inp_chars.next();
-                }
-                ')' => {
-                    return_stream.push(Token {
-                        operation: Some(Operations::RPAREN),
-                        value: None,
-                        line_number: self.line_number,
-                    });
-                    inp_chars.next();
-                }
-                ',' => {
-                    return_stream.push(Token {
-                        operation: Some(Operations::COMMA),
-                        value: None,
-                        line_number: self.line_number,
-                    });
-                    inp_chars.next();
-                }
-                '%' => {
-                    return_stream.push(Token {
-                        operation: Some(Operations::FNMOD),
-                        value: None,
-                        line_number: self.line_number,
-                    });
+                '>' => TokenKind::Greater,
+                '~' => TokenKind::Tilde,
+                '+' => TokenKind::Plus,
+                '-' if Self::followed_by(&inp_chars, '>') => {
                     inp_chars.next();
+                    TokenKind::Arrow
                 }
+                '-' => TokenKind::Minus,
+                '^' => TokenKind::Caret,
+                '*' => TokenKind::Star,
+                '/' => TokenKind::Slash,
+                '!' => TokenKind::Bang,
+                '(' => TokenKind::LParen,
+                ')' => TokenKind::RParen,
+                ',' => TokenKind::Comma,
+                '%' => TokenKind::Percent,
                 '\n' => {
                     self.line_number += 1;
                     inp_chars.next();
+                    continue;
                 }
                 ' ' => {
                     inp_chars.next();
+                    continue;
                 }
                 _ => return Err(String::from("Invalid character")),
-            }
+            };
+            inp_chars.next();
+            return_stream.push(Token {
+                kind,
+                line_number: self.line_number,
+                span: Span::new(start, self.offset(&mut inp_chars)),
+            });
         }
 
         Ok(return_stream)
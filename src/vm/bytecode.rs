@@ -0,0 +1,127 @@
+use crate::ast::ast::BinaryOp;
+use crate::ast::ast::Builtin;
+use crate::ast::ast::UnaryOp;
+use crate::tokeniser::token_enum::Span;
+
+/// A single virtual machine instruction
+///
+/// Names are indices into `Program::names` and locals are slots in the
+/// frame of the running chunk, jumps are absolute offsets into its code
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    /// Push `Chunk::numbers[idx]`
+    Number(u32),
+    True,
+    False,
+    Unit,
+    Pop,
+    GetLocal(u32),
+    /// Pop into a local slot
+    SetLocal(u32),
+    /// Push a global variable, the function of that name or the constant of
+    /// that name, whichever exists first
+    GetName(u32),
+    /// Pop into an existing mutable global
+    SetName(u32),
+    /// Pop into a global, defining or replacing it
+    DefineLet(u32),
+    DefineConst(u32),
+    /// Bind the function compiled as `Program::functions[proto]` to a name
+    DefineFunction(u32, u32),
+    /// Push the global variable of that name or else the function, to be
+    /// called
+    Callee(u32),
+    /// Check the callee on top of the stack is a function taking this many
+    /// arguments, the name is the one written at the call
+    Callable(u32, u32),
+    /// Call the callee below this many arguments
+    Call(u32),
+    Binary(BinaryOp),
+    Unary(UnaryOp),
+    /// Fail unless the top of the stack is a number, so builtin arguments are
+    /// checked as soon as they're evaluated
+    AssertNumber,
+    /// Apply a builtin to this many arguments
+    Builtin(Builtin, u32),
+    Jump(u32),
+    /// Pop a bool and jump if it's false
+    JumpUnless(u32),
+    /// Fail assigning to a local constant
+    AssignConst(u32),
+    /// Leave the frame with the value on top of the stack
+    Return,
+    /// Finish a top level definition, which produces no value
+    End,
+}
+
+/// Compiled code of one function body or top level statement
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    /// Source each instruction was compiled from
+    pub spans: Vec<Span>,
+    /// Number literals, indexed by `Op::Number`
+    pub numbers: Vec<f64>,
+    /// Local slots the frame needs, parameters come first
+    pub locals: u32,
+}
+
+/// A user function ready to be bound to its name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Proto {
+    pub name: String,
+    pub params: Vec<String>,
+    pub chunk: Chunk,
+}
+
+/// Code of a top level statement alongside the source it came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub chunk: Chunk,
+    pub span: Span,
+}
+
+/// A whole compiled program, independent of any virtual machine state
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Program {
+    /// Every name the code refers to
+    pub names: Vec<String>,
+    /// Every function defined anywhere in the program
+    pub functions: Vec<Proto>,
+    pub statements: Vec<Statement>,
+}
+
+impl Chunk {
+    /// Append an instruction, returning its offset
+    pub fn emit(&mut self, op: Op, span: Span) -> u32 {
+        self.code.push(op);
+        self.spans.push(span);
+        self.code.len() as u32 - 1
+    }
+    /// Offset the next instruction will have
+    pub fn here(&self) -> u32 {
+        self.code.len() as u32
+    }
+    /// Point the jump at `offset` to the next instruction
+    pub fn patch(&mut self, offset: u32) {
+        let target = self.here();
+        match &mut self.code[offset as usize] {
+            Op::Jump(to) | Op::JumpUnless(to) => *to = target,
+            op => unreachable!("{op:?} isn't a jump"),
+        }
+    }
+    pub fn number(&mut self, val: f64) -> u32 {
+        let idx = match self
+            .numbers
+            .iter()
+            .position(|num| num.to_bits() == val.to_bits())
+        {
+            Some(idx) => idx,
+            None => {
+                self.numbers.push(val);
+                self.numbers.len() - 1
+            }
+        };
+        idx as u32
+    }
+}
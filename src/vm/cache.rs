@@ -0,0 +1,487 @@
+use crate::ast::ast::BinaryOp;
+use crate::ast::ast::Builtin;
+use crate::ast::ast::UnaryOp;
+use crate::tokeniser::token_enum::Span;
+use crate::vm::bytecode::Chunk;
+use crate::vm::bytecode::Op;
+use crate::vm::bytecode::Program;
+use crate::vm::bytecode::Proto;
+use crate::vm::bytecode::Statement;
+use std::fmt;
+
+/// First bytes of every bytecode file
+pub const MAGIC: [u8; 4] = *b"APEB";
+/// Bumped whenever the layout or meaning of the bytecode changes
+pub const VERSION: u16 = 1;
+/// Magic, version and checksum
+const HEADER: usize = 4 + 2 + 8;
+
+/// Reasons a bytecode file can't be loaded
+#[derive(Debug, Clone, PartialEq)]
+pub enum CacheError {
+    NotBytecode,
+    Version {
+        found: u16,
+        expected: u16,
+    },
+    /// The contents don't match the checksum they were written with
+    Corrupt,
+    Truncated,
+    /// Intact, but not a program the virtual machine can run
+    Invalid(String),
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::NotBytecode => write!(f, "Not a bytecode file"),
+            CacheError::Version { found, expected } => write!(
+                f,
+                "Bytecode version {found} isn't supported, expected version {expected}"
+            ),
+            CacheError::Corrupt => write!(f, "Bytecode file is corrupt"),
+            CacheError::Truncated => write!(f, "Bytecode file is truncated"),
+            CacheError::Invalid(reason) => write!(f, "Invalid bytecode: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for CacheError {}
+
+/// Whether the bytes look like a bytecode file rather than source
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+/// Serialise a compiled program, little endian throughout
+pub fn encode(program: &Program) -> Vec<u8> {
+    let mut payload = Vec::new();
+    write_program(&mut payload, program);
+
+    let mut bytes = Vec::with_capacity(HEADER + payload.len());
+    bytes.extend(MAGIC);
+    bytes.extend(VERSION.to_le_bytes());
+    bytes.extend(checksum(&payload).to_le_bytes());
+    bytes.extend(payload);
+    bytes
+}
+
+/// Load a program written by `encode`, checking the version and checksum
+/// and that every instruction refers to something that exists
+pub fn decode(bytes: &[u8]) -> Result<Program, CacheError> {
+    if !is_bytecode(bytes) {
+        return Err(CacheError::NotBytecode);
+    }
+    if bytes.len() < HEADER {
+        return Err(CacheError::Truncated);
+    }
+    let found = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if found != VERSION {
+        return Err(CacheError::Version {
+            found,
+            expected: VERSION,
+        });
+    }
+    let expected = u64::from_le_bytes(bytes[6..HEADER].try_into().expect("eight bytes"));
+    let payload = &bytes[HEADER..];
+    if checksum(payload) != expected {
+        return Err(CacheError::Corrupt);
+    }
+
+    let mut reader = Reader { bytes: payload };
+    let program = reader.program()?;
+    if !reader.bytes.is_empty() {
+        return Err(CacheError::Invalid(String::from("trailing bytes")));
+    }
+    verify(&program).map_err(CacheError::Invalid)?;
+    Ok(program)
+}
+
+/// FNV-1a
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+const BINARY_OPS: [BinaryOp; 12] = [
+    BinaryOp::Add,
+    BinaryOp::Sub,
+    BinaryOp::Mul,
+    BinaryOp::Div,
+    BinaryOp::Pow,
+    BinaryOp::Mod,
+    BinaryOp::Less,
+    BinaryOp::LessEqual,
+    BinaryOp::Greater,
+    BinaryOp::GreaterEqual,
+    BinaryOp::Equal,
+    BinaryOp::NotEqual,
+];
+
+const UNARY_OPS: [UnaryOp; 3] = [UnaryOp::Neg, UnaryOp::Not, UnaryOp::Factorial];
+
+fn write_u32(out: &mut Vec<u8>, val: u32) {
+    out.extend(val.to_le_bytes());
+}
+
+fn write_len(out: &mut Vec<u8>, len: usize) {
+    write_u32(out, len as u32);
+}
+
+fn write_str(out: &mut Vec<u8>, text: &str) {
+    write_len(out, text.len());
+    out.extend(text.as_bytes());
+}
+
+fn write_program(out: &mut Vec<u8>, program: &Program) {
+    write_len(out, program.names.len());
+    program.names.iter().for_each(|name| write_str(out, name));
+    write_len(out, program.functions.len());
+    for proto in &program.functions {
+        write_str(out, &proto.name);
+        write_len(out, proto.params.len());
+        proto.params.iter().for_each(|param| write_str(out, param));
+        write_chunk(out, &proto.chunk);
+    }
+    write_len(out, program.statements.len());
+    for stmt in &program.statements {
+        write_span(out, stmt.span);
+        write_chunk(out, &stmt.chunk);
+    }
+}
+
+fn write_span(out: &mut Vec<u8>, span: Span) {
+    out.extend((span.start as u64).to_le_bytes());
+    out.extend((span.end as u64).to_le_bytes());
+}
+
+fn write_chunk(out: &mut Vec<u8>, chunk: &Chunk) {
+    write_u32(out, chunk.locals);
+    write_len(out, chunk.numbers.len());
+    for num in &chunk.numbers {
+        out.extend(num.to_bits().to_le_bytes());
+    }
+    write_len(out, chunk.code.len());
+    for (op, span) in chunk.code.iter().zip(&chunk.spans) {
+        write_op(out, *op);
+        write_span(out, *span);
+    }
+}
+
+fn index_of<T: PartialEq>(table: &[T], item: T) -> u32 {
+    table
+        .iter()
+        .position(|entry| *entry == item)
+        .expect("every operator is listed") as u32
+}
+
+/// A tag byte followed by the operands
+fn write_op(out: &mut Vec<u8>, op: Op) {
+    let (tag, operands): (u8, Vec<u32>) = match op {
+        Op::Number(idx) => (0, vec![idx]),
+        Op::True => (1, vec![]),
+        Op::False => (2, vec![]),
+        Op::Unit => (3, vec![]),
+        Op::Pop => (4, vec![]),
+        Op::GetLocal(slot) => (5, vec![slot]),
+        Op::SetLocal(slot) => (6, vec![slot]),
+        Op::GetName(idx) => (7, vec![idx]),
+        Op::SetName(idx) => (8, vec![idx]),
+        Op::DefineLet(idx) => (9, vec![idx]),
+        Op::DefineConst(idx) => (10, vec![idx]),
+        Op::DefineFunction(idx, proto) => (11, vec![idx, proto]),
+        Op::Callee(idx) => (12, vec![idx]),
+        Op::Callable(idx, argc) => (13, vec![idx, argc]),
+        Op::Call(argc) => (14, vec![argc]),
+        Op::Binary(op) => (15, vec![index_of(&BINARY_OPS, op)]),
+        Op::Unary(op) => (16, vec![index_of(&UNARY_OPS, op)]),
+        Op::AssertNumber => (17, vec![]),
+        Op::Builtin(builtin, argc) => (18, vec![index_of(&Builtin::ALL, builtin), argc]),
+        Op::Jump(to) => (19, vec![to]),
+        Op::JumpUnless(to) => (20, vec![to]),
+        Op::AssignConst(idx) => (21, vec![idx]),
+        Op::Return => (22, vec![]),
+        Op::End => (23, vec![]),
+    };
+    out.push(tag);
+    operands
+        .into_iter()
+        .for_each(|operand| write_u32(out, operand));
+}
+
+/// Cursor over the payload of a bytecode file
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], CacheError> {
+        if self.bytes.len() < len {
+            return Err(CacheError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+    fn u8(&mut self) -> Result<u8, CacheError> {
+        Ok(self.take(1)?[0])
+    }
+    fn u32(&mut self) -> Result<u32, CacheError> {
+        Ok(u32::from_le_bytes(
+            self.take(4)?.try_into().expect("four bytes"),
+        ))
+    }
+    fn u64(&mut self) -> Result<u64, CacheError> {
+        Ok(u64::from_le_bytes(
+            self.take(8)?.try_into().expect("eight bytes"),
+        ))
+    }
+    /// A count of things that follow, each at least `size` bytes, so a
+    /// corrupt count can't make us allocate more than the file holds
+    fn len(&mut self, size: usize) -> Result<usize, CacheError> {
+        let len = self.u32()? as usize;
+        if len.saturating_mul(size) > self.bytes.len() {
+            return Err(CacheError::Truncated);
+        }
+        Ok(len)
+    }
+    fn string(&mut self) -> Result<String, CacheError> {
+        let len = self.len(1)?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| CacheError::Invalid(String::from("name isn't valid utf-8")))
+    }
+    fn strings(&mut self) -> Result<Vec<String>, CacheError> {
+        let len = self.len(4)?;
+        (0..len).map(|_| self.string()).collect()
+    }
+    fn span(&mut self) -> Result<Span, CacheError> {
+        let start = self.u64()? as usize;
+        let end = self.u64()? as usize;
+        Ok(Span::new(start, end))
+    }
+    fn program(&mut self) -> Result<Program, CacheError> {
+        let names = self.strings()?;
+        let mut functions = Vec::new();
+        for _ in 0..self.len(4 + 4 + 4 * 4)? {
+            functions.push(Proto {
+                name: self.string()?,
+                params: self.strings()?,
+                chunk: self.chunk()?,
+            });
+        }
+        let mut statements = Vec::new();
+        for _ in 0..self.len(16 + 4 * 3)? {
+            statements.push(Statement {
+                span: self.span()?,
+                chunk: self.chunk()?,
+            });
+        }
+        Ok(Program {
+            names,
+            functions,
+            statements,
+        })
+    }
+    fn chunk(&mut self) -> Result<Chunk, CacheError> {
+        let locals = self.u32()?;
+        let mut numbers = Vec::new();
+        for _ in 0..self.len(8)? {
+            numbers.push(f64::from_bits(self.u64()?));
+        }
+        let mut code = Vec::new();
+        let mut spans = Vec::new();
+        for _ in 0..self.len(1 + 16)? {
+            code.push(self.op()?);
+            spans.push(self.span()?);
+        }
+        Ok(Chunk {
+            code,
+            spans,
+            numbers,
+            locals,
+        })
+    }
+    fn op(&mut self) -> Result<Op, CacheError> {
+        let tag = self.u8()?;
+        let op = match tag {
+            0 => Op::Number(self.u32()?),
+            1 => Op::True,
+            2 => Op::False,
+            3 => Op::Unit,
+            4 => Op::Pop,
+            5 => Op::GetLocal(self.u32()?),
+            6 => Op::SetLocal(self.u32()?),
+            7 => Op::GetName(self.u32()?),
+            8 => Op::SetName(self.u32()?),
+            9 => Op::DefineLet(self.u32()?),
+            10 => Op::DefineConst(self.u32()?),
+            11 => Op::DefineFunction(self.u32()?, self.u32()?),
+            12 => Op::Callee(self.u32()?),
+            13 => Op::Callable(self.u32()?, self.u32()?),
+            14 => Op::Call(self.u32()?),
+            15 => Op::Binary(Self::lookup(&BINARY_OPS, self.u32()?, "operator")?),
+            16 => Op::Unary(Self::lookup(&UNARY_OPS, self.u32()?, "operator")?),
+            17 => Op::AssertNumber,
+            18 => Op::Builtin(
+                Self::lookup(&Builtin::ALL, self.u32()?, "builtin")?,
+                self.u32()?,
+            ),
+            19 => Op::Jump(self.u32()?),
+            20 => Op::JumpUnless(self.u32()?),
+            21 => Op::AssignConst(self.u32()?),
+            22 => Op::Return,
+            23 => Op::End,
+            tag => return Err(CacheError::Invalid(format!("unknown instruction {tag}"))),
+        };
+        Ok(op)
+    }
+    fn lookup<T: Copy>(table: &[T], idx: u32, what: &str) -> Result<T, CacheError> {
+        table
+            .get(idx as usize)
+            .copied()
+            .ok_or_else(|| CacheError::Invalid(format!("unknown {what} {idx}")))
+    }
+}
+
+/// Check every chunk only refers to names, numbers, functions, locals and
+/// offsets that exist, and keeps the stack balanced, so running it can't
+/// crash the virtual machine
+fn verify(program: &Program) -> Result<(), String> {
+    for proto in &program.functions {
+        if (proto.chunk.locals as usize) < proto.params.len() {
+            return Err(format!("{0} has fewer locals than parameters", proto.name));
+        }
+        // Frames are allocated up front, so a huge count mustn't get that far
+        if proto.chunk.locals > slots_used(&proto.chunk).max(proto.params.len() as u32) {
+            return Err(format!("{0} has more locals than it uses", proto.name));
+        }
+        verify_chunk(&proto.chunk, program, false)
+            .map_err(|err| format!("{err} in {0}", proto.name))?;
+    }
+    for (idx, stmt) in program.statements.iter().enumerate() {
+        if stmt.chunk.locals > slots_used(&stmt.chunk) {
+            return Err(format!(
+                "statement {0} has more locals than it uses",
+                idx + 1
+            ));
+        }
+        verify_chunk(&stmt.chunk, program, true)
+            .map_err(|err| format!("{err} in statement {0}", idx + 1))?;
+    }
+    Ok(())
+}
+
+/// One past the highest local slot the chunk reads or writes
+fn slots_used(chunk: &Chunk) -> u32 {
+    chunk
+        .code
+        .iter()
+        .filter_map(|op| match op {
+            Op::GetLocal(slot) | Op::SetLocal(slot) => Some(slot.saturating_add(1)),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Follow every path through the chunk tracking the stack height, which has
+/// to be the same however an instruction is reached
+fn verify_chunk(chunk: &Chunk, program: &Program, top: bool) -> Result<(), String> {
+    let check = |ok: bool, what: &str, offset: usize| {
+        if ok {
+            Ok(())
+        } else {
+            Err(format!("{what} at {offset:04}"))
+        }
+    };
+    let mut heights: Vec<Option<usize>> = vec![None; chunk.code.len()];
+    let mut pending = vec![(0usize, 0usize)];
+    while let Some((offset, height)) = pending.pop() {
+        check(offset < chunk.code.len(), "code runs off the end", offset)?;
+        match heights[offset] {
+            Some(seen) if seen == height => continue,
+            Some(_) => return Err(format!("unbalanced stack at {offset:04}")),
+            None => heights[offset] = Some(height),
+        }
+
+        let op = chunk.code[offset];
+        let name = |idx: u32| (idx as usize) < program.names.len();
+        let local = |slot: u32| slot < chunk.locals;
+        let (pops, pushes) = match op {
+            Op::Number(idx) => {
+                check(
+                    (idx as usize) < chunk.numbers.len(),
+                    "unknown number",
+                    offset,
+                )?;
+                (0, 1)
+            }
+            Op::True | Op::False | Op::Unit => (0, 1),
+            Op::Pop => (1, 0),
+            Op::GetLocal(slot) => {
+                check(local(slot), "unknown local", offset)?;
+                (0, 1)
+            }
+            Op::SetLocal(slot) => {
+                check(local(slot), "unknown local", offset)?;
+                (1, 0)
+            }
+            Op::GetName(idx) | Op::Callee(idx) => {
+                check(name(idx), "unknown name", offset)?;
+                (0, 1)
+            }
+            Op::SetName(idx) | Op::DefineLet(idx) | Op::DefineConst(idx) => {
+                check(name(idx), "unknown name", offset)?;
+                (1, 0)
+            }
+            Op::AssignConst(idx) => {
+                check(name(idx), "unknown name", offset)?;
+                (0, 0)
+            }
+            Op::DefineFunction(idx, proto) => {
+                check(name(idx), "unknown name", offset)?;
+                check(
+                    (proto as usize) < program.functions.len(),
+                    "unknown function",
+                    offset,
+                )?;
+                (0, 0)
+            }
+            Op::Callable(idx, _) => {
+                check(name(idx), "unknown name", offset)?;
+                (1, 1)
+            }
+            Op::Call(argc) => (argc as usize + 1, 1),
+            Op::Binary(_) => (2, 1),
+            Op::Unary(_) | Op::AssertNumber => (1, 1),
+            Op::Builtin(builtin, argc) => {
+                let (min, max) = builtin.arity();
+                check(
+                    (min..=max).contains(&(argc as usize)),
+                    "wrong number of builtin arguments",
+                    offset,
+                )?;
+                (argc as usize, 1)
+            }
+            Op::Jump(_) => (0, 0),
+            Op::JumpUnless(_) => (1, 0),
+            Op::Return => (1, 0),
+            Op::End => (0, 0),
+        };
+        check(height >= pops, "stack underflow", offset)?;
+        let height = height - pops + pushes;
+        match op {
+            Op::Return => {}
+            Op::End => check(top && height == 0, "misplaced end", offset)?,
+            Op::Jump(to) => pending.push((to as usize, height)),
+            Op::JumpUnless(to) => {
+                pending.push((to as usize, height));
+                pending.push((offset + 1, height));
+            }
+            _ => pending.push((offset + 1, height)),
+        }
+    }
+    Ok(())
+}
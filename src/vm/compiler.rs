@@ -0,0 +1,290 @@
+use crate::ast::ast::Expr;
+use crate::ast::ast::ExprKind;
+use crate::ast::ast::FnDecl;
+use crate::ast::ast::Stmt;
+use crate::ast::ast::StmtKind;
+use crate::tokeniser::token_enum::Span;
+use crate::vm::bytecode::Chunk;
+use crate::vm::bytecode::Op;
+use crate::vm::bytecode::Program;
+use crate::vm::bytecode::Proto;
+use crate::vm::bytecode::Statement;
+use std::collections::HashMap;
+
+/// Compile statements to bytecode for the virtual machine
+///
+/// Names are looked up as the tree-walker would at runtime: locals of the
+/// enclosing blocks and function live in frame slots, anything else is
+/// looked up by name when it runs, so a program behaves the same whether or
+/// not it was resolved first
+pub fn compile(stmts: &[Stmt]) -> Program {
+    let mut compiler = Compiler {
+        program: Program::default(),
+        names: HashMap::new(),
+        frames: Vec::new(),
+        span: Span::default(),
+    };
+    for stmt in stmts {
+        compiler.frames.push(Frame::default());
+        compiler.stmt(stmt);
+        let end = match stmt.kind {
+            StmtKind::Expr(_) => Op::Return,
+            _ => Op::End,
+        };
+        compiler.frame().chunk.emit(end, stmt.span);
+        let frame = compiler.frames.pop().expect("pushed above");
+        compiler.program.statements.push(Statement {
+            chunk: frame.chunk,
+            span: stmt.span,
+        });
+    }
+    compiler.program
+}
+
+/// A `let` or `const` in a block, or a parameter
+#[derive(Debug, Clone, Copy)]
+struct Local {
+    slot: u32,
+    mutable: bool,
+}
+
+/// Chunk being compiled and its lexical scopes, a top level statement starts
+/// without any so its bindings are globals
+#[derive(Default)]
+struct Frame {
+    chunk: Chunk,
+    scopes: Vec<HashMap<String, Local>>,
+    /// First slot not used by an enclosing scope
+    next_slot: u32,
+}
+
+struct Compiler {
+    program: Program,
+    names: HashMap<String, u32>,
+    /// Function bodies being compiled, innermost last
+    frames: Vec<Frame>,
+    /// Source of the statement or expression being compiled
+    span: Span,
+}
+
+impl Compiler {
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("always compiling a chunk")
+    }
+    fn emit(&mut self, op: Op) -> u32 {
+        let span = self.span;
+        self.frame().chunk.emit(op, span)
+    }
+    fn name(&mut self, name: &str) -> u32 {
+        if let Some(&idx) = self.names.get(name) {
+            return idx;
+        }
+        self.program.names.push(name.to_string());
+        let idx = self.program.names.len() as u32 - 1;
+        self.names.insert(name.to_string(), idx);
+        idx
+    }
+    fn local(&mut self, name: &str) -> Option<Local> {
+        self.frame()
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+    /// Give a new binding a slot in the innermost scope
+    fn declare(&mut self, name: &str, mutable: bool) -> u32 {
+        let frame = self.frame();
+        let slot = frame.next_slot;
+        frame.next_slot += 1;
+        frame.chunk.locals = frame.chunk.locals.max(frame.next_slot);
+        frame
+            .scopes
+            .last_mut()
+            .expect("locals are declared in a scope")
+            .insert(name.to_string(), Local { slot, mutable });
+        slot
+    }
+    /// Leaves the value on the stack for expression statements only
+    fn stmt(&mut self, stmt: &Stmt) {
+        let outer = std::mem::replace(&mut self.span, stmt.span);
+        self.stmt_kind(stmt);
+        self.span = outer;
+    }
+    fn stmt_kind(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Let(id, expr) | StmtKind::Const(id, expr) => {
+                self.expr(expr);
+                let mutable = matches!(stmt.kind, StmtKind::Let(..));
+                if self.frame().scopes.is_empty() {
+                    let name = self.name(&id.name);
+                    self.emit(if mutable {
+                        Op::DefineLet(name)
+                    } else {
+                        Op::DefineConst(name)
+                    });
+                } else {
+                    let slot = self.declare(&id.name, mutable);
+                    self.emit(Op::SetLocal(slot));
+                }
+            }
+            StmtKind::Assign(id, expr) => {
+                self.expr(expr);
+                let op = match self.local(&id.name) {
+                    Some(Local {
+                        slot,
+                        mutable: true,
+                    }) => Op::SetLocal(slot),
+                    Some(_) => Op::AssignConst(self.name(&id.name)),
+                    None => Op::SetName(self.name(&id.name)),
+                };
+                self.emit(op);
+            }
+            StmtKind::Fn(decl) => {
+                let name = self.name(&decl.name.name);
+                let proto = self.function(decl);
+                self.emit(Op::DefineFunction(name, proto));
+            }
+            StmtKind::Expr(expr) => self.expr(expr),
+            StmtKind::Return(expr) => {
+                match expr {
+                    Some(expr) => self.expr(expr),
+                    None => {
+                        self.emit(Op::Unit);
+                    }
+                }
+                self.emit(Op::Return);
+            }
+        }
+    }
+    /// Compile a body in a frame of its own, which sees none of the locals
+    /// around it
+    fn function(&mut self, decl: &FnDecl) -> u32 {
+        let mut frame = Frame::default();
+        let mut params = HashMap::new();
+        for param in &decl.params {
+            params.insert(
+                param.name.name.clone(),
+                Local {
+                    slot: frame.next_slot,
+                    mutable: true,
+                },
+            );
+            frame.next_slot += 1;
+        }
+        frame.chunk.locals = frame.next_slot;
+        frame.scopes.push(params);
+        self.frames.push(frame);
+        self.block(&decl.body);
+        self.emit(Op::Return);
+        let frame = self.frames.pop().expect("pushed above");
+
+        self.program.functions.push(Proto {
+            name: decl.name.name.clone(),
+            params: decl
+                .params
+                .iter()
+                .map(|param| param.name.name.clone())
+                .collect(),
+            chunk: frame.chunk,
+        });
+        self.program.functions.len() as u32 - 1
+    }
+    /// Pushes the value of a trailing expression statement, or else unit
+    fn block(&mut self, stmts: &[Stmt]) {
+        let frame = self.frame();
+        frame.scopes.push(HashMap::new());
+        let next_slot = frame.next_slot;
+
+        for (idx, stmt) in stmts.iter().enumerate() {
+            self.stmt(stmt);
+            let last = idx + 1 == stmts.len();
+            match (&stmt.kind, last) {
+                (StmtKind::Expr(_), true) => {}
+                (StmtKind::Expr(_), false) => {
+                    self.emit(Op::Pop);
+                }
+                (_, true) => {
+                    self.emit(Op::Unit);
+                }
+                (_, false) => {}
+            }
+        }
+        if stmts.is_empty() {
+            self.emit(Op::Unit);
+        }
+
+        let frame = self.frame();
+        frame.scopes.pop();
+        frame.next_slot = next_slot;
+    }
+    fn expr(&mut self, expr: &Expr) {
+        let outer = std::mem::replace(&mut self.span, expr.span);
+        self.expr_kind(expr);
+        self.span = outer;
+    }
+    fn expr_kind(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Number(val) => {
+                let idx = self.frame().chunk.number(*val);
+                self.emit(Op::Number(idx));
+            }
+            ExprKind::Bool(true) => {
+                self.emit(Op::True);
+            }
+            ExprKind::Bool(false) => {
+                self.emit(Op::False);
+            }
+            ExprKind::Variable(name) => {
+                let op = match self.local(name) {
+                    Some(local) => Op::GetLocal(local.slot),
+                    None => Op::GetName(self.name(name)),
+                };
+                self.emit(op);
+            }
+            ExprKind::BinaryOp(left, op, right) => {
+                self.expr(left);
+                self.expr(right);
+                self.emit(Op::Binary(*op));
+            }
+            ExprKind::UnaryOp(operand, op) => {
+                self.expr(operand);
+                self.emit(Op::Unary(*op));
+            }
+            ExprKind::Builtin(builtin, args) => {
+                for (idx, arg) in args.iter().enumerate() {
+                    self.expr(arg);
+                    if idx + 1 < args.len() {
+                        self.emit(Op::AssertNumber);
+                    }
+                }
+                self.emit(Op::Builtin(*builtin, args.len() as u32));
+            }
+            ExprKind::Block(stmts) => self.block(stmts),
+            ExprKind::FunctionCall(id, args) => {
+                let op = match self.local(&id.name) {
+                    Some(local) => Op::GetLocal(local.slot),
+                    None => Op::Callee(self.name(&id.name)),
+                };
+                self.emit(op);
+                let name = self.name(&id.name);
+                self.emit(Op::Callable(name, args.len() as u32));
+                args.iter().for_each(|arg| self.expr(arg));
+                self.emit(Op::Call(args.len() as u32));
+            }
+            ExprKind::If(cond, then_branch, else_branch) => {
+                self.expr(cond);
+                let skip_then = self.emit(Op::JumpUnless(0));
+                self.expr(then_branch);
+                let skip_else = self.emit(Op::Jump(0));
+                self.frame().chunk.patch(skip_then);
+                match else_branch {
+                    Some(else_branch) => self.expr(else_branch),
+                    None => {
+                        self.emit(Op::Unit);
+                    }
+                }
+                self.frame().chunk.patch(skip_else);
+            }
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use crate::vm::bytecode::Chunk;
+use crate::vm::bytecode::Op;
+use crate::vm::bytecode::Program;
+use std::fmt::Write;
+
+/// Readable listing of every function and top level statement of a program
+///
+/// Each instruction is shown with its offset, names and numbers are written
+/// out, and the source line an instruction came from is printed as a
+/// `; line: text` comment whenever it changes. Without the source, as for a
+/// cache file, there are no comments
+pub fn disassemble(program: &Program, source: &str) -> String {
+    let mut out = String::new();
+    for (idx, proto) in program.functions.iter().enumerate() {
+        let header = format!("fn {0}({1}) #{idx}", proto.name, proto.params.join(", "));
+        chunk(&mut out, &header, &proto.chunk, program, source);
+    }
+    for (idx, stmt) in program.statements.iter().enumerate() {
+        let header = format!("statement {0}", idx + 1);
+        chunk(&mut out, &header, &stmt.chunk, program, source);
+    }
+    out.truncate(out.trim_end().len());
+    out
+}
+
+/// Line, counting from 1, holding the byte at `offset`
+fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count() + 1
+}
+
+fn chunk(out: &mut String, header: &str, chunk: &Chunk, program: &Program, source: &str) {
+    let locals = match chunk.locals {
+        1 => String::from("1 local"),
+        count => format!("{count} locals"),
+    };
+    writeln!(out, "{header}, {locals}").unwrap();
+    let mut last_line = None;
+    for (offset, (op, span)) in chunk.code.iter().zip(&chunk.spans).enumerate() {
+        let line = line_of(source, span.start);
+        if !source.is_empty() && last_line != Some(line) {
+            let text = source.lines().nth(line - 1).unwrap_or("").trim();
+            writeln!(out, "; {line}: {text}").unwrap();
+            last_line = Some(line);
+        }
+        writeln!(out, "{offset:04}  {0}", instruction(*op, chunk, program)).unwrap();
+    }
+    writeln!(out).unwrap();
+}
+
+fn instruction(op: Op, chunk: &Chunk, program: &Program) -> String {
+    let name = |idx: u32| program.names[idx as usize].as_str();
+    match op {
+        Op::Number(idx) => format!("number {0}", chunk.numbers[idx as usize]),
+        Op::True => String::from("true"),
+        Op::False => String::from("false"),
+        Op::Unit => String::from("unit"),
+        Op::Pop => String::from("pop"),
+        Op::GetLocal(slot) => format!("get_local {slot}"),
+        Op::SetLocal(slot) => format!("set_local {slot}"),
+        Op::GetName(idx) => format!("get_name {0}", name(idx)),
+        Op::SetName(idx) => format!("set_name {0}", name(idx)),
+        Op::DefineLet(idx) => format!("define_let {0}", name(idx)),
+        Op::DefineConst(idx) => format!("define_const {0}", name(idx)),
+        Op::DefineFunction(idx, proto) => format!("define_function {0} #{proto}", name(idx)),
+        Op::Callee(idx) => format!("callee {0}", name(idx)),
+        Op::Callable(idx, argc) => format!("callable {0} {argc}", name(idx)),
+        Op::Call(argc) => format!("call {argc}"),
+        Op::Binary(op) => format!("binary {op}"),
+        Op::Unary(op) => format!("unary {op}"),
+        Op::AssertNumber => String::from("assert_number"),
+        Op::Builtin(builtin, argc) => format!("builtin {builtin} {argc}"),
+        Op::Jump(to) => format!("jump {to:04}"),
+        Op::JumpUnless(to) => format!("jump_unless {to:04}"),
+        Op::AssignConst(idx) => format!("assign_const {0}", name(idx)),
+        Op::Return => String::from("return"),
+        Op::End => String::from("end"),
+    }
+}
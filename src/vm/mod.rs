@@ -0,0 +1,6 @@
+pub mod bytecode;
+pub mod cache;
+pub mod compiler;
+pub mod disassemble;
+#[allow(clippy::module_inception)]
+pub mod vm;
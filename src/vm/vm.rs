@@ -0,0 +1,354 @@
+use crate::ast::ast::constant;
+use crate::ast::error::RuntimeError;
+use crate::ast::evaluation::Evaluation;
+use crate::ast::evaluation::Outcome;
+use crate::ast::evaluation::StatementResult;
+use crate::ast::value::Function;
+use crate::ast::value::Value;
+use crate::semantic::resolver::Resolver;
+use crate::vm::bytecode::Chunk;
+use crate::vm::bytecode::Op;
+use crate::vm::bytecode::Program;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A global variable, `const` bindings can't be reassigned
+#[derive(Debug, Clone)]
+struct Binding {
+    value: Value,
+    mutable: bool,
+}
+
+/// Everything known by one name, variables are looked up before functions
+#[derive(Debug, Clone)]
+struct Symbol {
+    name: String,
+    variable: Option<Binding>,
+    function: Option<Rc<Function>>,
+}
+
+/// A function body or top level statement being run
+struct Frame {
+    code: usize,
+    ip: usize,
+    /// Stack index of the first local
+    base: usize,
+}
+
+/// Stack based virtual machine running compiled programs, variables and
+/// functions survive between programs like they do in the tree-walker
+#[derive(Default)]
+pub struct Vm {
+    symbols: Vec<Symbol>,
+    symbol_ids: HashMap<String, u32>,
+    /// Code of every function loaded so far, then that of the statements
+    /// being run
+    code: Vec<Chunk>,
+    /// Parameters of each function in `code`
+    params: Vec<Vec<String>>,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Drop every variable and function defined so far
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+    /// Global variables sorted by name
+    pub fn variables(&self) -> Vec<(String, Value)> {
+        let mut vars: Vec<(String, Value)> = self
+            .symbols
+            .iter()
+            .filter_map(|sym| Some((sym.name.clone(), sym.variable.as_ref()?.value.clone())))
+            .collect();
+        vars.sort_by(|a, b| a.0.cmp(&b.0));
+        vars
+    }
+    /// User defined functions and their parameters sorted by name
+    pub fn functions(&self) -> Vec<(String, Vec<String>)> {
+        let mut funcs: Vec<(String, Vec<String>)> = self
+            .symbols
+            .iter()
+            .filter_map(|sym| Some((sym.name.clone(), sym.function.as_ref()?.params.clone())))
+            .collect();
+        funcs.sort_by(|a, b| a.0.cmp(&b.0));
+        funcs
+    }
+    /// A resolver that already knows every global and function defined so far
+    pub fn resolver(&self) -> Resolver {
+        let mut resolver = Resolver::new();
+        for sym in &self.symbols {
+            if let Some(binding) = &sym.variable {
+                resolver.declare_global(&sym.name, binding.mutable);
+            }
+            if let Some(func) = &sym.function {
+                resolver.declare_function(&sym.name, func.params.len());
+            }
+        }
+        resolver
+    }
+    /// Define (or overwrite) a global variable
+    pub fn set_global(&mut self, id: &str, value: Value) {
+        let sym = self.symbol(id);
+        self.symbols[sym as usize].variable = Some(Binding {
+            value,
+            mutable: true,
+        });
+    }
+    /// Run each statement in order, recording what every statement produced
+    /// and stopping at the first error
+    pub fn evaluate(&mut self, program: &Program) -> Evaluation {
+        let names: Vec<u32> = program.names.iter().map(|name| self.symbol(name)).collect();
+        let offset = self.code.len() as u32;
+        for proto in &program.functions {
+            self.code.push(relink(&proto.chunk, &names, offset));
+            self.params.push(proto.params.clone());
+        }
+        let functions = self.code.len();
+
+        let mut evaluation = Evaluation::default();
+        for stmt in &program.statements {
+            self.code.push(relink(&stmt.chunk, &names, offset));
+            let outcome = match self.run(self.code.len() - 1) {
+                Ok(Some(val)) => Outcome::Value(val),
+                Ok(None) => Outcome::Unit,
+                Err(err) => Outcome::Error(err),
+            };
+            self.code.truncate(functions);
+            let failed = matches!(outcome, Outcome::Error(_));
+            evaluation.results.push(StatementResult {
+                outcome,
+                span: stmt.span,
+            });
+            if failed {
+                break;
+            }
+        }
+        evaluation
+    }
+    fn symbol(&mut self, name: &str) -> u32 {
+        if let Some(&id) = self.symbol_ids.get(name) {
+            return id;
+        }
+        self.symbols.push(Symbol {
+            name: name.to_string(),
+            variable: None,
+            function: None,
+        });
+        let id = self.symbols.len() as u32 - 1;
+        self.symbol_ids.insert(name.to_string(), id);
+        id
+    }
+    fn pop(&mut self) -> Value {
+        self.stack
+            .pop()
+            .expect("compiled code keeps the stack balanced")
+    }
+    /// Run a top level statement, which gives a value unless it's a
+    /// definition that ran to the end
+    fn run(&mut self, code: usize) -> Result<Option<Value>, RuntimeError> {
+        self.stack
+            .resize(self.code[code].locals as usize, Value::Unit);
+        self.frames.push(Frame {
+            code,
+            ip: 0,
+            base: 0,
+        });
+        let result = self.execute();
+        self.stack.clear();
+        self.frames.clear();
+        result
+    }
+    fn execute(&mut self) -> Result<Option<Value>, RuntimeError> {
+        loop {
+            let frame = self.frames.last_mut().expect("running a frame");
+            let code = frame.code;
+            let base = frame.base;
+            let op = self.code[code].code[frame.ip];
+            frame.ip += 1;
+
+            match op {
+                Op::Number(idx) => {
+                    let val = self.code[code].numbers[idx as usize];
+                    self.stack.push(Value::Number(val));
+                }
+                Op::True => self.stack.push(Value::Bool(true)),
+                Op::False => self.stack.push(Value::Bool(false)),
+                Op::Unit => self.stack.push(Value::Unit),
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::GetLocal(slot) => {
+                    let val = self.stack[base + slot as usize].clone();
+                    self.stack.push(val);
+                }
+                Op::SetLocal(slot) => {
+                    let val = self.pop();
+                    self.stack[base + slot as usize] = val;
+                }
+                Op::GetName(sym) => {
+                    let sym = &self.symbols[sym as usize];
+                    let val = match (&sym.variable, &sym.function) {
+                        (Some(binding), _) => binding.value.clone(),
+                        (None, Some(func)) => Value::Function(func.clone()),
+                        (None, None) => constant(&sym.name)
+                            .map(Value::Number)
+                            .ok_or_else(|| RuntimeError::UndeclaredVariable(sym.name.clone()))?,
+                    };
+                    self.stack.push(val);
+                }
+                Op::SetName(sym) => {
+                    let value = self.pop();
+                    let sym = &mut self.symbols[sym as usize];
+                    let binding = sym
+                        .variable
+                        .as_mut()
+                        .ok_or_else(|| RuntimeError::UndeclaredVariable(sym.name.clone()))?;
+                    if !binding.mutable {
+                        return Err(RuntimeError::AssignToConst(sym.name.clone()));
+                    }
+                    binding.value = value;
+                }
+                Op::DefineLet(sym) | Op::DefineConst(sym) => {
+                    let value = self.pop();
+                    self.symbols[sym as usize].variable = Some(Binding {
+                        value,
+                        mutable: matches!(op, Op::DefineLet(_)),
+                    });
+                }
+                Op::DefineFunction(sym, proto) => {
+                    let sym = &mut self.symbols[sym as usize];
+                    sym.function = Some(Rc::new(Function {
+                        name: sym.name.clone(),
+                        params: self.params[proto as usize].clone(),
+                        body: Vec::new(),
+                        code: Some(proto as usize),
+                    }));
+                }
+                Op::Callee(sym) => {
+                    let sym = &self.symbols[sym as usize];
+                    let callee = match (&sym.variable, &sym.function) {
+                        (Some(binding), _) => binding.value.clone(),
+                        (None, Some(func)) => Value::Function(func.clone()),
+                        (None, None) => {
+                            return Err(RuntimeError::UndefinedFunction(sym.name.clone()))
+                        }
+                    };
+                    self.stack.push(callee);
+                }
+                Op::Callable(sym, argc) => {
+                    let func = match self.stack.last() {
+                        Some(Value::Function(func)) => func,
+                        other => {
+                            return Err(RuntimeError::TypeMismatch {
+                                expected: "function",
+                                found: other.expect("callee was pushed").type_name(),
+                            })
+                        }
+                    };
+                    if func.params.len() != argc as usize {
+                        return Err(RuntimeError::ArityMismatch {
+                            name: self.symbols[sym as usize].name.clone(),
+                            expected: func.params.len(),
+                            found: argc as usize,
+                        });
+                    }
+                }
+                Op::Call(argc) => {
+                    let base = self.stack.len() - argc as usize;
+                    let func = match &self.stack[base - 1] {
+                        Value::Function(func) => func,
+                        other => {
+                            return Err(RuntimeError::TypeMismatch {
+                                expected: "function",
+                                found: other.type_name(),
+                            })
+                        }
+                    };
+                    let code = func.code.expect("functions are defined by the vm");
+                    self.stack
+                        .resize(base + self.code[code].locals as usize, Value::Unit);
+                    self.frames.push(Frame { code, ip: 0, base });
+                }
+                Op::Binary(op) => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.stack.push(Value::binary(op, &left, &right)?);
+                }
+                Op::Unary(op) => {
+                    let val = self.pop();
+                    self.stack.push(Value::unary(op, &val)?);
+                }
+                Op::AssertNumber => {
+                    self.stack
+                        .last()
+                        .expect("compiled code keeps the stack balanced")
+                        .as_number()?;
+                }
+                Op::Builtin(builtin, argc) => {
+                    let args = self.stack.split_off(self.stack.len() - argc as usize);
+                    let mut nums = Vec::new();
+                    for arg in &args {
+                        nums.push(arg.as_number()?);
+                    }
+                    self.stack.push(Value::Number(builtin.call(&nums)));
+                }
+                Op::Jump(to) => self.jump(to),
+                Op::JumpUnless(to) => {
+                    if !self.pop().as_bool()? {
+                        self.jump(to);
+                    }
+                }
+                Op::AssignConst(sym) => {
+                    return Err(RuntimeError::AssignToConst(
+                        self.symbols[sym as usize].name.clone(),
+                    ))
+                }
+                Op::Return => {
+                    let value = self.pop();
+                    self.frames.pop();
+                    if self.frames.is_empty() {
+                        return Ok(Some(value));
+                    }
+                    // Drop the locals and the callee below them
+                    self.stack.truncate(base - 1);
+                    self.stack.push(value);
+                }
+                Op::End => return Ok(None),
+            }
+        }
+    }
+    fn jump(&mut self, to: u32) {
+        self.frames.last_mut().expect("running a frame").ip = to as usize;
+    }
+}
+
+/// Point a chunk's names at symbols and its functions at loaded code
+fn relink(chunk: &Chunk, names: &[u32], offset: u32) -> Chunk {
+    let name = |idx: u32| names[idx as usize];
+    let code = chunk
+        .code
+        .iter()
+        .map(|&op| match op {
+            Op::GetName(idx) => Op::GetName(name(idx)),
+            Op::SetName(idx) => Op::SetName(name(idx)),
+            Op::DefineLet(idx) => Op::DefineLet(name(idx)),
+            Op::DefineConst(idx) => Op::DefineConst(name(idx)),
+            Op::DefineFunction(idx, proto) => Op::DefineFunction(name(idx), proto + offset),
+            Op::Callee(idx) => Op::Callee(name(idx)),
+            Op::Callable(idx, argc) => Op::Callable(name(idx), argc),
+            Op::AssignConst(idx) => Op::AssignConst(name(idx)),
+            op => op,
+        })
+        .collect();
+    Chunk {
+        code,
+        spans: chunk.spans.clone(),
+        numbers: chunk.numbers.clone(),
+        locals: chunk.locals,
+    }
+}